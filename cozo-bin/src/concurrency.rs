@@ -0,0 +1,77 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Limits on the number of requests a single client may have in flight at the same time,
+//! so that one client cannot occupy all the threads running queries.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub(crate) struct ClientConcurrency {
+    /// Requests allowed in flight per client, 0 for no limit
+    limit: usize,
+    in_flight: Mutex<BTreeMap<String, usize>>,
+}
+
+impl ClientConcurrency {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Take a slot for a request of `client`, `None` if the client already has as many requests
+    /// in flight as allowed. The slot is given back when the permit is dropped.
+    pub(crate) fn acquire(self: &Arc<Self>, client: String) -> Option<ConcurrencyPermit> {
+        if self.limit == 0 {
+            return Some(ConcurrencyPermit(None));
+        }
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(client.clone()).or_default();
+        if *count >= self.limit {
+            return None;
+        }
+        *count += 1;
+        Some(ConcurrencyPermit(Some((self.clone(), client))))
+    }
+
+    /// Number of requests in flight for `client`
+    #[cfg(test)]
+    pub(crate) fn in_flight(&self, client: &str) -> usize {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .get(client)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn release(&self, client: &str) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(client) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(client);
+            }
+        }
+    }
+}
+
+/// A slot taken by a request in flight. Dropping it, which also happens when the request
+/// fails or its handler panics, gives the slot back.
+pub(crate) struct ConcurrencyPermit(Option<(Arc<ClientConcurrency>, String)>);
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Some((limits, client)) = &self.0 {
+            limits.release(client)
+        }
+    }
+}
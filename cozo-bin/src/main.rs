@@ -11,14 +11,25 @@ extern crate core;
 use std::process::exit;
 
 use clap::{Parser, Subcommand};
+#[cfg(feature = "server")]
 use env_logger::Env;
 
 use crate::repl::{repl_main, ReplArgs};
+#[cfg(feature = "server")]
 use crate::server::{server_main, ServerArgs};
 
 mod client;
+#[cfg(feature = "server")]
+mod concurrency;
+#[cfg(feature = "server")]
+mod metrics;
+#[cfg(feature = "otel")]
+mod otel;
 mod repl;
+#[cfg(feature = "server")]
 mod server;
+#[cfg(feature = "server")]
+mod sql;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -30,12 +41,14 @@ struct AppArgs {
 
 #[derive(Subcommand)]
 enum Commands {
+    #[cfg(feature = "server")]
     Server(ServerArgs),
     Repl(ReplArgs),
 }
 
 fn main() {
     match AppArgs::parse().command {
+        #[cfg(feature = "server")]
         Commands::Server(args) => {
             env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
             tokio::runtime::Builder::new_multi_thread()
@@ -0,0 +1,101 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Request latency metrics served at `/metrics` in the Prometheus text format: a histogram of the
+//! time taken by every request, and gauges for the p50, p95 and p99 latencies computed over the
+//! most recent requests.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Upper bounds in seconds of the histogram buckets, `+Inf` is implied
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Number of most recent samples the percentiles are computed from
+const WINDOW: usize = 1000;
+
+const PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p95", 0.95), ("p99", 0.99)];
+
+#[derive(Debug)]
+pub(crate) struct LatencyMetrics {
+    count: u64,
+    sum: f64,
+    /// Non-cumulative counts of the samples falling into each bucket, the last one is `+Inf`
+    buckets: [u64; BUCKETS.len() + 1],
+    recent: VecDeque<f64>,
+}
+
+impl Default for LatencyMetrics {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.,
+            buckets: [0; BUCKETS.len() + 1],
+            recent: VecDeque::with_capacity(WINDOW),
+        }
+    }
+}
+
+impl LatencyMetrics {
+    pub(crate) fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        self.count += 1;
+        self.sum += secs;
+        let idx = BUCKETS
+            .iter()
+            .position(|b| secs <= *b)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[idx] += 1;
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(secs);
+    }
+
+    /// Nearest-rank percentile over the recent samples, `None` if there are none
+    pub(crate) fn percentile(&self, p: f64) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = (p * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        let name = "cozo_time_taken_seconds";
+        writeln!(out, "# HELP {name} Time taken to serve requests.").unwrap();
+        writeln!(out, "# TYPE {name} histogram").unwrap();
+        let mut cumulative = 0;
+        for (bound, n) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += n;
+            writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}").unwrap();
+        }
+        writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count).unwrap();
+        writeln!(out, "{name}_sum {}", self.sum).unwrap();
+        writeln!(out, "{name}_count {}", self.count).unwrap();
+        for (label, p) in PERCENTILES {
+            if let Some(v) = self.percentile(p) {
+                let gauge = format!("cozo_time_taken_{label}_seconds");
+                writeln!(
+                    out,
+                    "# HELP {gauge} The {label} latency over the last {WINDOW} requests."
+                )
+                .unwrap();
+                writeln!(out, "# TYPE {gauge} gauge").unwrap();
+                writeln!(out, "{gauge} {v}").unwrap();
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! OpenTelemetry traces of the requests served. Every request gets a span, continuing the trace
+//! of the `traceparent` header if present, under which the database records the parsing,
+//! optimization and execution of scripts.
+//!
+//! The spans are exported via OTLP, configured by the standard environment variables such as
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` and `OTEL_SERVICE_NAME`.
+
+use axum::body::BoxBody;
+use axum::http::{HeaderMap, Request, Response};
+use axum::middleware::Next;
+use log::error;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::Tracer;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Installs the OTLP exporter as the destination of the spans of this process.
+pub(crate) fn init_tracing() {
+    let tracer = match otlp_tracer() {
+        Ok(tracer) => tracer,
+        Err(err) => {
+            error!("Cannot set up the OpenTelemetry exporter: {}", err);
+            return;
+        }
+    };
+    // logs keep going to `env_logger`
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
+        error!("Cannot install the tracing subscriber: {}", err);
+    }
+}
+
+fn otlp_tracer() -> Result<Tracer, TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+        .install_batch(Tokio)
+}
+
+/// Exports the spans not yet sent, to be called before the process exits.
+pub(crate) fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Runs the request within a span whose parent is given by the `traceparent` header, if any.
+pub(crate) async fn trace_request<B>(request: Request<B>, next: Next<B>) -> Response<BoxBody> {
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", request.method(), request.uri().path()),
+        http.method = %request.method(),
+        http.target = %request.uri().path(),
+        http.status_code = tracing::field::Empty,
+    );
+    span.set_parent(parent);
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
@@ -9,34 +9,49 @@
 use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::net::{Ipv6Addr, SocketAddr};
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use axum::body::{Body, BoxBody};
-use axum::extract::{Path, Query, State};
-use axum::http::{Method, Request, Response, StatusCode};
+use axum::body::{boxed, Body, BoxBody, Bytes, HttpBody};
+use axum::extract::{ConnectInfo, FromRequest, Path, Query, State};
+use axum::http::header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use axum::middleware::{self, Next};
 use axum::response::sse::{Event, KeepAlive};
-use axum::response::{Html, Sse};
+use axum::response::{Html, IntoResponse, Sse};
 use axum::routing::{get, post, put};
-use axum::{Json, Router};
+use axum::{Extension, Json, Router};
 use clap::Args;
 use futures::stream::Stream;
+use futures::FutureExt;
+use http_body::SizeHint;
 use itertools::Itertools;
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use miette::miette;
 use rand::Rng;
 use serde_json::json;
-use tokio::task::spawn_blocking;
+use subtle::ConstantTimeEq;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use tower_http::auth::RequireAuthorizationLayer;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::timeout::TimeoutLayer;
 
 use cozo::{
-    format_error_as_json, DataValue, DbInstance, MultiTransaction, NamedRows, SimpleFixedRule,
+    evaluate_expression, format_error_as_json, CsvImportOptions, DataValue, DbInstance,
+    MultiTransaction, NamedRows, Num, Poison, SimpleFixedRule,
 };
 
+use crate::concurrency::{ClientConcurrency, ConcurrencyPermit};
+use crate::metrics::LatencyMetrics;
+use crate::sql::{relation_columns, translate_sql};
+
 #[derive(Args, Debug)]
 pub(crate) struct ServerArgs {
     /// Database engine, can be `mem`, `sqlite`, `rocksdb` and others.
@@ -65,6 +80,242 @@ pub(crate) struct ServerArgs {
     /// Port to use
     #[clap(short = 'P', long, default_value_t = 9070)]
     port: u16,
+
+    /// Address and port to listen on, e.g. `0.0.0.0:9070` or `[::1]:9070`.
+    /// Can be given multiple times to serve the same database on several addresses,
+    /// in which case `--bind` and `--port` are ignored.
+    #[clap(long)]
+    listen: Vec<String>,
+
+    /// Token for the admin endpoints, can also be set with the environment variable
+    /// `COZO_ADMIN_AUTH`. The admin endpoints are disabled if neither is given.
+    #[clap(long)]
+    admin_auth: Option<String>,
+
+    /// Token for `/metrics`, can also be set with the environment variable `COZO_METRICS_AUTH`.
+    /// If neither is given, `/metrics` requires the usual auth token instead.
+    #[clap(long)]
+    metrics_auth: Option<String>,
+
+    /// Seconds a client has to send the request headers before its connection is dropped,
+    /// 0 to wait indefinitely
+    #[clap(long, default_value_t = 30)]
+    read_timeout: u64,
+
+    /// Seconds a request may take before it is answered with `408 Request Timeout`,
+    /// 0 to wait indefinitely. This bounds the handler, not writes to the socket,
+    /// and a timed-out query may still run to completion in the background.
+    #[clap(long, default_value_t = 600)]
+    request_timeout: u64,
+
+    /// Seconds a connection may be idle before TCP keepalive probes check that the client
+    /// is still there. 0 to send no probes and close connections after every response
+    #[clap(long, default_value_t = 60)]
+    keep_alive: u64,
+
+    /// Abort queries whose intermediate results are estimated to exceed this many bytes,
+    /// 0 for no limit
+    #[clap(long, default_value_t = 0)]
+    query_memory_limit: usize,
+
+    /// Requests a single client, identified by its IP address, may have in flight at the same
+    /// time, further ones get `429 Too Many Requests`. 0 for no limit
+    #[clap(long, default_value_t = 0)]
+    max_concurrent_per_client: usize,
+
+    /// Format of the results of `/text-query` for requests asking for none in particular
+    /// by their `format` field or `Accept` header
+    #[clap(long, value_enum, default_value_t = ResultFormat::Json)]
+    default_format: ResultFormat,
+
+    /// Do not serve the web console at `/`, which then gives `404 Not Found`
+    /// like any unknown route
+    #[clap(long)]
+    disable_console: bool,
+}
+
+/// How `/text-query` returns the rows of successful queries
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ResultFormat {
+    /// JSON, with each row a list of values
+    #[default]
+    Json,
+    /// JSON, with each row an object keyed by the column headers
+    Objects,
+    /// CSV, with a header row
+    Csv,
+}
+
+/// Timeouts and keep-alive of the HTTP server, `None` meaning disabled
+#[derive(Debug, PartialEq, Eq)]
+struct ListenerConfig {
+    read_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    keep_alive: Option<Duration>,
+}
+
+impl ListenerConfig {
+    fn from_args(args: &ServerArgs) -> Self {
+        let secs = |n: u64| {
+            if n == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(n))
+            }
+        };
+        Self {
+            read_timeout: secs(args.read_timeout),
+            request_timeout: secs(args.request_timeout),
+            keep_alive: secs(args.keep_alive),
+        }
+    }
+}
+
+/// The ID of the request being served, taken from the `x-request-id` header of the request
+/// or generated if absent. Available to every handler as an extension.
+#[derive(Clone, Debug)]
+struct RequestId(String);
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+async fn propagate_request_id<B>(mut request: Request<B>, next: Next<B>) -> Response<BoxBody> {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let started = Instant::now();
+
+    let mut response = next.run(request).await;
+
+    info!(
+        "[{}] {} {} {} {:?}",
+        id,
+        method,
+        uri.path(),
+        response.status().as_u16(),
+        started.elapsed()
+    );
+    if let Ok(v) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, v);
+    }
+    response
+}
+
+/// Runs `f` on the threads reserved for blocking work, within the tracing span of the request
+/// if traces are emitted.
+fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    #[cfg(feature = "otel")]
+    let f = {
+        let span = tracing::Span::current();
+        let dispatch = tracing::dispatcher::get_default(|d| d.clone());
+        move || tracing::dispatcher::with_default(&dispatch, || span.in_scope(f))
+    };
+    tokio::task::spawn_blocking(f)
+}
+
+/// Turns away requests of clients already having as many requests in flight as allowed
+/// by `--max-concurrent-per-client`. A request is in flight until its response is fully sent,
+/// so a stream of server-sent events counts for as long as it is open.
+async fn limit_concurrency<B>(
+    State(st): State<DbState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response<BoxBody> {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_default();
+    let permit = match st.concurrency.acquire(client) {
+        Some(permit) => permit,
+        None => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({"ok": false, "message": "too many concurrent requests"})),
+            )
+                .into_response()
+        }
+    };
+    next.run(request).await.map(|body| {
+        boxed(BodyWithPermit {
+            body,
+            _permit: permit,
+        })
+    })
+}
+
+/// A response body keeping the permit of its request until the body is dropped
+struct BodyWithPermit {
+    body: BoxBody,
+    _permit: ConcurrencyPermit,
+}
+
+impl HttpBody for BodyWithPermit {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Records the time taken by every request except the scrapes of `/metrics` itself.
+async fn record_latency<B>(
+    State(st): State<DbState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response<BoxBody> {
+    if request.uri().path() == "/metrics" {
+        return next.run(request).await;
+    }
+    let started = Instant::now();
+    let response = next.run(request).await;
+    st.metrics.lock().unwrap().observe(started.elapsed());
+    response
+}
+
+fn listen_addrs(args: &ServerArgs) -> miette::Result<Vec<SocketAddr>> {
+    if args.listen.is_empty() {
+        let addr = if Ipv6Addr::from_str(&args.bind).is_ok() {
+            format!("[{}]:{}", args.bind, args.port)
+        } else {
+            format!("{}:{}", args.bind, args.port)
+        };
+        let addr = SocketAddr::from_str(&addr).map_err(|_| miette!("bad address: {}", addr))?;
+        return Ok(vec![addr]);
+    }
+    args.listen
+        .iter()
+        .map(|a| SocketAddr::from_str(a).map_err(|_| miette!("bad address: {}", a)))
+        .collect()
 }
 
 #[derive(Clone)]
@@ -74,10 +325,48 @@ struct DbState {
     rule_counter: Arc<AtomicU32>,
     tx_counter: Arc<AtomicU32>,
     txs: Arc<Mutex<BTreeMap<u32, Arc<MultiTransaction>>>>,
+    shutdown: Arc<Notify>,
+    /// Running queries that can be cancelled, keyed by the client-provided `query_id`
+    cancellable_queries: Arc<Mutex<BTreeMap<String, Poison>>>,
+    /// Status of the last compaction started through `/admin/compact`
+    compaction: Arc<Mutex<CompactionStatus>>,
+    /// Latencies of the requests served, exposed at `/metrics`
+    metrics: Arc<Mutex<LatencyMetrics>>,
+    /// Requests in flight per client
+    concurrency: Arc<ClientConcurrency>,
+    /// Result format of queries not asking for one
+    default_format: ResultFormat,
+}
+
+#[derive(Clone, Default)]
+enum CompactionStatus {
+    #[default]
+    Idle,
+    Running,
+    Done {
+        took: f64,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+impl CompactionStatus {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            CompactionStatus::Idle => json!({"ok": true, "status": "idle"}),
+            CompactionStatus::Running => json!({"ok": true, "status": "running"}),
+            CompactionStatus::Done { took } => json!({"ok": true, "status": "done", "took": took}),
+            CompactionStatus::Failed { message } => {
+                json!({"ok": false, "status": "failed", "message": message})
+            }
+        }
+    }
 }
 
 pub(crate) async fn server_main(args: ServerArgs) {
     let db = DbInstance::new(&args.engine, &args.path, &args.config).unwrap();
+    db.set_query_memory_limit(args.query_memory_limit);
     if let Some(p) = &args.restore {
         if let Err(err) = db.restore_backup(p) {
             error!("{}", err);
@@ -86,7 +375,15 @@ pub(crate) async fn server_main(args: ServerArgs) {
         }
     }
 
-    let skip_auth = args.bind == "127.0.0.1";
+    let addrs = match listen_addrs(&args) {
+        Ok(addrs) => addrs,
+        Err(err) => {
+            error!("{}", err);
+            panic!()
+        }
+    };
+    // auth is only required if any of the addresses is reachable from outside
+    let skip_auth = addrs.iter().all(|addr| addr.ip().is_loopback());
 
     let conf_path = if skip_auth {"".to_string()} else { format!("{}.{}.cozo_auth", args.path, args.engine)};
     let auth_guard = if skip_auth {
@@ -112,26 +409,58 @@ pub(crate) async fn server_main(args: ServerArgs) {
         rule_counter: Default::default(),
         tx_counter: Default::default(),
         txs: Default::default(),
+        shutdown: Default::default(),
+        cancellable_queries: Default::default(),
+        compaction: Default::default(),
+        metrics: Default::default(),
+        concurrency: Arc::new(ClientConcurrency::new(args.max_concurrent_per_client)),
+        default_format: args.default_format,
     };
+    let admin_auth = args
+        .admin_auth
+        .clone()
+        .or_else(|| std::env::var("COZO_ADMIN_AUTH").ok())
+        .filter(|s| !s.is_empty());
+    let metrics_auth = args
+        .metrics_auth
+        .clone()
+        .or_else(|| std::env::var("COZO_METRICS_AUTH").ok())
+        .filter(|s| !s.is_empty());
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_origin(Any);
 
     let app = Router::new()
-        .route("/text-query", post(text_query))
+        .route("/text-query", post(text_query_route))
+        .route("/cancel", post(cancel_query))
+        .route("/sql", post(sql_query))
+        .route("/eval", post(eval_expression))
+        .route("/explain", post(explain_query))
         .route("/export/:relations", get(export_relations))
         .route("/import", put(import_relations))
+        .route("/import/csv", post(import_csv))
         .route("/backup", post(backup))
         .route("/import-from-backup", post(import_from_backup))
         .route("/changes/:relation", get(observe_changes))
+        .route("/subscribe", get(subscribe))
         .route("/rules/:name", get(register_rule))
         .route(
             "/rule-result/:id",
             post(post_rule_result).delete(post_rule_err),
         ) // +keep alive
         .route("/transact", post(start_transact))
-        .route("/transact/:id", post(transact_query).put(finish_query))
-        .with_state(state)
+        .route("/transact/:id", post(transact_query).put(finish_query));
+    let app = if metrics_auth.is_none() {
+        app.route("/metrics", get(metrics))
+    } else {
+        app
+    };
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_concurrency,
+        ))
+        .with_state(state.clone())
         .layer(RequireAuthorizationLayer::custom(
             move |request: &mut Request<Body>| {
                 if skip_auth {
@@ -173,31 +502,229 @@ pub(crate) async fn server_main(args: ServerArgs) {
                 }
             },
         ))
+        .merge(admin_routes(admin_auth).with_state(state.clone()))
+        .merge(metrics_routes(metrics_auth).with_state(state.clone()))
         .fallback(not_found)
-        .route("/", get(root))
+        .merge(console_routes(!args.disable_console))
         .layer(cors)
         .layer(CompressionLayer::new());
-
-    let addr = if Ipv6Addr::from_str(&args.bind).is_ok() {
-        SocketAddr::from_str(&format!("[{}]:{}", args.bind, args.port)).unwrap()
-    } else {
-        SocketAddr::from_str(&format!("{}:{}", args.bind, args.port)).unwrap()
+    let listener_config = ListenerConfig::from_args(&args);
+    let app = match listener_config.request_timeout {
+        Some(timeout) => app.layer(TimeoutLayer::new(timeout)),
+        None => app,
+    };
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            record_latency,
+        ))
+        .layer(middleware::from_fn(propagate_request_id));
+    #[cfg(feature = "otel")]
+    let app = {
+        crate::otel::init_tracing();
+        app.layer(middleware::from_fn(crate::otel::trace_request))
     };
 
-    if args.bind != "127.0.0.1" {
+    if !skip_auth {
         warn!("{}", include_str!("./security.txt"));
         info!("The auth token is in the file: {conf_path}");
     }
 
-    info!(
-        "Starting Cozo ({}-backed) API at http://{}",
-        args.engine, addr
-    );
+    let mut listeners = vec![];
+    for addr in addrs {
+        let listener = std::net::TcpListener::bind(addr).unwrap();
+        info!(
+            "Starting Cozo ({}-backed) API at http://{}",
+            args.engine, addr
+        );
+        listeners.push(listener);
+    }
+
+    serve(listeners, app, &listener_config, state.shutdown.clone()).await;
+
+    info!("Server stopped, closing database");
+    #[cfg(feature = "otel")]
+    crate::otel::shutdown_tracing();
+    let txs = std::mem::take(&mut *state.txs.lock().unwrap());
+    for tx in txs.into_values() {
+        let _ = tx.abort();
+    }
+    // a compaction running in the background holds on to the database until it is done
+    while matches!(*state.compaction.lock().unwrap(), CompactionStatus::Running) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    // the storage is flushed and closed when the last handle to the database is dropped
+    drop(state);
+    info!("Database closed");
+}
+
+/// Serves `app` on all the listeners until `shutdown` is notified.
+async fn serve(
+    listeners: Vec<std::net::TcpListener>,
+    app: Router,
+    config: &ListenerConfig,
+    shutdown: Arc<Notify>,
+) {
+    let shutdown_signal = async move { shutdown.notified().await }.shared();
+    let servers = listeners.into_iter().map(|listener| {
+        let mut server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .tcp_keepalive(config.keep_alive)
+            .http1_keepalive(config.keep_alive.is_some());
+        if let Some(timeout) = config.read_timeout {
+            server = server.http1_header_read_timeout(timeout);
+        }
+        server
+            .serve(
+                app.clone()
+                    .into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal.clone())
+    });
+    for res in futures::future::join_all(servers).await {
+        res.unwrap();
+    }
+}
+
+/// Routes for `/metrics` guarded by its own token, if one is given
+#[allow(clippy::result_large_err)]
+fn metrics_routes(metrics_auth: Option<String>) -> Router<DbState> {
+    let metrics_auth = match metrics_auth {
+        None => return Router::new(),
+        Some(a) => a,
+    };
+    Router::new()
+        .route("/metrics", get(metrics))
+        .layer(RequireAuthorizationLayer::custom(
+            move |request: &mut Request<Body>| {
+                let ok = match request.headers().get("x-cozo-metrics-auth") {
+                    None => false,
+                    Some(data) => match data.to_str() {
+                        Ok(s) => token_matches(s, &metrics_auth),
+                        Err(_) => false,
+                    },
+                };
+                if ok {
+                    Ok(())
+                } else {
+                    let forbidden_response = Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body(BoxBody::default())
+                        .unwrap();
+
+                    Err(forbidden_response)
+                }
+            },
+        ))
+}
+
+async fn metrics(State(st): State<DbState>) -> impl IntoResponse {
+    let body = st.metrics.lock().unwrap().render();
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+}
 
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+/// Route for the web console at `/`, if it is enabled
+fn console_routes(enabled: bool) -> Router {
+    if !enabled {
+        return Router::new();
+    }
+    Router::new().route("/", get(root))
+}
+
+fn admin_routes(admin_auth: Option<String>) -> Router<DbState> {
+    let admin_auth = match admin_auth {
+        None => return Router::new(),
+        Some(a) => a,
+    };
+    Router::new()
+        .route("/admin/shutdown", post(shutdown))
+        .route(
+            "/admin/compact",
+            post(start_compaction).get(compaction_status),
+        )
+        .layer(RequireAuthorizationLayer::custom(
+            move |request: &mut Request<Body>| {
+                let ok = match request.headers().get("x-cozo-admin-auth") {
+                    None => false,
+                    Some(data) => match data.to_str() {
+                        Ok(s) => token_matches(s, &admin_auth),
+                        Err(_) => false,
+                    },
+                };
+                if ok {
+                    Ok(())
+                } else {
+                    let forbidden_response = Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body(BoxBody::default())
+                        .unwrap();
+
+                    Err(forbidden_response)
+                }
+            },
+        ))
+}
+
+/// Compares a token given in a request to the expected one in constant time,
+/// so that how long it takes does not tell how much of the token was guessed
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+async fn shutdown(
+    State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    warn!("[{request_id}] Shutdown requested through the admin endpoint");
+    st.shutdown.notify_one();
+    (StatusCode::OK, json!({"ok": true}).into())
+}
+
+/// Compaction can take a long time, so it runs in the background,
+/// and its progress is polled with `GET /admin/compact`.
+async fn start_compaction(
+    State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    {
+        let mut status = st.compaction.lock().unwrap();
+        if matches!(*status, CompactionStatus::Running) {
+            return (
+                StatusCode::CONFLICT,
+                json!({"ok": false, "status": "running", "message": "compaction is already running"})
+                    .into(),
+            );
+        }
+        *status = CompactionStatus::Running;
+    }
+    info!("[{request_id}] Compaction requested through the admin endpoint");
+    spawn_blocking(move || {
+        let start = Instant::now();
+        let status = match st.db.compact() {
+            Ok(()) => CompactionStatus::Done {
+                took: start.elapsed().as_secs_f64(),
+            },
+            Err(err) => {
+                error!("[{request_id}] Compaction failed: {err}");
+                CompactionStatus::Failed {
+                    message: err.to_string(),
+                }
+            }
+        };
+        *st.compaction.lock().unwrap() = status;
+    });
+    (
+        StatusCode::ACCEPTED,
+        CompactionStatus::Running.to_json().into(),
+    )
+}
+
+async fn compaction_status(State(st): State<DbState>) -> (StatusCode, Json<serde_json::Value>) {
+    let status = st.compaction.lock().unwrap().clone();
+    (StatusCode::OK, status.to_json().into())
 }
 
 #[derive(serde_derive::Deserialize)]
@@ -207,16 +734,22 @@ struct StartTransactPayload {
 
 async fn start_transact(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Query(payload): Query<StartTransactPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
     let tx = st.db.multi_transaction(payload.write);
     let id = st.tx_counter.fetch_add(1, Ordering::SeqCst);
+    info!(
+        "[{request_id}] Transaction {id} started (write: {})",
+        payload.write
+    );
     st.txs.lock().unwrap().insert(id, Arc::new(tx));
     (StatusCode::OK, json!({"ok": true, "id": id}).into())
 }
 
 async fn transact_query(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Path(id): Path<u32>,
     Json(payload): Json<QueryPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
@@ -224,23 +757,37 @@ async fn transact_query(
         None => return (StatusCode::NOT_FOUND, json!({"ok": false}).into()),
         Some(tx) => tx.clone(),
     };
+    debug!(
+        "[{request_id}] Query in transaction {id}: {}",
+        payload.script
+    );
     let src = payload.script.clone();
+    let params = convert_params(payload.params);
+    let resolved_params = if payload.echo_params {
+        Some(resolved_params_json(&params))
+    } else {
+        None
+    };
     let result = spawn_blocking(move || {
-        let params = payload
-            .params
-            .into_iter()
-            .map(|(k, v)| (k, DataValue::from(v)))
-            .collect();
         let query = payload.script;
         tx.run_script(&query, params)
     })
     .await;
     match result {
-        Ok(Ok(res)) => (StatusCode::OK, res.into_json().into()),
-        Ok(Err(err)) => (
-            StatusCode::BAD_REQUEST,
-            format_error_as_json(err, Some(&src)).into(),
-        ),
+        Ok(Ok(res)) => {
+            let mut res = res.into_json();
+            if let (Some(resolved), Some(obj)) = (resolved_params, res.as_object_mut()) {
+                obj.insert("resolved_params".to_string(), resolved);
+            }
+            (StatusCode::OK, res.into())
+        }
+        Ok(Err(err)) => {
+            info!("[{request_id}] Query in transaction {id} failed: {err}");
+            (
+                StatusCode::BAD_REQUEST,
+                format_error_as_json(err, Some(&src)).into(),
+            )
+        }
         Err(err) => internal_error(err),
     }
 }
@@ -252,6 +799,7 @@ struct FinishTransactPayload {
 
 async fn finish_query(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Path(id): Path<u32>,
     Json(payload): Json<FinishTransactPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
@@ -259,6 +807,11 @@ async fn finish_query(
         None => return (StatusCode::NOT_FOUND, json!({"ok": false}).into()),
         Some(tx) => tx,
     };
+    if payload.abort {
+        info!("[{request_id}] Transaction {id} aborted");
+    } else {
+        info!("[{request_id}] Transaction {id} committed");
+    }
     let res = if payload.abort {
         tx.abort()
     } else {
@@ -277,24 +830,419 @@ async fn finish_query(
 struct QueryPayload {
     script: String,
     params: BTreeMap<String, serde_json::Value>,
+    /// If true, the response includes how each param was interpreted, under `resolved_params`
+    #[serde(default)]
+    echo_params: bool,
+    /// If given, the query can be cancelled with this ID by `/cancel` while it is running
+    #[serde(default)]
+    query_id: Option<String>,
+    /// One of `json`, `objects` or `csv`, overriding the `Accept` header
+    /// and the default format of the server
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// A JSON request body, like [Json], but rejected with a 400 response telling apart bodies
+/// that are not UTF-8, not JSON, or lacking a required field. The kind of error is given
+/// by `error` in the response, and the field by `field`. As with [Json], requests without
+/// a JSON `Content-Type` are rejected with a 415 response.
+struct JsonBody<T>(T);
+
+#[axum::async_trait]
+impl<S, B, T> FromRequest<S, B> for JsonBody<T>
+where
+    T: serde::de::DeserializeOwned,
+    Bytes: FromRequest<S, B>,
+    B: Send + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Response<BoxBody>;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(req.headers()) {
+            let err = json!({
+                "ok": false,
+                "error": "unsupported_media_type",
+                "message": "expected request with `Content-Type: application/json`"
+            });
+            return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(err)).into_response());
+        }
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        parse_json_body(&body)
+            .map(JsonBody)
+            .map_err(|err| (StatusCode::BAD_REQUEST, Json(err)).into_response())
+    }
+}
+
+/// Whether the `Content-Type` is `application/json` or `application/*+json`, as [Json] requires
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let content_type = match headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        Some(v) => v,
+        None => return false,
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match mime.strip_prefix("application/") {
+        Some(subtype) => subtype == "json" || subtype.ends_with("+json"),
+        None => false,
+    }
+}
+
+fn parse_json_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, serde_json::Value> {
+    use serde_json::error::Category;
+
+    if let Err(err) = std::str::from_utf8(body) {
+        return Err(json!({
+            "ok": false,
+            "error": "invalid_utf8",
+            "message": format!("request body is not valid UTF-8: {err}")
+        }));
+    }
+    serde_json::from_slice(body).map_err(|err| match err.classify() {
+        Category::Data => {
+            let msg = err.to_string();
+            // serde reports missing fields as "missing field `name`"
+            match msg
+                .strip_prefix("missing field `")
+                .and_then(|rest| rest.split_once('`'))
+            {
+                Some((field, _)) => json!({
+                    "ok": false,
+                    "error": "missing_field",
+                    "field": field,
+                    "message": format!("request body is missing the required field '{field}'")
+                }),
+                None => json!({
+                    "ok": false,
+                    "error": "invalid_payload",
+                    "message": format!("invalid request body: {msg}")
+                }),
+            }
+        }
+        _ => json!({
+            "ok": false,
+            "error": "invalid_json",
+            "message": format!("request body is not valid JSON: {err}")
+        }),
+    })
+}
+
+fn convert_params(params: BTreeMap<String, serde_json::Value>) -> BTreeMap<String, DataValue> {
+    params
+        .into_iter()
+        .map(|(k, v)| (k, DataValue::from(v)))
+        .collect()
+}
+
+fn value_type_name(val: &DataValue) -> &'static str {
+    match val {
+        DataValue::Null => "null",
+        DataValue::Bool(_) => "bool",
+        DataValue::Num(Num::Int(_)) => "int",
+        DataValue::Num(Num::Float(_)) => "float",
+        DataValue::Str(_) => "string",
+        DataValue::Bytes(_) => "bytes",
+        DataValue::Uuid(_) => "uuid",
+        DataValue::Regex(_) => "regex",
+        DataValue::List(_) => "list",
+        DataValue::Set(_) => "set",
+        DataValue::Validity(_) => "validity",
+        DataValue::BitSet(_) => "bitset",
+        DataValue::Bot => "bottom",
+    }
+}
+
+fn resolved_params_json(params: &BTreeMap<String, DataValue>) -> serde_json::Value {
+    params
+        .iter()
+        .map(|(k, v)| {
+            let resolved =
+                json!({"type": value_type_name(v), "value": serde_json::Value::from(v.clone())});
+            (k.clone(), resolved)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
 }
 
 async fn text_query(
     State(st): State<DbState>,
     Json(payload): Json<QueryPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let params = payload
-        .params
-        .into_iter()
-        .map(|(k, v)| (k, DataValue::from(v)))
-        .collect();
-    let result = spawn_blocking(move || st.db.run_script_fold_err(&payload.script, params)).await;
+    let params = convert_params(payload.params);
+    let resolved_params = if payload.echo_params {
+        Some(resolved_params_json(&params))
+    } else {
+        None
+    };
+    let result = match payload.query_id {
+        None => spawn_blocking(move || st.db.run_script_fold_err(&payload.script, params)).await,
+        Some(query_id) => {
+            let cancellation = Poison::default();
+            {
+                let mut queries = st.cancellable_queries.lock().unwrap();
+                if queries.contains_key(&query_id) {
+                    return (
+                        StatusCode::CONFLICT,
+                        json!({"ok": false, "message": format!("query ID '{query_id}' is already running")})
+                            .into(),
+                    );
+                }
+                queries.insert(query_id.clone(), cancellation.clone());
+            }
+            let script = payload.script;
+            let db = st.db.clone();
+            let result = spawn_blocking(move || {
+                let start = Instant::now();
+                match db.run_script_cancellable(&script, params, cancellation.clone()) {
+                    Ok(rows) => {
+                        let mut res = rows.into_json();
+                        let map = res.as_object_mut().unwrap();
+                        map.insert("ok".to_string(), json!(true));
+                        map.insert("took".to_string(), json!(start.elapsed().as_secs_f64()));
+                        res
+                    }
+                    // the query may fail for other reasons after it is cancelled, but
+                    // the cancellation is what the client wants to know about
+                    Err(_) if cancellation.check().is_err() => {
+                        json!({"ok": false, "cancelled": true, "message": "query cancelled"})
+                    }
+                    Err(err) => format_error_as_json(err, Some(&script)),
+                }
+            })
+            .await;
+            st.cancellable_queries.lock().unwrap().remove(&query_id);
+            if let Ok(res) = &result {
+                if res.get("cancelled").is_some() {
+                    // non-standard status for requests cancelled by the client, as used by nginx
+                    return (StatusCode::from_u16(499).unwrap(), res.clone().into());
+                }
+            }
+            result
+        }
+    };
+    match result {
+        Ok(mut res) => {
+            if let (Some(resolved), Some(obj)) = (resolved_params, res.as_object_mut()) {
+                obj.insert("resolved_params".to_string(), resolved);
+            }
+            wrap_json(res)
+        }
+        Err(err) => internal_error(err),
+    }
+}
+
+/// The format of the results asked for by the `format` field of the payload, or else by the
+/// `Accept` header, falling back to the default of the server. Only CSV and JSON can be told
+/// apart by the header, so asking for JSON keeps rows as objects if that is the default.
+fn negotiate_format(
+    format: Option<&str>,
+    headers: &HeaderMap,
+    default: ResultFormat,
+) -> Result<ResultFormat, String> {
+    use clap::ValueEnum;
+
+    if let Some(f) = format {
+        return ResultFormat::from_str(f, true).map_err(|_| format!("unknown result format '{f}'"));
+    }
+    let accept = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    Ok(if accept.contains("text/csv") {
+        ResultFormat::Csv
+    } else if accept.contains("application/json") && default == ResultFormat::Csv {
+        ResultFormat::Json
+    } else {
+        default
+    })
+}
+
+/// Handles `/text-query`, returning successful results in the format negotiated
+/// by [negotiate_format].
+async fn text_query_route(
+    state: State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<QueryPayload>,
+) -> Response<BoxBody> {
+    let format = match negotiate_format(payload.format.as_deref(), &headers, state.default_format) {
+        Ok(format) => format,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"ok": false, "message": message})),
+            )
+                .into_response()
+        }
+    };
+    debug!("[{request_id}] Query: {}", payload.script);
+    let (code, Json(mut res)) = text_query(state, Json(payload)).await;
+    if res["ok"] != json!(true) {
+        info!("[{request_id}] Query failed: {}", res["message"]);
+    }
+    if code != StatusCode::OK {
+        return (code, Json(res)).into_response();
+    }
+    match format {
+        ResultFormat::Json => return (code, Json(res)).into_response(),
+        ResultFormat::Objects => {
+            rows_to_objects(&mut res);
+            return (code, Json(res)).into_response();
+        }
+        ResultFormat::Csv => {}
+    }
+    match rows_to_csv(&res) {
+        Ok(body) => (
+            [
+                (CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (CONTENT_DISPOSITION, "attachment; filename=\"result.csv\""),
+            ],
+            body,
+        )
+            .into_response(),
+        Err(err) => internal_error(err).into_response(),
+    }
+}
+
+/// Replaces the rows of the JSON form of a query result by objects keyed by the headers.
+fn rows_to_objects(res: &mut serde_json::Value) {
+    let headers = res["headers"].as_array().cloned().unwrap_or_default();
+    if let Some(rows) = res["rows"].as_array_mut() {
+        for row in rows {
+            let values = row.as_array_mut().map(std::mem::take).unwrap_or_default();
+            *row = headers
+                .iter()
+                .map(|h| h.as_str().unwrap_or_default().to_string())
+                .zip(values)
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+    }
+}
+
+/// Converts the JSON form of a query result to CSV with a header row.
+/// Strings are written as is, nulls as empty cells, and everything else JSON-encoded.
+fn rows_to_csv(res: &serde_json::Value) -> Result<Vec<u8>, csv::Error> {
+    let mut buf = vec![];
+    let mut wtr = csv::Writer::from_writer(&mut buf);
+    if let Some(headers) = res["headers"].as_array() {
+        wtr.write_record(headers.iter().map(|h| h.as_str().unwrap_or_default()))?;
+    }
+    for row in res["rows"].as_array().into_iter().flatten() {
+        wtr.write_record(row.as_array().into_iter().flatten().map(|v| match v {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }))?;
+    }
+    wtr.flush()?;
+    drop(wtr);
+    Ok(buf)
+}
+
+#[derive(serde_derive::Deserialize)]
+struct SqlPayload {
+    sql: String,
+}
+
+/// Experimental: runs a SQL query by translating it into CozoScript,
+/// which is returned as `script` in the response
+async fn sql_query(
+    State(st): State<DbState>,
+    Json(payload): Json<SqlPayload>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = spawn_blocking(move || {
+        let script = match translate_sql(&payload.sql, |table| relation_columns(&st.db, table)) {
+            Ok(script) => script,
+            Err(err) => return json!({"ok": false, "message": err.to_string()}),
+        };
+        let mut res = st.db.run_script_fold_err(&script, Default::default());
+        if let Some(obj) = res.as_object_mut() {
+            obj.insert("script".to_string(), json!(script));
+        }
+        res
+    })
+    .await;
+    match result {
+        Ok(res) => wrap_json(res),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[derive(serde_derive::Deserialize)]
+struct ExplainPayload {
+    script: String,
+    #[serde(default)]
+    params: BTreeMap<String, serde_json::Value>,
+}
+
+/// Plans a query without running it, returning the plan as `plan`
+async fn explain_query(
+    State(st): State<DbState>,
+    Json(payload): Json<ExplainPayload>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = spawn_blocking(move || {
+        let params = convert_params(payload.params);
+        match st.db.query_plan(&payload.script, params) {
+            Ok(plan) => json!({"ok": true, "plan": plan}),
+            Err(err) => format_error_as_json(err, Some(&payload.script)),
+        }
+    })
+    .await;
     match result {
         Ok(res) => wrap_json(res),
         Err(err) => internal_error(err),
     }
 }
 
+#[derive(serde_derive::Deserialize)]
+struct EvalPayload {
+    expr: String,
+    #[serde(default)]
+    params: BTreeMap<String, serde_json::Value>,
+}
+
+/// Evaluates a single expression, returning its value as `value`
+async fn eval_expression(
+    Json(payload): Json<EvalPayload>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let params = convert_params(payload.params);
+    let res = match evaluate_expression(&payload.expr, &params) {
+        Ok(val) => json!({"ok": true, "value": serde_json::Value::from(val)}),
+        Err(err) => format_error_as_json(err, Some(&payload.expr)),
+    };
+    wrap_json(res)
+}
+
+#[derive(serde_derive::Deserialize)]
+struct CancelPayload {
+    query_id: String,
+}
+
+async fn cancel_query(
+    State(st): State<DbState>,
+    Json(payload): Json<CancelPayload>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match st
+        .cancellable_queries
+        .lock()
+        .unwrap()
+        .get(&payload.query_id)
+    {
+        None => (StatusCode::NOT_FOUND, json!({"ok": false}).into()),
+        Some(cancellation) => {
+            cancellation.kill();
+            (StatusCode::OK, json!({"ok": true}).into())
+        }
+    }
+}
+
 async fn export_relations(
     State(st): State<DbState>,
     Path(relations): Path<String>,
@@ -325,6 +1273,7 @@ async fn export_relations(
 
 async fn import_relations(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Json(payload): Json<serde_json::Value>,
 ) -> (StatusCode, Json<serde_json::Value>) {
     let payload = match payload.as_object() {
@@ -352,6 +1301,10 @@ async fn import_relations(
         }
     };
 
+    info!(
+        "[{request_id}] Importing into relations {}",
+        payload.keys().join(", ")
+    );
     let result = spawn_blocking(move || st.db.import_relations(payload)).await;
     match result {
         Ok(Ok(_)) => (StatusCode::OK, json!({"ok": true}).into()),
@@ -362,6 +1315,46 @@ async fn import_relations(
         Err(err) => internal_error(err),
     }
 }
+#[derive(serde_derive::Deserialize)]
+struct CsvImportQuery {
+    relation: String,
+    delimiter: Option<String>,
+}
+
+async fn import_csv(
+    State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    Query(query): Query<CsvImportQuery>,
+    body: Bytes,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let mut options = CsvImportOptions::default();
+    if let Some(d) = &query.delimiter {
+        match d.as_bytes() {
+            [b] => options.delimiter = *b,
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    json!({"ok": false, "message": "delimiter must be a single byte"}).into(),
+                )
+            }
+        }
+    }
+    info!(
+        "[{request_id}] Importing CSV into relation {}",
+        query.relation
+    );
+    let result =
+        spawn_blocking(move || st.db.import_from_csv(&query.relation, &body[..], &options)).await;
+    match result {
+        Ok(Ok(n)) => (StatusCode::OK, json!({"ok": true, "rows": n}).into()),
+        Ok(Err(err)) => {
+            let ret = json!({"ok": false, "message": err.to_string()});
+            (StatusCode::BAD_REQUEST, ret.into())
+        }
+        Err(err) => internal_error(err),
+    }
+}
+
 #[derive(serde_derive::Deserialize)]
 struct BackupPayload {
     path: String,
@@ -369,8 +1362,10 @@ struct BackupPayload {
 
 async fn backup(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Json(payload): Json<BackupPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    info!("[{request_id}] Backing up to {}", payload.path);
     let result = spawn_blocking(move || st.db.backup_db(payload.path)).await;
 
     match result {
@@ -392,8 +1387,14 @@ struct BackupImportPayload {
 }
 async fn import_from_backup(
     State(st): State<DbState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
     Json(payload): Json<BackupImportPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    info!(
+        "[{request_id}] Importing relations {} from backup {}",
+        payload.relations.join(", "),
+        payload.path
+    );
     let result =
         spawn_blocking(move || st.db.import_from_backup(&payload.path, &payload.relations)).await;
 
@@ -558,6 +1559,88 @@ async fn observe_changes(
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
+#[derive(serde_derive::Deserialize)]
+struct SubscribeQuery {
+    script: String,
+    /// Seconds between runs of the script
+    #[serde(default = "default_subscribe_interval")]
+    interval: f64,
+    /// Parameters of the script as a JSON object
+    params: Option<String>,
+}
+
+fn default_subscribe_interval() -> f64 {
+    1.
+}
+
+/// Longest interval accepted by `/subscribe`, one day
+const MAX_SUBSCRIBE_INTERVAL: f64 = 86400.;
+
+/// Runs a script every `interval` seconds for as long as the client stays connected, sending
+/// the result as an event whenever it differs from the last one sent. If the script fails,
+/// the error is sent as an `error` event and the stream ends.
+async fn subscribe(
+    State(st): State<DbState>,
+    Query(query): Query<SubscribeQuery>,
+) -> Response<BoxBody> {
+    let bad_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"ok": false, "message": message})),
+        )
+            .into_response()
+    };
+    if !(query.interval > 0. && query.interval <= MAX_SUBSCRIBE_INTERVAL) {
+        return bad_request(format!(
+            "interval must be a positive number of seconds, at most {MAX_SUBSCRIBE_INTERVAL}"
+        ));
+    }
+    let params = match query.params.as_deref() {
+        None => BTreeMap::new(),
+        Some(p) => match serde_json::from_str(p) {
+            Ok(p) => convert_params(p),
+            Err(err) => return bad_request(format!("bad params: {err}")),
+        },
+    };
+    let period = Duration::from_secs_f64(query.interval);
+    let stream = async_stream::stream! {
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last: Option<(Vec<String>, Vec<Vec<DataValue>>)> = None;
+        loop {
+            ticker.tick().await;
+            let db = st.db.clone();
+            let script = query.script.clone();
+            let params = params.clone();
+            match spawn_blocking(move || db.run_script(&script, params)).await {
+                Ok(Ok(res)) => {
+                    let changed = match &last {
+                        None => true,
+                        Some((headers, rows)) => *headers != res.headers || *rows != res.rows,
+                    };
+                    if changed {
+                        last = Some((res.headers.clone(), res.rows.clone()));
+                        yield Ok::<_, Infallible>(Event::default().json_data(res.into_json()).unwrap());
+                    }
+                }
+                Ok(Err(err)) => {
+                    let err = format_error_as_json(err, Some(&query.script));
+                    yield Ok(Event::default().event("error").json_data(err).unwrap());
+                    break;
+                }
+                Err(err) => {
+                    let err = json!({"ok": false, "message": err.to_string()});
+                    yield Ok(Event::default().event("error").json_data(err).unwrap());
+                    break;
+                }
+            }
+        }
+    };
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
 async fn root() -> Html<&'static str> {
     Html(include_str!("./index.html"))
 }
@@ -587,3 +1670,1078 @@ pub async fn not_found(uri: axum::http::Uri) -> (StatusCode, Json<serde_json::Va
         json!({"ok": false, "message": format!("No route {}", uri)}).into(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_state() -> DbState {
+        DbState {
+            db: DbInstance::new("mem", "", "").unwrap(),
+            rule_senders: Default::default(),
+            rule_counter: Default::default(),
+            tx_counter: Default::default(),
+            txs: Default::default(),
+            shutdown: Default::default(),
+            cancellable_queries: Default::default(),
+            compaction: Default::default(),
+            metrics: Default::default(),
+            concurrency: Default::default(),
+            default_format: Default::default(),
+        }
+    }
+
+    fn request_id() -> Extension<RequestId> {
+        Extension(RequestId("test".to_string()))
+    }
+
+    async fn post_shutdown(app: Router, token: Option<&str>) -> StatusCode {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri("/admin/shutdown");
+        if let Some(token) = token {
+            builder = builder.header("x-cozo-admin-auth", token);
+        }
+        let req = builder.body(Body::empty()).unwrap();
+        app.layer(middleware::from_fn(propagate_request_id))
+            .oneshot(req)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn text_query_echoes_resolved_params() {
+        let payload = QueryPayload {
+            script: "?[a, b] <- [[$a, $b]]".to_string(),
+            params: [("a".to_string(), json!(1.0)), ("b".to_string(), json!(1))]
+                .into_iter()
+                .collect(),
+            echo_params: true,
+            query_id: None,
+            format: None,
+        };
+        let (code, Json(res)) = text_query(State(test_state()), Json(payload)).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(
+            res["resolved_params"],
+            json!({"a": {"type": "float", "value": 1.0}, "b": {"type": "int", "value": 1}})
+        );
+
+        let payload = QueryPayload {
+            script: "?[a] <- [[$a]]".to_string(),
+            params: [("a".to_string(), json!(1))].into_iter().collect(),
+            echo_params: false,
+            query_id: None,
+            format: None,
+        };
+        let (_, Json(res)) = text_query(State(test_state()), Json(payload)).await;
+        assert!(res.get("resolved_params").is_none());
+    }
+
+    #[tokio::test]
+    async fn text_query_reports_affected_rows_and_warnings() {
+        let st = test_state();
+        let query = |script: &str| {
+            let payload = QueryPayload {
+                script: script.to_string(),
+                params: Default::default(),
+                echo_params: false,
+                query_id: None,
+                format: None,
+            };
+            text_query(State(st.clone()), Json(payload))
+        };
+        let (_, Json(res)) = query("?[a] <- [[1], [2], [3]] :create r {a}").await;
+        assert_eq!(res["affected_rows"], json!(3));
+        let (_, Json(res)) = query("?[a] <- [[2], [4]] :rm r {a}").await;
+        assert_eq!(res["affected_rows"], json!(1));
+        let (_, Json(res)) = query("?[a] := *r[a]").await;
+        assert_eq!(res["ok"], json!(true));
+        assert!(res.get("affected_rows").is_none());
+        assert!(res.get("warnings").is_none());
+        let (_, Json(res)) = query("?[a] := *r[a] :order a :limit 1").await;
+        assert_eq!(
+            res["warnings"],
+            json!(["the result was truncated to 1 rows by :limit, 2 rows were found"])
+        );
+    }
+
+    #[tokio::test]
+    async fn text_query_returns_csv() {
+        let payload = |format: Option<&str>| QueryPayload {
+            script: "?[s, l, n] <- [[$a, [1, 'x'], null], [$b, [], 2.5]]".to_string(),
+            params: [
+                ("a".to_string(), json!("comma, separated")),
+                ("b".to_string(), json!("say \"hi\"\nbye")),
+            ]
+            .into_iter()
+            .collect(),
+            echo_params: false,
+            query_id: None,
+            format: format.map(|f| f.to_string()),
+        };
+        let (_, Json(expected)) = text_query(State(test_state()), Json(payload(None))).await;
+
+        let read_csv = |res: Response<BoxBody>| async move {
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()[CONTENT_TYPE], "text/csv; charset=utf-8");
+            assert!(res.headers()[CONTENT_DISPOSITION]
+                .to_str()
+                .unwrap()
+                .contains("filename="));
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let mut rdr = csv::Reader::from_reader(&body[..]);
+            let headers = rdr
+                .headers()
+                .unwrap()
+                .iter()
+                .map(|c| json!(c))
+                .collect_vec();
+            let rows = rdr
+                .records()
+                .map(|r| r.unwrap().iter().map(|c| json!(c)).collect_vec())
+                .collect_vec();
+            (headers, rows)
+        };
+
+        let res = text_query_route(
+            State(test_state()),
+            request_id(),
+            HeaderMap::new(),
+            JsonBody(payload(Some("csv"))),
+        )
+        .await;
+        let (headers, rows) = read_csv(res).await;
+        assert_eq!(json!(headers), expected["headers"]);
+        assert_eq!(rows.len(), 2);
+        // strings survive quoting and escaping, other values are JSON-encoded
+        assert_eq!(rows[0][0], expected["rows"][0][0]);
+        assert_eq!(rows[1][0], expected["rows"][1][0]);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(rows[0][1].as_str().unwrap()).unwrap(),
+            expected["rows"][0][1]
+        );
+        assert_eq!(rows[0][2], json!(""));
+        assert_eq!(rows[1][2], json!("2.5"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("text/csv"));
+        let res = text_query_route(
+            State(test_state()),
+            request_id(),
+            headers,
+            JsonBody(payload(None)),
+        )
+        .await;
+        assert_eq!(read_csv(res).await.1, rows);
+
+        let res = text_query_route(
+            State(test_state()),
+            request_id(),
+            HeaderMap::new(),
+            JsonBody(payload(Some("xml"))),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn text_query_format_defaults_to_server_setting() {
+        // rows of JSON results, or the whole CSV
+        #[derive(Debug, PartialEq)]
+        enum ResultBody {
+            Json(serde_json::Value),
+            Csv(String),
+        }
+
+        let payload = |format: Option<&str>| QueryPayload {
+            script: "?[a, b] <- [[1, 'x']]".to_string(),
+            params: Default::default(),
+            echo_params: false,
+            query_id: None,
+            format: format.map(|f| f.to_string()),
+        };
+        let run = |default_format, format: Option<&str>, accept: Option<&'static str>| {
+            let mut st = test_state();
+            st.default_format = default_format;
+            let mut headers = HeaderMap::new();
+            if let Some(accept) = accept {
+                headers.insert(ACCEPT, HeaderValue::from_static(accept));
+            }
+            let payload = payload(format);
+            async move {
+                let res =
+                    text_query_route(State(st), request_id(), headers, JsonBody(payload)).await;
+                assert_eq!(res.status(), StatusCode::OK);
+                let content_type = res.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                if content_type.starts_with("text/csv") {
+                    ResultBody::Csv(String::from_utf8(body.to_vec()).unwrap())
+                } else {
+                    let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    ResultBody::Json(res["rows"].clone())
+                }
+            }
+        };
+        let rows = ResultBody::Json(json!([[1, "x"]]));
+        let objects = ResultBody::Json(json!([{"a": 1, "b": "x"}]));
+        let csv = ResultBody::Csv("a,b\n1,x\n".to_string());
+
+        // the server default applies to requests not asking for a format
+        assert_eq!(run(ResultFormat::Json, None, None).await, rows);
+        assert_eq!(run(ResultFormat::Objects, None, None).await, objects);
+        assert_eq!(run(ResultFormat::Csv, None, None).await, csv);
+        assert_eq!(run(ResultFormat::Csv, None, Some("*/*")).await, csv);
+
+        // the payload overrides the default and the header
+        assert_eq!(run(ResultFormat::Csv, Some("json"), None).await, rows);
+        assert_eq!(
+            run(ResultFormat::Json, Some("objects"), None).await,
+            objects
+        );
+        assert_eq!(run(ResultFormat::Objects, Some("csv"), None).await, csv);
+        assert_eq!(
+            run(ResultFormat::Json, Some("objects"), Some("text/csv")).await,
+            objects
+        );
+
+        // and so does the header
+        assert_eq!(run(ResultFormat::Json, None, Some("text/csv")).await, csv);
+        assert_eq!(
+            run(ResultFormat::Csv, None, Some("application/json")).await,
+            rows
+        );
+        assert_eq!(
+            run(ResultFormat::Objects, None, Some("application/json")).await,
+            objects
+        );
+    }
+
+    #[tokio::test]
+    async fn text_query_reports_malformed_bodies() {
+        let post_typed_body = |content_type: Option<&'static str>, body: Vec<u8>| async move {
+            let app = Router::new()
+                .route("/text-query", post(text_query_route))
+                .with_state(test_state())
+                .layer(middleware::from_fn(propagate_request_id));
+            let mut req = Request::builder().method(Method::POST).uri("/text-query");
+            if let Some(content_type) = content_type {
+                req = req.header(CONTENT_TYPE, content_type);
+            }
+            let req = req.body(Body::from(body)).unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            let code = res.status();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (code, res)
+        };
+        let post_body = |body: Vec<u8>| post_typed_body(Some("application/json"), body);
+
+        let (code, res) = post_body(b"{\"script\": \"\xff\xfe\"}".to_vec()).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["ok"], json!(false));
+        assert_eq!(res["error"], json!("invalid_utf8"));
+
+        let (code, res) = post_body(b"{\"script\": ".to_vec()).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["error"], json!("invalid_json"));
+
+        let (code, res) = post_body(b"{\"params\": {}}".to_vec()).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["error"], json!("missing_field"));
+        assert_eq!(res["field"], json!("script"));
+
+        let (code, res) = post_body(b"{\"script\": 1, \"params\": {}}".to_vec()).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["error"], json!("invalid_payload"));
+
+        let (code, res) =
+            post_body(b"{\"script\": \"?[a] <- [[1]]\", \"params\": {}}".to_vec()).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(res["rows"], json!([[1]]));
+
+        let valid = b"{\"script\": \"?[a] <- [[1]]\", \"params\": {}}";
+        for content_type in [None, Some("text/plain")] {
+            let (code, res) = post_typed_body(content_type, valid.to_vec()).await;
+            assert_eq!(code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            assert_eq!(res["error"], json!("unsupported_media_type"));
+        }
+        let (code, _) =
+            post_typed_body(Some("application/json; charset=utf-8"), valid.to_vec()).await;
+        assert_eq!(code, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn sql_query_runs_translated_script() {
+        let state = test_state();
+        state
+            .db
+            .run_script(
+                "?[a, b] <- [[1, 'x'], [2, 'y']] :create t {a => b}",
+                Default::default(),
+            )
+            .unwrap();
+        let sql = |sql: &str| {
+            sql_query(
+                State(state.clone()),
+                Json(SqlPayload {
+                    sql: sql.to_string(),
+                }),
+            )
+        };
+        let (code, Json(res)) = sql("SELECT b FROM t WHERE a > 1").await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(res["rows"], json!([["y"]]));
+        assert!(res["script"].as_str().unwrap().starts_with("?[b] := *t{"));
+
+        let (code, Json(res)) = sql("SELECT DISTINCT b FROM t").await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["message"], json!("unsupported SQL: DISTINCT"));
+    }
+
+    #[tokio::test]
+    async fn explain_returns_query_plan() {
+        let state = test_state();
+        state
+            .db
+            .run_script("?[a] <- [[1], [2]] :create t {a}", Default::default())
+            .unwrap();
+        let explain = |script: &str| {
+            explain_query(
+                State(state.clone()),
+                Json(
+                    serde_json::from_value(json!({"script": script, "params": {"x": 1}})).unwrap(),
+                ),
+            )
+        };
+        let (code, Json(res)) = explain("?[a] := *t{a}, a > $x").await;
+        assert_eq!(code, StatusCode::OK);
+        let steps = res["plan"]["steps"].as_array().unwrap();
+        assert_eq!(steps[0]["op"]["type"], json!("load_stored"));
+        assert_eq!(steps[0]["op"]["relation"], json!(":t"));
+        assert_eq!(steps.last().unwrap()["op"]["type"], json!("out"));
+
+        let (code, Json(res)) = explain("?[a] := *nope{a}").await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["ok"], json!(false));
+    }
+
+    #[tokio::test]
+    async fn eval_evaluates_expressions() {
+        let eval = |expr: &str, params: serde_json::Value| {
+            eval_expression(Json(
+                serde_json::from_value(json!({"expr": expr, "params": params})).unwrap(),
+            ))
+        };
+        let (code, Json(res)) = eval("2 + 3 * $x", json!({"x": 4})).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(res["value"], json!(14));
+
+        let (code, Json(res)) = eval("concat($a, '!')", json!({"a": "hi"})).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(res["value"], json!("hi!"));
+
+        let (code, Json(res)) = eval("2 + 3 * x", json!({"x": 4})).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(res["ok"], json!(false));
+        assert_eq!(res["code"], json!("eval::not_reducible"));
+
+        let (code, _) = eval("2 + 3 * $y", json!({})).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn admin_shutdown_requires_admin_token() {
+        let state = test_state();
+        let app = admin_routes(None)
+            .with_state(state.clone())
+            .fallback(not_found);
+        assert_eq!(
+            post_shutdown(app, Some("secret")).await,
+            StatusCode::NOT_FOUND
+        );
+
+        let app = admin_routes(Some("secret".to_string())).with_state(state.clone());
+        assert_eq!(
+            post_shutdown(app.clone(), None).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            post_shutdown(app, Some("wrong")).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_shutdown_triggers_shutdown() {
+        let state = test_state();
+        let app = admin_routes(Some("secret".to_string())).with_state(state.clone());
+        assert_eq!(post_shutdown(app, Some("secret")).await, StatusCode::OK);
+        tokio::time::timeout(std::time::Duration::from_secs(1), state.shutdown.notified())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn admin_compaction_runs_in_background() {
+        let state = test_state();
+        state
+            .db
+            .run_script("?[a] <- [[1], [2]] :create t {a}", Default::default())
+            .unwrap();
+        let app = admin_routes(Some("secret".to_string()))
+            .with_state(state.clone())
+            .layer(middleware::from_fn(propagate_request_id));
+        let request = |method: Method| {
+            Request::builder()
+                .method(method)
+                .uri("/admin/compact")
+                .header("x-cozo-admin-auth", "secret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let read_json = |res: Response<BoxBody>| async move {
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let res = app.clone().oneshot(request(Method::GET)).await.unwrap();
+        assert_eq!(read_json(res).await["status"], json!("idle"));
+
+        let res = app.clone().oneshot(request(Method::POST)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        assert_eq!(read_json(res).await["status"], json!("running"));
+
+        let status = loop {
+            let res = app.clone().oneshot(request(Method::GET)).await.unwrap();
+            let status = read_json(res).await;
+            if status["status"] != json!("running") {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status["status"], json!("done"));
+        assert_eq!(status["ok"], json!(true));
+
+        let res = state
+            .db
+            .run_script("?[a] := *t{a}", Default::default())
+            .unwrap();
+        assert_eq!(res.rows.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn request_id_is_echoed_or_generated() {
+        let app = Router::new()
+            .route(
+                "/id",
+                get(|Extension(RequestId(id)): Extension<RequestId>| async move { id }),
+            )
+            .layer(middleware::from_fn(propagate_request_id));
+
+        let req = Request::builder()
+            .uri("/id")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[REQUEST_ID_HEADER], "abc-123");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"abc-123");
+
+        let req = Request::builder().uri("/id").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let generated = res.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], generated.as_bytes());
+    }
+
+    #[test]
+    fn listener_config_from_args() {
+        #[derive(clap::Parser)]
+        struct TestArgs {
+            #[command(flatten)]
+            server: ServerArgs,
+        }
+        let parse = |args: &[&str]| {
+            let args = <TestArgs as clap::Parser>::try_parse_from(
+                std::iter::once("cozo").chain(args.iter().copied()),
+            )
+            .unwrap();
+            ListenerConfig::from_args(&args.server)
+        };
+
+        assert_eq!(
+            parse(&[]),
+            ListenerConfig {
+                read_timeout: Some(Duration::from_secs(30)),
+                request_timeout: Some(Duration::from_secs(600)),
+                keep_alive: Some(Duration::from_secs(60)),
+            }
+        );
+        assert_eq!(
+            parse(&[
+                "--read-timeout",
+                "5",
+                "--request-timeout",
+                "0",
+                "--keep-alive",
+                "0"
+            ]),
+            ListenerConfig {
+                read_timeout: Some(Duration::from_secs(5)),
+                request_timeout: None,
+                keep_alive: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn multiple_listeners_share_database() {
+        let state = test_state();
+        let app = Router::new()
+            .route("/text-query", post(text_query))
+            .with_state(state.clone());
+        let listeners = (0..2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect_vec();
+        let urls = listeners
+            .iter()
+            .map(|l| format!("http://{}/text-query", l.local_addr().unwrap()))
+            .collect_vec();
+        let config = ListenerConfig {
+            read_timeout: None,
+            request_timeout: None,
+            keep_alive: None,
+        };
+        let shutdown = state.shutdown.clone();
+        let server = tokio::spawn(async move { serve(listeners, app, &config, shutdown).await });
+
+        let query = |url: String, script: &'static str| {
+            spawn_blocking(move || {
+                let res = minreq::post(url)
+                    .with_header("content-type", "application/json")
+                    .with_body(json!({"script": script, "params": {}}).to_string())
+                    .send()
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(res.as_str().unwrap()).unwrap()
+            })
+        };
+        let res = query(urls[0].clone(), ":create t {a}").await.unwrap();
+        assert_eq!(res["ok"], json!(true));
+        let (a, b) = tokio::join!(
+            query(urls[0].clone(), "?[a] <- [[1]] :put t {a}"),
+            query(urls[1].clone(), "?[a] <- [[2]] :put t {a}"),
+        );
+        assert_eq!(a.unwrap()["ok"], json!(true));
+        assert_eq!(b.unwrap()["ok"], json!(true));
+        for url in urls {
+            let res = query(url, "?[a] := *t{a}").await.unwrap();
+            assert_eq!(res["rows"], json!([[1], [2]]));
+        }
+
+        state.shutdown.notify_one();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn header_read_timeout_drops_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let state = test_state();
+        let app = Router::new()
+            .route("/text-query", post(text_query))
+            .with_state(state.clone());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ListenerConfig {
+            read_timeout: Some(Duration::from_millis(200)),
+            request_timeout: None,
+            keep_alive: None,
+        };
+        let shutdown = state.shutdown.clone();
+        let server =
+            tokio::spawn(async move { serve(vec![listener], app, &config, shutdown).await });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"POST /text-query HTTP/1.1\r\nHost: localhost\r\n")
+            .await
+            .unwrap();
+        let started = std::time::Instant::now();
+        let mut buf = vec![];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+            .await
+            .expect("connection with incomplete headers was kept open");
+        // the connection is closed, possibly after a 408 response, instead of waiting for more
+        assert!(read.is_err() || buf.is_empty() || buf.starts_with(b"HTTP/1.1 408"));
+        assert!(started.elapsed() >= Duration::from_millis(150));
+
+        state.shutdown.notify_one();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_running_query() {
+        let state = test_state();
+        let payload = QueryPayload {
+            script: r#"
+                r[n] := n = 0
+                r[m] := r[n], m = n + 1, m < 100000000
+                ?[count(n)] := r[n]
+            "#
+            .to_string(),
+            params: Default::default(),
+            echo_params: false,
+            query_id: Some("slow".to_string()),
+            format: None,
+        };
+        let running = tokio::spawn(text_query(State(state.clone()), Json(payload)));
+        while !state
+            .cancellable_queries
+            .lock()
+            .unwrap()
+            .contains_key("slow")
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let cancel = |id: &str| {
+            cancel_query(
+                State(state.clone()),
+                Json(CancelPayload {
+                    query_id: id.to_string(),
+                }),
+            )
+        };
+        let (code, _) = cancel("slow").await;
+        assert_eq!(code, StatusCode::OK);
+        let (code, Json(res)) = running.await.unwrap();
+        assert_eq!(code.as_u16(), 499);
+        assert_eq!(res["cancelled"], json!(true));
+
+        // the ID is released once the query ends
+        let (code, _) = cancel("slow").await;
+        assert_eq!(code, StatusCode::NOT_FOUND);
+        let payload = QueryPayload {
+            script: "?[a] <- [[1]]".to_string(),
+            params: Default::default(),
+            echo_params: false,
+            query_id: Some("slow".to_string()),
+            format: None,
+        };
+        let (code, Json(res)) = text_query(State(state), Json(payload)).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(res["rows"], json!([[1]]));
+    }
+
+    #[tokio::test]
+    async fn metrics_report_latency_percentiles() {
+        let state = test_state();
+        let app = Router::new()
+            .route("/text-query", post(text_query_route))
+            .route("/metrics", get(metrics))
+            .with_state(state.clone())
+            .layer(middleware::from_fn_with_state(state, record_latency))
+            .layer(middleware::from_fn(propagate_request_id));
+
+        for i in 0..5 {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri("/text-query")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({"script": format!("?[a] <- [[{i}]]"), "params": {}}).to_string(),
+                ))
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("cozo_time_taken_seconds_count 5\n"));
+        assert!(body.contains("cozo_time_taken_seconds_bucket{le=\"+Inf\"} 5\n"));
+        for gauge in ["p50", "p95", "p99"] {
+            let line = body
+                .lines()
+                .find(|l| l.starts_with(&format!("cozo_time_taken_{gauge}_seconds ")))
+                .unwrap();
+            let v: f64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+            assert!(v > 0.);
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_require_their_own_token() {
+        let app: Router =
+            metrics_routes(Some("metrics-secret".to_string())).with_state(test_state());
+        let status = |header: Option<(&'static str, &'static str)>| {
+            let app = app.clone();
+            async move {
+                let mut builder = Request::builder().uri("/metrics");
+                if let Some((k, v)) = header {
+                    builder = builder.header(k, v);
+                }
+                let req = builder.body(Body::empty()).unwrap();
+                app.oneshot(req).await.unwrap().status()
+            }
+        };
+        assert_eq!(status(None).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(Some(("x-cozo-metrics-auth", "wrong"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Some(("x-cozo-auth", "metrics-secret"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Some(("x-cozo-metrics-auth", "metrics-secret"))).await,
+            StatusCode::OK
+        );
+
+        let app: Router = metrics_routes(None).with_state(test_state());
+        let req = Request::builder()
+            .uri("/metrics")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            app.oneshot(req).await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn console_can_be_disabled() {
+        let get_root = |enabled| async move {
+            let app = console_routes(enabled).fallback(not_found);
+            let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+            let resp = app.oneshot(req).await.unwrap();
+            let status = resp.status();
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        let (status, body) = get_root(true).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("<!DOCTYPE html>"));
+
+        let (status, body) = get_root(false).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(!body.contains("<html"));
+    }
+
+    #[tokio::test]
+    async fn csv_upload_imports_rows() {
+        let state = test_state();
+        state
+            .db
+            .run_script(
+                ":create t {k: Int => v: Float, s: String?}",
+                Default::default(),
+            )
+            .unwrap();
+        let app = Router::new()
+            .route("/import/csv", post(import_csv))
+            .with_state(state.clone())
+            .layer(middleware::from_fn(propagate_request_id));
+        let upload = |uri: &str, body: &'static str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(CONTENT_TYPE, "text/csv")
+                .body(Body::from(body))
+                .unwrap();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+
+        let (status, res) = upload("/import/csv?relation=t", "k,v,s\n1,1.5,a\n2,2,\n").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(res, json!({"ok": true, "rows": 2}));
+
+        let (status, res) = upload("/import/csv?relation=t&delimiter=%7C", "k|v|s\n3|x|c\n").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(res["message"].as_str().unwrap().contains("line 2:"));
+
+        let rows = state
+            .db
+            .run_script("?[k, v, s] := *t{k, v, s}", Default::default())
+            .unwrap()
+            .into_json();
+        assert_eq!(rows["rows"], json!([[1, 1.5, "a"], [2, 2.0, null]]));
+    }
+
+    #[tokio::test]
+    async fn subscription_streams_changed_results() {
+        use hyper::body::HttpBody;
+
+        async fn next_event(body: &mut BoxBody) -> serde_json::Value {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.data())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+            let data = chunk.strip_prefix("data:").unwrap().trim();
+            serde_json::from_str(data).unwrap()
+        }
+
+        let state = test_state();
+        state
+            .db
+            .run_script("?[a] <- [[1]] :create t {a}", Default::default())
+            .unwrap();
+        let app = Router::new()
+            .route("/subscribe", get(subscribe))
+            .with_state(state.clone());
+
+        // `?[a] := *t{a}`
+        let req = Request::builder()
+            .uri("/subscribe?script=%3F%5Ba%5D%20%3A%3D%20%2At%7Ba%7D&interval=0.02")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let mut body = res.into_body();
+        assert_eq!(next_event(&mut body).await["rows"], json!([[1]]));
+        state
+            .db
+            .run_script("?[a] <- [[2]] :put t {a}", Default::default())
+            .unwrap();
+        // unchanged results in between are not sent
+        assert_eq!(next_event(&mut body).await["rows"], json!([[1], [2]]));
+        drop(body);
+
+        for interval in ["0", "-1", "1e300", "inf", "NaN"] {
+            let req = Request::builder()
+                .uri(format!(
+                    "/subscribe?script=%3F%5Ba%5D%20%3A%3D%20%2At%7Ba%7D&interval={interval}"
+                ))
+                .body(Body::empty())
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{interval}");
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_per_client_are_limited() {
+        let mut st = test_state();
+        st.concurrency = Arc::new(ClientConcurrency::new(2));
+        // requests are held in flight until permits are added
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let held = gate.clone();
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    held.acquire().await.unwrap().forget();
+                    "done"
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                st.clone(),
+                limit_concurrency,
+            ))
+            .with_state(st.clone());
+        let request = |ip: &str| {
+            let mut req = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+            let addr = SocketAddr::from_str(&format!("{ip}:4321")).unwrap();
+            req.extensions_mut().insert(ConnectInfo(addr));
+            req
+        };
+        async fn wait_for(st: &DbState, client: &str, n: usize) {
+            while st.concurrency.in_flight(client) < n {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let mut running = vec![];
+        for _ in 0..2 {
+            running.push(tokio::spawn(app.clone().oneshot(request("10.0.0.1"))));
+        }
+        wait_for(&st, "10.0.0.1", 2).await;
+        let res = app.clone().oneshot(request("10.0.0.1")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // other clients are not affected
+        running.push(tokio::spawn(app.clone().oneshot(request("10.0.0.2"))));
+        wait_for(&st, "10.0.0.2", 1).await;
+
+        // one more for the request after the others are done
+        gate.add_permits(4);
+        for res in running {
+            assert_eq!(res.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(st.concurrency.in_flight("10.0.0.1"), 0);
+        assert_eq!(st.concurrency.in_flight("10.0.0.2"), 0);
+        let res = app.oneshot(request("10.0.0.1")).await;
+        assert_eq!(res.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn concurrency_permit_lasts_as_long_as_the_body() {
+        let mut st = test_state();
+        st.concurrency = Arc::new(ClientConcurrency::new(1));
+        let app = Router::new()
+            .route("/", get(|| async { "done" }))
+            .layer(middleware::from_fn_with_state(
+                st.clone(),
+                limit_concurrency,
+            ))
+            .with_state(st.clone());
+        let mut req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from_str("10.0.0.1:4321").unwrap()));
+
+        // the handler is done, but the body is yet to be sent
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body().size_hint().exact(), Some(4));
+        assert_eq!(st.concurrency.in_flight("10.0.0.1"), 1);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"done");
+        assert_eq!(st.concurrency.in_flight("10.0.0.1"), 0);
+    }
+
+    /// Keeps the spans ended, in order
+    #[cfg(feature = "otel")]
+    #[derive(Clone, Debug, Default)]
+    struct CollectedSpans(Arc<Mutex<Vec<opentelemetry_sdk::export::trace::SpanData>>>);
+
+    #[cfg(feature = "otel")]
+    impl opentelemetry_sdk::trace::SpanProcessor for CollectedSpans {
+        fn on_start(
+            &self,
+            _span: &mut opentelemetry_sdk::trace::Span,
+            _cx: &opentelemetry::Context,
+        ) {
+        }
+
+        fn on_end(&self, span: opentelemetry_sdk::export::trace::SpanData) {
+            self.0.lock().unwrap().push(span)
+        }
+
+        fn force_flush(&self) -> opentelemetry::trace::TraceResult<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> opentelemetry::trace::TraceResult<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    #[tokio::test]
+    async fn requests_are_traced_through_script_phases() {
+        use opentelemetry::trace::{SpanId, TraceId, TracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let spans = CollectedSpans::default();
+        let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+            .with_span_processor(spans.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route("/text-query", post(text_query_route))
+            .with_state(test_state())
+            .layer(middleware::from_fn(propagate_request_id))
+            .layer(middleware::from_fn(crate::otel::trace_request));
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/text-query")
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::from(r#"{"script": "?[a] := a = 1", "params": {}}"#))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let spans = spans.0.lock().unwrap();
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        assert!(spans.iter().all(|s| s.span_context.trace_id() == trace_id));
+        let parent_name = |name: &str| {
+            let parent = spans
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap_or_else(|| panic!("no span {name}"))
+                .parent_span_id;
+            spans
+                .iter()
+                .find(|s| s.span_context.span_id() == parent)
+                .map(|s| s.name.to_string())
+        };
+        let request = spans.iter().find(|s| s.name == "POST /text-query").unwrap();
+        assert_eq!(
+            request.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert_eq!(
+            parent_name("run_script").as_deref(),
+            Some("POST /text-query")
+        );
+        for phase in ["parse", "optimize", "execute"] {
+            assert_eq!(parent_name(phase).as_deref(), Some("run_script"));
+        }
+    }
+
+    #[tokio::test]
+    async fn transact_query_echoes_resolved_params() {
+        let st = test_state();
+        let (_, Json(res)) = start_transact(
+            State(st.clone()),
+            request_id(),
+            Query(StartTransactPayload { write: false }),
+        )
+        .await;
+        let id = res["id"].as_u64().unwrap() as u32;
+        let query = |echo_params: bool| {
+            let payload = QueryPayload {
+                script: "?[a, b] <- [[$a, $b]]".to_string(),
+                params: [("a".to_string(), json!(1.0)), ("b".to_string(), json!(1))]
+                    .into_iter()
+                    .collect(),
+                echo_params,
+                query_id: None,
+                format: None,
+            };
+            transact_query(State(st.clone()), request_id(), Path(id), Json(payload))
+        };
+        let (code, Json(res)) = query(true).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(
+            res["resolved_params"],
+            json!({"a": {"type": "float", "value": 1.0}, "b": {"type": "int", "value": 1}})
+        );
+        let (_, Json(res)) = query(false).await;
+        assert!(res.get("resolved_params").is_none());
+    }
+}
@@ -0,0 +1,992 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Translation of a small subset of SQL `SELECT` statements into CozoScript, for the experimental
+//! `/sql` endpoint. Supported are projections (with `AS`), `FROM` with inner joins,
+//! `WHERE` with comparisons, arithmetic and logical operators, `IS [NOT] NULL`, `[NOT] IN` and
+//! `BETWEEN`, and `ORDER BY`, `LIMIT` and `OFFSET`. Everything else is rejected as unsupported.
+//!
+//! Since Cozo relations are sets, the results are always distinct, as if `SELECT DISTINCT`
+//! were used.
+//!
+//! Conditions follow SQL's three-valued logic: comparisons and `IN` involving nulls are neither
+//! true nor false but unknown, `NOT` of unknown is unknown, and `WHERE` keeps only the rows for
+//! which the condition is true. Arithmetic on nulls is an error, as it is in Cozo.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use itertools::Itertools;
+use miette::{bail, ensure, miette, Result};
+
+use cozo::{DataValue, DbInstance};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    QuotedIdent(String),
+    Number(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<>", "!=", "<=", ">=", "||", "=", "<", ">", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = sql.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() || c == ';' {
+            chars.next();
+        } else if c == '.' {
+            tokens.push(Token::Symbol("."));
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = i;
+            while let Some(&(j, d)) = chars.peek() {
+                if !(d.is_ascii_digit() || d == '.') {
+                    break;
+                }
+                end = j + d.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Number(sql[i..end].to_string()));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = i;
+            while let Some(&(j, d)) = chars.peek() {
+                if !(d.is_alphanumeric() || d == '_') {
+                    break;
+                }
+                end = j + d.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(sql[i..end].to_string()));
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None => bail!("SQL syntax error: unterminated quote"),
+                    // quotes are escaped by doubling them
+                    Some((_, d)) if d == c => {
+                        if matches!(chars.peek(), Some(&(_, e)) if e == c) {
+                            s.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    Some((_, d)) => s.push(d),
+                }
+            }
+            tokens.push(if c == '\'' {
+                Token::Str(s)
+            } else {
+                Token::QuotedIdent(s)
+            });
+        } else {
+            let sym = SYMBOLS
+                .iter()
+                .find(|sym| sql[i..].starts_with(**sym))
+                .ok_or_else(|| miette!("SQL syntax error: unexpected character '{}'", c))?;
+            for _ in 0..sym.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(sym));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum SqlExpr {
+    Column(Option<String>, String),
+    Literal(DataValue),
+    /// Unary operators, given as the Cozo operator
+    Unary(&'static str, Box<SqlExpr>),
+    /// Binary operators, given as the Cozo operator
+    Binary(&'static str, Box<SqlExpr>, Box<SqlExpr>),
+    IsNull(Box<SqlExpr>, bool),
+    InList(Box<SqlExpr>, Vec<SqlExpr>, bool),
+}
+
+#[derive(Debug)]
+enum Projection {
+    /// `*` or `alias.*`
+    Star(Option<String>),
+    Expr(SqlExpr, Option<String>),
+}
+
+#[derive(Debug)]
+struct TableRef {
+    table: String,
+    alias: String,
+}
+
+#[derive(Debug)]
+struct Select {
+    projections: Vec<Projection>,
+    tables: Vec<TableRef>,
+    conditions: Vec<SqlExpr>,
+    order: Vec<(String, bool)>,
+    limit: Option<String>,
+    offset: Option<String>,
+}
+
+const RESERVED: [&str; 15] = [
+    "select", "from", "where", "join", "inner", "on", "and", "or", "not", "order", "by", "limit",
+    "offset", "as", "group",
+];
+
+const UNSUPPORTED: [&str; 14] = [
+    "distinct",
+    "group",
+    "having",
+    "union",
+    "intersect",
+    "except",
+    "left",
+    "right",
+    "full",
+    "outer",
+    "cross",
+    "like",
+    "case",
+    "with",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+    fn peek_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+    }
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        let found = self.peek_keyword(kw);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn expect_keyword(&mut self, kw: &str) -> Result<()> {
+        ensure!(
+            self.eat_keyword(kw),
+            "SQL syntax error: expected {}",
+            kw.to_uppercase()
+        );
+        Ok(())
+    }
+    fn eat_symbol(&mut self, sym: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn expect_symbol(&mut self, sym: &str) -> Result<()> {
+        ensure!(self.eat_symbol(sym), "SQL syntax error: expected '{}'", sym);
+        Ok(())
+    }
+    fn check_unsupported(&self) -> Result<()> {
+        if let Some(Token::Ident(s)) = self.peek() {
+            let lower = s.to_lowercase();
+            if UNSUPPORTED.contains(&lower.as_str()) {
+                bail!("unsupported SQL: {}", lower.to_uppercase())
+            }
+        }
+        Ok(())
+    }
+    fn identifier(&mut self) -> Result<String> {
+        self.check_unsupported()?;
+        match self.next() {
+            Some(Token::Ident(s)) if !RESERVED.contains(&s.to_lowercase().as_str()) => Ok(s),
+            Some(Token::QuotedIdent(s)) => Ok(s),
+            _ => bail!("SQL syntax error: expected an identifier"),
+        }
+    }
+    fn number(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Number(n)) if n.parse::<u64>().is_ok() => Ok(n),
+            _ => bail!("SQL syntax error: expected a non-negative integer"),
+        }
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        self.expect_keyword("select")?;
+        self.check_unsupported()?;
+        let mut projections = vec![];
+        loop {
+            projections.push(self.projection()?);
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        self.expect_keyword("from")?;
+        let mut tables = vec![self.table_ref()?];
+        let mut conditions = vec![];
+        loop {
+            self.check_unsupported()?;
+            if self.eat_symbol(",") {
+                tables.push(self.table_ref()?);
+            } else if self.peek_keyword("join") || self.peek_keyword("inner") {
+                if self.eat_keyword("inner") {
+                    self.check_unsupported()?;
+                }
+                self.expect_keyword("join")?;
+                tables.push(self.table_ref()?);
+                self.expect_keyword("on")?;
+                conditions.push(self.expr()?);
+            } else {
+                break;
+            }
+        }
+        if self.eat_keyword("where") {
+            conditions.push(self.expr()?);
+        }
+        self.check_unsupported()?;
+        let mut order = vec![];
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let name = self.identifier()?;
+                let desc = if self.eat_keyword("desc") {
+                    true
+                } else {
+                    self.eat_keyword("asc");
+                    false
+                };
+                order.push((name, desc));
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.eat_keyword("limit") {
+            Some(self.number()?)
+        } else {
+            None
+        };
+        let offset = if self.eat_keyword("offset") {
+            Some(self.number()?)
+        } else {
+            None
+        };
+        self.check_unsupported()?;
+        ensure!(
+            self.peek().is_none(),
+            "SQL syntax error: unexpected input after the query"
+        );
+        Ok(Select {
+            projections,
+            tables,
+            conditions,
+            order,
+            limit,
+            offset,
+        })
+    }
+
+    fn projection(&mut self) -> Result<Projection> {
+        if self.eat_symbol("*") {
+            return Ok(Projection::Star(None));
+        }
+        if let (Some(Token::Ident(alias)), Some(Token::Symbol(".")), Some(Token::Symbol("*"))) = (
+            self.tokens.get(self.pos),
+            self.tokens.get(self.pos + 1),
+            self.tokens.get(self.pos + 2),
+        ) {
+            let alias = alias.clone();
+            self.pos += 3;
+            return Ok(Projection::Star(Some(alias)));
+        }
+        let expr = self.expr()?;
+        let name = if self.eat_keyword("as") {
+            Some(self.identifier()?)
+        } else {
+            None
+        };
+        Ok(Projection::Expr(expr, name))
+    }
+
+    fn table_ref(&mut self) -> Result<TableRef> {
+        if matches!(self.peek(), Some(Token::Symbol("("))) {
+            bail!("unsupported SQL: subqueries")
+        }
+        let table = self.identifier()?;
+        self.eat_keyword("as");
+        let alias = match self.peek() {
+            Some(Token::Ident(s)) if !RESERVED.contains(&s.to_lowercase().as_str()) => {
+                self.check_unsupported()?;
+                self.identifier()?
+            }
+            Some(Token::QuotedIdent(_)) => self.identifier()?,
+            _ => table.clone(),
+        };
+        Ok(TableRef { table, alias })
+    }
+
+    fn expr(&mut self) -> Result<SqlExpr> {
+        let mut left = self.and_expr()?;
+        while self.eat_keyword("or") {
+            let right = self.and_expr()?;
+            left = SqlExpr::Binary("||", left.into(), right.into());
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<SqlExpr> {
+        let mut left = self.not_expr()?;
+        while self.eat_keyword("and") {
+            let right = self.not_expr()?;
+            left = SqlExpr::Binary("&&", left.into(), right.into());
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<SqlExpr> {
+        if self.eat_keyword("not") {
+            Ok(SqlExpr::Unary("!", self.not_expr()?.into()))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<SqlExpr> {
+        let left = self.additive()?;
+        self.check_unsupported()?;
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(SqlExpr::IsNull(left.into(), negated));
+        }
+        let negated = self.eat_keyword("not");
+        self.check_unsupported()?;
+        if self.eat_keyword("in") {
+            self.expect_symbol("(")?;
+            if self.peek_keyword("select") {
+                bail!("unsupported SQL: subqueries")
+            }
+            let mut items = vec![self.expr()?];
+            while self.eat_symbol(",") {
+                items.push(self.expr()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(SqlExpr::InList(left.into(), items, negated));
+        }
+        if self.eat_keyword("between") {
+            let low = self.additive()?;
+            self.expect_keyword("and")?;
+            let high = self.additive()?;
+            let left: Box<SqlExpr> = left.into();
+            let between = SqlExpr::Binary(
+                "&&",
+                SqlExpr::Binary(">=", left.clone_column()?.into(), low.into()).into(),
+                SqlExpr::Binary("<=", left, high.into()).into(),
+            );
+            return Ok(if negated {
+                SqlExpr::Unary("!", between.into())
+            } else {
+                between
+            });
+        }
+        ensure!(
+            !negated,
+            "SQL syntax error: expected IN or BETWEEN after NOT"
+        );
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => "==",
+            Some(Token::Symbol("<>")) | Some(Token::Symbol("!=")) => "!=",
+            Some(Token::Symbol("<")) => "<",
+            Some(Token::Symbol("<=")) => "<=",
+            Some(Token::Symbol(">")) => ">",
+            Some(Token::Symbol(">=")) => ">=",
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(SqlExpr::Binary(op, left.into(), right.into()))
+    }
+
+    fn additive(&mut self) -> Result<SqlExpr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => "+",
+                Some(Token::Symbol("-")) => "-",
+                Some(Token::Symbol("||")) => "++",
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.multiplicative()?;
+            left = SqlExpr::Binary(op, left.into(), right.into());
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<SqlExpr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => "*",
+                Some(Token::Symbol("/")) => "/",
+                Some(Token::Symbol("%")) => "%",
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.unary()?;
+            left = SqlExpr::Binary(op, left.into(), right.into());
+        }
+    }
+
+    fn unary(&mut self) -> Result<SqlExpr> {
+        if self.eat_symbol("-") {
+            Ok(SqlExpr::Unary("-", self.unary()?.into()))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<SqlExpr> {
+        self.check_unsupported()?;
+        if self.eat_symbol("(") {
+            if self.peek_keyword("select") {
+                bail!("unsupported SQL: subqueries")
+            }
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        match self.next() {
+            Some(Token::Number(n)) => Ok(SqlExpr::Literal(match n.parse::<i64>() {
+                Ok(i) => DataValue::from(i),
+                Err(_) => DataValue::from(
+                    n.parse::<f64>()
+                        .map_err(|_| miette!("SQL syntax error: bad number {}", n))?,
+                ),
+            })),
+            Some(Token::Str(s)) => Ok(SqlExpr::Literal(DataValue::from(s))),
+            Some(Token::Ident(s)) if s.eq_ignore_ascii_case("null") => {
+                Ok(SqlExpr::Literal(DataValue::Null))
+            }
+            Some(Token::Ident(s)) if s.eq_ignore_ascii_case("true") => {
+                Ok(SqlExpr::Literal(DataValue::from(true)))
+            }
+            Some(Token::Ident(s)) if s.eq_ignore_ascii_case("false") => {
+                Ok(SqlExpr::Literal(DataValue::from(false)))
+            }
+            Some(Token::Ident(_) | Token::QuotedIdent(_)) => {
+                self.pos -= 1;
+                let name = self.identifier()?;
+                if matches!(self.peek(), Some(Token::Symbol("("))) {
+                    bail!("unsupported SQL: function call {}()", name)
+                }
+                if self.eat_symbol(".") {
+                    let column = self.identifier()?;
+                    Ok(SqlExpr::Column(Some(name), column))
+                } else {
+                    Ok(SqlExpr::Column(None, name))
+                }
+            }
+            _ => bail!("SQL syntax error: expected an expression"),
+        }
+    }
+}
+
+impl SqlExpr {
+    /// Whether the expression is true, false or unknown rather than a value
+    fn is_condition(&self) -> bool {
+        match self {
+            SqlExpr::Column(..) | SqlExpr::Literal(_) => false,
+            SqlExpr::Unary(op, _) | SqlExpr::Binary(op, _, _) => {
+                !matches!(*op, "-" | "+" | "*" | "/" | "%" | "++")
+            }
+            SqlExpr::IsNull(..) | SqlExpr::InList(..) => true,
+        }
+    }
+
+    /// `BETWEEN` uses its operand twice, which is only allowed for columns to avoid
+    /// duplicating arbitrary expressions
+    fn clone_column(&self) -> Result<SqlExpr> {
+        match self {
+            SqlExpr::Column(table, column) => Ok(SqlExpr::Column(table.clone(), column.clone())),
+            _ => bail!("unsupported SQL: BETWEEN on anything other than a column"),
+        }
+    }
+}
+
+/// Resolves column references to the variables bound to them
+struct Scope {
+    /// alias -> (table, columns of the table)
+    tables: BTreeMap<String, (String, Vec<String>)>,
+    /// (alias, column) -> variable
+    bound: BTreeMap<(String, String), String>,
+    reserved: BTreeSet<String>,
+    counter: usize,
+}
+
+impl Scope {
+    fn resolve(&mut self, table: &Option<String>, column: &str) -> Result<String> {
+        let alias = match table {
+            Some(alias) => {
+                let (_, columns) = self
+                    .tables
+                    .get(alias)
+                    .ok_or_else(|| miette!("unknown table or alias '{}'", alias))?;
+                ensure!(
+                    columns.iter().any(|c| c == column),
+                    "unknown column '{}.{}'",
+                    alias,
+                    column
+                );
+                alias.clone()
+            }
+            None => {
+                let candidates = self
+                    .tables
+                    .iter()
+                    .filter(|(_, (_, columns))| columns.iter().any(|c| c == column))
+                    .map(|(alias, _)| alias.clone())
+                    .collect_vec();
+                match candidates.len() {
+                    0 => bail!("unknown column '{}'", column),
+                    1 => candidates.into_iter().next().unwrap(),
+                    _ => bail!("ambiguous column '{}', qualify it with a table", column),
+                }
+            }
+        };
+        let key = (alias, column.to_string());
+        if let Some(var) = self.bound.get(&key) {
+            return Ok(var.clone());
+        }
+        let var = loop {
+            let var = format!("v{}", self.counter);
+            self.counter += 1;
+            if !self.reserved.contains(&var) {
+                break var;
+            }
+        };
+        self.bound.insert(key, var.clone());
+        Ok(var)
+    }
+
+    /// The value of the expression, with conditions that are unknown giving null
+    fn emit(&mut self, expr: &SqlExpr) -> Result<String> {
+        Ok(match expr {
+            SqlExpr::Column(table, column) => self.resolve(table, column)?,
+            SqlExpr::Literal(val) => val.display_literal(),
+            SqlExpr::Unary("-", arg) => format!("-({})", self.emit(arg)?),
+            SqlExpr::Binary(op, left, right) if !expr.is_condition() => {
+                format!("({} {} {})", self.emit(left)?, op, self.emit(right)?)
+            }
+            _ => format!(
+                "cond({}, true, {}, false)",
+                self.emit_truth(expr, true)?,
+                self.emit_truth(expr, false)?
+            ),
+        })
+    }
+
+    /// A boolean expression telling whether the condition is true, if `truth` is set,
+    /// or whether it is false. Both are false if the condition is unknown. Cozo's own
+    /// comparisons raise errors on nulls instead of giving unknown, so they are guarded.
+    fn emit_truth(&mut self, expr: &SqlExpr, truth: bool) -> Result<String> {
+        Ok(match expr {
+            SqlExpr::Unary("!", arg) => self.emit_truth(arg, !truth)?,
+            SqlExpr::Binary(op @ ("&&" | "||"), left, right) => {
+                // `a AND b` is false when either is false, and dually for `OR`
+                let op = if (*op == "&&") == truth { "&&" } else { "||" };
+                format!(
+                    "({} {} {})",
+                    self.emit_truth(left, truth)?,
+                    op,
+                    self.emit_truth(right, truth)?
+                )
+            }
+            SqlExpr::Binary(op, left, right) if expr.is_condition() => {
+                let (l, r) = (self.emit(left)?, self.emit(right)?);
+                let negate = if truth { "" } else { "!" };
+                Self::unless_null(
+                    &[(left, &l), (right, &r)],
+                    format!("{negate}({l} {op} {r})"),
+                )
+            }
+            SqlExpr::IsNull(arg, negated) => {
+                let negate = if *negated == truth { "!" } else { "" };
+                format!("{}is_null({})", negate, self.emit(arg)?)
+            }
+            SqlExpr::InList(arg, items, negated) => {
+                let x = self.emit(arg)?;
+                let items: Vec<_> = items.iter().map(|item| self.emit(item)).try_collect()?;
+                let items = items.join(", ");
+                if *negated != truth {
+                    Self::unless_null(&[(arg, &x)], format!("is_in({x}, [{items}])"))
+                } else {
+                    // not being among the items is unknown if one of them is null
+                    Self::unless_null(
+                        &[(arg, &x)],
+                        format!("!is_in({x}, [{items}]) && !is_in(null, [{items}])"),
+                    )
+                }
+            }
+            _ => format!("({} == {})", self.emit(expr)?, truth),
+        })
+    }
+
+    /// `cond`, or false if one of the operands that may be null is null
+    fn unless_null(operands: &[(&SqlExpr, &String)], cond: String) -> String {
+        let checks = operands
+            .iter()
+            .filter(|(expr, _)| !matches!(expr, SqlExpr::Literal(val) if *val != DataValue::Null))
+            .map(|(_, emitted)| format!("is_null({emitted})"))
+            .join(" || ");
+        if checks.is_empty() {
+            cond
+        } else {
+            format!("if({checks}, false, {cond})")
+        }
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    ensure!(
+        chars.next().is_some_and(|c| c.is_alphabetic())
+            && chars.all(|c| c.is_alphanumeric() || c == '_'),
+        "unsupported SQL: the name '{}' is not usable in Cozo",
+        name
+    );
+    Ok(())
+}
+
+/// Translates a SQL `SELECT` into CozoScript. `columns` gives the columns of a stored relation,
+/// which are needed to expand `*` and to resolve unqualified column names.
+pub(crate) fn translate_sql(
+    sql: &str,
+    columns: impl Fn(&str) -> Result<Vec<String>>,
+) -> Result<String> {
+    let select = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+    }
+    .select()?;
+
+    let mut tables = BTreeMap::new();
+    for TableRef { table, alias } in &select.tables {
+        check_name(table)?;
+        let cols = columns(table)?;
+        ensure!(
+            tables
+                .insert(alias.clone(), (table.clone(), cols))
+                .is_none(),
+            "duplicate table alias '{}', give the tables different aliases",
+            alias
+        );
+    }
+
+    // output columns, with the expressions computing them
+    let mut outputs: Vec<(String, SqlExpr)> = vec![];
+    for (i, projection) in select.projections.into_iter().enumerate() {
+        match projection {
+            Projection::Star(alias) => {
+                for TableRef { alias: a, .. } in &select.tables {
+                    if alias.is_none() || alias.as_ref() == Some(a) {
+                        for col in &tables[a].1 {
+                            outputs
+                                .push((col.clone(), SqlExpr::Column(Some(a.clone()), col.clone())));
+                        }
+                    }
+                }
+                if let Some(alias) = alias {
+                    ensure!(
+                        tables.contains_key(&alias),
+                        "unknown table or alias '{}'",
+                        alias
+                    );
+                }
+            }
+            Projection::Expr(expr, name) => {
+                let name = match (name, &expr) {
+                    (Some(name), _) => name,
+                    (None, SqlExpr::Column(_, col)) => col.clone(),
+                    (None, _) => format!("col{}", i + 1),
+                };
+                outputs.push((name, expr));
+            }
+        }
+    }
+    let mut reserved = BTreeSet::new();
+    for (name, _) in &outputs {
+        check_name(name)?;
+        ensure!(
+            reserved.insert(name.clone()),
+            "duplicate output column '{}', rename it with AS",
+            name
+        );
+    }
+    for (name, _) in &select.order {
+        ensure!(
+            reserved.contains(name),
+            "ORDER BY can only refer to output columns, '{}' is not one",
+            name
+        );
+    }
+
+    let mut scope = Scope {
+        tables,
+        bound: Default::default(),
+        reserved,
+        counter: 0,
+    };
+    let mut body = vec![];
+    for (name, expr) in &outputs {
+        body.push(format!("{} = {}", name, scope.emit(expr)?));
+    }
+    for cond in &select.conditions {
+        body.push(scope.emit_truth(cond, true)?);
+    }
+    let atoms = select
+        .tables
+        .iter()
+        .map(|TableRef { table, alias }| {
+            let bindings = scope
+                .bound
+                .iter()
+                .filter(|((a, _), _)| a == alias)
+                .map(|((_, col), var)| format!("{col}: {var}"))
+                .join(", ");
+            format!("*{table}{{{bindings}}}")
+        })
+        .collect_vec();
+
+    let mut script = format!(
+        "?[{}] := {}",
+        outputs.iter().map(|(name, _)| name).join(", "),
+        atoms.into_iter().chain(body).join(", ")
+    );
+    if !select.order.is_empty() {
+        let order = select
+            .order
+            .iter()
+            .map(|(name, desc)| format!("{}{}", if *desc { "-" } else { "" }, name))
+            .join(", ");
+        script += &format!(" :order {order}");
+    }
+    if let Some(limit) = select.limit {
+        script += &format!(" :limit {limit}");
+    }
+    if let Some(offset) = select.offset {
+        script += &format!(" :offset {offset}");
+    }
+    Ok(script)
+}
+
+/// The columns of a stored relation, keys first
+pub(crate) fn relation_columns(db: &DbInstance, table: &str) -> Result<Vec<String>> {
+    check_name(table)?;
+    let res = db
+        .run_script(&format!("::columns {table}"), Default::default())
+        .map_err(|_| miette!("unknown table '{}'", table))?;
+    Ok(res
+        .rows
+        .into_iter()
+        .filter_map(|row| row[0].get_str().map(|s| s.to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> DbInstance {
+        let db = DbInstance::new("mem", "", "").unwrap();
+        db.run_script(
+            r#"
+            {
+                ?[id, name, dept] <- [[1, 'Ann', 10], [2, "O'Brien", 20], [3, 'Cho', null]]
+                :create emp {id => name, dept}
+            }
+            {
+                ?[id, title] <- [[10, 'R&D'], [20, 'Sales']]
+                :create dept {id => title}
+            }
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+        db
+    }
+
+    fn run_sql(db: &DbInstance, sql: &str) -> Result<cozo::NamedRows> {
+        let script = translate_sql(sql, |table| relation_columns(db, table))?;
+        db.run_script(&script, Default::default())
+    }
+
+    #[test]
+    fn translates_simple_selects() {
+        let db = test_db();
+        assert_eq!(
+            translate_sql("SELECT name FROM emp WHERE id >= 2", |t| relation_columns(
+                &db, t
+            ))
+            .unwrap(),
+            "?[name] := *emp{id: v1, name: v0}, name = v0, if(is_null(v1), false, (v1 >= 2))"
+        );
+
+        let cases = [
+            (
+                "select name, id * 10 as x from emp where dept is not null and not id = 2",
+                "?[name, x] := *emp{id, name, dept}, !is_null(dept), id != 2, x = id * 10",
+            ),
+            (
+                "SELECT * FROM emp WHERE name IN ('Ann', 'O''Brien') ORDER BY id DESC LIMIT 1",
+                "?[id, name, dept] := *emp{id, name, dept}, is_in(name, ['Ann', \"O'Brien\"])
+                 :order -id :limit 1",
+            ),
+            (
+                "SELECT e.name, d.title AS dept_title FROM emp e JOIN dept AS d ON e.dept = d.id;",
+                "?[name, dept_title] := *emp{name, dept}, *dept{id: dept, title: dept_title}",
+            ),
+            (
+                "SELECT e.name FROM emp e, dept d WHERE e.dept = d.id AND d.title = 'R&D' OR e.id BETWEEN 3 AND 5",
+                "?[name] := *emp{id, name, dept}, *dept{id: d_id, title},
+                 (dept == d_id && title == 'R&D') || (id >= 3 && id <= 5)",
+            ),
+        ];
+        for (sql, cozo) in cases {
+            let translated = run_sql(&db, sql).unwrap();
+            let expected = db.run_script(cozo, Default::default()).unwrap();
+            assert_eq!(translated.headers, expected.headers, "{sql}");
+            assert_eq!(translated.rows, expected.rows, "{sql}");
+            assert!(!translated.rows.is_empty(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_sql() {
+        let db = test_db();
+        let err = |sql: &str| run_sql(&db, sql).unwrap_err().to_string();
+        for (sql, msg) in [
+            ("SELECT DISTINCT name FROM emp", "unsupported SQL: DISTINCT"),
+            (
+                "SELECT dept FROM emp GROUP BY dept",
+                "unsupported SQL: GROUP",
+            ),
+            (
+                "SELECT name FROM emp e LEFT JOIN dept d ON e.dept = d.id",
+                "unsupported SQL: LEFT",
+            ),
+            (
+                "SELECT upper(name) FROM emp",
+                "unsupported SQL: function call upper()",
+            ),
+            (
+                "SELECT name FROM emp WHERE name LIKE 'A%'",
+                "unsupported SQL: LIKE",
+            ),
+            (
+                "SELECT name FROM (SELECT name FROM emp)",
+                "unsupported SQL: subqueries",
+            ),
+            (
+                "SELECT id FROM emp, dept",
+                "ambiguous column 'id', qualify it with a table",
+            ),
+            ("SELECT * FROM emp, dept", "duplicate output column 'id'"),
+            ("SELECT salary FROM emp", "unknown column 'salary'"),
+            ("SELECT x FROM nowhere", "unknown table 'nowhere'"),
+            (
+                "SELECT name FROM emp ORDER BY id",
+                "ORDER BY can only refer",
+            ),
+            ("SELECT name FROM emp WHERE", "SQL syntax error"),
+            ("DELETE FROM emp", "SQL syntax error: expected SELECT"),
+        ] {
+            assert!(err(sql).contains(msg), "{sql}: {}", err(sql));
+        }
+    }
+
+    #[test]
+    fn follows_sql_null_semantics() {
+        let db = test_db();
+        let names = |sql: &str| -> Vec<String> {
+            run_sql(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].get_str().unwrap().to_string())
+                .collect()
+        };
+        // Cho's dept is null, so every comparison with it is unknown
+        for (sql, expected) in [
+            ("SELECT name FROM emp WHERE dept > 15", vec!["O'Brien"]),
+            ("SELECT name FROM emp WHERE dept < 15", vec!["Ann"]),
+            ("SELECT name FROM emp WHERE NOT dept = 10", vec!["O'Brien"]),
+            ("SELECT name FROM emp WHERE NOT dept > 15", vec!["Ann"]),
+            (
+                "SELECT name FROM emp WHERE NOT (dept < 15 AND id > 0)",
+                vec!["O'Brien"],
+            ),
+            (
+                "SELECT name FROM emp WHERE NOT (dept < 15 OR id > 2)",
+                vec!["O'Brien"],
+            ),
+            (
+                "SELECT name FROM emp WHERE dept > 15 OR id = 3",
+                vec!["Cho", "O'Brien"],
+            ),
+            (
+                "SELECT name FROM emp WHERE dept NOT IN (10)",
+                vec!["O'Brien"],
+            ),
+            ("SELECT name FROM emp WHERE dept NOT IN (10, NULL)", vec![]),
+            (
+                "SELECT name FROM emp WHERE dept NOT BETWEEN 5 AND 15",
+                vec!["O'Brien"],
+            ),
+            (
+                "SELECT name FROM emp WHERE dept = NULL OR dept <> NULL",
+                vec![],
+            ),
+        ] {
+            assert_eq!(names(sql), expected, "{sql}");
+        }
+
+        let res = run_sql(
+            &db,
+            "SELECT id, dept > 15 AS big, NOT dept > 15 AS small FROM emp",
+        )
+        .unwrap();
+        assert_eq!(
+            res.rows,
+            vec![
+                vec![
+                    DataValue::from(1),
+                    DataValue::from(false),
+                    DataValue::from(true)
+                ],
+                vec![
+                    DataValue::from(2),
+                    DataValue::from(true),
+                    DataValue::from(false)
+                ],
+                vec![DataValue::from(3), DataValue::Null, DataValue::Null],
+            ]
+        );
+    }
+}
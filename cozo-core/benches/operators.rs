@@ -0,0 +1,157 @@
+/*
+ *  Copyright 2023, The Cozo Project Authors.
+ *
+ *  This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ *  If a copy of the MPL was not distributed with this file,
+ *  You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ */
+
+use std::collections::BTreeMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use cozo::{evaluate_expression, BitSetWrapper, CompiledExpression, DataValue};
+
+const N_ROWS: i64 = 10000;
+
+/// Rows binding `x` to each of the integers in `0..N_ROWS`.
+fn rows() -> Vec<Vec<DataValue>> {
+    (0..N_ROWS).map(|x| vec![DataValue::from(x)]).collect()
+}
+
+/// Benchmarks evaluating each expression over all the rows, as done for the rows of queries.
+fn bench_rows(c: &mut Criterion, group: &str, exprs: &[(&str, &str)]) {
+    let rows = rows();
+    let mut group = c.benchmark_group(group);
+    for (name, src) in exprs {
+        let mut expr = CompiledExpression::new(src, &["x"]).unwrap();
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for row in &rows {
+                    black_box(expr.eval(row).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn arithmetic(c: &mut Criterion) {
+    bench_rows(
+        c,
+        "arithmetic",
+        &[
+            ("baseline", "x"),
+            ("mixed_chain", "(x + 1) * 2 - x / 3 + x % 7 - 5 * x + x ^ 2"),
+            (
+                "addition_chain",
+                "x + 1 + x + 2 + x + 3 + x + 4 + x + 5 + x + 6 + x + 7 + x + 8",
+            ),
+            (
+                "multiplication_chain",
+                "x * 3 * 0.5 * x * 2 * 0.25 * x * 4 * 0.125 * x * 8",
+            ),
+            (
+                "chain_with_constants",
+                "x * (1 + 2 * 3 - 4) + (10 / 5 - 1) * (x - (100 - 99))",
+            ),
+        ],
+    )
+}
+
+fn functions(c: &mut Criterion) {
+    bench_rows(
+        c,
+        "functions",
+        &[
+            (
+                "nested_calls",
+                "abs(round(pow(to_float(x) + 1.5, 0.5) * 10.0) - floor(x / 2))",
+            ),
+            (
+                "string_building",
+                "length(concat('item-', to_string(x), '-', to_string(x % 10)))",
+            ),
+            (
+                "cond",
+                "cond(x % 15 == 0, 'fizzbuzz', x % 5 == 0, 'buzz', x % 3 == 0, 'fizz', true, x)",
+            ),
+        ],
+    )
+}
+
+// `&&` and `||` associate to the left, so the infix forms build nested binary applications
+fn and_or(c: &mut Criterion) {
+    bench_rows(
+        c,
+        "and_or",
+        &[
+            (
+                "nested_and",
+                "x > 1 && x < 9999 && x % 2 == 0 && x != 500 && x % 3 != 1 && x % 7 != 2",
+            ),
+            (
+                "variadic_and",
+                "and(x > 1, x < 9999, x % 2 == 0, x != 500, x % 3 != 1, x % 7 != 2)",
+            ),
+            (
+                "nested_or",
+                "x < 0 || x > 9998 || x % 13 == 1 || x == 500 || x % 17 == 2 || x % 19 == 3",
+            ),
+            (
+                "variadic_or",
+                "or(x < 0, x > 9998, x % 13 == 1, x == 500, x % 17 == 2, x % 19 == 3)",
+            ),
+        ],
+    )
+}
+
+fn compile(c: &mut Criterion) {
+    let src = (0..200)
+        .map(|i| format!("abs((x * {i} - (3 * {i} + 5)) % 7)"))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    c.bench_function("compile/large_tree", |b| {
+        b.iter(|| CompiledExpression::new(black_box(&src), &["x"]).unwrap())
+    });
+}
+
+fn set_ops(c: &mut Criterion) {
+    // two overlapping sets of ids, as lists and as bitsets
+    let a = (0..200000i64).step_by(2);
+    let b = (0..300000i64).step_by(3);
+    let params = BTreeMap::from([
+        (
+            "a".to_string(),
+            DataValue::List(a.clone().map(DataValue::from).collect()),
+        ),
+        (
+            "b".to_string(),
+            DataValue::List(b.clone().map(DataValue::from).collect()),
+        ),
+        (
+            "sa".to_string(),
+            DataValue::BitSet(BitSetWrapper(a.map(|x| x as u64).collect())),
+        ),
+        (
+            "sb".to_string(),
+            DataValue::BitSet(BitSetWrapper(b.map(|x| x as u64).collect())),
+        ),
+    ]);
+    let mut group = c.benchmark_group("set_ops");
+    for (name, src) in [
+        ("list_intersection", "length(intersection($a, $b))"),
+        ("bitset_intersection", "bitset_count(bitset_and($sa, $sb))"),
+        ("list_union", "length(union($a, $b))"),
+        ("bitset_union", "bitset_count(bitset_or($sa, $sb))"),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| evaluate_expression(src, &params).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, arithmetic, functions, and_or, compile, set_ops);
+criterion_main!(benches);
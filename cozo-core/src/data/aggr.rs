@@ -8,10 +8,12 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
 
 use miette::{bail, ensure, miette, Result};
 use rand::prelude::*;
 
+use crate::data::functions::{interpolated_percentile, op_to_string, StableHasher};
 use crate::data::value::DataValue;
 
 pub(crate) struct Aggregation {
@@ -214,6 +216,76 @@ impl NormalAggrObj for AggrCountUnique {
     }
 }
 
+define_aggr!(AGGR_APPROX_COUNT_DISTINCT, false);
+
+const HLL_PRECISION: u32 = 14;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+/// Past this many registers set, they are stored densely, in less memory than sparsely
+const HLL_SPARSE_MAX: usize = HLL_REGISTERS / 16;
+
+/// Estimates the number of distinct non-null values with a HyperLogLog sketch of
+/// `HLL_REGISTERS` registers. The standard error is about 0.8%, and small counts are
+/// estimated by linear counting, which is nearly exact. Only the registers set are kept
+/// until there are `HLL_SPARSE_MAX` of them, so that small groups take little memory.
+#[derive(Default)]
+pub(crate) struct AggrApproxCountDistinct {
+    sparse: BTreeMap<u16, u8>,
+    dense: Option<Vec<u8>>,
+}
+
+impl NormalAggrObj for AggrApproxCountDistinct {
+    fn set(&mut self, value: &DataValue) -> Result<()> {
+        if *value == DataValue::Null {
+            return Ok(());
+        }
+        // the hash must not change with the platform or the version of Rust
+        let mut hasher = StableHasher::default();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        // the first bits choose the register, the rest give the rank of the first set bit
+        let idx = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION).leading_zeros() + 1).min(64 - HLL_PRECISION + 1) as u8;
+        let register = match &mut self.dense {
+            Some(dense) => &mut dense[idx],
+            None => self.sparse.entry(idx as u16).or_default(),
+        };
+        if *register < rank {
+            *register = rank;
+        }
+        if self.dense.is_none() && self.sparse.len() > HLL_SPARSE_MAX {
+            let mut dense = vec![0; HLL_REGISTERS];
+            for (idx, rank) in std::mem::take(&mut self.sparse) {
+                dense[idx as usize] = rank;
+            }
+            self.dense = Some(dense);
+        }
+        Ok(())
+    }
+
+    fn get(&self) -> Result<DataValue> {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1. + 1.079 / m);
+        let (sum, zeros) = match &self.dense {
+            Some(dense) => (
+                dense.iter().map(|r| 2f64.powi(-(*r as i32))).sum::<f64>(),
+                dense.iter().filter(|r| **r == 0).count(),
+            ),
+            None => {
+                let zeros = HLL_REGISTERS - self.sparse.len();
+                let set: f64 = self.sparse.values().map(|r| 2f64.powi(-(*r as i32))).sum();
+                (zeros as f64 + set, zeros)
+            }
+        };
+        let raw = alpha * m * m / sum;
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        Ok(DataValue::from(estimate.round() as i64))
+    }
+}
+
 define_aggr!(AGGR_UNION, true);
 
 #[derive(Default)]
@@ -385,6 +457,108 @@ impl NormalAggrObj for AggrCollect {
     }
 }
 
+define_aggr!(AGGR_GROUP_CONCAT, false);
+// the same as `group_concat`, under its own name so that it shows up as written in result headers
+define_aggr!(AGGR_STRING_AGG, false);
+
+pub(crate) struct AggrGroupConcat {
+    separator: String,
+    accum: String,
+    is_empty: bool,
+}
+
+impl AggrGroupConcat {
+    fn new(separator: String) -> Self {
+        Self {
+            separator,
+            accum: String::new(),
+            is_empty: true,
+        }
+    }
+}
+
+impl Default for AggrGroupConcat {
+    fn default() -> Self {
+        Self::new(",".to_string())
+    }
+}
+
+impl NormalAggrObj for AggrGroupConcat {
+    fn set(&mut self, value: &DataValue) -> Result<()> {
+        if *value == DataValue::Null {
+            return Ok(());
+        }
+        if !self.is_empty {
+            self.accum += &self.separator;
+        }
+        self.is_empty = false;
+        match op_to_string(std::slice::from_ref(value))? {
+            DataValue::Str(s) => self.accum += &s,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn get(&self) -> Result<DataValue> {
+        Ok(DataValue::from(&self.accum as &str))
+    }
+}
+
+define_aggr!(AGGR_PERCENTILE_CONT, false);
+define_aggr!(AGGR_PERCENTILE_DISC, false);
+
+/// Buffers the numbers of a group to find the `p`-th percentile, with `p` between 0 and 100.
+/// The continuous percentile interpolates between the two closest numbers, the discrete one is
+/// the first number at or above the percentile.
+pub(crate) struct AggrPercentile {
+    p: f64,
+    discrete: bool,
+    values: Vec<DataValue>,
+}
+
+impl AggrPercentile {
+    fn new(name: &str, discrete: bool, args: &[DataValue]) -> Result<Self> {
+        let p = match args {
+            [p] => p.get_float(),
+            _ => None,
+        };
+        let p = p
+            .filter(|p| (0. ..=100.).contains(p))
+            .ok_or_else(|| miette!("'{}' requires a percentile between 0 and 100", name))?;
+        Ok(Self {
+            p,
+            discrete,
+            values: vec![],
+        })
+    }
+}
+
+impl NormalAggrObj for AggrPercentile {
+    fn set(&mut self, value: &DataValue) -> Result<()> {
+        match value {
+            DataValue::Null => {}
+            DataValue::Num(_) => self.values.push(value.clone()),
+            v => bail!("cannot compute percentiles of {:?}", v),
+        }
+        Ok(())
+    }
+
+    fn get(&self) -> Result<DataValue> {
+        if self.values.is_empty() {
+            return Ok(DataValue::Null);
+        }
+        let mut sorted = self.values.clone();
+        sorted.sort();
+        Ok(if self.discrete {
+            let rank = (self.p / 100. * sorted.len() as f64).ceil() as usize;
+            sorted[rank.max(1) - 1].clone()
+        } else {
+            let nums: Vec<_> = sorted.iter().map(|v| v.get_float().unwrap()).collect();
+            DataValue::from(interpolated_percentile(&nums, self.p))
+        })
+    }
+}
+
 define_aggr!(AGGR_CHOICE_RAND, false);
 
 pub(crate) struct AggrChoiceRand {
@@ -419,14 +593,18 @@ impl NormalAggrObj for AggrChoiceRand {
 
 define_aggr!(AGGR_COUNT, false);
 
+/// Counts the non-null values. `count(*)` counts every row,
+/// as the parser makes it aggregate a constant.
 #[derive(Default)]
 pub(crate) struct AggrCount {
     count: i64,
 }
 
 impl NormalAggrObj for AggrCount {
-    fn set(&mut self, _value: &DataValue) -> Result<()> {
-        self.count += 1;
+    fn set(&mut self, value: &DataValue) -> Result<()> {
+        if *value != DataValue::Null {
+            self.count += 1;
+        }
         Ok(())
     }
 
@@ -497,7 +675,10 @@ impl NormalAggrObj for AggrStdDev {
 }
 
 define_aggr!(AGGR_MEAN, false);
+// the same as `mean`, under its own name so that it shows up as written in result headers
+define_aggr!(AGGR_AVG, false);
 
+/// Averages the non-null values, giving null if there are none.
 #[derive(Default)]
 pub(crate) struct AggrMean {
     count: i64,
@@ -511,18 +692,23 @@ impl NormalAggrObj for AggrMean {
                 self.sum += n.get_float();
                 self.count += 1;
             }
+            DataValue::Null => {}
             v => bail!("cannot compute 'mean': encountered value {:?}", v),
         }
         Ok(())
     }
 
     fn get(&self) -> Result<DataValue> {
+        if self.count == 0 {
+            return Ok(DataValue::Null);
+        }
         Ok(DataValue::from(self.sum / (self.count as f64)))
     }
 }
 
 define_aggr!(AGGR_SUM, false);
 
+/// Sums the non-null values, giving zero if there are none.
 #[derive(Default)]
 pub(crate) struct AggrSum {
     sum: f64,
@@ -534,6 +720,7 @@ impl NormalAggrObj for AggrSum {
             DataValue::Num(n) => {
                 self.sum += n.get_float();
             }
+            DataValue::Null => {}
             v => bail!("cannot compute 'sum': encountered value {:?}", v),
         }
         Ok(())
@@ -1165,6 +1352,7 @@ pub(crate) fn parse_aggr(name: &str) -> Option<&'static Aggregation> {
         "intersection" => &AGGR_INTERSECTION,
         "count" => &AGGR_COUNT,
         "count_unique" => &AGGR_COUNT_UNIQUE,
+        "approx_count_distinct" => &AGGR_APPROX_COUNT_DISTINCT,
         "variance" => &AGGR_VARIANCE,
         "std_dev" => &AGGR_STD_DEV,
         "sum" => &AGGR_SUM,
@@ -1172,8 +1360,13 @@ pub(crate) fn parse_aggr(name: &str) -> Option<&'static Aggregation> {
         "min" => &AGGR_MIN,
         "max" => &AGGR_MAX,
         "mean" => &AGGR_MEAN,
+        "avg" => &AGGR_AVG,
         "choice" => &AGGR_CHOICE,
         "collect" => &AGGR_COLLECT,
+        "group_concat" => &AGGR_GROUP_CONCAT,
+        "string_agg" => &AGGR_STRING_AGG,
+        "percentile_cont" => &AGGR_PERCENTILE_CONT,
+        "percentile_disc" => &AGGR_PERCENTILE_DISC,
         "shortest" => &AGGR_SHORTEST,
         "min_cost" => &AGGR_MIN_COST,
         "bit_and" => &AGGR_BIT_AND,
@@ -1212,11 +1405,16 @@ impl Aggregation {
             name if name == AGGR_COUNT.name => Box::new(AggrCount::default()),
             name if name == AGGR_GROUP_COUNT.name => Box::new(AggrGroupCount::default()),
             name if name == AGGR_COUNT_UNIQUE.name => Box::new(AggrCountUnique::default()),
+            name if name == AGGR_APPROX_COUNT_DISTINCT.name => {
+                Box::new(AggrApproxCountDistinct::default())
+            }
             name if name == AGGR_SUM.name => Box::new(AggrSum::default()),
             name if name == AGGR_PRODUCT.name => Box::new(AggrProduct::default()),
             name if name == AGGR_MIN.name => Box::new(AggrMin::default()),
             name if name == AGGR_MAX.name => Box::new(AggrMax::default()),
-            name if name == AGGR_MEAN.name => Box::new(AggrMean::default()),
+            name if name == AGGR_MEAN.name || name == AGGR_AVG.name => {
+                Box::new(AggrMean::default())
+            }
             name if name == AGGR_VARIANCE.name => Box::new(AggrVariance::default()),
             name if name == AGGR_STD_DEV.name => Box::new(AggrStdDev::default()),
             name if name == AGGR_CHOICE.name => Box::new(AggrChoice::default()),
@@ -1249,6 +1447,28 @@ impl Aggregation {
                     AggrCollect::new(arg as usize)
                 }
             }),
+            name if name == AGGR_GROUP_CONCAT.name || name == AGGR_STRING_AGG.name => {
+                Box::new(match args {
+                    [] => AggrGroupConcat::default(),
+                    [sep] => AggrGroupConcat::new(
+                        sep.get_str()
+                            .ok_or_else(|| {
+                                miette!(
+                                    "the separator for 'group_concat' must be a string, got {:?}",
+                                    sep
+                                )
+                            })?
+                            .to_string(),
+                    ),
+                    _ => bail!("'group_concat' takes at most one argument besides the value"),
+                })
+            }
+            name if name == AGGR_PERCENTILE_CONT.name => {
+                Box::new(AggrPercentile::new("percentile_cont", false, args)?)
+            }
+            name if name == AGGR_PERCENTILE_DISC.name => {
+                Box::new(AggrPercentile::new("percentile_disc", true, args)?)
+            }
             _ => unreachable!(),
         });
         Ok(())
@@ -20,9 +20,10 @@ use thiserror::Error;
 
 use crate::data::functions::*;
 use crate::data::symb::Symbol;
+use crate::data::type_infer::ValueType;
 use crate::data::value::{DataValue, LARGEST_UTF_CHAR};
-use crate::parse::expr::expr2bytecode;
-use crate::parse::SourceSpan;
+use crate::parse::expr::{expr2bytecode, expr2bytecode_shared};
+use crate::parse::{parse_expression, SourceSpan};
 
 #[derive(Clone, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize, Debug)]
 pub enum Bytecode {
@@ -56,6 +57,12 @@ pub enum Bytecode {
         #[serde(skip)]
         span: SourceSpan,
     },
+    /// push n, only ever the first instruction: slots for shared subexpressions
+    Reserve { slots: usize },
+    /// unchanged, copies the top of the stack into the slot
+    Store { slot: usize },
+    /// push 1, from the slot
+    Load { slot: usize },
 }
 
 #[derive(Error, Diagnostic, Debug)]
@@ -148,11 +155,121 @@ pub fn eval_bytecode(
             Bytecode::Goto { jump_to, .. } => {
                 pointer = *jump_to;
             }
+            Bytecode::Reserve { slots } => {
+                stack.resize(*slots, DataValue::Null);
+                pointer += 1;
+            }
+            Bytecode::Store { slot } => {
+                stack[*slot] = stack.last().unwrap().clone();
+                pointer += 1;
+            }
+            Bytecode::Load { slot } => {
+                stack.push(stack[*slot].clone());
+                pointer += 1;
+            }
         }
     }
     Ok(stack.pop().unwrap())
 }
 
+/// An expression with the source spans left out, so that structurally identical
+/// subexpressions from different places in the source compare equal
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ExprShape<'a> {
+    Binding(&'a str, Option<usize>),
+    Const(&'a DataValue),
+    Apply(&'static str, Vec<ExprShape<'a>>),
+    Cond(Vec<(ExprShape<'a>, ExprShape<'a>)>),
+}
+
+impl<'a> From<&'a Expr> for ExprShape<'a> {
+    fn from(expr: &'a Expr) -> Self {
+        match expr {
+            Expr::Binding { var, tuple_pos } => ExprShape::Binding(&var.name, *tuple_pos),
+            Expr::Const { val, .. } => ExprShape::Const(val),
+            Expr::Apply { op, args, .. } => {
+                ExprShape::Apply(op.name, args.iter().map(ExprShape::from).collect())
+            }
+            Expr::Cond { clauses, .. } => ExprShape::Cond(
+                clauses
+                    .iter()
+                    .map(|(cond, val)| (cond.into(), val.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Common subexpression elimination during compilation to bytecode.
+///
+/// Deterministic applications occurring more than once are computed once per evaluation
+/// and kept in a slot at the bottom of the stack. The slot is only filled where the
+/// subexpression is evaluated unconditionally, i.e. not within a branch of a `cond`,
+/// so that every later occurrence is guaranteed to find it filled.
+#[derive(Default)]
+pub(crate) struct SharedSubexprs<'a> {
+    repeated: BTreeSet<ExprShape<'a>>,
+    slots: BTreeMap<ExprShape<'a>, usize>,
+}
+
+impl<'a> SharedSubexprs<'a> {
+    // the interior mutability of `DataValue` is only a regex cache, not affecting ordering
+    #[allow(clippy::mutable_key_type)]
+    pub(crate) fn find(expr: &'a Expr) -> Self {
+        fn count<'a>(expr: &'a Expr, counts: &mut BTreeMap<ExprShape<'a>, usize>) {
+            match expr {
+                Expr::Binding { .. } | Expr::Const { .. } => {}
+                Expr::Apply { args, .. } => {
+                    if expr.is_deterministic() {
+                        *counts.entry(expr.into()).or_default() += 1;
+                    }
+                    for arg in args.iter() {
+                        count(arg, counts);
+                    }
+                }
+                Expr::Cond { clauses, .. } => {
+                    for (cond, val) in clauses {
+                        count(cond, counts);
+                        count(val, counts);
+                    }
+                }
+            }
+        }
+        let mut counts = BTreeMap::new();
+        count(expr, &mut counts);
+        Self {
+            repeated: counts
+                .into_iter()
+                .filter(|(_, n)| *n > 1)
+                .map(|(shape, _)| shape)
+                .collect(),
+            slots: Default::default(),
+        }
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.repeated.is_empty()
+    }
+    pub(crate) fn num_slots(&self) -> usize {
+        self.slots.len()
+    }
+    /// The shape of `expr` if it is worth sharing
+    pub(crate) fn shape_if_repeated(&self, expr: &'a Expr) -> Option<ExprShape<'a>> {
+        if self.repeated.is_empty() {
+            return None;
+        }
+        let shape = ExprShape::from(expr);
+        self.repeated.contains(&shape).then_some(shape)
+    }
+    pub(crate) fn slot(&self, shape: &ExprShape<'a>) -> Option<usize> {
+        self.slots.get(shape).copied()
+    }
+    pub(crate) fn assign_slot(&mut self, shape: ExprShape<'a>) -> usize {
+        let slot = self.slots.len();
+        self.slots.insert(shape, slot);
+        slot
+    }
+}
+
 /// Expression can be evaluated to yield a DataValue
 #[derive(Clone, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
 pub enum Expr {
@@ -191,6 +308,41 @@ pub enum Expr {
     },
 }
 
+/// A rewrite of an expression node done by the optimizer, as recorded by
+/// [Expr::constant_fold_traced] and [Expr::partial_eval_traced]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RewriteStep {
+    /// Name of the rewrite rule applied
+    pub rule: &'static str,
+    /// The node before the rewrite, with its subexpressions already rewritten
+    pub before: Expr,
+    /// The node after the rewrite
+    pub after: Expr,
+}
+
+impl RewriteStep {
+    fn record(
+        trace: &mut Option<&mut Vec<RewriteStep>>,
+        rule: &'static str,
+        before: impl FnOnce() -> Expr,
+        after: &Expr,
+    ) {
+        if let Some(trace) = trace {
+            trace.push(RewriteStep {
+                rule,
+                before: before(),
+                after: after.clone(),
+            })
+        }
+    }
+}
+
+impl Display for RewriteStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.rule, self.before, self.after)
+    }
+}
+
 impl Debug for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{self}")
@@ -227,12 +379,12 @@ impl Display for Expr {
 }
 
 #[derive(Debug, Error, Diagnostic)]
-#[error("Found value {1:?} where a boolean value is expected")]
+#[error("Found value {} where a boolean value is expected", .1.display_literal())]
 #[diagnostic(code(eval::predicate_not_bool))]
 pub(crate) struct PredicateTypeError(#[label] pub(crate) SourceSpan, pub(crate) DataValue);
 
 #[derive(Debug, Error, Diagnostic)]
-#[error("Cannot build entity ID from {0:?}")]
+#[error("Cannot build entity ID from {}", .0.display_literal())]
 #[diagnostic(code(parser::bad_eid))]
 #[diagnostic(help("Entity ID should be an integer satisfying certain constraints"))]
 struct BadEntityId(DataValue, #[label] SourceSpan);
@@ -242,10 +394,69 @@ struct BadEntityId(DataValue, #[label] SourceSpan);
 #[diagnostic(code(eval::throw))]
 struct EvalRaisedError(#[label] SourceSpan, #[help] String);
 
+#[derive(Error, Diagnostic, Debug)]
+#[error("The expression cannot be reduced to a value: {0}")]
+#[diagnostic(code(eval::not_reducible))]
+#[diagnostic(help(
+    "There is nothing to bind variables to here, pass values as parameters instead"
+))]
+struct NotReducibleError(String, #[label] SourceSpan);
+
+/// Evaluates a single expression such as `2 + 3 * $x` outside of any query,
+/// taking the values of parameters from `params`.
+pub fn evaluate_expression(src: &str, params: &BTreeMap<String, DataValue>) -> Result<DataValue> {
+    let mut expr = parse_expression(src, params)?.constant_fold();
+    // raises the errors that constant folding leaves for evaluation time
+    expr.partial_eval()?;
+    match expr {
+        Expr::Const { val, .. } => Ok(val),
+        expr => bail!(NotReducibleError(expr.to_string(), expr.span())),
+    }
+}
+
+/// An expression compiled to the bytecode the rows of queries are evaluated with.
+/// Useful for timing the evaluation of operators apart from the rest of a query.
+pub struct CompiledExpression {
+    bytecode: Vec<Bytecode>,
+    stack: Vec<DataValue>,
+}
+
+impl CompiledExpression {
+    /// Compiles an expression such as `x * 2 + 1`, taking the values of its variables
+    /// from the rows given to [CompiledExpression::eval], in the order of `bindings`.
+    pub fn new(src: &str, bindings: &[&str]) -> Result<Self> {
+        let mut expr = parse_expression(src, &Default::default())?;
+        let binding_map = bindings
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (Symbol::new(*name, SourceSpan(0, 0)), i))
+            .collect();
+        expr.fill_binding_indices(&binding_map)?;
+        Ok(Self {
+            bytecode: expr.compile(),
+            stack: vec![],
+        })
+    }
+    /// Evaluates the expression for one row
+    pub fn eval(&mut self, row: &[DataValue]) -> Result<DataValue> {
+        eval_bytecode(&self.bytecode, row, &mut self.stack)
+    }
+}
+
 impl Expr {
     pub(crate) fn compile(&self) -> Vec<Bytecode> {
-        let mut collector = vec![];
-        expr2bytecode(self, &mut collector);
+        let folded = self.clone().constant_fold();
+        let mut sharing = SharedSubexprs::find(&folded);
+        if sharing.is_empty() {
+            let mut collector = vec![];
+            expr2bytecode(&folded, &mut collector);
+            return collector;
+        }
+        let mut collector = vec![Bytecode::Reserve { slots: 0 }];
+        expr2bytecode_shared(&folded, &mut sharing, true, &mut collector);
+        collector[0] = Bytecode::Reserve {
+            slots: sharing.num_slots(),
+        };
         collector
     }
     pub(crate) fn span(&self) -> SourceSpan {
@@ -371,17 +582,314 @@ impl Expr {
             _ => bail!(NotConstError),
         }
     }
+    /// Replaces `skip()` occurring as the value of this list element, possibly
+    /// as a branch of a conditional, with the bottom value that list construction drops.
+    fn mark_skipped_list_element(&mut self) {
+        match self {
+            Expr::Apply { op, span, .. } if op.name == OP_SKIP.name => {
+                *self = Expr::Const {
+                    val: DataValue::Bot,
+                    span: *span,
+                }
+            }
+            Expr::Cond { clauses, .. } => {
+                for (_, val) in clauses.iter_mut() {
+                    val.mark_skipped_list_element();
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Drops the elements of a list that are known to be skipped, so that they are not
+    /// evaluated at runtime.
+    fn drop_skipped_elements(&mut self, trace: &mut Option<&mut Vec<RewriteStep>>) {
+        if let Expr::Apply { op, args, span } = self {
+            let is_skipped = |arg: &Expr| arg.get_const() == Some(&DataValue::Bot);
+            if op.name != OP_LIST.name || !args.iter().any(is_skipped) {
+                return;
+            }
+            let mut folded = Expr::Apply {
+                op,
+                args: args
+                    .iter()
+                    .filter(|arg| !is_skipped(arg))
+                    .cloned()
+                    .collect(),
+                span: *span,
+            };
+            mem::swap(self, &mut folded);
+            RewriteStep::record(trace, "drop_skipped_elements", || folded, self);
+        }
+    }
+    /// Turns `(a + b) + c` into `add_chain(a, b, c)`, and likewise for multiplications, so that
+    /// long chains are evaluated in one application. Arguments are folded first, so the
+    /// left operand is already a chain for longer chains.
+    fn fuse_arithmetic_chain(&mut self, trace: &mut Option<&mut Vec<RewriteStep>>) {
+        let Expr::Apply { op, args, span } = self else {
+            return;
+        };
+        let chain = if op.name == OP_ADD.name {
+            &OP_ADD_CHAIN
+        } else if op.name == OP_MUL.name {
+            &OP_MUL_CHAIN
+        } else {
+            return;
+        };
+        let [Expr::Apply {
+            op: left_op,
+            args: left_args,
+            ..
+        }, right] = &**args
+        else {
+            return;
+        };
+        let left_is_link =
+            (left_op.name == op.name && left_args.len() == 2) || left_op.name == chain.name;
+        if !left_is_link {
+            return;
+        }
+        let mut folded = Expr::Apply {
+            op: chain,
+            args: left_args.iter().chain([right]).cloned().collect(),
+            span: *span,
+        };
+        mem::swap(self, &mut folded);
+        RewriteStep::record(trace, "fuse_chain", || folded, self);
+    }
+    /// Folds constant subexpressions and drops conditional branches that can never be taken,
+    /// leaving bindings untouched. Non-deterministic functions are not folded, and
+    /// applications that fail are left to fail at runtime. Applications of null-propagating
+    /// functions to a constant null are replaced by null, pruning their other arguments
+    /// unless one of them is non-deterministic. Errors the pruned arguments would raise are
+    /// then not raised.
+    pub(crate) fn constant_fold(self) -> Self {
+        self.do_constant_fold(&mut None)
+    }
+    /// Same as folding constants during compilation, but also returns the rewrites done,
+    /// innermost first. Intended for debugging the optimizer.
+    pub fn constant_fold_traced(self) -> (Self, Vec<RewriteStep>) {
+        let mut trace = vec![];
+        let folded = self.do_constant_fold(&mut Some(&mut trace));
+        (folded, trace)
+    }
+    fn do_constant_fold(self, trace: &mut Option<&mut Vec<RewriteStep>>) -> Self {
+        match self {
+            Expr::Apply { op, args, span } => {
+                let args: Box<[Expr]> = args
+                    .into_vec()
+                    .into_iter()
+                    .map(|arg| arg.do_constant_fold(trace))
+                    .collect();
+                // the other arguments are dropped, so that they are not evaluated at all
+                if op.propagates_null
+                    && args
+                        .iter()
+                        .any(|arg| matches!(arg.get_const(), Some(DataValue::Null)))
+                    && args.iter().all(|arg| arg.is_deterministic())
+                {
+                    let folded = Expr::Const {
+                        val: DataValue::Null,
+                        span,
+                    };
+                    RewriteStep::record(
+                        trace,
+                        "propagate_null",
+                        || Expr::Apply { op, args, span },
+                        &folded,
+                    );
+                    return folded;
+                }
+                if op.is_deterministic() {
+                    let vals: Option<Vec<_>> =
+                        args.iter().map(|arg| arg.get_const().cloned()).collect();
+                    if let Some(Ok(val)) = vals.map(|vals| (op.inner)(&vals)) {
+                        let folded = Expr::Const { val, span };
+                        RewriteStep::record(
+                            trace,
+                            "fold_constant",
+                            || Expr::Apply { op, args, span },
+                            &folded,
+                        );
+                        return folded;
+                    }
+                }
+                if op.name == OP_AND.name || op.name == OP_OR.name {
+                    return Self::fold_and_or(op, args, span, trace);
+                }
+                let mut folded = Expr::Apply { op, args, span };
+                folded.drop_skipped_elements(trace);
+                folded.fuse_arithmetic_chain(trace);
+                folded
+            }
+            Expr::Cond { clauses, span } => {
+                let clauses = clauses
+                    .into_iter()
+                    .map(|(cond, val)| (cond.do_constant_fold(trace), val.do_constant_fold(trace)))
+                    .collect_vec();
+                // the clauses before the rewrite are only needed for the trace
+                let before = trace.as_ref().map(|_| clauses.clone());
+                let n_clauses = clauses.len();
+                let mut kept = vec![];
+                let mut taken = None;
+                for (cond, val) in clauses {
+                    match cond.get_const() {
+                        Some(DataValue::Bool(false)) => {}
+                        Some(DataValue::Bool(true)) => {
+                            if kept.is_empty() {
+                                taken = Some(val);
+                            } else {
+                                kept.push((cond, val));
+                            }
+                            break;
+                        }
+                        _ => kept.push((cond, val)),
+                    }
+                }
+                let (rule, folded) = match taken {
+                    Some(val) => ("take_branch", val),
+                    None if kept.is_empty() => (
+                        "no_branch_taken",
+                        Expr::Const {
+                            val: DataValue::Null,
+                            span,
+                        },
+                    ),
+                    None if kept.len() < n_clauses => (
+                        "drop_branches",
+                        Expr::Cond {
+                            clauses: kept,
+                            span,
+                        },
+                    ),
+                    None => {
+                        return Expr::Cond {
+                            clauses: kept,
+                            span,
+                        }
+                    }
+                };
+                RewriteStep::record(
+                    trace,
+                    rule,
+                    || Expr::Cond {
+                        clauses: before.unwrap(),
+                        span,
+                    },
+                    &folded,
+                );
+                folded
+            }
+            expr => expr,
+        }
+    }
+    // `and` is false as soon as one operand is false, and `true` operands can be dropped,
+    // and dually for `or`. All operands are evaluated and the ones before the deciding one are
+    // checked to be booleans, so only operands that cannot raise errors are dropped with them,
+    // and a single remaining operand is kept under the `and` or `or`. Such an operand is
+    // unwrapped again when it ends up inside another `and` or `or` of the same kind.
+    fn fold_and_or(
+        op: &'static Op,
+        args: Box<[Expr]>,
+        span: SourceSpan,
+        trace: &mut Option<&mut Vec<RewriteStep>>,
+    ) -> Self {
+        let identity = DataValue::from(op.name == OP_AND.name);
+        let absorbing = DataValue::from(op.name != OP_AND.name);
+        let short_circuits = match args
+            .iter()
+            .position(|arg| arg.get_const() == Some(&absorbing))
+        {
+            None => false,
+            Some(i) => {
+                args[..i]
+                    .iter()
+                    .all(|arg| arg.get_const() == Some(&identity))
+                    && args[i + 1..]
+                        .iter()
+                        .all(|arg| matches!(arg, Expr::Const { .. } | Expr::Binding { .. }))
+            }
+        };
+        let (rule, folded) = if short_circuits {
+            (
+                "short_circuit",
+                Expr::Const {
+                    val: absorbing,
+                    span,
+                },
+            )
+        } else {
+            let mut changed = false;
+            let kept = args
+                .iter()
+                .filter(|arg| arg.get_const() != Some(&identity))
+                .map(|arg| match arg {
+                    Expr::Apply {
+                        op: inner_op,
+                        args: inner_args,
+                        ..
+                    } if inner_op.name == op.name && inner_args.len() == 1 => {
+                        changed = true;
+                        inner_args[0].clone()
+                    }
+                    arg => arg.clone(),
+                })
+                .collect_vec();
+            match kept.len() {
+                n if n == args.len() && !changed => return Expr::Apply { op, args, span },
+                0 => (
+                    "drop_identities",
+                    Expr::Const {
+                        val: identity,
+                        span,
+                    },
+                ),
+                _ => (
+                    "drop_identities",
+                    Expr::Apply {
+                        op,
+                        args: kept.into(),
+                        span,
+                    },
+                ),
+            }
+        };
+        RewriteStep::record(trace, rule, || Expr::Apply { op, args, span }, &folded);
+        folded
+    }
     pub(crate) fn partial_eval(&mut self) -> Result<()> {
+        self.do_partial_eval(&mut None)
+    }
+    /// Same as [Expr::partial_eval], but also returns the rewrites done, innermost first.
+    /// Intended for debugging the optimizer.
+    pub fn partial_eval_traced(&mut self) -> Result<Vec<RewriteStep>> {
+        let mut trace = vec![];
+        self.do_partial_eval(&mut Some(&mut trace))?;
+        Ok(trace)
+    }
+    fn do_partial_eval(&mut self, trace: &mut Option<&mut Vec<RewriteStep>>) -> Result<()> {
+        if let Expr::Cond { span, .. } = self {
+            // branches not taken may fail, so errors are left to runtime as in constant folding
+            let placeholder = Expr::Const {
+                val: DataValue::Null,
+                span: *span,
+            };
+            *self = mem::replace(self, placeholder).do_constant_fold(trace);
+            return Ok(());
+        }
         if let Expr::Apply { args, span, .. } = self {
             let span = *span;
             let mut all_evaluated = true;
             for arg in args.iter_mut() {
-                arg.partial_eval()?;
+                arg.do_partial_eval(trace)?;
                 all_evaluated = all_evaluated && matches!(arg, Expr::Const { .. });
             }
             if all_evaluated {
                 let result = self.eval(&vec![])?;
-                mem::swap(self, &mut Expr::Const { val: result, span });
+                let mut folded = Expr::Const { val: result, span };
+                mem::swap(self, &mut folded);
+                RewriteStep::record(trace, "fold_constant", || folded, self);
+            } else {
+                self.drop_skipped_elements(trace);
             }
             // nested not's can accumulate during conversion to normal form
             if let Expr::Apply {
@@ -400,6 +908,7 @@ impl Expr {
                         if op2.name == OP_NEGATE.name {
                             let mut new_self = arg2[0].clone();
                             mem::swap(self, &mut new_self);
+                            RewriteStep::record(trace, "double_negation", || new_self, self);
                         }
                     }
                 }
@@ -452,12 +961,19 @@ impl Expr {
             },
             Expr::Const { val, .. } => Ok(val.clone()),
             Expr::Apply { op, args, .. } => {
-                let args: Box<[DataValue]> = args
-                    .iter()
-                    .map(|v| v.eval(bindings.as_ref()))
-                    .try_collect()?;
-                Ok((op.inner)(&args)
-                    .map_err(|err| EvalRaisedError(self.span(), err.to_string()))?)
+                let bindings = bindings.as_ref();
+                // arguments of the common unary and binary operators are kept on the stack
+                let res = match &args[..] {
+                    [] => (op.inner)(&[]),
+                    [a] => (op.inner)(&[a.eval(bindings)?]),
+                    [a, b] => (op.inner)(&[a.eval(bindings)?, b.eval(bindings)?]),
+                    args => {
+                        let args: Box<[DataValue]> =
+                            args.iter().map(|v| v.eval(bindings)).try_collect()?;
+                        (op.inner)(&args)
+                    }
+                };
+                Ok(res.map_err(|err| EvalRaisedError(self.span(), err.to_string()))?)
             }
             Expr::Cond { clauses, .. } => {
                 for (cond, val) in clauses {
@@ -474,6 +990,18 @@ impl Expr {
             }
         }
     }
+    /// Whether evaluating the expression always gives the same result for the same bindings
+    pub(crate) fn is_deterministic(&self) -> bool {
+        match self {
+            Expr::Binding { .. } | Expr::Const { .. } => true,
+            Expr::Apply { op, args, .. } => {
+                op.is_deterministic() && args.iter().all(|arg| arg.is_deterministic())
+            }
+            Expr::Cond { clauses, .. } => clauses
+                .iter()
+                .all(|(cond, val)| cond.is_deterministic() && val.is_deterministic()),
+        }
+    }
     pub(crate) fn extract_bound(&self, target: &Symbol) -> Result<ValueRange> {
         Ok(match self {
             Expr::Binding { .. } | Expr::Const { .. } | Expr::Cond { .. } => ValueRange::default(),
@@ -536,7 +1064,7 @@ impl Expr {
                             if target == symb {
                                 let s = val.get_str().ok_or_else(|| {
                                     #[derive(Debug, Error, Diagnostic)]
-                                    #[error("Cannot prefix scan with {0:?}")]
+                                    #[error("Cannot prefix scan with {}", .0.display_literal())]
                                     #[diagnostic(code(eval::bad_string_range_scan))]
                                     #[diagnostic(help("A string argument is required"))]
                                     struct StrRangeScanError(DataValue, #[label] SourceSpan);
@@ -634,6 +1162,13 @@ pub struct Op {
     pub(crate) min_arity: usize,
     pub(crate) vararg: bool,
     pub(crate) inner: fn(&[DataValue]) -> Result<DataValue>,
+    /// Whether the result is null as soon as any argument is null, whatever the other arguments
+    pub(crate) propagates_null: bool,
+    /// The types expected for the arguments, the last one repeated for any further arguments.
+    /// Empty if any arguments are accepted.
+    pub(crate) arg_types: &'static [ValueType],
+    /// The type of the result given the types of the arguments, see [Expr::infer_type]
+    pub(crate) output_type: fn(&[ValueType]) -> ValueType,
 }
 
 impl serde::Serialize for &'_ Op {
@@ -668,7 +1203,13 @@ impl<'de> Visitor<'de> for OpVisitor {
         E: Error,
     {
         let name = v.strip_prefix("OP_").unwrap().to_ascii_lowercase();
-        get_op(&name).ok_or_else(|| E::custom(format!("op not found in serialized data: {v}")))
+        // the fused chains are not callable by name, but may be found in folded expressions
+        let op = match name.as_str() {
+            "add_chain" => Some(&OP_ADD_CHAIN),
+            "mul_chain" => Some(&OP_MUL_CHAIN),
+            _ => get_op(&name),
+        };
+        op.ok_or_else(|| E::custom(format!("op not found in serialized data: {v}")))
     }
 }
 
@@ -689,11 +1230,21 @@ impl Debug for Op {
 pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
     Some(match name {
         "coalesce" => &OP_COALESCE,
+        "first_non_null" => &OP_COALESCE,
+        "last_non_null" => &OP_LAST_NON_NULL,
+        "if_null" => &OP_IF_NULL,
+        "if_not_null" => &OP_IF_NOT_NULL,
         "list" => &OP_LIST,
+        "skip" => &OP_SKIP,
         "add" => &OP_ADD,
         "sub" => &OP_SUB,
         "mul" => &OP_MUL,
+        "add_nn" => &OP_ADD_NN,
+        "mul_nn" => &OP_MUL_NN,
+        "concat_nn" => &OP_CONCAT_NN,
         "div" => &OP_DIV,
+        "true_div" => &OP_TRUE_DIV,
+        "floor_div" => &OP_FLOOR_DIV,
         "minus" => &OP_MINUS,
         "abs" => &OP_ABS,
         "signum" => &OP_SIGNUM,
@@ -701,8 +1252,12 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "ceil" => &OP_CEIL,
         "round" => &OP_ROUND,
         "mod" => &OP_MOD,
+        "clamp" => &OP_CLAMP,
+        "wrap" => &OP_WRAP,
         "max" => &OP_MAX,
         "min" => &OP_MIN,
+        "greatest" => &OP_GREATEST,
+        "least" => &OP_LEAST,
         "pow" => &OP_POW,
         "exp" => &OP_EXP,
         "exp2" => &OP_EXP2,
@@ -722,8 +1277,11 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "asinh" => &OP_ASINH,
         "acosh" => &OP_ACOSH,
         "atanh" => &OP_ATANH,
+        "sigmoid" => &OP_SIGMOID,
+        "relu" => &OP_RELU,
         "eq" => &OP_EQ,
         "neq" => &OP_NEQ,
+        "approx_eq" => &OP_APPROX_EQ,
         "gt" => &OP_GT,
         "ge" => &OP_GE,
         "lt" => &OP_LT,
@@ -739,8 +1297,12 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "unpack_bits" => &OP_UNPACK_BITS,
         "concat" => &OP_CONCAT,
         "str_includes" => &OP_STR_INCLUDES,
+        "count_matches" => &OP_COUNT_MATCHES,
         "lowercase" => &OP_LOWERCASE,
         "uppercase" => &OP_UPPERCASE,
+        "capitalize" => &OP_CAPITALIZE,
+        "title_case" => &OP_TITLE_CASE,
+        "swap_case" => &OP_SWAP_CASE,
         "trim" => &OP_TRIM,
         "trim_start" => &OP_TRIM_START,
         "trim_end" => &OP_TRIM_END,
@@ -760,16 +1322,35 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "is_uuid" => &OP_IS_UUID,
         "length" => &OP_LENGTH,
         "sorted" => &OP_SORTED,
+        "natural_sort" => &OP_NATURAL_SORT,
         "reverse" => &OP_REVERSE,
+        "compact" => &OP_COMPACT,
+        "rotate" => &OP_ROTATE,
+        "shift" => &OP_SHIFT,
+        "ntile" => &OP_NTILE,
+        "fill_forward" => &OP_FILL_FORWARD,
+        "fill_backward" => &OP_FILL_BACKWARD,
+        "cumsum" => &OP_CUMSUM,
+        "cumprod" => &OP_CUMPROD,
+        "cummax" => &OP_CUMMAX,
+        "cummin" => &OP_CUMMIN,
+        "median" => &OP_MEDIAN,
+        "percentile" => &OP_PERCENTILE,
         "append" => &OP_APPEND,
         "prepend" => &OP_PREPEND,
         "unicode_normalize" => &OP_UNICODE_NORMALIZE,
+        "strip_accents" => &OP_STRIP_ACCENTS,
+        "to_ascii" => &OP_TO_ASCII,
+        "normalize" => &OP_UNICODE_NORMALIZE,
         "haversine" => &OP_HAVERSINE,
         "haversine_deg_input" => &OP_HAVERSINE_DEG_INPUT,
         "deg_to_rad" => &OP_DEG_TO_RAD,
         "rad_to_deg" => &OP_RAD_TO_DEG,
         "get" => &OP_GET,
         "maybe_get" => &OP_MAYBE_GET,
+        "group_by" => &OP_GROUP_BY,
+        "bincount" => &OP_BINCOUNT,
+        "value_counts" => &OP_VALUE_COUNTS,
         "chars" => &OP_CHARS,
         "from_substrings" => &OP_FROM_SUBSTRINGS,
         "slice" => &OP_SLICE,
@@ -780,22 +1361,60 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "regex_extract_first" => &OP_REGEX_EXTRACT_FIRST,
         "encode_base64" => &OP_ENCODE_BASE64,
         "decode_base64" => &OP_DECODE_BASE64,
+        "byte_at" => &OP_BYTE_AT,
+        "bytes_to_ints" => &OP_BYTES_TO_INTS,
+        "ints_to_bytes" => &OP_INTS_TO_BYTES,
+        "to_base32" => &OP_TO_BASE32,
+        "from_base32" => &OP_FROM_BASE32,
+        "to_base58" => &OP_TO_BASE58,
+        "from_base58" => &OP_FROM_BASE58,
         "first" => &OP_FIRST,
         "last" => &OP_LAST,
+        "transpose" => &OP_TRANSPOSE,
         "chunks" => &OP_CHUNKS,
         "chunks_exact" => &OP_CHUNKS_EXACT,
         "windows" => &OP_WINDOWS,
+        "zip_with" => &OP_ZIP_WITH,
+        "find" => &OP_FIND,
+        "find_index" => &OP_FIND_INDEX,
+        "any" => &OP_ANY,
+        "all" => &OP_ALL,
         "to_int" => &OP_TO_INT,
         "to_float" => &OP_TO_FLOAT,
+        "parse_number" => &OP_PARSE_NUMBER,
+        "parse_number_strict" => &OP_PARSE_NUMBER_STRICT,
         "to_string" => &OP_TO_STRING,
+        "from_json_lines" => &OP_FROM_JSON_LINES,
+        "to_json_lines" => &OP_TO_JSON_LINES,
+        "json_path" => &OP_JSON_PATH,
+        "json_merge_patch" => &OP_JSON_MERGE_PATCH,
+        "parse_url" => &OP_PARSE_URL,
+        "parse_query" => &OP_PARSE_QUERY,
+        "encode_url_query" => &OP_ENCODE_URL_QUERY,
         "rand_float" => &OP_RAND_FLOAT,
         "rand_bernoulli" => &OP_RAND_BERNOULLI,
         "rand_int" => &OP_RAND_INT,
         "rand_choose" => &OP_RAND_CHOOSE,
+        "sample" => &OP_SAMPLE,
+        "shuffle" => &OP_SHUFFLE,
         "assert" => &OP_ASSERT,
+        "ensure_not_null" => &OP_ENSURE_NOT_NULL,
         "union" => &OP_UNION,
         "intersection" => &OP_INTERSECTION,
+        "list_union" => &OP_LIST_UNION,
+        "list_intersect" => &OP_LIST_INTERSECT,
+        "list_difference" => &OP_LIST_DIFFERENCE,
+        "jaccard" => &OP_JACCARD,
+        "similarity" => &OP_SIMILARITY,
+        "cosine" => &OP_COSINE,
+        "dot" => &OP_DOT,
+        "product" => &OP_PRODUCT,
         "difference" => &OP_DIFFERENCE,
+        "bitset_from_list" => &OP_BITSET_FROM_LIST,
+        "bitset_contains" => &OP_BITSET_CONTAINS,
+        "bitset_and" => &OP_BITSET_AND,
+        "bitset_or" => &OP_BITSET_OR,
+        "bitset_count" => &OP_BITSET_COUNT,
         "to_uuid" => &OP_TO_UUID,
         "to_bool" => &OP_TO_BOOL,
         "to_unity" => &OP_TO_UNITY,
@@ -804,12 +1423,23 @@ pub(crate) fn get_op(name: &str) -> Option<&'static Op> {
         "uuid_timestamp" => &OP_UUID_TIMESTAMP,
         "now" => &OP_NOW,
         "format_timestamp" => &OP_FORMAT_TIMESTAMP,
+        "date_trunc" => &OP_DATE_TRUNC,
+        "date_range" => &OP_DATE_RANGE,
+        "generate_series" => &OP_GENERATE_SERIES,
+        "month_name" => &OP_MONTH_NAME,
+        "weekday_name" => &OP_WEEKDAY_NAME,
+        "parse_duration" => &OP_PARSE_DURATION,
+        "format_duration" => &OP_FORMAT_DURATION,
         "parse_timestamp" => &OP_PARSE_TIMESTAMP,
         _ => return None,
     })
 }
 
 impl Op {
+    pub(crate) fn is_deterministic(&self) -> bool {
+        !(self.name.starts_with("OP_RAND_")
+            || [OP_NOW.name, OP_SAMPLE.name, OP_SHUFFLE.name].contains(&self.name))
+    }
     pub(crate) fn post_process_args(&self, args: &mut [Expr]) {
         if self.name.starts_with("OP_REGEX_") {
             args[1] = Expr::Apply {
@@ -818,5 +1448,10 @@ impl Op {
                 span: args[1].span(),
             }
         }
+        if self.name == OP_LIST.name {
+            for arg in args.iter_mut() {
+                arg.mark_skipped_list_element();
+            }
+        }
     }
 }
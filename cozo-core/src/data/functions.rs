@@ -6,38 +6,73 @@
  * You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::cmp::Reverse;
-use std::collections::BTreeSet;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::iter;
 use std::ops::{Div, Rem};
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use data_encoding::BASE32;
 use itertools::Itertools;
 #[cfg(target_arch = "wasm32")]
 use js_sys::Date;
 use miette::{bail, ensure, miette, Result};
 use num_traits::FloatConst;
 use rand::prelude::*;
+use roaring::RoaringTreemap;
 use smartstring::SmartString;
 use unicode_normalization::UnicodeNormalization;
 use uuid::v1::Timestamp;
 
-use crate::data::expr::Op;
+use crate::data::expr::{get_op, Op};
 use crate::data::json::JsonValue;
-use crate::data::value::{DataValue, Num, RegexWrapper, UuidWrapper, Validity, ValidityTs};
+use crate::data::type_infer::{
+    concat_type, date_trunc_type, first_arg_type, joined_type, numeric_type, second_arg_type,
+    signum_type, ValueType,
+};
+use crate::data::value::{
+    BitSetWrapper, DataValue, NullOrder, Num, RegexWrapper, UuidWrapper, Validity, ValidityTs,
+};
 
 macro_rules! define_op {
-    ($name:ident, $min_arity:expr, $vararg:expr) => {
-        pub(crate) const $name: Op = Op {
-            name: stringify!($name),
-            min_arity: $min_arity,
-            vararg: $vararg,
-            inner: ::casey::lower!($name),
+    ($name:ident, $min_arity:expr, $vararg:expr $(; $($props:tt)*)?) => {
+        pub(crate) const $name: Op = {
+            #[allow(unused_mut)]
+            let mut op = Op {
+                name: stringify!($name),
+                min_arity: $min_arity,
+                vararg: $vararg,
+                inner: ::casey::lower!($name),
+                propagates_null: false,
+                arg_types: &[],
+                output_type: |_| ValueType::Any,
+            };
+            $(define_op!(@props op; $($props)*);)?
+            op
         };
     };
+    // `[T1, T2] -> T` declares the types of the arguments, the last one repeated for any further
+    // arguments, and that of the result, `-> fn f` computes the latter from the argument types
+    (@props $op:ident; [$($arg:ident),*] -> fn $output:expr $(; $($props:tt)*)?) => {
+        $op.arg_types = &[$(ValueType::$arg),*];
+        $op.output_type = $output;
+        $(define_op!(@props $op; $($props)*);)?
+    };
+    (@props $op:ident; [$($arg:ident),*] -> $output:ident $(; $($props:tt)*)?) => {
+        $op.arg_types = &[$(ValueType::$arg),*];
+        $op.output_type = |_| ValueType::$output;
+        $(define_op!(@props $op; $($props)*);)?
+    };
+    // the result is null as soon as any argument is null
+    (@props $op:ident; propagates_null $(; $($props:tt)*)?) => {
+        $op.propagates_null = true;
+        $(define_op!(@props $op; $($props)*);)?
+    };
 }
 
 fn ensure_same_value_type(a: &DataValue, b: &DataValue) -> Result<()> {
@@ -63,12 +98,23 @@ fn ensure_same_value_type(a: &DataValue, b: &DataValue) -> Result<()> {
     Ok(())
 }
 
-define_op!(OP_LIST, 0, true);
+define_op!(OP_LIST, 0, true; [Any] -> List);
 pub(crate) fn op_list(args: &[DataValue]) -> Result<DataValue> {
-    Ok(DataValue::List(args.to_vec()))
+    // elements given as `skip()` were turned into bottoms by the parser and are left out
+    Ok(DataValue::List(
+        args.iter()
+            .filter(|v| **v != DataValue::Bot)
+            .cloned()
+            .collect(),
+    ))
 }
 
-define_op!(OP_COALESCE, 0, true);
+define_op!(OP_SKIP, 0, false);
+pub(crate) fn op_skip(_args: &[DataValue]) -> Result<DataValue> {
+    bail!("'skip' can only be used as an element of a list")
+}
+
+define_op!(OP_COALESCE, 0, true; [Any] -> fn joined_type);
 pub(crate) fn op_coalesce(args: &[DataValue]) -> Result<DataValue> {
     for val in args {
         if *val != DataValue::Null {
@@ -78,21 +124,254 @@ pub(crate) fn op_coalesce(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Null)
 }
 
-define_op!(OP_EQ, 2, false);
+define_op!(OP_LAST_NON_NULL, 0, true; [Any] -> fn joined_type);
+pub(crate) fn op_last_non_null(args: &[DataValue]) -> Result<DataValue> {
+    Ok(args
+        .iter()
+        .rev()
+        .find(|val| **val != DataValue::Null)
+        .cloned()
+        .unwrap_or(DataValue::Null))
+}
+
+define_op!(OP_IF_NULL, 2, false; [Any] -> fn joined_type);
+pub(crate) fn op_if_null(args: &[DataValue]) -> Result<DataValue> {
+    Ok(match &args[0] {
+        DataValue::Null => args[1].clone(),
+        v => v.clone(),
+    })
+}
+
+define_op!(OP_IF_NOT_NULL, 2, false; [Any] -> fn second_arg_type);
+pub(crate) fn op_if_not_null(args: &[DataValue]) -> Result<DataValue> {
+    Ok(match &args[0] {
+        DataValue::Null => DataValue::Null,
+        _ => args[1].clone(),
+    })
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of non-list values compared by [values_eq] on this thread
+    pub(crate) static VALUES_EQ_COMPARISONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Tolerance for comparing two floats with `eq` and `neq`, see
+/// [Db::set_float_eq_tolerance](crate::Db::set_float_eq_tolerance). Two floats are equal if
+/// their difference is at most `absolute`, or at most `relative` times the larger magnitude.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FloatEqTolerance {
+    /// Largest difference allowed regardless of the magnitude of the floats
+    pub absolute: f64,
+    /// Largest difference allowed relative to the magnitude of the floats
+    pub relative: f64,
+}
+
+impl FloatEqTolerance {
+    fn floats_eq(&self, a: f64, b: f64) -> bool {
+        let diff = (a - b).abs();
+        diff <= self.absolute || diff <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+thread_local! {
+    static FLOAT_EQ_TOLERANCE: std::cell::Cell<Option<FloatEqTolerance>> = const { std::cell::Cell::new(None) };
+}
+
+/// The tolerance in effect on this thread, `None` if floats are compared exactly
+pub(crate) fn current_float_eq_tolerance() -> Option<FloatEqTolerance> {
+    FLOAT_EQ_TOLERANCE.with(|t| t.get())
+}
+
+/// Run `f` with float comparisons on this thread using `tolerance`, restoring the previous
+/// tolerance afterwards, also if `f` panics.
+pub(crate) fn with_float_eq_tolerance<T>(
+    tolerance: Option<FloatEqTolerance>,
+    f: impl FnOnce() -> T,
+) -> T {
+    struct Restore(Option<FloatEqTolerance>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            FLOAT_EQ_TOLERANCE.with(|t| t.set(self.0));
+        }
+    }
+    let _restore = Restore(FLOAT_EQ_TOLERANCE.with(|t| t.replace(tolerance)));
+    f()
+}
+
+/// What dividing by zero with `/`, `mod`, `floor_div` and `true_div` gives,
+/// see [Db::set_division_by_zero](crate::Db::set_division_by_zero).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DivisionByZero {
+    /// Raise an error if both operands are integers, and give a float as
+    /// [DivisionByZero::Infinity] does otherwise
+    #[default]
+    IntegerError,
+    /// Raise an error, for integer and float operands alike
+    Error,
+    /// Give null
+    Null,
+    /// Give a float as IEEE 754 division does: an infinity, or NaN for `0 / 0` and for `mod`
+    Infinity,
+}
+
+thread_local! {
+    static DIVISION_BY_ZERO: std::cell::Cell<DivisionByZero> = const { std::cell::Cell::new(DivisionByZero::IntegerError) };
+}
+
+/// The behaviour of division by zero in effect on this thread
+pub(crate) fn current_division_by_zero() -> DivisionByZero {
+    DIVISION_BY_ZERO.with(|m| m.get())
+}
+
+/// Run `f` with division by zero on this thread behaving as `mode` says, restoring the previous
+/// behaviour afterwards, also if `f` panics.
+pub(crate) fn with_division_by_zero<T>(mode: DivisionByZero, f: impl FnOnce() -> T) -> T {
+    struct Restore(DivisionByZero);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DIVISION_BY_ZERO.with(|m| m.set(self.0));
+        }
+    }
+    let _restore = Restore(DIVISION_BY_ZERO.with(|m| m.replace(mode)));
+    f()
+}
+
+/// The result of dividing `a` by zero `b` according to the mode in effect, `ieee` giving the
+/// float result
+fn divided_by_zero(a: &Num, b: &Num, ieee: impl FnOnce(f64, f64) -> f64) -> Result<DataValue> {
+    match current_division_by_zero() {
+        DivisionByZero::IntegerError if matches!((a, b), (Num::Int(_), Num::Int(_))) => {
+            bail!("division by zero")
+        }
+        DivisionByZero::Error => bail!("division by zero"),
+        DivisionByZero::Null => Ok(DataValue::Null),
+        DivisionByZero::IntegerError | DivisionByZero::Infinity => {
+            Ok(DataValue::from(ieee(a.get_float(), b.get_float())))
+        }
+    }
+}
+
+thread_local! {
+    static QUERY_SEED: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+/// The seed given by the `:seed` option of the query run on this thread, if any
+pub(crate) fn current_query_seed() -> Option<u64> {
+    QUERY_SEED.with(|s| s.get())
+}
+
+/// Run `f` with the random functions honouring `seed` on this thread, restoring the previous
+/// seed afterwards, also if `f` panics.
+pub(crate) fn with_query_seed<T>(seed: Option<u64>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<u64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            QUERY_SEED.with(|s| s.set(self.0));
+        }
+    }
+    let _restore = Restore(QUERY_SEED.with(|s| s.replace(seed)));
+    f()
+}
+
+/// A hasher giving the same hashes on every platform and with every version of Rust, unlike
+/// the one of the standard library. This is FNV-1a, with the bits of the result mixed as in
+/// MurmurHash3 so that they are all usable.
+#[derive(Clone, Copy)]
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        let mut h = self.0;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        h ^ (h >> 33)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    // integers are hashed as little-endian, lengths and enum discriminants as 64 bits
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+}
+
+/// The random numbers for `sample` and `shuffle`. Under a query seed they only depend on the
+/// seed and the list given, so that the same list gets the same result in every run.
+fn seeded_rng(list: &[DataValue]) -> StdRng {
+    match current_query_seed() {
+        None => StdRng::from_entropy(),
+        Some(seed) => {
+            let mut hasher = StableHasher::default();
+            seed.hash(&mut hasher);
+            list.hash(&mut hasher);
+            StdRng::seed_from_u64(hasher.finish())
+        }
+    }
+}
+
+/// The equality used by `eq` and `neq`: integers and floats are equal if they have the same
+/// value, also when nested inside lists and sets. Lists and sets of different lengths are
+/// unequal without any of their elements being compared. Two floats are compared with the
+/// tolerance set by [with_float_eq_tolerance], if any.
+pub(crate) fn values_eq(a: &DataValue, b: &DataValue) -> bool {
+    match (a, b) {
+        (DataValue::List(a), DataValue::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| values_eq(x, y))
+        }
+        (DataValue::Set(a), DataValue::Set(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| values_eq(x, y))
+        }
+        (a, b) => {
+            #[cfg(test)]
+            VALUES_EQ_COMPARISONS.with(|c| c.set(c.get() + 1));
+            match (a, b) {
+                (DataValue::Num(Num::Float(f)), DataValue::Num(Num::Int(i)))
+                | (DataValue::Num(Num::Int(i)), DataValue::Num(Num::Float(f))) => *i as f64 == *f,
+                (DataValue::Num(Num::Float(x)), DataValue::Num(Num::Float(y))) => {
+                    a == b
+                        || current_float_eq_tolerance()
+                            .is_some_and(|tolerance| tolerance.floats_eq(*x, *y))
+                }
+                (a, b) => a == b,
+            }
+        }
+    }
+}
+
+define_op!(OP_EQ, 2, false; [Any] -> Bool);
 pub(crate) fn op_eq(args: &[DataValue]) -> Result<DataValue> {
-    Ok(DataValue::from(match (&args[0], &args[1]) {
-        (DataValue::Num(Num::Float(f)), DataValue::Num(Num::Int(i)))
-        | (DataValue::Num(Num::Int(i)), DataValue::Num(Num::Float(f))) => *i as f64 == *f,
-        (a, b) => a == b,
-    }))
+    Ok(DataValue::from(values_eq(&args[0], &args[1])))
 }
 
-define_op!(OP_IS_UUID, 1, false);
+define_op!(OP_IS_UUID, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_uuid(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(args[0], DataValue::Uuid(_))))
 }
 
-define_op!(OP_IS_IN, 2, false);
+define_op!(OP_IS_IN, 2, false; [Any, List] -> Bool);
 pub(crate) fn op_is_in(args: &[DataValue]) -> Result<DataValue> {
     let left = &args[0];
     let right = args[1]
@@ -101,16 +380,37 @@ pub(crate) fn op_is_in(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(right.contains(left)))
 }
 
-define_op!(OP_NEQ, 2, false);
+define_op!(OP_NEQ, 2, false; [Any] -> Bool);
 pub(crate) fn op_neq(args: &[DataValue]) -> Result<DataValue> {
-    Ok(DataValue::from(match (&args[0], &args[1]) {
-        (DataValue::Num(Num::Float(f)), DataValue::Num(Num::Int(i)))
-        | (DataValue::Num(Num::Int(i)), DataValue::Num(Num::Float(f))) => *i as f64 != *f,
-        (a, b) => a != b,
-    }))
+    Ok(DataValue::from(!values_eq(&args[0], &args[1])))
+}
+
+// The tolerance used by `approx_eq` when none is given
+const DEFAULT_APPROX_EQ_EPSILON: f64 = 1e-9;
+
+define_op!(OP_APPROX_EQ, 2, true; [Number] -> Bool);
+pub(crate) fn op_approx_eq(args: &[DataValue]) -> Result<DataValue> {
+    ensure!(args.len() <= 3, "'approx_eq' takes at most 3 arguments");
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let a = args[0]
+        .get_float()
+        .ok_or_else(|| miette!("'approx_eq' requires numbers"))?;
+    let b = args[1]
+        .get_float()
+        .ok_or_else(|| miette!("'approx_eq' requires numbers"))?;
+    let epsilon = match args.get(2) {
+        None => DEFAULT_APPROX_EQ_EPSILON,
+        Some(v) => v
+            .get_float()
+            .ok_or_else(|| miette!("'approx_eq' requires a number as epsilon"))?,
+    };
+    // comparisons involving NaN are always false
+    Ok(DataValue::from((a - b).abs() <= epsilon))
 }
 
-define_op!(OP_GT, 2, false);
+define_op!(OP_GT, 2, false; [Any] -> Bool);
 pub(crate) fn op_gt(args: &[DataValue]) -> Result<DataValue> {
     ensure_same_value_type(&args[0], &args[1])?;
     Ok(DataValue::from(match (&args[0], &args[1]) {
@@ -120,7 +420,7 @@ pub(crate) fn op_gt(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_GE, 2, false);
+define_op!(OP_GE, 2, false; [Any] -> Bool);
 pub(crate) fn op_ge(args: &[DataValue]) -> Result<DataValue> {
     ensure_same_value_type(&args[0], &args[1])?;
     Ok(DataValue::from(match (&args[0], &args[1]) {
@@ -130,7 +430,7 @@ pub(crate) fn op_ge(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_LT, 2, false);
+define_op!(OP_LT, 2, false; [Any] -> Bool);
 pub(crate) fn op_lt(args: &[DataValue]) -> Result<DataValue> {
     ensure_same_value_type(&args[0], &args[1])?;
     Ok(DataValue::from(match (&args[0], &args[1]) {
@@ -140,7 +440,7 @@ pub(crate) fn op_lt(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_LE, 2, false);
+define_op!(OP_LE, 2, false; [Any] -> Bool);
 pub(crate) fn op_le(args: &[DataValue]) -> Result<DataValue> {
     ensure_same_value_type(&args[0], &args[1])?;
     Ok(DataValue::from(match (&args[0], &args[1]) {
@@ -150,25 +450,43 @@ pub(crate) fn op_le(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_ADD, 0, true);
+define_op!(OP_ADD, 0, true; [Number] -> fn numeric_type);
 pub(crate) fn op_add(args: &[DataValue]) -> Result<DataValue> {
     let mut i_accum = 0i64;
-    let mut f_accum = 0.0f64;
+    let mut f_accum = None;
     for arg in args {
         match arg {
             DataValue::Num(Num::Int(i)) => i_accum += i,
-            DataValue::Num(Num::Float(f)) => f_accum += f,
+            DataValue::Num(Num::Float(f)) => *f_accum.get_or_insert(0.0f64) += f,
             _ => bail!("addition requires numbers"),
         }
     }
-    if f_accum == 0.0f64 {
-        Ok(DataValue::Num(Num::Int(i_accum)))
-    } else {
-        Ok(DataValue::Num(Num::Float(i_accum as f64 + f_accum)))
+    match f_accum {
+        None => Ok(DataValue::from(i_accum)),
+        Some(f) => {
+            // any float makes the sum a float, the integers promoted as by `coerce_to`
+            let promoted = DataValue::from(i_accum).coerce_to(ValueType::Float)?;
+            Ok(DataValue::from(promoted.get_float().unwrap() + f))
+        }
     }
 }
 
-define_op!(OP_MAX, 1, true);
+define_op!(OP_ADD_CHAIN, 2, true; [Number] -> fn numeric_type);
+pub(crate) fn op_add_chain(args: &[DataValue]) -> Result<DataValue> {
+    // `((a + b) + c) + ...` evaluated in one go. Unlike `add`, which sums up the integers and the
+    // floats separately, this adds the arguments pairwise from the left, as the nested additions
+    // it replaces do, so that overflows and rounding are the same.
+    args[1..]
+        .iter()
+        .try_fold(args[0].clone(), |accum, arg| match (&accum, arg) {
+            (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
+                Ok(DataValue::Num(Num::Int(a + b)))
+            }
+            _ => op_add(&[accum, arg.clone()]),
+        })
+}
+
+define_op!(OP_MAX, 1, true; [Number] -> fn numeric_type);
 pub(crate) fn op_max(args: &[DataValue]) -> Result<DataValue> {
     let res = args
         .iter()
@@ -183,7 +501,7 @@ pub(crate) fn op_max(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_MIN, 1, true);
+define_op!(OP_MIN, 1, true; [Number] -> fn numeric_type);
 pub(crate) fn op_min(args: &[DataValue]) -> Result<DataValue> {
     let res = args
         .iter()
@@ -198,7 +516,27 @@ pub(crate) fn op_min(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_SUB, 2, false);
+define_op!(OP_GREATEST, 1, true; [Any] -> fn joined_type);
+pub(crate) fn op_greatest(args: &[DataValue]) -> Result<DataValue> {
+    Ok(args
+        .iter()
+        .filter(|v| **v != DataValue::Null)
+        .max()
+        .cloned()
+        .unwrap_or(DataValue::Null))
+}
+
+define_op!(OP_LEAST, 1, true; [Any] -> fn joined_type);
+pub(crate) fn op_least(args: &[DataValue]) -> Result<DataValue> {
+    Ok(args
+        .iter()
+        .filter(|v| **v != DataValue::Null)
+        .min()
+        .cloned()
+        .unwrap_or(DataValue::Null))
+}
+
+define_op!(OP_SUB, 2, false; [Number] -> fn numeric_type);
 pub(crate) fn op_sub(args: &[DataValue]) -> Result<DataValue> {
     Ok(match (&args[0], &args[1]) {
         (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
@@ -207,54 +545,149 @@ pub(crate) fn op_sub(args: &[DataValue]) -> Result<DataValue> {
         (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Float(b))) => {
             DataValue::Num(Num::Float(*a - *b))
         }
-        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Float(b))) => {
-            DataValue::Num(Num::Float((*a as f64) - b))
-        }
-        (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Int(b))) => {
-            DataValue::Num(Num::Float(a - (*b as f64)))
-        }
+        (a @ DataValue::Num(_), b @ DataValue::Num(_)) => op_sub(&[
+            a.coerce_to(ValueType::Float)?,
+            b.coerce_to(ValueType::Float)?,
+        ])?,
         _ => bail!("subtraction requires numbers"),
     })
 }
 
-define_op!(OP_MUL, 0, true);
+define_op!(OP_MUL, 0, true; [Number] -> fn numeric_type);
 pub(crate) fn op_mul(args: &[DataValue]) -> Result<DataValue> {
     let mut i_accum = 1i64;
-    let mut f_accum = 1.0f64;
+    let mut f_accum = None;
     for arg in args {
         match arg {
             DataValue::Num(Num::Int(i)) => i_accum *= i,
-            DataValue::Num(Num::Float(f)) => f_accum *= f,
+            DataValue::Num(Num::Float(f)) => *f_accum.get_or_insert(1.0f64) *= f,
             _ => bail!("multiplication requires numbers"),
         }
     }
-    if f_accum == 1.0f64 {
-        Ok(DataValue::Num(Num::Int(i_accum)))
-    } else {
-        Ok(DataValue::Num(Num::Float(i_accum as f64 * f_accum)))
+    match f_accum {
+        None => Ok(DataValue::from(i_accum)),
+        Some(f) => {
+            // any float makes the product a float, the integers promoted as by `coerce_to`
+            let promoted = DataValue::from(i_accum).coerce_to(ValueType::Float)?;
+            Ok(DataValue::from(promoted.get_float().unwrap() * f))
+        }
+    }
+}
+
+define_op!(OP_MUL_CHAIN, 2, true; [Number] -> fn numeric_type);
+pub(crate) fn op_mul_chain(args: &[DataValue]) -> Result<DataValue> {
+    // `((a * b) * c) * ...` evaluated in one go, multiplying pairwise from the left
+    // as `op_add_chain` does for additions.
+    args[1..]
+        .iter()
+        .try_fold(args[0].clone(), |accum, arg| match (&accum, arg) {
+            (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
+                Ok(DataValue::Num(Num::Int(a * b)))
+            }
+            _ => op_mul(&[accum, arg.clone()]),
+        })
+}
+
+define_op!(OP_ADD_NN, 0, true; [Number] -> fn numeric_type);
+pub(crate) fn op_add_nn(args: &[DataValue]) -> Result<DataValue> {
+    let args = args
+        .iter()
+        .filter(|v| **v != DataValue::Null)
+        .cloned()
+        .collect_vec();
+    op_add(&args)
+}
+
+define_op!(OP_MUL_NN, 0, true; [Number] -> fn numeric_type);
+pub(crate) fn op_mul_nn(args: &[DataValue]) -> Result<DataValue> {
+    let args = args
+        .iter()
+        .filter(|v| **v != DataValue::Null)
+        .cloned()
+        .collect_vec();
+    op_mul(&args)
+}
+
+define_op!(OP_CONCAT_NN, 0, true; [Any] -> fn concat_type);
+pub(crate) fn op_concat_nn(args: &[DataValue]) -> Result<DataValue> {
+    let args = args
+        .iter()
+        .filter(|v| **v != DataValue::Null)
+        .cloned()
+        .collect_vec();
+    if args.is_empty() {
+        return Ok(DataValue::from(""));
     }
+    op_concat(&args)
 }
 
-define_op!(OP_DIV, 2, false);
+define_op!(OP_DIV, 2, false; [Number] -> Float);
 pub(crate) fn op_div(args: &[DataValue]) -> Result<DataValue> {
     Ok(match (&args[0], &args[1]) {
-        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
-            DataValue::Num(Num::Float((*a as f64) / (*b as f64)))
+        (DataValue::Num(a), DataValue::Num(b)) if is_zero(&args[1]) => {
+            divided_by_zero(a, b, |a, b| a / b)?
         }
         (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Float(b))) => {
             DataValue::Num(Num::Float(*a / *b))
         }
-        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Float(b))) => {
-            DataValue::Num(Num::Float((*a as f64) / b))
+        // integer division gives floats as well
+        (a @ DataValue::Num(_), b @ DataValue::Num(_)) => op_div(&[
+            a.coerce_to(ValueType::Float)?,
+            b.coerce_to(ValueType::Float)?,
+        ])?,
+        _ => bail!("division requires numbers"),
+    })
+}
+
+fn is_zero(v: &DataValue) -> bool {
+    match v {
+        DataValue::Num(Num::Int(i)) => *i == 0,
+        DataValue::Num(Num::Float(f)) => *f == 0.,
+        _ => false,
+    }
+}
+
+define_op!(OP_TRUE_DIV, 2, false; [Number] -> Float; propagates_null);
+pub(crate) fn op_true_div(args: &[DataValue]) -> Result<DataValue> {
+    // same as `/`, which always gives floats, but with nulls propagated
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    op_div(args)
+}
+
+define_op!(OP_FLOOR_DIV, 2, false; [Number] -> Int; propagates_null);
+pub(crate) fn op_floor_div(args: &[DataValue]) -> Result<DataValue> {
+    // integer division rounding toward negative infinity, so that `floor_div(-7, 2)` is `-4`
+    Ok(match (&args[0], &args[1]) {
+        (DataValue::Null, _) | (_, DataValue::Null) => DataValue::Null,
+        (DataValue::Num(a), DataValue::Num(b)) if is_zero(&args[1]) => {
+            divided_by_zero(a, b, |a, b| (a / b).floor())?
+        }
+        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
+            let q = a
+                .checked_div(*b)
+                .ok_or_else(|| miette!("integer overflow in 'floor_div'"))?;
+            if a % b != 0 && (*a < 0) != (*b < 0) {
+                DataValue::from(q - 1)
+            } else {
+                DataValue::from(q)
+            }
         }
-        (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Int(b))) => {
-            DataValue::Num(Num::Float(a / (*b as f64)))
+        (DataValue::Num(a), DataValue::Num(b)) => {
+            let q = (a.get_float() / b.get_float()).floor();
+            ensure!(
+                q.is_finite() && q >= i64::MIN as f64 && q < i64::MAX as f64,
+                "'floor_div' cannot represent {} as an integer",
+                q
+            );
+            DataValue::from(q as i64)
         }
-        _ => bail!("division requires numbers"),
+        _ => bail!("'floor_div' requires numbers"),
     })
 }
 
-define_op!(OP_MINUS, 1, false);
+define_op!(OP_MINUS, 1, false; [Number] -> fn numeric_type);
 pub(crate) fn op_minus(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(-(*i))),
@@ -263,7 +696,7 @@ pub(crate) fn op_minus(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_ABS, 1, false);
+define_op!(OP_ABS, 1, false; [Number] -> fn numeric_type);
 pub(crate) fn op_abs(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(i.abs())),
@@ -272,7 +705,7 @@ pub(crate) fn op_abs(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_SIGNUM, 1, false);
+define_op!(OP_SIGNUM, 1, false; [Number] -> fn signum_type);
 pub(crate) fn op_signum(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(i.signum())),
@@ -291,7 +724,7 @@ pub(crate) fn op_signum(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_FLOOR, 1, false);
+define_op!(OP_FLOOR, 1, false; [Number] -> fn numeric_type);
 pub(crate) fn op_floor(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(*i)),
@@ -300,7 +733,7 @@ pub(crate) fn op_floor(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_CEIL, 1, false);
+define_op!(OP_CEIL, 1, false; [Number] -> fn numeric_type);
 pub(crate) fn op_ceil(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(*i)),
@@ -309,7 +742,7 @@ pub(crate) fn op_ceil(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_ROUND, 1, false);
+define_op!(OP_ROUND, 1, false; [Number] -> fn numeric_type);
 pub(crate) fn op_round(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Num(Num::Int(i)) => DataValue::Num(Num::Int(*i)),
@@ -318,7 +751,7 @@ pub(crate) fn op_round(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_EXP, 1, false);
+define_op!(OP_EXP, 1, false; [Number] -> Float);
 pub(crate) fn op_exp(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -328,7 +761,7 @@ pub(crate) fn op_exp(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.exp())))
 }
 
-define_op!(OP_EXP2, 1, false);
+define_op!(OP_EXP2, 1, false; [Number] -> Float);
 pub(crate) fn op_exp2(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -338,7 +771,7 @@ pub(crate) fn op_exp2(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.exp2())))
 }
 
-define_op!(OP_LN, 1, false);
+define_op!(OP_LN, 1, false; [Number] -> Float);
 pub(crate) fn op_ln(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -348,7 +781,7 @@ pub(crate) fn op_ln(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.ln())))
 }
 
-define_op!(OP_LOG2, 1, false);
+define_op!(OP_LOG2, 1, false; [Number] -> Float);
 pub(crate) fn op_log2(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -358,7 +791,7 @@ pub(crate) fn op_log2(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.log2())))
 }
 
-define_op!(OP_LOG10, 1, false);
+define_op!(OP_LOG10, 1, false; [Number] -> Float);
 pub(crate) fn op_log10(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -368,7 +801,7 @@ pub(crate) fn op_log10(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.log10())))
 }
 
-define_op!(OP_SIN, 1, false);
+define_op!(OP_SIN, 1, false; [Number] -> Float);
 pub(crate) fn op_sin(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -378,7 +811,7 @@ pub(crate) fn op_sin(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.sin())))
 }
 
-define_op!(OP_COS, 1, false);
+define_op!(OP_COS, 1, false; [Number] -> Float);
 pub(crate) fn op_cos(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -388,7 +821,7 @@ pub(crate) fn op_cos(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.cos())))
 }
 
-define_op!(OP_TAN, 1, false);
+define_op!(OP_TAN, 1, false; [Number] -> Float);
 pub(crate) fn op_tan(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -398,7 +831,7 @@ pub(crate) fn op_tan(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.tan())))
 }
 
-define_op!(OP_ASIN, 1, false);
+define_op!(OP_ASIN, 1, false; [Number] -> Float);
 pub(crate) fn op_asin(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -408,7 +841,7 @@ pub(crate) fn op_asin(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.asin())))
 }
 
-define_op!(OP_ACOS, 1, false);
+define_op!(OP_ACOS, 1, false; [Number] -> Float);
 pub(crate) fn op_acos(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -418,7 +851,7 @@ pub(crate) fn op_acos(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.acos())))
 }
 
-define_op!(OP_ATAN, 1, false);
+define_op!(OP_ATAN, 1, false; [Number] -> Float);
 pub(crate) fn op_atan(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -428,7 +861,7 @@ pub(crate) fn op_atan(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.atan())))
 }
 
-define_op!(OP_ATAN2, 2, false);
+define_op!(OP_ATAN2, 2, false; [Number] -> Float);
 pub(crate) fn op_atan2(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -444,7 +877,7 @@ pub(crate) fn op_atan2(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.atan2(b))))
 }
 
-define_op!(OP_SINH, 1, false);
+define_op!(OP_SINH, 1, false; [Number] -> Float);
 pub(crate) fn op_sinh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -454,7 +887,7 @@ pub(crate) fn op_sinh(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.sinh())))
 }
 
-define_op!(OP_COSH, 1, false);
+define_op!(OP_COSH, 1, false; [Number] -> Float);
 pub(crate) fn op_cosh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -464,17 +897,18 @@ pub(crate) fn op_cosh(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.cosh())))
 }
 
-define_op!(OP_TANH, 1, false);
+define_op!(OP_TANH, 1, false; [Number] -> Float; propagates_null);
 pub(crate) fn op_tanh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
         DataValue::Num(Num::Float(f)) => *f,
+        DataValue::Null => return Ok(DataValue::Null),
         _ => bail!("'tanh' requires numbers"),
     };
     Ok(DataValue::Num(Num::Float(a.tanh())))
 }
 
-define_op!(OP_ASINH, 1, false);
+define_op!(OP_ASINH, 1, false; [Number] -> Float);
 pub(crate) fn op_asinh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -484,7 +918,7 @@ pub(crate) fn op_asinh(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.asinh())))
 }
 
-define_op!(OP_ACOSH, 1, false);
+define_op!(OP_ACOSH, 1, false; [Number] -> Float);
 pub(crate) fn op_acosh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -494,7 +928,7 @@ pub(crate) fn op_acosh(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.acosh())))
 }
 
-define_op!(OP_ATANH, 1, false);
+define_op!(OP_ATANH, 1, false; [Number] -> Float);
 pub(crate) fn op_atanh(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -504,7 +938,36 @@ pub(crate) fn op_atanh(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.atanh())))
 }
 
-define_op!(OP_POW, 2, false);
+define_op!(OP_SIGMOID, 1, false; [Number] -> Float; propagates_null);
+pub(crate) fn op_sigmoid(args: &[DataValue]) -> Result<DataValue> {
+    let a = match &args[0] {
+        DataValue::Num(Num::Int(i)) => *i as f64,
+        DataValue::Num(Num::Float(f)) => *f,
+        DataValue::Null => return Ok(DataValue::Null),
+        _ => bail!("'sigmoid' requires numbers"),
+    };
+    // only ever exponentiate non-positive numbers so that `exp` cannot overflow
+    let res = if a >= 0. {
+        1. / (1. + (-a).exp())
+    } else {
+        let e = a.exp();
+        e / (1. + e)
+    };
+    Ok(DataValue::Num(Num::Float(res)))
+}
+
+define_op!(OP_RELU, 1, false; [Number] -> Float; propagates_null);
+pub(crate) fn op_relu(args: &[DataValue]) -> Result<DataValue> {
+    let a = match &args[0] {
+        DataValue::Num(Num::Int(i)) => *i as f64,
+        DataValue::Num(Num::Float(f)) => *f,
+        DataValue::Null => return Ok(DataValue::Null),
+        _ => bail!("'relu' requires numbers"),
+    };
+    Ok(DataValue::Num(Num::Float(if a > 0. { a } else { 0. })))
+}
+
+define_op!(OP_POW, 2, false; [Number] -> Float);
 pub(crate) fn op_pow(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Num(Num::Int(i)) => *i as f64,
@@ -519,26 +982,99 @@ pub(crate) fn op_pow(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::Num(Num::Float(a.powf(b))))
 }
 
-define_op!(OP_MOD, 2, false);
+define_op!(OP_MOD, 2, false; [Number] -> fn numeric_type);
 pub(crate) fn op_mod(args: &[DataValue]) -> Result<DataValue> {
     Ok(match (&args[0], &args[1]) {
+        (DataValue::Num(a), DataValue::Num(b)) if is_zero(&args[1]) => {
+            divided_by_zero(a, b, |a, b| a % b)?
+        }
         (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => {
             DataValue::Num(Num::Int(a.rem(b)))
         }
         (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Float(b))) => {
             DataValue::Num(Num::Float(a.rem(*b)))
         }
-        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Float(b))) => {
-            DataValue::Num(Num::Float((*a as f64).rem(b)))
+        (a @ DataValue::Num(_), b @ DataValue::Num(_)) => op_mod(&[
+            a.coerce_to(ValueType::Float)?,
+            b.coerce_to(ValueType::Float)?,
+        ])?,
+        _ => bail!("'mod' requires numbers"),
+    })
+}
+
+/// Compares two numbers, exactly for integers, which may be equal as floats when large.
+fn cmp_nums(a: &DataValue, b: &DataValue) -> Option<Ordering> {
+    match (a, b) {
+        (DataValue::Num(Num::Int(a)), DataValue::Num(Num::Int(b))) => Some(a.cmp(b)),
+        _ => a.get_float()?.partial_cmp(&b.get_float()?),
+    }
+}
+
+fn range_args(name: &str, args: &[DataValue], allow_empty: bool) -> Result<(f64, f64, f64)> {
+    let mut nums = args.iter().map(|a| {
+        a.get_float()
+            .ok_or_else(|| miette!("'{}' requires numbers", name))
+    });
+    let x = nums.next().unwrap()?;
+    let lo = nums.next().unwrap()?;
+    let hi = nums.next().unwrap()?;
+    match cmp_nums(&args[1], &args[2]) {
+        Some(Ordering::Less) => {}
+        Some(Ordering::Equal) if allow_empty => {}
+        _ => bail!(
+            "'{}' requires the lower bound to be below the upper bound",
+            name
+        ),
+    }
+    Ok((x, lo, hi))
+}
+
+define_op!(OP_CLAMP, 3, false; [Number] -> fn numeric_type; propagates_null);
+pub(crate) fn op_clamp(args: &[DataValue]) -> Result<DataValue> {
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    range_args("clamp", args, true)?;
+    Ok(if cmp_nums(&args[0], &args[1]) == Some(Ordering::Less) {
+        args[1].clone()
+    } else if cmp_nums(&args[0], &args[2]) == Some(Ordering::Greater) {
+        args[2].clone()
+    } else {
+        args[0].clone()
+    })
+}
+
+define_op!(OP_WRAP, 3, false; [Number] -> fn numeric_type; propagates_null);
+pub(crate) fn op_wrap(args: &[DataValue]) -> Result<DataValue> {
+    // Wraps `x` into the half-open range `[lo, hi)`, so that `wrap(370, 0, 360)` is `10`
+    // and `wrap(-10, 0, 360)` is `350`.
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let (x, lo, hi) = range_args("wrap", args, false)?;
+    Ok(match (&args[0], &args[1], &args[2]) {
+        (
+            DataValue::Num(Num::Int(x)),
+            DataValue::Num(Num::Int(lo)),
+            DataValue::Num(Num::Int(hi)),
+        ) => {
+            // widened so that the differences cannot overflow
+            let (x, lo, hi) = (*x as i128, *lo as i128, *hi as i128);
+            DataValue::from((lo + (x - lo).rem_euclid(hi - lo)) as i64)
         }
-        (DataValue::Num(Num::Float(a)), DataValue::Num(Num::Int(b))) => {
-            DataValue::Num(Num::Float(a.rem(*b as f64)))
+        _ => {
+            let width = hi - lo;
+            let mut r = (x - lo).rem_euclid(width);
+            // `rem_euclid` may round up to `width` for tiny negative offsets
+            if r >= width {
+                r = 0.;
+            }
+            DataValue::from(lo + r)
         }
-        _ => bail!("'mod' requires numbers"),
     })
 }
 
-define_op!(OP_AND, 0, true);
+define_op!(OP_AND, 0, true; [Bool] -> Bool);
 pub(crate) fn op_and(args: &[DataValue]) -> Result<DataValue> {
     for arg in args {
         if !arg
@@ -551,7 +1087,7 @@ pub(crate) fn op_and(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(true))
 }
 
-define_op!(OP_OR, 0, true);
+define_op!(OP_OR, 0, true; [Bool] -> Bool);
 pub(crate) fn op_or(args: &[DataValue]) -> Result<DataValue> {
     for arg in args {
         if arg
@@ -564,7 +1100,7 @@ pub(crate) fn op_or(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(false))
 }
 
-define_op!(OP_NEGATE, 1, false);
+define_op!(OP_NEGATE, 1, false; [Bool] -> Bool);
 pub(crate) fn op_negate(args: &[DataValue]) -> Result<DataValue> {
     if let DataValue::Bool(b) = &args[0] {
         Ok(DataValue::from(!*b))
@@ -573,7 +1109,7 @@ pub(crate) fn op_negate(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_BIT_AND, 2, false);
+define_op!(OP_BIT_AND, 2, false; [Bytes] -> Bytes);
 pub(crate) fn op_bit_and(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Bytes(left), DataValue::Bytes(right)) => {
@@ -591,7 +1127,7 @@ pub(crate) fn op_bit_and(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_BIT_OR, 2, false);
+define_op!(OP_BIT_OR, 2, false; [Bytes] -> Bytes);
 pub(crate) fn op_bit_or(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Bytes(left), DataValue::Bytes(right)) => {
@@ -609,7 +1145,7 @@ pub(crate) fn op_bit_or(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_BIT_NOT, 1, false);
+define_op!(OP_BIT_NOT, 1, false; [Bytes] -> Bytes);
 pub(crate) fn op_bit_not(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Bytes(arg) => {
@@ -623,7 +1159,7 @@ pub(crate) fn op_bit_not(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_BIT_XOR, 2, false);
+define_op!(OP_BIT_XOR, 2, false; [Bytes] -> Bytes);
 pub(crate) fn op_bit_xor(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Bytes(left), DataValue::Bytes(right)) => {
@@ -641,7 +1177,7 @@ pub(crate) fn op_bit_xor(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_UNPACK_BITS, 1, false);
+define_op!(OP_UNPACK_BITS, 1, false; [Bytes] -> List);
 pub(crate) fn op_unpack_bits(args: &[DataValue]) -> Result<DataValue> {
     if let DataValue::Bytes(bs) = &args[0] {
         let mut ret = vec![false; bs.len() * 8];
@@ -663,7 +1199,7 @@ pub(crate) fn op_unpack_bits(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_PACK_BITS, 1, false);
+define_op!(OP_PACK_BITS, 1, false; [List] -> Bytes);
 pub(crate) fn op_pack_bits(args: &[DataValue]) -> Result<DataValue> {
     if let DataValue::List(v) = &args[0] {
         let l = (v.len() as f64 / 8.).ceil() as usize;
@@ -700,7 +1236,7 @@ pub(crate) fn op_pack_bits(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_CONCAT, 1, true);
+define_op!(OP_CONCAT, 1, true; [Any] -> fn concat_type);
 pub(crate) fn op_concat(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(_) => {
@@ -731,7 +1267,7 @@ pub(crate) fn op_concat(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_STR_INCLUDES, 2, false);
+define_op!(OP_STR_INCLUDES, 2, false; [String] -> Bool);
 pub(crate) fn op_str_includes(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Str(l), DataValue::Str(r)) => Ok(DataValue::from(l.find(r as &str).is_some())),
@@ -739,7 +1275,22 @@ pub(crate) fn op_str_includes(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_LOWERCASE, 1, false);
+define_op!(OP_COUNT_MATCHES, 2, false; [String] -> Int; propagates_null);
+pub(crate) fn op_count_matches(args: &[DataValue]) -> Result<DataValue> {
+    match (&args[0], &args[1]) {
+        (DataValue::Null, _) | (_, DataValue::Null) => Ok(DataValue::Null),
+        (DataValue::Str(s), DataValue::Str(needle)) => {
+            ensure!(
+                !needle.is_empty(),
+                "'count_matches' requires a non-empty string to count"
+            );
+            Ok(DataValue::from(s.matches(needle as &str).count() as i64))
+        }
+        _ => bail!("'count_matches' requires strings"),
+    }
+}
+
+define_op!(OP_LOWERCASE, 1, false; [String] -> String);
 pub(crate) fn op_lowercase(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => Ok(DataValue::from(s.to_lowercase())),
@@ -747,7 +1298,7 @@ pub(crate) fn op_lowercase(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_UPPERCASE, 1, false);
+define_op!(OP_UPPERCASE, 1, false; [String] -> String);
 pub(crate) fn op_uppercase(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => Ok(DataValue::from(s.to_uppercase())),
@@ -755,7 +1306,65 @@ pub(crate) fn op_uppercase(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_TRIM, 1, false);
+define_op!(OP_CAPITALIZE, 1, false; [String] -> String; propagates_null);
+pub(crate) fn op_capitalize(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Str(s) => {
+            let mut chars = s.chars();
+            Ok(DataValue::from(match chars.next() {
+                None => String::new(),
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+            }))
+        }
+        DataValue::Null => Ok(DataValue::Null),
+        _ => bail!("'capitalize' requires strings"),
+    }
+}
+
+define_op!(OP_TITLE_CASE, 1, false; [String] -> String; propagates_null);
+pub(crate) fn op_title_case(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Str(s) => {
+            // words are delimited by whitespace only, so `o'neil` becomes `O'neil`
+            let mut ret = String::with_capacity(s.len());
+            let mut at_word_start = true;
+            for c in s.chars() {
+                if at_word_start {
+                    ret.extend(c.to_uppercase());
+                } else {
+                    ret.push(c);
+                }
+                at_word_start = c.is_whitespace();
+            }
+            Ok(DataValue::from(ret))
+        }
+        DataValue::Null => Ok(DataValue::Null),
+        _ => bail!("'title_case' requires strings"),
+    }
+}
+
+define_op!(OP_SWAP_CASE, 1, false; [String] -> String; propagates_null);
+pub(crate) fn op_swap_case(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Str(s) => {
+            let mut ret = String::with_capacity(s.len());
+            for c in s.chars() {
+                if c.is_uppercase() {
+                    ret.extend(c.to_lowercase());
+                } else if c.is_lowercase() {
+                    ret.extend(c.to_uppercase());
+                } else {
+                    ret.push(c);
+                }
+            }
+            Ok(DataValue::from(ret))
+        }
+        DataValue::Null => Ok(DataValue::Null),
+        _ => bail!("'swap_case' requires strings"),
+    }
+}
+
+define_op!(OP_TRIM, 1, false; [String] -> String);
 pub(crate) fn op_trim(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => Ok(DataValue::from(s.trim())),
@@ -763,7 +1372,7 @@ pub(crate) fn op_trim(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_TRIM_START, 1, false);
+define_op!(OP_TRIM_START, 1, false; [String] -> String);
 pub(crate) fn op_trim_start(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => Ok(DataValue::from(s.trim_start())),
@@ -771,7 +1380,7 @@ pub(crate) fn op_trim_start(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_TRIM_END, 1, false);
+define_op!(OP_TRIM_END, 1, false; [String] -> String);
 pub(crate) fn op_trim_end(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => Ok(DataValue::from(s.trim_end())),
@@ -779,7 +1388,7 @@ pub(crate) fn op_trim_end(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_STARTS_WITH, 2, false);
+define_op!(OP_STARTS_WITH, 2, false; [String] -> Bool);
 pub(crate) fn op_starts_with(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Str(s) => s,
@@ -792,7 +1401,7 @@ pub(crate) fn op_starts_with(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(a.starts_with(b as &str)))
 }
 
-define_op!(OP_ENDS_WITH, 2, false);
+define_op!(OP_ENDS_WITH, 2, false; [String] -> Bool);
 pub(crate) fn op_ends_with(args: &[DataValue]) -> Result<DataValue> {
     let a = match &args[0] {
         DataValue::Str(s) => s,
@@ -805,7 +1414,7 @@ pub(crate) fn op_ends_with(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(a.ends_with(b as &str)))
 }
 
-define_op!(OP_REGEX, 1, false);
+define_op!(OP_REGEX, 1, false; [String] -> Regex);
 pub(crate) fn op_regex(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         r @ DataValue::Regex(_) => r.clone(),
@@ -818,7 +1427,7 @@ pub(crate) fn op_regex(args: &[DataValue]) -> Result<DataValue> {
     })
 }
 
-define_op!(OP_REGEX_MATCHES, 2, false);
+define_op!(OP_REGEX_MATCHES, 2, false; [String, Any] -> Bool);
 pub(crate) fn op_regex_matches(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Str(s), DataValue::Regex(r)) => Ok(DataValue::from(r.0.is_match(s))),
@@ -826,7 +1435,7 @@ pub(crate) fn op_regex_matches(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_REGEX_REPLACE, 3, false);
+define_op!(OP_REGEX_REPLACE, 3, false; [String, Any, String] -> String);
 pub(crate) fn op_regex_replace(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1], &args[2]) {
         (DataValue::Str(s), DataValue::Regex(r), DataValue::Str(rp)) => {
@@ -836,7 +1445,7 @@ pub(crate) fn op_regex_replace(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_REGEX_REPLACE_ALL, 3, false);
+define_op!(OP_REGEX_REPLACE_ALL, 3, false; [String, Any, String] -> String);
 pub(crate) fn op_regex_replace_all(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1], &args[2]) {
         (DataValue::Str(s), DataValue::Regex(r), DataValue::Str(rp)) => {
@@ -846,7 +1455,7 @@ pub(crate) fn op_regex_replace_all(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_REGEX_EXTRACT, 2, false);
+define_op!(OP_REGEX_EXTRACT, 2, false; [String, Any] -> List);
 pub(crate) fn op_regex_extract(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Str(s), DataValue::Regex(r)) => {
@@ -860,7 +1469,7 @@ pub(crate) fn op_regex_extract(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_REGEX_EXTRACT_FIRST, 2, false);
+define_op!(OP_REGEX_EXTRACT_FIRST, 2, false; [String, Any] -> String);
 pub(crate) fn op_regex_extract_first(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
         (DataValue::Str(s), DataValue::Regex(r)) => {
@@ -871,12 +1480,12 @@ pub(crate) fn op_regex_extract_first(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_IS_NULL, 1, false);
+define_op!(OP_IS_NULL, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_null(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(args[0], DataValue::Null)))
 }
 
-define_op!(OP_IS_INT, 1, false);
+define_op!(OP_IS_INT, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_int(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(
         args[0],
@@ -884,7 +1493,7 @@ pub(crate) fn op_is_int(args: &[DataValue]) -> Result<DataValue> {
     )))
 }
 
-define_op!(OP_IS_FLOAT, 1, false);
+define_op!(OP_IS_FLOAT, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_float(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(
         args[0],
@@ -892,7 +1501,7 @@ pub(crate) fn op_is_float(args: &[DataValue]) -> Result<DataValue> {
     )))
 }
 
-define_op!(OP_IS_NUM, 1, false);
+define_op!(OP_IS_NUM, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_num(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(
         args[0],
@@ -900,7 +1509,7 @@ pub(crate) fn op_is_num(args: &[DataValue]) -> Result<DataValue> {
     )))
 }
 
-define_op!(OP_IS_FINITE, 1, false);
+define_op!(OP_IS_FINITE, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_finite(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(match &args[0] {
         DataValue::Num(Num::Int(_)) => true,
@@ -909,7 +1518,7 @@ pub(crate) fn op_is_finite(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_IS_INFINITE, 1, false);
+define_op!(OP_IS_INFINITE, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_infinite(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(match &args[0] {
         DataValue::Num(Num::Float(f)) => f.is_infinite(),
@@ -917,7 +1526,7 @@ pub(crate) fn op_is_infinite(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_IS_NAN, 1, false);
+define_op!(OP_IS_NAN, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_nan(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(match &args[0] {
         DataValue::Num(Num::Float(f)) => f.is_nan(),
@@ -925,12 +1534,12 @@ pub(crate) fn op_is_nan(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_IS_STRING, 1, false);
+define_op!(OP_IS_STRING, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_string(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(args[0], DataValue::Str(_))))
 }
 
-define_op!(OP_IS_LIST, 1, false);
+define_op!(OP_IS_LIST, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_list(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(
         args[0],
@@ -938,7 +1547,7 @@ pub(crate) fn op_is_list(args: &[DataValue]) -> Result<DataValue> {
     )))
 }
 
-define_op!(OP_APPEND, 2, false);
+define_op!(OP_APPEND, 2, false; [List, Any] -> List);
 pub(crate) fn op_append(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::List(l) => {
@@ -955,7 +1564,7 @@ pub(crate) fn op_append(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_PREPEND, 2, false);
+define_op!(OP_PREPEND, 2, false; [List, Any] -> List);
 pub(crate) fn op_prepend(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::List(pl) => {
@@ -972,15 +1581,16 @@ pub(crate) fn op_prepend(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_IS_BYTES, 1, false);
+define_op!(OP_IS_BYTES, 1, false; [Any] -> Bool);
 pub(crate) fn op_is_bytes(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(matches!(args[0], DataValue::Bytes(_))))
 }
 
-define_op!(OP_LENGTH, 1, false);
+define_op!(OP_LENGTH, 1, false; [Any] -> Int);
 pub(crate) fn op_length(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(match &args[0] {
         DataValue::Set(s) => s.len() as i64,
+        DataValue::BitSet(bs) => bs.0.len() as i64,
         DataValue::List(l) => l.len() as i64,
         DataValue::Str(s) => s.chars().count() as i64,
         DataValue::Bytes(b) => b.len() as i64,
@@ -988,42 +1598,438 @@ pub(crate) fn op_length(args: &[DataValue]) -> Result<DataValue> {
     }))
 }
 
-define_op!(OP_UNICODE_NORMALIZE, 2, false);
+define_op!(OP_UNICODE_NORMALIZE, 2, false; [String] -> String);
 pub(crate) fn op_unicode_normalize(args: &[DataValue]) -> Result<DataValue> {
     match (&args[0], &args[1]) {
-        (DataValue::Str(s), DataValue::Str(n)) => Ok(DataValue::Str(match n as &str {
-            "nfc" => s.nfc().collect(),
-            "nfd" => s.nfd().collect(),
-            "nfkc" => s.nfkc().collect(),
-            "nfkd" => s.nfkd().collect(),
-            u => bail!("unknown normalization {} for 'unicode_normalize'", u),
-        })),
+        (DataValue::Str(s), DataValue::Str(n)) => {
+            Ok(DataValue::Str(match &n.to_ascii_lowercase() as &str {
+                "nfc" => s.nfc().collect(),
+                "nfd" => s.nfd().collect(),
+                "nfkc" => s.nfkc().collect(),
+                "nfkd" => s.nfkd().collect(),
+                u => bail!("unknown normalization {} for 'unicode_normalize'", u),
+            }))
+        }
+        (DataValue::Null, DataValue::Str(_)) => Ok(DataValue::Null),
         _ => bail!("'unicode_normalize' requires strings"),
     }
 }
 
-define_op!(OP_SORTED, 1, false);
+define_op!(OP_STRIP_ACCENTS, 1, false; [String] -> String; propagates_null);
+pub(crate) fn op_strip_accents(args: &[DataValue]) -> Result<DataValue> {
+    // Removes diacritics, e.g. `é` becomes `e`, leaving other characters as they are
+    match &args[0] {
+        DataValue::Str(s) => Ok(DataValue::Str(
+            s.nfd()
+                .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+                .nfc()
+                .collect(),
+        )),
+        DataValue::Null => Ok(DataValue::Null),
+        _ => bail!("'strip_accents' requires strings"),
+    }
+}
+
+// Latin letters and punctuation that do not decompose into ASCII characters
+const ASCII_REPLACEMENTS: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('æ', "ae"),
+    ('Æ', "AE"),
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('ø', "o"),
+    ('Ø', "O"),
+    ('đ', "d"),
+    ('Đ', "D"),
+    ('ð', "d"),
+    ('Ð', "D"),
+    ('ł', "l"),
+    ('Ł', "L"),
+    ('þ', "th"),
+    ('Þ', "Th"),
+    ('ı', "i"),
+    ('‘', "'"),
+    ('’', "'"),
+    ('“', "\""),
+    ('”', "\""),
+    ('–', "-"),
+    ('—', "-"),
+    ('…', "..."),
+];
+
+define_op!(OP_TO_ASCII, 1, true; [String] -> String; propagates_null);
+pub(crate) fn op_to_ascii(args: &[DataValue]) -> Result<DataValue> {
+    // Transliterates to ASCII as far as possible: diacritics are removed, compatibility
+    // characters such as ligatures and full-width forms are decomposed, and some Latin letters
+    // and punctuation are spelled out. Other characters, such as those of non-Latin scripts,
+    // are replaced by the optional second argument, and dropped if it is not given.
+    ensure!(args.len() <= 2, "'to_ascii' takes at most two arguments");
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let replacement = match args.get(1) {
+        None => "",
+        Some(DataValue::Str(r)) => r as &str,
+        Some(_) => bail!("'to_ascii' requires a string as the replacement"),
+    };
+    let s = match &args[0] {
+        DataValue::Str(s) => s,
+        _ => bail!("'to_ascii' requires strings"),
+    };
+    let mut ret = String::with_capacity(s.len());
+    for c in s
+        .nfkd()
+        .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+    {
+        if c.is_ascii() {
+            ret.push(c);
+        } else if let Some((_, r)) = ASCII_REPLACEMENTS.iter().find(|(from, _)| *from == c) {
+            ret.push_str(r);
+        } else {
+            ret.push_str(replacement);
+        }
+    }
+    Ok(DataValue::from(ret))
+}
+
+define_op!(OP_SORTED, 1, true; [List, Any] -> List);
 pub(crate) fn op_sorted(args: &[DataValue]) -> Result<DataValue> {
+    ensure!(args.len() <= 2, "'sorted' takes at most two arguments");
     let mut arg = args[0]
         .get_slice()
         .ok_or_else(|| miette!("'sort' requires lists"))?
         .to_vec();
-    arg.sort();
+    let nulls = match args.get(1) {
+        None => NullOrder::First,
+        Some(v) => match v.get_str() {
+            Some("nulls_first") => NullOrder::First,
+            Some("nulls_last") => NullOrder::Last,
+            _ => bail!(
+                "second argument to 'sorted' must be 'nulls_first' or 'nulls_last', got {}",
+                v.display_literal()
+            ),
+        },
+    };
+    arg.sort_by(|a, b| a.cmp_total(b, nulls));
     Ok(DataValue::List(arg))
 }
 
-define_op!(OP_REVERSE, 1, false);
-pub(crate) fn op_reverse(args: &[DataValue]) -> Result<DataValue> {
-    let mut arg = args[0]
+/// Compares strings with runs of digits compared by their numeric values, so that `item2` comes
+/// before `item10`. Numerically equal runs with more leading zeros come last.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> impl Iterator<Item = &str> {
+        s.as_bytes()
+            .chunk_by(|x, y| x.is_ascii_digit() == y.is_ascii_digit())
+            // the chunks split at ASCII digits, which are char boundaries
+            .map(|c| std::str::from_utf8(c).unwrap())
+    }
+    let mut xs = chunks(a);
+    let mut ys = chunks(b);
+    loop {
+        let (x, y) = match (xs.next(), ys.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        let both_digits = x.as_bytes()[0].is_ascii_digit() && y.as_bytes()[0].is_ascii_digit();
+        let ord = if both_digits {
+            let xt = x.trim_start_matches('0');
+            let yt = y.trim_start_matches('0');
+            xt.len()
+                .cmp(&yt.len())
+                .then_with(|| xt.cmp(yt))
+                .then_with(|| x.len().cmp(&y.len()))
+        } else {
+            x.cmp(y)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+define_op!(OP_NATURAL_SORT, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_natural_sort(args: &[DataValue]) -> Result<DataValue> {
+    let list = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_slice()
+            .ok_or_else(|| miette!("'natural_sort' requires a list of strings"))?,
+    };
+    let mut strs: Vec<_> = list
+        .iter()
+        .map(|v| {
+            v.get_str()
+                .ok_or_else(|| miette!("'natural_sort' requires a list of strings"))
+        })
+        .try_collect()?;
+    strs.sort_by(|a, b| natural_cmp(a, b));
+    Ok(DataValue::List(
+        strs.into_iter().map(DataValue::from).collect(),
+    ))
+}
+
+fn sorted_numbers(arg: &DataValue, name: &str) -> Result<Vec<f64>> {
+    let list = arg
         .get_slice()
-        .ok_or_else(|| miette!("'reverse' requires lists"))?
-        .to_vec();
-    arg.reverse();
-    Ok(DataValue::List(arg))
+        .ok_or_else(|| miette!("'{}' requires a list of numbers", name))?;
+    let mut nums = vec![];
+    for el in list {
+        match el {
+            DataValue::Null => {}
+            DataValue::Num(n) => nums.push(n.get_float()),
+            _ => bail!("'{}' requires a list of numbers", name),
+        }
+    }
+    ensure!(!nums.is_empty(), "'{}' requires a non-empty list", name);
+    nums.sort_by(|a, b| a.total_cmp(b));
+    Ok(nums)
 }
 
-define_op!(OP_HAVERSINE, 4, false);
-pub(crate) fn op_haversine(args: &[DataValue]) -> Result<DataValue> {
+// Linear interpolation between closest ranks: the rank of `p` is `p / 100 * (n - 1)`,
+// and for a fractional rank the result lies on the line between its two neighbours.
+pub(crate) fn interpolated_percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100. * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+define_op!(OP_MEDIAN, 1, false; [List, Number] -> Float);
+pub(crate) fn op_median(args: &[DataValue]) -> Result<DataValue> {
+    let nums = sorted_numbers(&args[0], "median")?;
+    Ok(DataValue::from(interpolated_percentile(&nums, 50.)))
+}
+
+define_op!(OP_PERCENTILE, 2, false; [List, Number] -> Float);
+pub(crate) fn op_percentile(args: &[DataValue]) -> Result<DataValue> {
+    let nums = sorted_numbers(&args[0], "percentile")?;
+    let p = args[1]
+        .get_float()
+        .ok_or_else(|| miette!("'percentile' requires a number as the second argument"))?;
+    ensure!(
+        (0. ..=100.).contains(&p),
+        "'percentile' requires the percentile to be between 0 and 100, got {}",
+        p
+    );
+    Ok(DataValue::from(interpolated_percentile(&nums, p)))
+}
+
+define_op!(OP_REVERSE, 1, false; [List, Any] -> List);
+pub(crate) fn op_reverse(args: &[DataValue]) -> Result<DataValue> {
+    let mut arg = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'reverse' requires lists"))?
+        .to_vec();
+    arg.reverse();
+    Ok(DataValue::List(arg))
+}
+
+define_op!(OP_ROTATE, 2, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_rotate(args: &[DataValue]) -> Result<DataValue> {
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let mut l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'rotate' requires a list"))?
+        .to_vec();
+    let n = args[1]
+        .get_int()
+        .ok_or_else(|| miette!("'rotate' requires an integer amount"))?;
+    if !l.is_empty() {
+        let k = n.rem_euclid(l.len() as i64) as usize;
+        l.rotate_right(k);
+    }
+    Ok(DataValue::List(l))
+}
+
+define_op!(OP_SHIFT, 3, false; [List, Any] -> List);
+pub(crate) fn op_shift(args: &[DataValue]) -> Result<DataValue> {
+    // a null `fill` is a legitimate filler, so only the list and amount propagate null
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'shift' requires a list"))?;
+    let n = args[1]
+        .get_int()
+        .ok_or_else(|| miette!("'shift' requires an integer amount"))?;
+    let fill = &args[2];
+    let k = n.unsigned_abs().min(l.len() as u64) as usize;
+    let kept = l.len() - k;
+    let res = if n >= 0 {
+        iter::repeat(fill)
+            .take(k)
+            .chain(&l[..kept])
+            .cloned()
+            .collect()
+    } else {
+        l[k..]
+            .iter()
+            .chain(iter::repeat(fill).take(k))
+            .cloned()
+            .collect()
+    };
+    Ok(DataValue::List(res))
+}
+
+define_op!(OP_NTILE, 2, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_ntile(args: &[DataValue]) -> Result<DataValue> {
+    // Assigns each element of a list of numbers to one of `n` buckets numbered from 1,
+    // following SQL's `NTILE`: the elements are ranked in ascending order, and the first
+    // `len % n` buckets get one element more than the rest. Equal elements are ranked in
+    // the order they appear in the list, so they may end up in adjacent buckets.
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'ntile' requires a list"))?;
+    let n = match args[1].get_int() {
+        Some(n) if n > 0 => n as usize,
+        _ => bail!("'ntile' requires a positive number of buckets"),
+    };
+    let mut ranked = l
+        .iter()
+        .enumerate()
+        .map(|(i, v)| match v {
+            DataValue::Num(x) => Ok((x, i)),
+            _ => bail!("'ntile' requires a list of numbers"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ranked.sort();
+    let size = l.len() / n;
+    let larger = l.len() % n;
+    let mut buckets = vec![DataValue::Null; l.len()];
+    for (rank, (_, i)) in ranked.into_iter().enumerate() {
+        // the first `larger` buckets hold `size + 1` elements each
+        let bucket = if rank < larger * (size + 1) {
+            rank / (size + 1)
+        } else {
+            larger + (rank - larger * (size + 1)) / size
+        };
+        buckets[i] = DataValue::from(bucket as i64 + 1);
+    }
+    Ok(DataValue::List(buckets))
+}
+
+define_op!(OP_COMPACT, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_compact(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Null => Ok(DataValue::Null),
+        arg => {
+            let l = arg
+                .get_slice()
+                .ok_or_else(|| miette!("'compact' requires lists"))?;
+            Ok(DataValue::List(
+                l.iter()
+                    .filter(|v| **v != DataValue::Null)
+                    .cloned()
+                    .collect(),
+            ))
+        }
+    }
+}
+
+define_op!(OP_FILL_FORWARD, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_fill_forward(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'fill_forward' requires lists"))?;
+    let mut last = DataValue::Null;
+    let res = l
+        .iter()
+        .map(|v| {
+            if *v != DataValue::Null {
+                last = v.clone();
+            }
+            last.clone()
+        })
+        .collect();
+    Ok(DataValue::List(res))
+}
+
+define_op!(OP_FILL_BACKWARD, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_fill_backward(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'fill_backward' requires lists"))?;
+    let mut next = DataValue::Null;
+    let mut res = l
+        .iter()
+        .rev()
+        .map(|v| {
+            if *v != DataValue::Null {
+                next = v.clone();
+            }
+            next.clone()
+        })
+        .collect_vec();
+    res.reverse();
+    Ok(DataValue::List(res))
+}
+
+// nulls take the running value so far, and stay null before the first number
+fn cumulative(
+    name: &str,
+    arg: &DataValue,
+    combine: fn(&[DataValue]) -> Result<DataValue>,
+) -> Result<DataValue> {
+    if *arg == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = arg
+        .get_slice()
+        .ok_or_else(|| miette!("'{}' requires lists", name))?;
+    let mut acc: Option<DataValue> = None;
+    let mut res = Vec::with_capacity(l.len());
+    for v in l {
+        match v {
+            DataValue::Null => {}
+            DataValue::Num(_) => {
+                acc = Some(match acc {
+                    None => v.clone(),
+                    Some(a) => combine(&[a, v.clone()])?,
+                })
+            }
+            _ => bail!("'{}' requires lists of numbers", name),
+        }
+        res.push(acc.clone().unwrap_or(DataValue::Null));
+    }
+    Ok(DataValue::List(res))
+}
+
+define_op!(OP_CUMSUM, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_cumsum(args: &[DataValue]) -> Result<DataValue> {
+    cumulative("cumsum", &args[0], op_add)
+}
+
+define_op!(OP_CUMPROD, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_cumprod(args: &[DataValue]) -> Result<DataValue> {
+    cumulative("cumprod", &args[0], op_mul)
+}
+
+define_op!(OP_CUMMAX, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_cummax(args: &[DataValue]) -> Result<DataValue> {
+    cumulative("cummax", &args[0], op_max)
+}
+
+define_op!(OP_CUMMIN, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_cummin(args: &[DataValue]) -> Result<DataValue> {
+    cumulative("cummin", &args[0], op_min)
+}
+
+define_op!(OP_HAVERSINE, 4, false; [Number] -> Float);
+pub(crate) fn op_haversine(args: &[DataValue]) -> Result<DataValue> {
     let miette = || miette!("'haversine' requires numbers");
     let lat1 = args[0].get_float().ok_or_else(miette)?;
     let lon1 = args[1].get_float().ok_or_else(miette)?;
@@ -1037,7 +2043,7 @@ pub(crate) fn op_haversine(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(ret))
 }
 
-define_op!(OP_HAVERSINE_DEG_INPUT, 4, false);
+define_op!(OP_HAVERSINE_DEG_INPUT, 4, false; [Number] -> Float);
 pub(crate) fn op_haversine_deg_input(args: &[DataValue]) -> Result<DataValue> {
     let miette = || miette!("'haversine_deg_input' requires numbers");
     let lat1 = args[0].get_float().ok_or_else(miette)? * f64::PI() / 180.;
@@ -1052,7 +2058,7 @@ pub(crate) fn op_haversine_deg_input(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(ret))
 }
 
-define_op!(OP_DEG_TO_RAD, 1, false);
+define_op!(OP_DEG_TO_RAD, 1, false; [Number] -> Float);
 pub(crate) fn op_deg_to_rad(args: &[DataValue]) -> Result<DataValue> {
     let x = args[0]
         .get_float()
@@ -1060,7 +2066,7 @@ pub(crate) fn op_deg_to_rad(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(x * f64::PI() / 180.))
 }
 
-define_op!(OP_RAD_TO_DEG, 1, false);
+define_op!(OP_RAD_TO_DEG, 1, false; [Number] -> Float);
 pub(crate) fn op_rad_to_deg(args: &[DataValue]) -> Result<DataValue> {
     let x = args[0]
         .get_float()
@@ -1068,7 +2074,7 @@ pub(crate) fn op_rad_to_deg(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(x * 180. / f64::PI()))
 }
 
-define_op!(OP_FIRST, 1, false);
+define_op!(OP_FIRST, 1, false; [List] -> Any);
 pub(crate) fn op_first(args: &[DataValue]) -> Result<DataValue> {
     Ok(args[0]
         .get_slice()
@@ -1078,7 +2084,7 @@ pub(crate) fn op_first(args: &[DataValue]) -> Result<DataValue> {
         .unwrap_or(DataValue::Null))
 }
 
-define_op!(OP_LAST, 1, false);
+define_op!(OP_LAST, 1, false; [List] -> Any);
 pub(crate) fn op_last(args: &[DataValue]) -> Result<DataValue> {
     Ok(args[0]
         .get_slice()
@@ -1088,7 +2094,34 @@ pub(crate) fn op_last(args: &[DataValue]) -> Result<DataValue> {
         .unwrap_or(DataValue::Null))
 }
 
-define_op!(OP_CHUNKS, 2, false);
+define_op!(OP_TRANSPOSE, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_transpose(args: &[DataValue]) -> Result<DataValue> {
+    let rows = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_slice()
+            .ok_or_else(|| miette!("'transpose' requires a list of lists"))?,
+    };
+    let rows: Vec<_> = rows
+        .iter()
+        .map(|row| {
+            row.get_slice()
+                .ok_or_else(|| miette!("'transpose' requires a list of lists"))
+        })
+        .try_collect()?;
+    let width = rows.first().map_or(0, |row| row.len());
+    ensure!(
+        rows.iter().all(|row| row.len() == width),
+        "'transpose' requires lists of equal length"
+    );
+    Ok(DataValue::List(
+        (0..width)
+            .map(|i| DataValue::List(rows.iter().map(|row| row[i].clone()).collect()))
+            .collect(),
+    ))
+}
+
+define_op!(OP_CHUNKS, 2, false; [List, Any] -> List);
 pub(crate) fn op_chunks(args: &[DataValue]) -> Result<DataValue> {
     let arg = args[0]
         .get_slice()
@@ -1104,7 +2137,7 @@ pub(crate) fn op_chunks(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(res))
 }
 
-define_op!(OP_CHUNKS_EXACT, 2, false);
+define_op!(OP_CHUNKS_EXACT, 2, false; [List, Any] -> List);
 pub(crate) fn op_chunks_exact(args: &[DataValue]) -> Result<DataValue> {
     let arg = args[0]
         .get_slice()
@@ -1120,7 +2153,7 @@ pub(crate) fn op_chunks_exact(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(res))
 }
 
-define_op!(OP_WINDOWS, 2, false);
+define_op!(OP_WINDOWS, 2, false; [List, Any] -> List);
 pub(crate) fn op_windows(args: &[DataValue]) -> Result<DataValue> {
     let arg = args[0]
         .get_slice()
@@ -1136,6 +2169,135 @@ pub(crate) fn op_windows(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(res))
 }
 
+define_op!(OP_ZIP_WITH, 3, false; [List, List, String, Any] -> List);
+pub(crate) fn op_zip_with(args: &[DataValue]) -> Result<DataValue> {
+    // there are no lambdas, so the combining function is given by name
+    let name = args[2]
+        .get_str()
+        .ok_or_else(|| miette!("third argument of 'zip_with' must be a function name"))?;
+    let op = get_op(name).ok_or_else(|| miette!("unknown function '{}' for 'zip_with'", name))?;
+    ensure!(
+        op.min_arity == 2 || (op.vararg && op.min_arity <= 2),
+        "function '{}' given to 'zip_with' cannot take two arguments",
+        name
+    );
+    ensure!(
+        op.is_deterministic(),
+        "function '{}' given to 'zip_with' must be deterministic",
+        name
+    );
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("first argument of 'zip_with' must be a list"))?;
+    let b = args[1]
+        .get_slice()
+        .ok_or_else(|| miette!("second argument of 'zip_with' must be a list"))?;
+    let res: Vec<_> = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (op.inner)(&[x.clone(), y.clone()]))
+        .try_collect()?;
+    Ok(DataValue::List(res))
+}
+
+/// The function named by `args[1]`, to be called on each element of the list by the
+/// operator `name`, e.g. as a predicate. It is called with an element of the list followed
+/// by the rest of `args`, e.g. `find(l, 'gt', 3)` finds the first element greater than 3.
+fn predicate_op(name: &str, args: &[DataValue]) -> Result<&'static Op> {
+    // there are no lambdas, so the predicate is given by name, as with 'zip_with'
+    let f = args[1]
+        .get_str()
+        .ok_or_else(|| miette!("second argument of '{}' must be a function name", name))?;
+    let op = get_op(f).ok_or_else(|| miette!("unknown function '{}' for '{}'", f, name))?;
+    let arity = args.len() - 1;
+    ensure!(
+        op.min_arity == arity || (op.vararg && op.min_arity <= arity),
+        "function '{}' given to '{}' cannot take {} arguments",
+        f,
+        name,
+        arity
+    );
+    ensure!(
+        op.is_deterministic(),
+        "function '{}' given to '{}' must be deterministic",
+        f,
+        name
+    );
+    Ok(op)
+}
+
+/// Finds the first element of the list in `args[0]` for which the predicate returns `target`.
+fn find_by_predicate<'a>(
+    name: &str,
+    pred: &Op,
+    args: &'a [DataValue],
+    target: bool,
+) -> Result<Option<(usize, &'a DataValue)>> {
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("first argument of '{}' must be a list", name))?;
+    let mut call_args = args[1..].to_vec();
+    for (i, el) in l.iter().enumerate() {
+        call_args[0] = el.clone();
+        let matched = (pred.inner)(&call_args)?
+            .get_bool()
+            .ok_or_else(|| miette!("the function given to '{}' must return booleans", name))?;
+        if matched == target {
+            return Ok(Some((i, el)));
+        }
+    }
+    Ok(None)
+}
+
+define_op!(OP_FIND, 2, true; [List, Any] -> Any);
+pub(crate) fn op_find(args: &[DataValue]) -> Result<DataValue> {
+    let pred = predicate_op("find", args)?;
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    Ok(match find_by_predicate("find", pred, args, true)? {
+        Some((_, el)) => el.clone(),
+        None => DataValue::Null,
+    })
+}
+
+define_op!(OP_FIND_INDEX, 2, true; [List, Any] -> Int);
+pub(crate) fn op_find_index(args: &[DataValue]) -> Result<DataValue> {
+    let pred = predicate_op("find_index", args)?;
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    Ok(match find_by_predicate("find_index", pred, args, true)? {
+        Some((i, _)) => DataValue::from(i as i64),
+        None => DataValue::Null,
+    })
+}
+
+define_op!(OP_ANY, 2, true; [List, Any] -> Bool);
+pub(crate) fn op_any(args: &[DataValue]) -> Result<DataValue> {
+    // True if the predicate holds for any element, so false for an empty list
+    let pred = predicate_op("any", args)?;
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let found = find_by_predicate("any", pred, args, true)?;
+    Ok(DataValue::from(found.is_some()))
+}
+
+define_op!(OP_ALL, 2, true; [List, Any] -> Bool);
+pub(crate) fn op_all(args: &[DataValue]) -> Result<DataValue> {
+    // True if the predicate holds for all elements, so true for an empty list
+    let pred = predicate_op("all", args)?;
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let counterexample = find_by_predicate("all", pred, args, false)?;
+    Ok(DataValue::from(counterexample.is_none()))
+}
+
 fn get_index(mut i: i64, total: usize) -> Result<usize> {
     if i < 0 {
         i += total as i64;
@@ -1152,7 +2314,7 @@ fn get_index(mut i: i64, total: usize) -> Result<usize> {
     })
 }
 
-define_op!(OP_GET, 2, false);
+define_op!(OP_GET, 2, false; [List, Int] -> Any);
 pub(crate) fn op_get(args: &[DataValue]) -> Result<DataValue> {
     let l = args[0]
         .get_slice()
@@ -1164,7 +2326,7 @@ pub(crate) fn op_get(args: &[DataValue]) -> Result<DataValue> {
     Ok(l[idx].clone())
 }
 
-define_op!(OP_MAYBE_GET, 2, false);
+define_op!(OP_MAYBE_GET, 2, false; [List, Int] -> Any);
 pub(crate) fn op_maybe_get(args: &[DataValue]) -> Result<DataValue> {
     let l = args[0]
         .get_slice()
@@ -1179,7 +2341,117 @@ pub(crate) fn op_maybe_get(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_SLICE, 3, false);
+define_op!(OP_GROUP_BY, 2, true; [List, String, Any] -> List);
+pub(crate) fn op_group_by(args: &[DataValue]) -> Result<DataValue> {
+    // There are no lambdas, so the key of each element is found by calling the function named
+    // by the second argument on it and any further arguments, e.g. `group_by(rows, 'get', 0)`
+    // groups the rows by their first fields. There are no maps either, so instead of a dict
+    // the groups are returned as a list of `[key, elements]` pairs, in the order their keys
+    // first appear. Numeric keys equal in value, such as `1` and `1.0`, make up the same group.
+    let key_fn = predicate_op("group_by", args)?;
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("first argument to 'group_by' must be a list"))?;
+    let mut call_args = args[1..].to_vec();
+    let mut groups: Vec<(DataValue, Vec<DataValue>)> = vec![];
+    for el in l {
+        call_args[0] = el.clone();
+        let key = match (key_fn.inner)(&call_args)? {
+            DataValue::Num(Num::Float(f)) if f.fract() == 0. && f.abs() < i64::MAX as f64 => {
+                DataValue::from(f as i64)
+            }
+            key @ (DataValue::Str(_) | DataValue::Num(_)) => key,
+            key => bail!(
+                "'group_by' requires keys to be strings or numbers, got {}",
+                key
+            ),
+        };
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, els)) => els.push(el.clone()),
+            None => groups.push((key, vec![el.clone()])),
+        }
+    }
+    Ok(DataValue::List(
+        groups
+            .into_iter()
+            .map(|(k, els)| DataValue::List(vec![k, DataValue::List(els)]))
+            .collect(),
+    ))
+}
+
+define_op!(OP_BINCOUNT, 4, false; [List, Any] -> List);
+pub(crate) fn op_bincount(args: &[DataValue]) -> Result<DataValue> {
+    // Counts the numbers of the list falling into each of the equal-width bins spanning [min, max].
+    // The last bin also includes `max`. Nulls, NaNs and numbers outside the range are dropped.
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("first argument to 'bincount' must be a list"))?;
+    let n_bins = args[1]
+        .get_int()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| miette!("second argument to 'bincount' must be a positive integer"))?
+        as usize;
+    let (min, max) = match (args[2].get_float(), args[3].get_float()) {
+        (Some(min), Some(max)) if min.is_finite() && max.is_finite() && min < max => (min, max),
+        _ => bail!("'bincount' requires finite numbers `min` and `max` with `min` < `max`"),
+    };
+    let width = (max - min) / n_bins as f64;
+    let mut counts = vec![0i64; n_bins];
+    for el in l {
+        let x = match el {
+            DataValue::Null => continue,
+            DataValue::Num(n) => n.get_float(),
+            v => bail!(
+                "'bincount' requires a list of numbers, got {}",
+                v.display_literal()
+            ),
+        };
+        if !(min..=max).contains(&x) {
+            continue;
+        }
+        let idx = (((x - min) / width) as usize).min(n_bins - 1);
+        counts[idx] += 1;
+    }
+    Ok(DataValue::List(
+        counts.into_iter().map(DataValue::from).collect(),
+    ))
+}
+
+define_op!(OP_VALUE_COUNTS, 1, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_value_counts(args: &[DataValue]) -> Result<DataValue> {
+    // Returns `[value, count]` pairs, in the order the values first appear in the list.
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'value_counts' requires a list"))?;
+    let mut positions: BTreeMap<&DataValue, usize> = BTreeMap::new();
+    let mut counts: Vec<(&DataValue, i64)> = vec![];
+    for el in l {
+        match positions.get(el) {
+            Some(i) => counts[*i].1 += 1,
+            None => {
+                positions.insert(el, counts.len());
+                counts.push((el, 1));
+            }
+        }
+    }
+    Ok(DataValue::List(
+        counts
+            .into_iter()
+            .map(|(v, n)| DataValue::List(vec![v.clone(), DataValue::from(n)]))
+            .collect(),
+    ))
+}
+
+define_op!(OP_SLICE, 3, false; [List, Int] -> List);
 pub(crate) fn op_slice(args: &[DataValue]) -> Result<DataValue> {
     let l = args[0]
         .get_slice()
@@ -1195,7 +2467,7 @@ pub(crate) fn op_slice(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(l[m..n].to_vec()))
 }
 
-define_op!(OP_CHARS, 1, false);
+define_op!(OP_CHARS, 1, false; [String] -> List);
 pub(crate) fn op_chars(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(
         args[0]
@@ -1211,7 +2483,7 @@ pub(crate) fn op_chars(args: &[DataValue]) -> Result<DataValue> {
     ))
 }
 
-define_op!(OP_FROM_SUBSTRINGS, 1, false);
+define_op!(OP_FROM_SUBSTRINGS, 1, false; [List] -> String);
 pub(crate) fn op_from_substrings(args: &[DataValue]) -> Result<DataValue> {
     let mut ret = String::new();
     match &args[0] {
@@ -1238,7 +2510,7 @@ pub(crate) fn op_from_substrings(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(ret))
 }
 
-define_op!(OP_ENCODE_BASE64, 1, false);
+define_op!(OP_ENCODE_BASE64, 1, false; [Bytes] -> String);
 pub(crate) fn op_encode_base64(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Bytes(b) => {
@@ -1249,7 +2521,7 @@ pub(crate) fn op_encode_base64(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_DECODE_BASE64, 1, false);
+define_op!(OP_DECODE_BASE64, 1, false; [String] -> Bytes);
 pub(crate) fn op_decode_base64(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::Str(s) => {
@@ -1262,24 +2534,12 @@ pub(crate) fn op_decode_base64(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_TO_BOOL, 1, false);
+define_op!(OP_TO_BOOL, 1, false; [Any] -> Bool);
 pub(crate) fn op_to_bool(args: &[DataValue]) -> Result<DataValue> {
-    Ok(DataValue::from(match &args[0] {
-        DataValue::Null => false,
-        DataValue::Bool(b) => *b,
-        DataValue::Num(n) => n.get_int() != Some(0),
-        DataValue::Str(s) => !s.is_empty(),
-        DataValue::Bytes(b) => !b.is_empty(),
-        DataValue::Uuid(u) => !u.0.is_nil(),
-        DataValue::Regex(r) => !r.0.as_str().is_empty(),
-        DataValue::List(l) => !l.is_empty(),
-        DataValue::Set(s) => !s.is_empty(),
-        DataValue::Validity(vld) => vld.is_assert.0,
-        DataValue::Bot => false,
-    }))
+    args[0].coerce_to(ValueType::Bool)
 }
 
-define_op!(OP_TO_UNITY, 1, false);
+define_op!(OP_TO_UNITY, 1, false; [Any] -> Int);
 pub(crate) fn op_to_unity(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(match &args[0] {
         DataValue::Null => 0,
@@ -1292,71 +2552,350 @@ pub(crate) fn op_to_unity(args: &[DataValue]) -> Result<DataValue> {
         DataValue::List(l) => i64::from(!l.is_empty()),
         DataValue::Set(s) => i64::from(!s.is_empty()),
         DataValue::Validity(vld) => i64::from(vld.is_assert.0),
+        DataValue::BitSet(bs) => i64::from(!bs.0.is_empty()),
         DataValue::Bot => 0,
     }))
 }
 
-define_op!(OP_TO_INT, 1, false);
+define_op!(OP_TO_INT, 1, false; [Any] -> Int);
 pub(crate) fn op_to_int(args: &[DataValue]) -> Result<DataValue> {
+    args[0].coerce_to(ValueType::Int)
+}
+
+define_op!(OP_TO_FLOAT, 1, false; [Any] -> Float);
+pub(crate) fn op_to_float(args: &[DataValue]) -> Result<DataValue> {
+    args[0].coerce_to(ValueType::Float)
+}
+
+// Accepts surrounding whitespace, an optional sign and scientific notation, but not the
+// spellings of infinities and NaN that `f64::from_str` also understands.
+fn parse_number_str(s: &str) -> Option<DataValue> {
+    let s = s.trim();
+    if !s.bytes().any(|b| b.is_ascii_digit())
+        || !s
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E'))
+    {
+        return None;
+    }
+    if let Ok(i) = i64::from_str(s) {
+        return Some(DataValue::from(i));
+    }
+    f64::from_str(s).ok().map(DataValue::from)
+}
+
+define_op!(OP_PARSE_NUMBER, 1, false; [String] -> Number);
+pub(crate) fn op_parse_number(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
-        DataValue::Num(n) => match n.get_int() {
-            None => {
-                let f = n.get_float();
-                DataValue::Num(Num::Int(f as i64))
-            }
-            Some(i) => DataValue::Num(Num::Int(i)),
-        },
-        DataValue::Null => DataValue::from(0),
-        DataValue::Bool(b) => DataValue::from(if *b { 1 } else { 0 }),
-        DataValue::Str(t) => {
-            let s = t as &str;
-            i64::from_str(s)
-                .map_err(|_| miette!("The string cannot be interpreted as int"))?
-                .into()
-        }
-        DataValue::Validity(vld) => DataValue::Num(Num::Int(vld.timestamp.0 .0)),
-        v => bail!("'to_int' does not recognize {:?}", v),
+        n @ DataValue::Num(_) => n.clone(),
+        DataValue::Str(s) => parse_number_str(s).unwrap_or(DataValue::Null),
+        _ => DataValue::Null,
     })
 }
 
-define_op!(OP_TO_FLOAT, 1, false);
-pub(crate) fn op_to_float(args: &[DataValue]) -> Result<DataValue> {
+define_op!(OP_PARSE_NUMBER_STRICT, 1, false; [String] -> Number);
+pub(crate) fn op_parse_number_strict(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
-        DataValue::Num(n) => n.get_float().into(),
-        DataValue::Null => DataValue::from(0.0),
-        DataValue::Bool(b) => DataValue::from(if *b { 1.0 } else { 0.0 }),
-        DataValue::Str(t) => match t as &str {
-            "PI" => f64::PI().into(),
-            "E" => f64::E().into(),
-            "NAN" => f64::NAN.into(),
-            "INF" => f64::INFINITY.into(),
-            "NEG_INF" => f64::NEG_INFINITY.into(),
-            s => f64::from_str(s)
-                .map_err(|_| miette!("The string cannot be interpreted as float"))?
-                .into(),
-        },
-        v => bail!("'to_float' does not recognize {:?}", v),
+        n @ DataValue::Num(_) => n.clone(),
+        DataValue::Null => DataValue::Null,
+        DataValue::Str(s) => parse_number_str(s)
+            .ok_or_else(|| miette!("'parse_number_strict' cannot parse {:?} as a number", s))?,
+        v => bail!(
+            "'parse_number_strict' requires strings, got {}",
+            v.display_literal()
+        ),
     })
 }
 
-define_op!(OP_TO_STRING, 1, false);
+define_op!(OP_TO_STRING, 1, false; [Any] -> String);
 pub(crate) fn op_to_string(args: &[DataValue]) -> Result<DataValue> {
-    Ok(match &args[0] {
-        DataValue::Str(s) => DataValue::Str(s.clone()),
-        v => {
-            let jv = JsonValue::from(v.clone());
-            let s = jv.to_string();
-            DataValue::from(s)
+    args[0].coerce_to(ValueType::String)
+}
+
+define_op!(OP_FROM_JSON_LINES, 1, false; [String] -> List; propagates_null);
+pub(crate) fn op_from_json_lines(args: &[DataValue]) -> Result<DataValue> {
+    let s = match &args[0] {
+        DataValue::Str(s) => s,
+        DataValue::Null => return Ok(DataValue::Null),
+        _ => bail!("'from_json_lines' requires a string"),
+    };
+    let mut ret = vec![];
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
         }
-    })
+        let jv: JsonValue = serde_json::from_str(line)
+            .map_err(|err| miette!("'from_json_lines' cannot parse line {}: {}", i + 1, err))?;
+        ret.push(DataValue::from(jv));
+    }
+    Ok(DataValue::List(ret))
+}
+
+enum JsonPathStep {
+    Key(String),
+    Index(i64),
+}
+
+fn parse_json_path(path: &str) -> Result<Vec<JsonPathStep>> {
+    let malformed = || miette!("malformed path for 'json_path': {}", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(malformed)?;
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            ensure!(end > 0, malformed());
+            steps.push(JsonPathStep::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(malformed)?;
+            let inner = r[..end].trim();
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|k| k.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')));
+            steps.push(match quoted {
+                Some(key) => JsonPathStep::Key(key.to_string()),
+                None => JsonPathStep::Index(inner.parse().map_err(|_| malformed())?),
+            });
+            rest = &r[end + 1..];
+        } else {
+            bail!(malformed())
+        }
+    }
+    Ok(steps)
+}
+
+define_op!(OP_JSON_PATH, 2, false; [Any, String] -> Any);
+pub(crate) fn op_json_path(args: &[DataValue]) -> Result<DataValue> {
+    let path = args[1]
+        .get_str()
+        .ok_or_else(|| miette!("second argument of 'json_path' must be a string"))?;
+    let steps = parse_json_path(path)?;
+    let mut cur = &args[0];
+    for step in steps {
+        // objects converted from JSON are lists of key-value pairs
+        let found = match (step, cur) {
+            (JsonPathStep::Key(key), DataValue::List(l)) => l.iter().find_map(|pair| match pair {
+                DataValue::List(kv) if kv.len() == 2 && kv[0].get_str() == Some(&key) => {
+                    Some(&kv[1])
+                }
+                _ => None,
+            }),
+            (JsonPathStep::Index(i), DataValue::List(l)) => {
+                let i = if i < 0 { i + l.len() as i64 } else { i };
+                usize::try_from(i).ok().and_then(|i| l.get(i))
+            }
+            _ => None,
+        };
+        match found {
+            Some(v) => cur = v,
+            None => return Ok(DataValue::Null),
+        }
+    }
+    Ok(cur.clone())
+}
+
+define_op!(OP_JSON_MERGE_PATCH, 2, false; [Any, Any] -> Any; propagates_null);
+pub(crate) fn op_json_merge_patch(args: &[DataValue]) -> Result<DataValue> {
+    // Applies `patch` to `target` as described by RFC 7386, except that a null target gives null.
+    // Objects are lists of `[key, value]` pairs, as for objects converted from JSON.
+    // An empty list in the patch is an empty array, replacing the target.
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    Ok(json_merge_patch(&args[0], &args[1]))
+}
+
+fn json_merge_patch(target: &DataValue, patch: &DataValue) -> DataValue {
+    let patch = match json_object_entries(patch) {
+        Some(entries) if !entries.is_empty() => entries,
+        _ => return patch.clone(),
+    };
+    let mut merged: BTreeMap<&str, DataValue> = json_object_entries(target)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| (k, v.clone()))
+        .collect();
+    for (key, value) in patch {
+        if *value == DataValue::Null {
+            merged.remove(key);
+        } else {
+            let target = merged.get(key).unwrap_or(&DataValue::Null);
+            let value = json_merge_patch(target, value);
+            merged.insert(key, value);
+        }
+    }
+    pairs_to_object(merged.into_iter().collect())
+}
+
+/// The entries of a JSON object, `None` if `v` is not a list of `[key, value]` pairs
+fn json_object_entries(v: &DataValue) -> Option<Vec<(&str, &DataValue)>> {
+    match v {
+        DataValue::List(l) => l
+            .iter()
+            .map(|pair| match pair {
+                DataValue::List(kv) if kv.len() == 2 => Some((kv[0].get_str()?, &kv[1])),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+// Objects are lists of `[key, value]` pairs, as for objects converted from JSON
+fn pairs_to_object(pairs: Vec<(&str, DataValue)>) -> DataValue {
+    DataValue::List(
+        pairs
+            .into_iter()
+            .map(|(k, v)| DataValue::List(vec![DataValue::from(k), v]))
+            .collect(),
+    )
+}
+
+define_op!(OP_PARSE_URL, 1, false; [String] -> List; propagates_null);
+pub(crate) fn op_parse_url(args: &[DataValue]) -> Result<DataValue> {
+    // Relative URLs have no scheme, host or port. The port is the default one for the scheme
+    // if not given.
+    let s = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_str()
+            .ok_or_else(|| miette!("'parse_url' requires a string"))?,
+    };
+    let opt_str = |s: Option<&str>| s.map_or(DataValue::Null, DataValue::from);
+    let parts = match url::Url::parse(s) {
+        Ok(url) => vec![
+            ("scheme", DataValue::from(url.scheme())),
+            ("host", opt_str(url.host_str())),
+            (
+                "port",
+                url.port_or_known_default()
+                    .map_or(DataValue::Null, |p| DataValue::from(p as i64)),
+            ),
+            ("path", DataValue::from(url.path())),
+            ("query", opt_str(url.query())),
+            ("fragment", opt_str(url.fragment())),
+        ],
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let (rest, fragment) = match s.split_once('#') {
+                Some((rest, fragment)) => (rest, Some(fragment)),
+                None => (s, None),
+            };
+            let (path, query) = match rest.split_once('?') {
+                Some((path, query)) => (path, Some(query)),
+                None => (rest, None),
+            };
+            vec![
+                ("scheme", DataValue::Null),
+                ("host", DataValue::Null),
+                ("port", DataValue::Null),
+                ("path", DataValue::from(path)),
+                ("query", opt_str(query)),
+                ("fragment", opt_str(fragment)),
+            ]
+        }
+        Err(err) => bail!("'parse_url' cannot parse {:?}: {}", s, err),
+    };
+    Ok(pairs_to_object(parts))
+}
+
+define_op!(OP_PARSE_QUERY, 1, false; [String] -> List; propagates_null);
+pub(crate) fn op_parse_query(args: &[DataValue]) -> Result<DataValue> {
+    // Values are decoded. A key given more than once has the list of all its values.
+    let s = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_str()
+            .ok_or_else(|| miette!("'parse_query' requires a string"))?,
+    };
+    let s = s.strip_prefix('?').unwrap_or(s);
+    let mut params: Vec<(String, Vec<String>)> = vec![];
+    for (k, v) in url::form_urlencoded::parse(s.as_bytes()) {
+        match params.iter_mut().find(|(key, _)| *key == k) {
+            Some((_, vals)) => vals.push(v.into_owned()),
+            None => params.push((k.into_owned(), vec![v.into_owned()])),
+        }
+    }
+    Ok(pairs_to_object(
+        params
+            .iter()
+            .map(|(k, vals)| {
+                let val = match &vals[..] {
+                    [val] => DataValue::from(&val[..]),
+                    vals => DataValue::List(vals.iter().map(|v| DataValue::from(&v[..])).collect()),
+                };
+                (&k[..], val)
+            })
+            .collect(),
+    ))
+}
+
+define_op!(OP_ENCODE_URL_QUERY, 1, false; [List] -> String; propagates_null);
+pub(crate) fn op_encode_url_query(args: &[DataValue]) -> Result<DataValue> {
+    // The inverse of `parse_query`: list values give repeated keys, and null values empty ones.
+    fn query_value(v: &DataValue) -> Result<String> {
+        Ok(match v {
+            DataValue::Null => String::new(),
+            DataValue::Str(s) => s.to_string(),
+            DataValue::Bool(b) => b.to_string(),
+            DataValue::Num(Num::Int(i)) => i.to_string(),
+            DataValue::Num(Num::Float(f)) => f.to_string(),
+            v => bail!(
+                "'encode_url_query' cannot encode {} as a value",
+                v.display_literal()
+            ),
+        })
+    }
+    let pairs = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_slice()
+            .ok_or_else(|| miette!("'encode_url_query' requires a list of key-value pairs"))?,
+    };
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for pair in pairs {
+        let (k, v) = match pair {
+            DataValue::List(kv) if kv.len() == 2 => (&kv[0], &kv[1]),
+            _ => bail!("'encode_url_query' requires a list of key-value pairs"),
+        };
+        let k = k
+            .get_str()
+            .ok_or_else(|| miette!("'encode_url_query' requires strings as keys"))?;
+        match v {
+            DataValue::List(vals) => {
+                for v in vals {
+                    serializer.append_pair(k, &query_value(v)?);
+                }
+            }
+            v => {
+                serializer.append_pair(k, &query_value(v)?);
+            }
+        }
+    }
+    Ok(DataValue::from(serializer.finish()))
 }
 
-define_op!(OP_RAND_FLOAT, 0, false);
+define_op!(OP_TO_JSON_LINES, 1, false; [Any] -> String; propagates_null);
+pub(crate) fn op_to_json_lines(args: &[DataValue]) -> Result<DataValue> {
+    let l = match &args[0] {
+        DataValue::List(l) => l,
+        DataValue::Null => return Ok(DataValue::Null),
+        _ => bail!("'to_json_lines' requires a list"),
+    };
+    Ok(DataValue::from(
+        l.iter()
+            .map(|v| JsonValue::from(v.clone()).to_string())
+            .join("\n"),
+    ))
+}
+
+define_op!(OP_RAND_FLOAT, 0, false; [] -> Float);
 pub(crate) fn op_rand_float(_args: &[DataValue]) -> Result<DataValue> {
     Ok(thread_rng().gen::<f64>().into())
 }
 
-define_op!(OP_RAND_BERNOULLI, 1, false);
+define_op!(OP_RAND_BERNOULLI, 1, false; [Number] -> Bool);
 pub(crate) fn op_rand_bernoulli(args: &[DataValue]) -> Result<DataValue> {
     let prob = match &args[0] {
         DataValue::Num(n) => {
@@ -1372,7 +2911,7 @@ pub(crate) fn op_rand_bernoulli(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::from(thread_rng().gen_bool(prob)))
 }
 
-define_op!(OP_RAND_INT, 2, false);
+define_op!(OP_RAND_INT, 2, false; [Int] -> Int);
 pub(crate) fn op_rand_int(args: &[DataValue]) -> Result<DataValue> {
     let lower = &args[0]
         .get_int()
@@ -1383,7 +2922,7 @@ pub(crate) fn op_rand_int(args: &[DataValue]) -> Result<DataValue> {
     Ok(thread_rng().gen_range(*lower..=*upper).into())
 }
 
-define_op!(OP_RAND_CHOOSE, 1, false);
+define_op!(OP_RAND_CHOOSE, 1, false; [List] -> Any);
 pub(crate) fn op_rand_choose(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
         DataValue::List(l) => Ok(l
@@ -1401,15 +2940,165 @@ pub(crate) fn op_rand_choose(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_ASSERT, 1, true);
-pub(crate) fn op_assert(args: &[DataValue]) -> Result<DataValue> {
+define_op!(OP_SAMPLE, 2, false; [List, Int] -> List; propagates_null);
+pub(crate) fn op_sample(args: &[DataValue]) -> Result<DataValue> {
+    // `n` elements of the list chosen without replacement, in random order. It is an error
+    // to ask for more elements than the list has.
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'sample' requires a list"))?;
+    let n = args[1]
+        .get_non_neg_int()
+        .ok_or_else(|| miette!("'sample' requires a non-negative integer"))? as usize;
+    ensure!(
+        n <= l.len(),
+        "'sample' cannot take {} elements from a list of length {}",
+        n,
+        l.len()
+    );
+    let mut rng = seeded_rng(l);
+    Ok(DataValue::List(
+        rand::seq::index::sample(&mut rng, l.len(), n)
+            .into_iter()
+            .map(|i| l[i].clone())
+            .collect(),
+    ))
+}
+
+define_op!(OP_SHUFFLE, 1, false; [List] -> List; propagates_null);
+pub(crate) fn op_shuffle(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let mut l = args[0]
+        .get_slice()
+        .ok_or_else(|| miette!("'shuffle' requires a list"))?
+        .to_vec();
+    let mut rng = seeded_rng(&l);
+    l.shuffle(&mut rng);
+    Ok(DataValue::List(l))
+}
+
+define_op!(OP_BYTE_AT, 2, false; [Bytes, Int] -> Int; propagates_null);
+pub(crate) fn op_byte_at(args: &[DataValue]) -> Result<DataValue> {
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let b = match &args[0] {
+        DataValue::Bytes(b) => b,
+        _ => bail!("first argument to 'byte_at' must be bytes"),
+    };
+    let n = args[1]
+        .get_int()
+        .ok_or_else(|| miette!("second argument to 'byte_at' must be an integer"))?;
+    let idx = get_index(n, b.len())?;
+    Ok(DataValue::from(b[idx] as i64))
+}
+
+define_op!(OP_BYTES_TO_INTS, 1, false; [Bytes] -> List; propagates_null);
+pub(crate) fn op_bytes_to_ints(args: &[DataValue]) -> Result<DataValue> {
     match &args[0] {
-        DataValue::Bool(true) => Ok(DataValue::from(true)),
+        DataValue::Null => Ok(DataValue::Null),
+        DataValue::Bytes(b) => Ok(DataValue::List(
+            b.iter().map(|x| DataValue::from(*x as i64)).collect(),
+        )),
+        _ => bail!("'bytes_to_ints' requires bytes"),
+    }
+}
+
+define_op!(OP_INTS_TO_BYTES, 1, false; [List] -> Bytes; propagates_null);
+pub(crate) fn op_ints_to_bytes(args: &[DataValue]) -> Result<DataValue> {
+    let l = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_slice()
+            .ok_or_else(|| miette!("'ints_to_bytes' requires a list of integers"))?,
+    };
+    let bytes: Vec<u8> = l
+        .iter()
+        .map(|x| {
+            let i = x
+                .get_int()
+                .ok_or_else(|| miette!("'ints_to_bytes' requires a list of integers"))?;
+            u8::try_from(i).map_err(|_| miette!("'ints_to_bytes' got {} outside of 0..=255", i))
+        })
+        .try_collect()?;
+    Ok(DataValue::Bytes(bytes))
+}
+
+define_op!(OP_TO_BASE32, 1, false; [Bytes] -> String; propagates_null);
+pub(crate) fn op_to_base32(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Null => Ok(DataValue::Null),
+        DataValue::Bytes(b) => Ok(DataValue::from(BASE32.encode(b))),
+        DataValue::Str(s) => Ok(DataValue::from(BASE32.encode(s.as_bytes()))),
+        _ => bail!("'to_base32' requires bytes or strings"),
+    }
+}
+
+define_op!(OP_FROM_BASE32, 1, false; [String] -> Bytes; propagates_null);
+pub(crate) fn op_from_base32(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Null => Ok(DataValue::Null),
+        DataValue::Str(s) => {
+            let b = BASE32
+                .decode(s.as_bytes())
+                .map_err(|_| miette!("Data is not properly encoded"))?;
+            Ok(DataValue::Bytes(b))
+        }
+        _ => bail!("'from_base32' requires strings"),
+    }
+}
+
+define_op!(OP_TO_BASE58, 1, false; [Bytes] -> String; propagates_null);
+pub(crate) fn op_to_base58(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Null => Ok(DataValue::Null),
+        DataValue::Bytes(b) => Ok(DataValue::from(bs58::encode(b).into_string())),
+        DataValue::Str(s) => Ok(DataValue::from(bs58::encode(s.as_bytes()).into_string())),
+        _ => bail!("'to_base58' requires bytes or strings"),
+    }
+}
+
+define_op!(OP_FROM_BASE58, 1, false; [String] -> Bytes; propagates_null);
+pub(crate) fn op_from_base58(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        DataValue::Null => Ok(DataValue::Null),
+        DataValue::Str(s) => {
+            let b = bs58::decode(s.as_str())
+                .into_vec()
+                .map_err(|_| miette!("Data is not properly encoded"))?;
+            Ok(DataValue::Bytes(b))
+        }
+        _ => bail!("'from_base58' requires strings"),
+    }
+}
+
+define_op!(OP_ASSERT, 1, true; [Any] -> Bool);
+pub(crate) fn op_assert(args: &[DataValue]) -> Result<DataValue> {
+    match (&args[0], args) {
+        (DataValue::Bool(true), _) => Ok(DataValue::from(true)),
+        // a single string after the condition is the message
+        (_, [_, DataValue::Str(msg)]) => bail!("assertion failed: {}", msg),
         _ => bail!("assertion failed: {:?}", args),
     }
 }
 
-define_op!(OP_UNION, 1, true);
+define_op!(OP_ENSURE_NOT_NULL, 2, false; [Any, String] -> fn first_arg_type);
+pub(crate) fn op_ensure_not_null(args: &[DataValue]) -> Result<DataValue> {
+    let msg = args[1]
+        .get_str()
+        .ok_or_else(|| miette!("second argument of 'ensure_not_null' must be a string"))?;
+    match &args[0] {
+        DataValue::Null => bail!("unexpected null: {}", msg),
+        v => Ok(v.clone()),
+    }
+}
+
+define_op!(OP_UNION, 1, true; [List, Any] -> List);
 pub(crate) fn op_union(args: &[DataValue]) -> Result<DataValue> {
     let mut ret = BTreeSet::new();
     for arg in args {
@@ -1430,7 +3119,7 @@ pub(crate) fn op_union(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(ret.into_iter().collect()))
 }
 
-define_op!(OP_DIFFERENCE, 2, true);
+define_op!(OP_DIFFERENCE, 2, true; [List, Any] -> List);
 pub(crate) fn op_difference(args: &[DataValue]) -> Result<DataValue> {
     let mut start: BTreeSet<_> = match &args[0] {
         DataValue::List(l) => l.iter().cloned().collect(),
@@ -1455,7 +3144,7 @@ pub(crate) fn op_difference(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(start.into_iter().collect()))
 }
 
-define_op!(OP_INTERSECTION, 1, true);
+define_op!(OP_INTERSECTION, 1, true; [List, Any] -> List);
 pub(crate) fn op_intersection(args: &[DataValue]) -> Result<DataValue> {
     let mut start: BTreeSet<_> = match &args[0] {
         DataValue::List(l) => l.iter().cloned().collect(),
@@ -1475,19 +3164,313 @@ pub(crate) fn op_intersection(args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::List(start.into_iter().collect()))
 }
 
-define_op!(OP_TO_UUID, 1, false);
-pub(crate) fn op_to_uuid(args: &[DataValue]) -> Result<DataValue> {
+define_op!(OP_BITSET_FROM_LIST, 1, false; [List] -> BitSet);
+pub(crate) fn op_bitset_from_list(args: &[DataValue]) -> Result<DataValue> {
+    // Bitsets hold non-negative integers only. Floats with integral values are accepted
+    // when building them, as elsewhere where integers are required.
+    let mut ret = RoaringTreemap::new();
+    let mut add = |el: &DataValue| -> Result<()> {
+        match el.get_non_neg_int() {
+            Some(i) => {
+                ret.insert(i);
+                Ok(())
+            }
+            None => bail!(
+                "'bitset_from_list' requires non-negative integers, got {}",
+                el.display_literal()
+            ),
+        }
+    };
     match &args[0] {
-        d @ DataValue::Uuid(_u) => Ok(d.clone()),
-        DataValue::Str(s) => {
-            let id = uuid::Uuid::try_parse(s).map_err(|_| miette!("invalid UUID"))?;
-            Ok(DataValue::uuid(id))
+        DataValue::Null => return Ok(DataValue::Null),
+        DataValue::List(l) => l.iter().try_for_each(&mut add)?,
+        DataValue::Set(s) => s.iter().try_for_each(&mut add)?,
+        DataValue::BitSet(bs) => return Ok(DataValue::BitSet(bs.clone())),
+        _ => bail!("'bitset_from_list' requires lists"),
+    }
+    Ok(DataValue::BitSet(BitSetWrapper(ret)))
+}
+
+fn bitset_in<'a>(arg: &'a DataValue, name: &str) -> Result<&'a RoaringTreemap> {
+    match arg {
+        DataValue::BitSet(bs) => Ok(&bs.0),
+        _ => bail!("'{}' requires bitsets", name),
+    }
+}
+
+define_op!(OP_BITSET_CONTAINS, 2, false; [BitSet, Int] -> Bool);
+pub(crate) fn op_bitset_contains(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let set = bitset_in(&args[0], "bitset_contains")?;
+    Ok(DataValue::from(match args[1].get_int() {
+        Some(i) => i >= 0 && set.contains(i as u64),
+        None => bail!("'bitset_contains' requires an integer as its second argument"),
+    }))
+}
+
+define_op!(OP_BITSET_AND, 1, true; [BitSet] -> BitSet);
+pub(crate) fn op_bitset_and(args: &[DataValue]) -> Result<DataValue> {
+    let mut ret = bitset_in(&args[0], "bitset_and")?.clone();
+    for arg in &args[1..] {
+        ret &= bitset_in(arg, "bitset_and")?;
+    }
+    Ok(DataValue::BitSet(BitSetWrapper(ret)))
+}
+
+define_op!(OP_BITSET_OR, 1, true; [BitSet] -> BitSet);
+pub(crate) fn op_bitset_or(args: &[DataValue]) -> Result<DataValue> {
+    let mut ret = bitset_in(&args[0], "bitset_or")?.clone();
+    for arg in &args[1..] {
+        ret |= bitset_in(arg, "bitset_or")?;
+    }
+    Ok(DataValue::BitSet(BitSetWrapper(ret)))
+}
+
+define_op!(OP_BITSET_COUNT, 1, false; [BitSet] -> Int);
+pub(crate) fn op_bitset_count(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    Ok(DataValue::from(
+        bitset_in(&args[0], "bitset_count")?.len() as i64
+    ))
+}
+
+fn contains_by_op_eq(haystack: &[&DataValue], needle: &DataValue) -> Result<bool> {
+    Ok(haystack.iter().any(|el| values_eq(el, needle)))
+}
+
+// Elements are deduplicated with the same equality as `==`, so that `1` and `1.0` coincide.
+// The first appearance of each element is kept, in order.
+fn distinct_by_op_eq<'a>(arg: &'a DataValue, name: &str) -> Result<Vec<&'a DataValue>> {
+    let mut ret: Vec<&DataValue> = vec![];
+    let mut add = |el: &'a DataValue| -> Result<()> {
+        if !contains_by_op_eq(&ret, el)? {
+            ret.push(el);
         }
+        Ok(())
+    };
+    match arg {
+        DataValue::List(l) => l.iter().try_for_each(&mut add)?,
+        DataValue::Set(s) => s.iter().try_for_each(&mut add)?,
+        _ => bail!("'{}' requires lists", name),
+    }
+    Ok(ret)
+}
+
+define_op!(OP_LIST_UNION, 2, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_list_union(args: &[DataValue]) -> Result<DataValue> {
+    // Unlike `union`, `intersection` and `difference`, the `list_*` variants compare elements with
+    // `==` and keep the order of first appearance. Duplicates within each input are collapsed.
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let mut ret = distinct_by_op_eq(&args[0], "list_union")?;
+    for el in distinct_by_op_eq(&args[1], "list_union")? {
+        if !contains_by_op_eq(&ret, el)? {
+            ret.push(el);
+        }
+    }
+    Ok(DataValue::List(ret.into_iter().cloned().collect()))
+}
+
+define_op!(OP_LIST_INTERSECT, 2, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_list_intersect(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = distinct_by_op_eq(&args[0], "list_intersect")?;
+    let b = distinct_by_op_eq(&args[1], "list_intersect")?;
+    let mut ret = vec![];
+    for el in a {
+        if contains_by_op_eq(&b, el)? {
+            ret.push(el.clone());
+        }
+    }
+    Ok(DataValue::List(ret))
+}
+
+define_op!(OP_LIST_DIFFERENCE, 2, false; [List, Any] -> List; propagates_null);
+pub(crate) fn op_list_difference(args: &[DataValue]) -> Result<DataValue> {
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = distinct_by_op_eq(&args[0], "list_difference")?;
+    let b = distinct_by_op_eq(&args[1], "list_difference")?;
+    let mut ret = vec![];
+    for el in a {
+        if !contains_by_op_eq(&b, el)? {
+            ret.push(el.clone());
+        }
+    }
+    Ok(DataValue::List(ret))
+}
+
+define_op!(OP_JACCARD, 2, false; [List] -> Float; propagates_null);
+pub(crate) fn op_jaccard(args: &[DataValue]) -> Result<DataValue> {
+    // By convention the similarity of two empty sets is 1.
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = distinct_by_op_eq(&args[0], "jaccard")?;
+    let b = distinct_by_op_eq(&args[1], "jaccard")?;
+    if a.is_empty() && b.is_empty() {
+        return Ok(DataValue::from(1.));
+    }
+    let mut common = 0;
+    for x in &a {
+        if contains_by_op_eq(&b, x)? {
+            common += 1;
+        }
+    }
+    let total = a.len() + b.len() - common;
+    Ok(DataValue::from(common as f64 / total as f64))
+}
+
+// Trigrams as in PostgreSQL's pg_trgm: the string is lowercased and split into words of
+// alphanumeric characters, each padded with two spaces in front and one behind.
+fn trigrams(s: &str) -> BTreeSet<[char; 3]> {
+    let mut ret = BTreeSet::new();
+    for word in s.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let padded = [' ', ' ']
+            .into_iter()
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain([' '])
+            .collect_vec();
+        ret.extend(padded.windows(3).map(|w| [w[0], w[1], w[2]]));
+    }
+    ret
+}
+
+define_op!(OP_SIMILARITY, 2, false; [String] -> Float; propagates_null);
+pub(crate) fn op_similarity(args: &[DataValue]) -> Result<DataValue> {
+    // The number of shared trigrams over the number of trigrams in either string.
+    // Strings without any trigrams, such as empty strings, are only similar to each other,
+    // with 1.0.
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = args[0]
+        .get_str()
+        .ok_or_else(|| miette!("'similarity' requires strings"))?;
+    let b = args[1]
+        .get_str()
+        .ok_or_else(|| miette!("'similarity' requires strings"))?;
+    let a = trigrams(a);
+    let b = trigrams(b);
+    if a.is_empty() && b.is_empty() {
+        return Ok(DataValue::from(1.));
+    }
+    let common = a.intersection(&b).count();
+    let total = a.len() + b.len() - common;
+    Ok(DataValue::from(common as f64 / total as f64))
+}
+
+define_op!(OP_COSINE, 2, false; [List] -> Float; propagates_null);
+pub(crate) fn op_cosine(args: &[DataValue]) -> Result<DataValue> {
+    // The similarity is undefined, and an error is raised, when either vector has zero magnitude,
+    // which includes empty vectors.
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let to_vec = |arg: &DataValue| -> Result<Vec<f64>> {
+        arg.get_slice()
+            .ok_or_else(|| miette!("'cosine' requires lists of numbers"))?
+            .iter()
+            .map(|el| {
+                el.get_float()
+                    .ok_or_else(|| miette!("'cosine' requires lists of numbers"))
+            })
+            .collect()
+    };
+    let a = to_vec(&args[0])?;
+    let b = to_vec(&args[1])?;
+    ensure!(
+        a.len() == b.len(),
+        "'cosine' requires lists of equal length, got {} and {}",
+        a.len(),
+        b.len()
+    );
+    let dot: f64 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    ensure!(
+        norm_a != 0. && norm_b != 0.,
+        "'cosine' is undefined for zero vectors"
+    );
+    Ok(DataValue::from(dot / (norm_a * norm_b)))
+}
+
+fn numbers_in<'a>(arg: &'a DataValue, name: &str) -> Result<&'a [DataValue]> {
+    match arg.get_slice() {
+        Some(list) if list.iter().all(|el| matches!(el, DataValue::Num(_))) => Ok(list),
+        _ => bail!("'{}' requires lists of numbers", name),
+    }
+}
+
+fn all_ints(list: &[DataValue]) -> bool {
+    list.iter()
+        .all(|el| matches!(el, DataValue::Num(Num::Int(_))))
+}
+
+define_op!(OP_DOT, 2, false; [List] -> Number; propagates_null);
+pub(crate) fn op_dot(args: &[DataValue]) -> Result<DataValue> {
+    // The result is an integer if all elements are integers.
+    if args[0] == DataValue::Null || args[1] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let a = numbers_in(&args[0], "dot")?;
+    let b = numbers_in(&args[1], "dot")?;
+    ensure!(
+        a.len() == b.len(),
+        "'dot' requires lists of equal length, got {} and {}",
+        a.len(),
+        b.len()
+    );
+    let pairs = a.iter().zip(b);
+    Ok(if all_ints(a) && all_ints(b) {
+        DataValue::from(
+            pairs
+                .map(|(x, y)| x.get_int().unwrap() * y.get_int().unwrap())
+                .sum::<i64>(),
+        )
+    } else {
+        DataValue::from(
+            pairs
+                .map(|(x, y)| x.get_float().unwrap() * y.get_float().unwrap())
+                .sum::<f64>(),
+        )
+    })
+}
+
+define_op!(OP_PRODUCT, 1, false; [List] -> Number; propagates_null);
+pub(crate) fn op_product(args: &[DataValue]) -> Result<DataValue> {
+    // The product of an empty list is 1. The result is an integer if all elements are integers.
+    if args[0] == DataValue::Null {
+        return Ok(DataValue::Null);
+    }
+    let list = numbers_in(&args[0], "product")?;
+    Ok(if all_ints(list) {
+        DataValue::from(list.iter().map(|x| x.get_int().unwrap()).product::<i64>())
+    } else {
+        DataValue::from(list.iter().map(|x| x.get_float().unwrap()).product::<f64>())
+    })
+}
+
+define_op!(OP_TO_UUID, 1, false; [Any] -> Uuid);
+pub(crate) fn op_to_uuid(args: &[DataValue]) -> Result<DataValue> {
+    match &args[0] {
+        d @ (DataValue::Uuid(_) | DataValue::Str(_)) => d.coerce_to(ValueType::Uuid),
         _ => bail!("'to_uuid' requires a string"),
     }
 }
 
-define_op!(OP_NOW, 0, false);
+define_op!(OP_NOW, 0, false; [] -> Float);
 #[cfg(target_arch = "wasm32")]
 pub(crate) fn op_now(_args: &[DataValue]) -> Result<DataValue> {
     let d: f64 = Date::now() / 1000.;
@@ -1520,7 +3503,7 @@ pub(crate) const TERMINAL_VALIDITY: Validity = Validity {
     is_assert: Reverse(false),
 };
 
-define_op!(OP_FORMAT_TIMESTAMP, 1, true);
+define_op!(OP_FORMAT_TIMESTAMP, 1, true; [Any, String] -> String);
 pub(crate) fn op_format_timestamp(args: &[DataValue]) -> Result<DataValue> {
     let dt = {
         let millis = match &args[0] {
@@ -1554,7 +3537,7 @@ pub(crate) fn op_format_timestamp(args: &[DataValue]) -> Result<DataValue> {
     }
 }
 
-define_op!(OP_PARSE_TIMESTAMP, 1, false);
+define_op!(OP_PARSE_TIMESTAMP, 1, false; [String] -> Float);
 pub(crate) fn op_parse_timestamp(args: &[DataValue]) -> Result<DataValue> {
     let s = args[0]
         .get_str()
@@ -1566,6 +3549,300 @@ pub(crate) fn op_parse_timestamp(args: &[DataValue]) -> Result<DataValue> {
     ))
 }
 
+// Timestamps are either validities or seconds since the epoch
+fn timestamp_to_datetime(op_name: &str, ts: &DataValue) -> Result<DateTime<Utc>> {
+    let micros = match ts {
+        DataValue::Validity(vld) => vld.timestamp.0 .0,
+        DataValue::Num(n) => (n.get_float() * 1_000_000.) as i64,
+        v => bail!(
+            "'{}' requires a timestamp, got {}",
+            op_name,
+            v.display_literal()
+        ),
+    };
+    Utc.timestamp_opt(
+        micros.div_euclid(1_000_000),
+        (micros.rem_euclid(1_000_000) * 1000) as u32,
+    )
+    .latest()
+    .ok_or_else(|| miette!("bad time: {}", ts))
+}
+
+// Names are in English, `style` is either 'long' (the default) or 'short'.
+fn datetime_name(
+    op_name: &str,
+    long_fmt: &str,
+    short_fmt: &str,
+    args: &[DataValue],
+) -> Result<DataValue> {
+    ensure!(args.len() <= 2, "'{}' takes at most two arguments", op_name);
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let fmt = match args.get(1) {
+        None => long_fmt,
+        Some(style) => match style.get_str() {
+            Some("long") => long_fmt,
+            Some("short") => short_fmt,
+            _ => bail!(
+                "'{}' requires 'long' or 'short' as the style, got {}",
+                op_name,
+                style.display_literal()
+            ),
+        },
+    };
+    let dt = timestamp_to_datetime(op_name, &args[0])?;
+    Ok(DataValue::from(dt.format(fmt).to_string()))
+}
+
+define_op!(OP_MONTH_NAME, 1, true; [Any] -> String);
+pub(crate) fn op_month_name(args: &[DataValue]) -> Result<DataValue> {
+    datetime_name("month_name", "%B", "%b", args)
+}
+
+define_op!(OP_WEEKDAY_NAME, 1, true; [Any] -> String);
+pub(crate) fn op_weekday_name(args: &[DataValue]) -> Result<DataValue> {
+    datetime_name("weekday_name", "%A", "%a", args)
+}
+
+const DURATION_UNITS: [(char, u64); 4] = [('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+
+define_op!(OP_PARSE_DURATION, 1, false; [String] -> Int; propagates_null);
+pub(crate) fn op_parse_duration(args: &[DataValue]) -> Result<DataValue> {
+    // Parses durations such as `1h30m` or `-2d` into seconds. Each of the units `d`, `h`, `m`
+    // and `s` may appear at most once, in that order.
+    let s = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        DataValue::Str(s) => s,
+        _ => bail!("'parse_duration' requires a string"),
+    };
+    let malformed = || miette!("'parse_duration' cannot parse '{}' as a duration", s);
+    let (negative, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s as &str),
+    };
+    if rest.is_empty() {
+        return Err(malformed());
+    }
+    let mut units = DURATION_UNITS.iter();
+    let mut total: u64 = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(malformed)?;
+        let n: u64 = rest[..digits].parse().map_err(|_| malformed())?;
+        let unit = rest[digits..].chars().next().unwrap();
+        let (_, secs) = units.find(|(u, _)| *u == unit).ok_or_else(malformed)?;
+        total = n
+            .checked_mul(*secs)
+            .and_then(|n| total.checked_add(n))
+            .ok_or_else(malformed)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    let total = i64::try_from(total).map_err(|_| malformed())?;
+    Ok(DataValue::from(if negative { -total } else { total }))
+}
+
+define_op!(OP_FORMAT_DURATION, 1, false; [Number] -> String; propagates_null);
+pub(crate) fn op_format_duration(args: &[DataValue]) -> Result<DataValue> {
+    // Formats seconds as a duration in the form accepted by `parse_duration`, such as `1h30m`
+    let secs = match &args[0] {
+        DataValue::Null => return Ok(DataValue::Null),
+        v => v
+            .get_int()
+            .ok_or_else(|| miette!("'format_duration' requires an integer number of seconds"))?,
+    };
+    if secs == 0 {
+        return Ok(DataValue::from("0s"));
+    }
+    let mut ret = String::new();
+    if secs < 0 {
+        ret.push('-');
+    }
+    let mut rest = secs.unsigned_abs();
+    for (unit, unit_secs) in DURATION_UNITS {
+        if rest >= unit_secs {
+            ret.push_str(&(rest / unit_secs).to_string());
+            ret.push(unit);
+            rest %= unit_secs;
+        }
+    }
+    Ok(DataValue::from(ret))
+}
+
+// Truncates to the start of the unit as observed in the timezone of `dt`. Weeks start on Mondays.
+fn truncate_datetime<Tz: TimeZone>(unit: &str, dt: DateTime<Tz>) -> Result<DateTime<Tz>> {
+    let local = dt.naive_local();
+    let date = local.date();
+    let truncated = match unit {
+        "second" => date.and_hms_opt(local.hour(), local.minute(), local.second()),
+        "minute" => date.and_hms_opt(local.hour(), local.minute(), 0),
+        "hour" => date.and_hms_opt(local.hour(), 0, 0),
+        "day" => date.and_hms_opt(0, 0, 0),
+        "week" => (date - Duration::days(date.weekday().num_days_from_monday() as i64))
+            .and_hms_opt(0, 0, 0),
+        "month" => NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0)),
+        "quarter" => NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0)),
+        "year" => NaiveDate::from_ymd_opt(date.year(), 1, 1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+        u => bail!("'date_trunc' does not recognize the unit {:?}", u),
+    };
+    truncated
+        .and_then(|t| dt.timezone().from_local_datetime(&t).earliest())
+        .ok_or_else(|| miette!("'date_trunc' cannot truncate {} to {}", local, unit))
+}
+
+define_op!(OP_DATE_TRUNC, 2, true; [String, Any, String] -> fn date_trunc_type);
+pub(crate) fn op_date_trunc(args: &[DataValue]) -> Result<DataValue> {
+    // Timestamps are truncated in UTC unless a timezone is given as the third argument.
+    ensure!(
+        args.len() <= 3,
+        "'date_trunc' takes at most three arguments"
+    );
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let unit = args[0]
+        .get_str()
+        .ok_or_else(|| miette!("'date_trunc' requires a string as the unit"))?;
+    let dt = timestamp_to_datetime("date_trunc", &args[1])?;
+    let secs = match args.get(2) {
+        Some(tz_v) => {
+            let tz_s = tz_v
+                .get_str()
+                .ok_or_else(|| miette!("'date_trunc' timezone specification requires a string"))?;
+            let tz = chrono_tz::Tz::from_str(tz_s)
+                .map_err(|_| miette!("bad timezone specification: {}", tz_s))?;
+            truncate_datetime(unit, dt.with_timezone(&tz))?.timestamp()
+        }
+        None => truncate_datetime(unit, dt)?.timestamp(),
+    };
+    Ok(match &args[1] {
+        DataValue::Validity(vld) => DataValue::Validity(Validity {
+            timestamp: ValidityTs(Reverse(secs * 1_000_000)),
+            is_assert: vld.is_assert,
+        }),
+        DataValue::Num(Num::Int(_)) => DataValue::from(secs),
+        _ => DataValue::from(secs as f64),
+    })
+}
+
+/// Most elements a series made by `generate_series` or `date_range` may have
+pub(crate) const MAX_SERIES_LEN: u64 = 1 << 24;
+
+/// Numbers from `start` to `stop`, both included if reached, `step` apart. The numbers are
+/// integers if all of the arguments are. Steps that would never reach `stop` are errors,
+/// as are series longer than [MAX_SERIES_LEN].
+fn arithmetic_series(
+    op_name: &str,
+    start: &DataValue,
+    stop: &DataValue,
+    step: &DataValue,
+) -> Result<DataValue> {
+    let (start, stop, step) = match (start, stop, step) {
+        (DataValue::Num(a), DataValue::Num(b), DataValue::Num(s)) => (a, b, s),
+        _ => bail!("'{}' requires numbers", op_name),
+    };
+    ensure!(
+        step.get_float() != 0.,
+        "'{}' requires a non-zero step",
+        op_name
+    );
+    ensure!(
+        start.get_float().is_finite()
+            && stop.get_float().is_finite()
+            && step.get_float().is_finite(),
+        "'{}' requires finite numbers",
+        op_name
+    );
+    ensure!(
+        start == stop || (stop.get_float() > start.get_float()) == (step.get_float() > 0.),
+        "'{}' cannot reach {} from {} in steps of {}",
+        op_name,
+        stop,
+        start,
+        step
+    );
+    let len = match (start, stop, step) {
+        (Num::Int(a), Num::Int(b), Num::Int(s)) => {
+            ((*b as i128 - *a as i128) / *s as i128) as f64 + 1.
+        }
+        (a, b, s) => ((b.get_float() - a.get_float()) / s.get_float()).floor() + 1.,
+    };
+    ensure!(
+        len <= MAX_SERIES_LEN as f64,
+        "'{}' would give {} elements, more than the maximum of {}",
+        op_name,
+        len,
+        MAX_SERIES_LEN
+    );
+    let mut ret = Vec::with_capacity(len as usize);
+    match (start, stop, step) {
+        (Num::Int(a), Num::Int(b), Num::Int(s)) => {
+            let mut cur = *a;
+            while (*s > 0 && cur <= *b) || (*s < 0 && cur >= *b) {
+                ret.push(DataValue::from(cur));
+                match cur.checked_add(*s) {
+                    Some(next) => cur = next,
+                    None => break,
+                }
+            }
+        }
+        (a, b, s) => {
+            let (a, b, s) = (a.get_float(), b.get_float(), s.get_float());
+            // multiplying instead of adding up the steps keeps rounding errors from accumulating
+            for i in 0.. {
+                let cur = a + i as f64 * s;
+                if (s > 0. && cur > b) || (s < 0. && cur < b) {
+                    break;
+                }
+                ret.push(DataValue::from(cur));
+            }
+        }
+    }
+    Ok(DataValue::List(ret))
+}
+
+define_op!(OP_GENERATE_SERIES, 2, true; [Number] -> List; propagates_null);
+pub(crate) fn op_generate_series(args: &[DataValue]) -> Result<DataValue> {
+    // the step is 1 if not given
+    ensure!(
+        args.len() <= 3,
+        "'generate_series' takes at most three arguments"
+    );
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let step = args.get(2).cloned().unwrap_or_else(|| DataValue::from(1));
+    arithmetic_series("generate_series", &args[0], &args[1], &step)
+}
+
+define_op!(OP_DATE_RANGE, 3, false; [Any] -> List; propagates_null);
+pub(crate) fn op_date_range(args: &[DataValue]) -> Result<DataValue> {
+    // timestamps are seconds since the epoch or RFC 3339 strings, and the interval is in seconds
+    // or a duration string as accepted by `parse_duration`. The result is a list of timestamps.
+    if args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    let timestamp = |v: &DataValue| -> Result<DataValue> {
+        Ok(match v {
+            DataValue::Str(_) => op_parse_timestamp(std::slice::from_ref(v))?,
+            v => v.clone(),
+        })
+    };
+    let interval = match &args[2] {
+        v @ DataValue::Str(_) => op_parse_duration(std::slice::from_ref(v))?,
+        v => v.clone(),
+    };
+    arithmetic_series(
+        "date_range",
+        &timestamp(&args[0])?,
+        &timestamp(&args[1])?,
+        &interval,
+    )
+}
+
 pub(crate) fn str2vld(s: &str) -> Result<ValidityTs> {
     let dt = DateTime::parse_from_rfc3339(s).map_err(|_| miette!("bad datetime: {}", s))?;
     let st: SystemTime = dt.into();
@@ -1573,7 +3850,7 @@ pub(crate) fn str2vld(s: &str) -> Result<ValidityTs> {
     Ok(ValidityTs(Reverse(microseconds as i64)))
 }
 
-define_op!(OP_RAND_UUID_V1, 0, false);
+define_op!(OP_RAND_UUID_V1, 0, false; [] -> Uuid);
 pub(crate) fn op_rand_uuid_v1(_args: &[DataValue]) -> Result<DataValue> {
     let mut rng = rand::thread_rng();
     let uuid_ctx = uuid::v1::Context::new(rng.gen());
@@ -1596,13 +3873,13 @@ pub(crate) fn op_rand_uuid_v1(_args: &[DataValue]) -> Result<DataValue> {
     Ok(DataValue::uuid(id))
 }
 
-define_op!(OP_RAND_UUID_V4, 0, false);
+define_op!(OP_RAND_UUID_V4, 0, false; [] -> Uuid);
 pub(crate) fn op_rand_uuid_v4(_args: &[DataValue]) -> Result<DataValue> {
     let id = uuid::Uuid::new_v4();
     Ok(DataValue::uuid(id))
 }
 
-define_op!(OP_UUID_TIMESTAMP, 1, false);
+define_op!(OP_UUID_TIMESTAMP, 1, false; [Uuid] -> Float);
 pub(crate) fn op_uuid_timestamp(args: &[DataValue]) -> Result<DataValue> {
     Ok(match &args[0] {
         DataValue::Uuid(UuidWrapper(id)) => match id.get_timestamp() {
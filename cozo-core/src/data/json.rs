@@ -27,11 +27,19 @@ impl From<JsonValue> for DataValue {
             },
             JsonValue::String(s) => DataValue::from(s),
             JsonValue::Array(arr) => DataValue::List(arr.iter().map(DataValue::from).collect()),
-            JsonValue::Object(d) => DataValue::List(
-                d.into_iter()
-                    .map(|(k, v)| DataValue::List([DataValue::from(k), DataValue::from(v)].into()))
-                    .collect(),
-            ),
+            JsonValue::Object(d) => {
+                // sorted explicitly, as the order of `serde_json::Map` depends on its features
+                let mut pairs: Vec<_> = d.into_iter().collect();
+                pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+                DataValue::List(
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| {
+                            DataValue::List([DataValue::from(k), DataValue::from(v)].into())
+                        })
+                        .collect(),
+                )
+            }
         }
     }
 }
@@ -50,13 +58,18 @@ impl<'a> From<&'a JsonValue> for DataValue {
             },
             JsonValue::String(s) => DataValue::Str(s.into()),
             JsonValue::Array(arr) => DataValue::List(arr.iter().map(DataValue::from).collect()),
-            JsonValue::Object(d) => DataValue::List(
-                d.into_iter()
-                    .map(|(k, v)| {
-                        DataValue::List([DataValue::Str(k.into()), DataValue::from(v)].into())
-                    })
-                    .collect(),
-            ),
+            JsonValue::Object(d) => {
+                let mut pairs: Vec<_> = d.iter().collect();
+                pairs.sort_by_key(|(k, _)| *k);
+                DataValue::List(
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| {
+                            DataValue::List([DataValue::Str(k.into()), DataValue::from(v)].into())
+                        })
+                        .collect(),
+                )
+            }
         }
     }
 }
@@ -100,6 +113,7 @@ impl From<DataValue> for JsonValue {
             DataValue::Validity(v) => {
                 json!([v.timestamp.0, v.is_assert])
             }
+            DataValue::BitSet(bs) => JsonValue::Array(bs.0.iter().map(|el| json!(el)).collect()),
         }
     }
 }
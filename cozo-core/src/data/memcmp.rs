@@ -13,8 +13,11 @@ use std::str::FromStr;
 
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use regex::Regex;
+use roaring::RoaringTreemap;
 
-use crate::data::value::{DataValue, Num, RegexWrapper, UuidWrapper, Validity, ValidityTs};
+use crate::data::value::{
+    BitSetWrapper, DataValue, Num, RegexWrapper, UuidWrapper, Validity, ValidityTs,
+};
 
 const INIT_TAG: u8 = 0x00;
 const NULL_TAG: u8 = 0x01;
@@ -28,6 +31,7 @@ const REGEX_TAG: u8 = 0x09;
 const LIST_TAG: u8 = 0x0A;
 const SET_TAG: u8 = 0x0B;
 const VLD_TAG: u8 = 0x0C;
+const BITSET_TAG: u8 = 0x0D;
 const BOT_TAG: u8 = 0xFF;
 
 const IS_FLOAT: u8 = 0b00010000;
@@ -88,6 +92,16 @@ pub(crate) trait MemCmpEncoder: Write {
                 self.write_u64::<BigEndian>(ts_flipped).unwrap();
                 self.write_u8(!vld.is_assert.0 as u8).unwrap();
             }
+            DataValue::BitSet(bs) => {
+                // each element is preceded by a non-zero marker, so that a shorter set
+                // sorts before any set it is a prefix of
+                self.write_u8(BITSET_TAG).unwrap();
+                for el in bs.0.iter() {
+                    self.write_u8(INIT_TAG + 1).unwrap();
+                    self.write_u64::<BigEndian>(el).unwrap();
+                }
+                self.write_u8(INIT_TAG).unwrap()
+            }
             DataValue::Bot => self.write_u8(BOT_TAG).unwrap(),
         }
     }
@@ -294,6 +308,16 @@ impl DataValue {
                     rest,
                 )
             }
+            BITSET_TAG => {
+                let mut collected = RoaringTreemap::new();
+                let mut remaining = remaining;
+                while remaining[0] != INIT_TAG {
+                    let (el_bytes, next_chunk) = remaining[1..].split_at(8);
+                    remaining = next_chunk;
+                    collected.insert(BigEndian::read_u64(el_bytes));
+                }
+                (DataValue::BitSet(BitSetWrapper(collected)), &remaining[1..])
+            }
             BOT_TAG => (DataValue::Bot, remaining),
             _ => unreachable!("{:?}", bs),
         }
@@ -15,6 +15,7 @@ pub(crate) mod program;
 pub(crate) mod relation;
 pub(crate) mod symb;
 pub(crate) mod tuple;
+pub(crate) mod type_infer;
 pub(crate) mod value;
 
 #[cfg(test)]
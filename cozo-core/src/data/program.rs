@@ -21,7 +21,7 @@ use crate::data::expr::Expr;
 use crate::data::relation::StoredRelationMetadata;
 use crate::data::symb::{Symbol, PROG_ENTRY};
 use crate::data::value::{DataValue, ValidityTs};
-use crate::fixed_rule::{FixedRule, FixedRuleHandle};
+use crate::fixed_rule::{FixedRule, FixedRuleHandle, DEFAULT_FIXED_RULES};
 use crate::parse::SourceSpan;
 use crate::runtime::relation::InputRelationHandle;
 use crate::runtime::temp_store::EpochStore;
@@ -39,6 +39,8 @@ pub(crate) struct QueryOutOptions {
     pub(crate) offset: Option<usize>,
     pub(crate) timeout: Option<f64>,
     pub(crate) sleep: Option<f64>,
+    /// Seed of the random functions that honour it, making their results reproducible
+    pub(crate) seed: Option<u64>,
     pub(crate) sorters: Vec<(Symbol, SortDir)>,
     pub(crate) store_relation: Option<(InputRelationHandle, RelationOp)>,
     pub(crate) assertion: Option<QueryAssertion>,
@@ -61,6 +63,9 @@ impl Display for QueryOutOptions {
         if let Some(l) = self.timeout {
             writeln!(f, ":timeout {l};")?;
         }
+        if let Some(s) = self.seed {
+            writeln!(f, ":seed {s};")?;
+        }
         for (symb, dir) in &self.sorters {
             write!(f, ":order ")?;
             if *dir == SortDir::Dsc {
@@ -524,7 +529,68 @@ struct EntryHeadNotExplicitlyDefinedError(#[label] SourceSpan);
 #[diagnostic(help("You need to have one rule named '?'"))]
 pub(crate) struct NoEntryError;
 
+/// Builtin fixed rules whose results may differ between runs on the same data
+const NON_DETERMINISTIC_FIXED_RULES: [&str; 5] = [
+    "CommunityDetectionLouvain",
+    "LabelPropagation",
+    "RandomWalk",
+    "JsonReader",
+    "CsvReader",
+];
+
 impl InputProgram {
+    /// If the result of the program depends only on the data in the stored relations it reads,
+    /// returns the names of these relations. Otherwise the result cannot be cached.
+    /// Programs asking for `:sleep` are not cached either, as a cached result would skip it.
+    pub(crate) fn cacheable_relations(&self) -> Option<BTreeSet<SmartString<LazyCompact>>> {
+        if self.out_opts.store_relation.is_some() || self.out_opts.sleep.is_some() {
+            return None;
+        }
+        let mut relations = BTreeSet::new();
+        for rules_or_fixed in self.prog.values() {
+            match rules_or_fixed {
+                InputInlineRulesOrFixed::Rules { rules } => {
+                    for rule in rules {
+                        if rule
+                            .aggr
+                            .iter()
+                            .flatten()
+                            .any(|(aggr, _)| aggr.name == "AGGR_CHOICE_RAND")
+                        {
+                            return None;
+                        }
+                        for atom in &rule.body {
+                            if !atom.collect_cacheable_relations(&mut relations) {
+                                return None;
+                            }
+                        }
+                    }
+                }
+                InputInlineRulesOrFixed::Fixed { fixed } => {
+                    let name = &fixed.fixed_handle.name.name as &str;
+                    if !DEFAULT_FIXED_RULES.contains_key(name)
+                        || NON_DETERMINISTIC_FIXED_RULES.contains(&name)
+                        || !fixed.options.values().all(|v| v.is_deterministic())
+                    {
+                        return None;
+                    }
+                    for arg in &fixed.rule_args {
+                        match arg {
+                            FixedRuleArg::InMem { .. } => {}
+                            FixedRuleArg::Stored { name, valid_at, .. }
+                            | FixedRuleArg::NamedStored { name, valid_at, .. } => {
+                                if valid_at.is_some() || name.name.starts_with('_') {
+                                    return None;
+                                }
+                                relations.insert(name.name.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(relations)
+    }
     pub(crate) fn needs_write_lock(&self) -> Option<SmartString<LazyCompact>> {
         if let Some((h, _)) = &self.out_opts.store_relation {
             if !h.name.name.starts_with('_') {
@@ -985,6 +1051,31 @@ impl Display for InputAtom {
 }
 
 impl InputAtom {
+    fn collect_cacheable_relations(&self, coll: &mut BTreeSet<SmartString<LazyCompact>>) -> bool {
+        match self {
+            InputAtom::Rule { inner } => inner.args.iter().all(|arg| arg.is_deterministic()),
+            InputAtom::NamedFieldRelation { inner } => {
+                if inner.valid_at.is_some() || inner.name.name.starts_with('_') {
+                    return false;
+                }
+                coll.insert(inner.name.name.clone());
+                inner.args.values().all(|arg| arg.is_deterministic())
+            }
+            InputAtom::Relation { inner } => {
+                if inner.valid_at.is_some() || inner.name.name.starts_with('_') {
+                    return false;
+                }
+                coll.insert(inner.name.name.clone());
+                inner.args.iter().all(|arg| arg.is_deterministic())
+            }
+            InputAtom::Predicate { inner } => inner.is_deterministic(),
+            InputAtom::Negation { inner, .. } => inner.collect_cacheable_relations(coll),
+            InputAtom::Conjunction { inner, .. } | InputAtom::Disjunction { inner, .. } => inner
+                .iter()
+                .all(|atom| atom.collect_cacheable_relations(coll)),
+            InputAtom::Unification { inner } => inner.expr.is_deterministic(),
+        }
+    }
     // pub(crate) fn used_rule(&self, rule_name: &Symbol) -> bool {
     //     match self {
     //         InputAtom::Rule { inner } => inner.name == *rule_name,
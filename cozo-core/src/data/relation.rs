@@ -160,7 +160,7 @@ impl NullableColType {
         }
 
         #[derive(Debug, Error, Diagnostic)]
-        #[error("data coercion failed: expected type {0}, got value {1:?}")]
+        #[error("data coercion failed: expected type {0}, got value {value}", value = .1.display_literal())]
         #[diagnostic(code(eval::coercion_failed))]
         struct DataCoercionFailed(NullableColType, DataValue);
 
@@ -238,7 +238,7 @@ impl NullableColType {
             }
             ColType::Validity => {
                 #[derive(Debug, Error, Diagnostic)]
-                #[error("{0} cannot be coerced into validity")]
+                #[error("{} cannot be coerced into validity", .0.display_literal())]
                 #[diagnostic(code(eval::invalid_validity))]
                 struct InvalidValidity(DataValue);
 
@@ -11,6 +11,7 @@ use itertools::Itertools;
 
 use crate::data::aggr::parse_aggr;
 use crate::data::value::DataValue;
+use crate::new_cozo_mem;
 
 #[test]
 fn test_and() {
@@ -233,6 +234,155 @@ fn test_collect() {
     );
 }
 
+#[test]
+fn test_approx_count_distinct() {
+    let estimate = |values: &mut dyn Iterator<Item = DataValue>| {
+        let mut aggr = parse_aggr("approx_count_distinct").unwrap().clone();
+        aggr.normal_init(&[]).unwrap();
+        let mut aggr = aggr.normal_op.unwrap();
+        for v in values {
+            aggr.set(&v).unwrap();
+        }
+        aggr.get().unwrap().get_int().unwrap()
+    };
+
+    assert_eq!(estimate(&mut std::iter::empty()), 0);
+    assert_eq!(
+        estimate(&mut std::iter::repeat(DataValue::Null).take(10)),
+        0
+    );
+    // small counts are exact, repetitions and nulls are ignored
+    assert_eq!(
+        estimate(
+            &mut [1, 2, 3, 1, 2, 1]
+                .into_iter()
+                .map(DataValue::from)
+                .chain([DataValue::Null, DataValue::from("a")])
+        ),
+        4
+    );
+    // each value is seen several times, in an order unrelated to the values,
+    // the registers becoming dense for all but the smallest count
+    for n in [1000, 2000, 50000, 200000] {
+        let est =
+            estimate(&mut (0..3 * n).map(|i| DataValue::from(format!("item-{}", (i * 7919) % n))));
+        // four times the standard error of 1.04 / sqrt(2^14)
+        let err = (est - n).abs() as f64 / n as f64;
+        assert!(err < 0.033, "estimated {est} for {n} distinct values");
+    }
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            r#"
+            data[k, v] <- [[1, 'x'], [1, 'y'], [1, 'x'], [1, null], [2, null]]
+            ?[k, approx_count_distinct(v)] := data[k, v]
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(res.into_json()["rows"], serde_json::json!([[1, 2], [2, 0]]));
+}
+
+#[test]
+fn test_group_concat() {
+    let mut aggr = parse_aggr("group_concat").unwrap().clone();
+    aggr.normal_init(&[DataValue::from(", ")]).unwrap();
+    let mut concat_aggr = aggr.normal_op.unwrap();
+    assert_eq!(concat_aggr.get().unwrap(), DataValue::from(""));
+    concat_aggr.set(&DataValue::from("a")).unwrap();
+    concat_aggr.set(&DataValue::Null).unwrap();
+    concat_aggr.set(&DataValue::from("b")).unwrap();
+    concat_aggr.set(&DataValue::from(3)).unwrap();
+    assert_eq!(concat_aggr.get().unwrap(), DataValue::from("a, b, 3"));
+
+    let mut aggr = parse_aggr("string_agg").unwrap().clone();
+    aggr.normal_init(&[]).unwrap();
+    let mut concat_aggr = aggr.normal_op.unwrap();
+    concat_aggr.set(&DataValue::from("a")).unwrap();
+    concat_aggr.set(&DataValue::from("b")).unwrap();
+    assert_eq!(concat_aggr.get().unwrap(), DataValue::from("a,b"));
+
+    let mut aggr = parse_aggr("group_concat").unwrap().clone();
+    assert!(aggr.normal_init(&[DataValue::from(1)]).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            r#"
+            data[k, v] <- [[1, 'x'], [1, 'y'], [1, null], [2, 'z'], [3, null]]
+            ?[k, group_concat(v, '; ')] := data[k, v]
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.into_json()["rows"],
+        serde_json::json!([[1, "x; y"], [2, "z"], [3, ""]])
+    );
+    let res = db
+        .run_script(
+            r#"
+            data[v] <- []
+            ?[group_concat(v)] := data[v]
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    // without grouping keys, an empty input still gives one row
+    assert_eq!(res.rows, vec![vec![DataValue::from("")]]);
+    let res = db
+        .run_script("?[string_agg(v)] := v in ['a', 'b']", Default::default())
+        .unwrap();
+    assert_eq!(res.headers, vec!["string_agg(v)"]);
+    assert_eq!(res.rows, vec![vec![DataValue::from("a,b")]]);
+}
+
+#[test]
+fn test_percentile_cont_disc() {
+    let percentile = |name: &str, p: f64| {
+        let mut aggr = parse_aggr(name).unwrap().clone();
+        aggr.normal_init(&[DataValue::from(p)]).unwrap();
+        let mut aggr = aggr.normal_op.unwrap();
+        for x in [40, 10, 30, 20] {
+            aggr.set(&DataValue::from(x)).unwrap();
+        }
+        aggr.set(&DataValue::Null).unwrap();
+        aggr.get().unwrap()
+    };
+    assert_eq!(percentile("percentile_cont", 0.), DataValue::from(10.));
+    assert_eq!(percentile("percentile_cont", 50.), DataValue::from(25.));
+    assert_eq!(percentile("percentile_cont", 90.), DataValue::from(37.));
+    assert_eq!(percentile("percentile_cont", 100.), DataValue::from(40.));
+    assert_eq!(percentile("percentile_disc", 0.), DataValue::from(10));
+    assert_eq!(percentile("percentile_disc", 50.), DataValue::from(20));
+    assert_eq!(percentile("percentile_disc", 51.), DataValue::from(30));
+    assert_eq!(percentile("percentile_disc", 100.), DataValue::from(40));
+
+    let mut aggr = parse_aggr("percentile_disc").unwrap().clone();
+    assert!(aggr.normal_init(&[]).is_err());
+    assert!(aggr.normal_init(&[DataValue::from(101)]).is_err());
+    aggr.normal_init(&[DataValue::from(50)]).unwrap();
+    let mut aggr = aggr.normal_op.unwrap();
+    assert_eq!(aggr.get().unwrap(), DataValue::Null);
+    assert!(aggr.set(&DataValue::from("a")).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            r#"
+            data[k, v] <- [['a', 1], ['a', 2], ['a', 3], ['a', 4], ['b', 7]]
+            ?[k, percentile_cont(v, 25), percentile_disc(v, 25)] := data[k, v]
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.into_json()["rows"],
+        serde_json::json!([["a", 1.75, 1], ["b", 7.0, 7]])
+    );
+}
+
 #[test]
 fn test_count() {
     let mut aggr = parse_aggr("count").unwrap().clone();
@@ -245,7 +395,7 @@ fn test_count() {
     count_aggr.set(&DataValue::Null).unwrap();
     count_aggr.set(&DataValue::from(true)).unwrap();
     count_aggr.set(&DataValue::from(true)).unwrap();
-    assert_eq!(count_aggr.get().unwrap(), DataValue::from(6));
+    assert_eq!(count_aggr.get().unwrap(), DataValue::from(2));
 }
 
 #[test]
@@ -282,7 +432,17 @@ fn test_mean() {
     mean_aggr.set(&DataValue::from(3)).unwrap();
     mean_aggr.set(&DataValue::from(4)).unwrap();
     mean_aggr.set(&DataValue::from(5)).unwrap();
+    mean_aggr.set(&DataValue::Null).unwrap();
     assert_eq!(mean_aggr.get().unwrap(), DataValue::from(3.));
+
+    let mut aggr = parse_aggr("avg").unwrap().clone();
+    aggr.normal_init(&[]).unwrap();
+
+    let mut avg_aggr = aggr.normal_op.unwrap();
+    assert_eq!(avg_aggr.get().unwrap(), DataValue::Null);
+    avg_aggr.set(&DataValue::Null).unwrap();
+    assert_eq!(avg_aggr.get().unwrap(), DataValue::Null);
+    assert!(avg_aggr.set(&DataValue::from("a")).is_err());
 }
 
 #[test]
@@ -296,9 +456,52 @@ fn test_sum() {
     sum_aggr.set(&DataValue::from(3)).unwrap();
     sum_aggr.set(&DataValue::from(4)).unwrap();
     sum_aggr.set(&DataValue::from(5)).unwrap();
+    sum_aggr.set(&DataValue::Null).unwrap();
     assert_eq!(sum_aggr.get().unwrap(), DataValue::from(15.));
 }
 
+#[test]
+fn test_null_policy() {
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            r#"
+            data[k, id, v] <- [['a', 1, 1], ['a', 2, null], ['a', 3, 4], ['a', 4, null],
+                               ['b', 5, null], ['b', 6, null], ['c', 7, 2]]
+            ?[k, count(*), count(v), sum(v), avg(v)] := data[k, id, v]
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.headers,
+        vec!["k", "count(*)", "count(v)", "sum(v)", "avg(v)"]
+    );
+    assert_eq!(
+        res.into_json()["rows"],
+        serde_json::json!([
+            ["a", 4, 2, 5.0, 2.5],
+            ["b", 2, 0, 0.0, null],
+            ["c", 1, 1, 2.0, 2.0]
+        ])
+    );
+
+    let res = db
+        .run_script(
+            "?[count(*), count(v), sum(v), avg(v)] := v in []",
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.into_json()["rows"],
+        serde_json::json!([[0, 0, 0.0, null]])
+    );
+
+    assert!(db
+        .run_script("?[sum(*)] := v in [1, 2]", Default::default())
+        .is_err());
+}
+
 #[test]
 fn test_product() {
     let mut aggr = parse_aggr("product").unwrap().clone();
@@ -6,7 +6,22 @@
  * You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use crate::{new_cozo_mem, DataValue};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use itertools::Itertools;
+
+use crate::data::expr::{
+    eval_bytecode, evaluate_expression, Bytecode, CompiledExpression, Expr, Op,
+};
+use crate::data::functions::{op_abs, OP_ABS};
+use crate::data::symb::Symbol;
+use crate::data::type_infer::ValueType;
+use crate::parse::{parse_expression, SourceSpan};
+use crate::{new_cozo_mem, DataValue, RewriteStep};
+
+fn parse_expr(src: &str) -> miette::Result<Expr> {
+    parse_expression(src, &Default::default())
+}
 
 #[test]
 fn expression_eval() {
@@ -32,3 +47,562 @@ fn expression_eval() {
         .unwrap();
     assert_eq!(res.rows[0][0].get_bool().unwrap(), true);
 }
+
+#[test]
+fn constant_folding() {
+    let fold = |src: &str| parse_expr(src).unwrap().constant_fold();
+
+    assert_eq!(fold("1 + 2 * 3").get_const(), Some(&DataValue::from(7)));
+    assert_eq!(
+        fold("relu(sigmoid(0) - 1)").get_const(),
+        Some(&DataValue::from(0.))
+    );
+    assert_eq!(fold("x + (1 + 2) * y").to_string(), "add(x, mul(3, y))");
+    assert_eq!(fold("if(1 > 2, x, y - 2 * 3)").to_string(), "sub(y, 6)");
+    assert_eq!(
+        fold("cond(x > 1, 'a', 1 == 2, 'b', 2 > 1, 'c', true, 'd')").to_string(),
+        "cond(gt(x, 1), \"a\", true, \"c\")"
+    );
+    assert_eq!(fold("if(1 > 2, x)").get_const(), Some(&DataValue::Null));
+    assert_eq!(
+        fold("[1, if(x, 2, 3 + 4)]").to_string(),
+        "list(1, cond(x, 2, true, 7))"
+    );
+    // non-deterministic and failing applications are left alone
+    assert_eq!(
+        fold("rand_float() + 1 * 2").to_string(),
+        "add(rand_float, 2)"
+    );
+    assert_eq!(fold("1 / 'a'").to_string(), "div(1, \"a\")");
+    assert_eq!(
+        fold("[1, if(2 > 1, skip(), 3)]").get_const(),
+        Some(&DataValue::List(vec![DataValue::from(1)]))
+    );
+}
+
+#[test]
+fn wrong_number_of_arguments() {
+    assert_eq!(
+        parse_expr("sigmoid(1, 2)").unwrap_err().to_string(),
+        "Wrong number of arguments for function 'sigmoid': expected exactly 1, got 2"
+    );
+    assert_eq!(
+        parse_expr("zip_with([1], [2])").unwrap_err().to_string(),
+        "Wrong number of arguments for function 'zip_with': expected exactly 3, got 2"
+    );
+    assert_eq!(
+        parse_expr("concat()").unwrap_err().to_string(),
+        "Wrong number of arguments for function 'concat': expected at least 1, got 0"
+    );
+    assert!(parse_expr("concat('a', 'b', 'c')").is_ok());
+}
+
+#[test]
+fn and_or_folding() {
+    let fold = |src: &str| {
+        let (folded, trace) = parse_expr(src).unwrap().constant_fold_traced();
+        (
+            folded.to_string(),
+            trace.iter().map(|s| s.rule).collect_vec(),
+        )
+    };
+
+    assert_eq!(
+        fold("true && x"),
+        ("and(x)".into(), vec!["drop_identities"])
+    );
+    assert_eq!(fold("false && x"), ("false".into(), vec!["short_circuit"]));
+    assert_eq!(
+        fold("x || false"),
+        ("or(x)".into(), vec!["drop_identities"])
+    );
+    // constant operands are folded first
+    assert_eq!(fold("x && 1 < 2").0, "and(x)");
+    assert_eq!(fold("x || 1 > 2 || y").0, "or(x, y)");
+    assert_eq!(fold("x && (false || 1 == 1) && z").0, "and(x, z)");
+    assert_eq!(fold("x || (false && y)").0, "or(x)");
+    // a false operand wins if nothing before it needs checking, and a true one for `or`
+    assert_eq!(fold("and(true, false, x, z)").0, "false");
+    assert_eq!(fold("or(false, true, x, z)").0, "true");
+    assert_eq!(fold("and(x, true, y, true)").0, "and(x, y)");
+    // `x` must still be checked to be a boolean
+    assert!(fold("x || true").1.is_empty());
+    assert!(fold("and(x, false)").1.is_empty());
+    // errors of the later operands are kept
+    assert!(fold("false && to_int(x) > 1").1.is_empty());
+    // with all operands dropped, the identity is left
+    assert_eq!(fold("and(true, true)").0, "true");
+    assert_eq!(fold("or(false, false)").0, "false");
+    assert_eq!(fold("and()").0, "true");
+    // nothing to do
+    assert_eq!(fold("x && y"), ("and(x, y)".into(), vec![]));
+    assert_eq!(fold("x || null").0, "or(x, null)");
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[x] := x in [1, 2, 3], (x > 1 || 1 > 2) && true",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res,
+        vec![vec![DataValue::from(2)], vec![DataValue::from(3)]]
+    );
+    assert!(db
+        .run_script("?[y] := y = true && 5", Default::default())
+        .is_err());
+    assert!(db
+        .run_script("?[y] := x = 5, y = x || false", Default::default())
+        .is_err());
+}
+
+#[test]
+fn null_propagation_folding() {
+    let fold = |src: &str| parse_expr(src).unwrap().constant_fold_traced();
+
+    let (folded, trace) = fold("jaccard(null, x)");
+    assert_eq!(folded.get_const(), Some(&DataValue::Null));
+    assert_eq!(
+        trace.iter().map(|s| s.rule).collect_vec(),
+        vec!["propagate_null"]
+    );
+    // the other arguments are pruned without being evaluated
+    let (folded, trace) = fold("jaccard(null, sorted(list_union(concat(x, [y]), reverse(z))))");
+    assert_eq!(folded.get_const(), Some(&DataValue::Null));
+    assert_eq!(
+        trace.iter().map(|s| s.rule).collect_vec(),
+        vec!["propagate_null"]
+    );
+    // unless they are non-deterministic
+    assert_eq!(
+        fold("jaccard(null, list_union(x, [rand_int(0, 10)]))")
+            .0
+            .to_string(),
+        "jaccard(null, list_union(x, list(rand_int(0, 10))))"
+    );
+    assert_eq!(
+        fold("cosine(x, if(1 > 2, [1]))").0.get_const(),
+        Some(&DataValue::Null)
+    );
+    assert_eq!(
+        fold("[sigmoid(null), 1]").0.get_const(),
+        Some(&DataValue::List(vec![DataValue::Null, DataValue::from(1)]))
+    );
+    // functions that handle nulls differently are left alone
+    assert_eq!(fold("coalesce(null, x)").0.to_string(), "coalesce(null, x)");
+    assert_eq!(fold("add_nn(null, x)").0.to_string(), "add_nn(null, x)");
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[a, b] := x in [[1, 2]], a = list_union(x, null), b = list_union(x, [3])",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(res[0][0], DataValue::Null);
+    assert_eq!(res[0][1].get_slice().unwrap().len(), 3);
+}
+
+#[test]
+fn rewrite_trace() {
+    let parse = |src: &str| parse_expr(src).unwrap();
+    let steps = |trace: Vec<RewriteStep>| trace.iter().map(|s| s.to_string()).collect_vec();
+
+    let (folded, trace) = parse("if(1 + 1 == 2, x * (2 * 3), y)").constant_fold_traced();
+    assert_eq!(folded.to_string(), "mul(x, 6)");
+    assert_eq!(
+        steps(trace),
+        vec![
+            "fold_constant: add(1, 1) -> 2",
+            "fold_constant: eq(2, 2) -> true",
+            "fold_constant: mul(2, 3) -> 6",
+            "take_branch: cond(true, mul(x, 6), true, y) -> mul(x, 6)",
+        ]
+    );
+
+    let (_, trace) = parse("cond(1 > 2, 'a', x, 'b')").constant_fold_traced();
+    assert_eq!(
+        steps(trace),
+        vec![
+            "fold_constant: gt(1, 2) -> false",
+            "drop_branches: cond(false, \"a\", x, \"b\", true, null) -> cond(x, \"b\", true, null)",
+        ]
+    );
+
+    // the result is the same as without tracing
+    let expr = parse("[1 + 2, if(x, 2, 3 + 4), cond(false, 1)]");
+    let (folded, trace) = expr.clone().constant_fold_traced();
+    assert_eq!(folded, expr.constant_fold());
+    assert_eq!(
+        trace.iter().map(|s| s.rule).collect_vec(),
+        vec!["fold_constant", "fold_constant", "take_branch"]
+    );
+
+    let (_, trace) = parse("x + y").constant_fold_traced();
+    assert!(trace.is_empty());
+
+    let mut expr = parse("!!(x > 1 + 1)");
+    let trace = expr.partial_eval_traced().unwrap();
+    assert_eq!(expr.to_string(), "gt(x, 2)");
+    assert_eq!(
+        steps(trace),
+        vec![
+            "fold_constant: add(1, 1) -> 2",
+            "double_negation: negate(negate(gt(x, 2))) -> gt(x, 2)",
+        ]
+    );
+}
+
+#[test]
+fn nvl2() {
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[x, y] := x in [1, null, false], y = nvl2(x, 'set', 'unset')",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res,
+        vec![
+            vec![DataValue::Null, DataValue::from("unset")],
+            vec![DataValue::from(false), DataValue::from("set")],
+            vec![DataValue::from(1), DataValue::from("set")],
+        ]
+    );
+
+    // the branch not taken is not evaluated
+    let res = db
+        .run_script(
+            "?[x, y] := x in [1, 2], y = nvl2(x, x + 1, assert(false))",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res,
+        vec![
+            vec![DataValue::from(1), DataValue::from(2)],
+            vec![DataValue::from(2), DataValue::from(3)],
+        ]
+    );
+    let res = db
+        .run_script(
+            "?[x, y] := x in [null], y = nvl2(x, assert(false), 0)",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(res, vec![vec![DataValue::Null, DataValue::from(0)]]);
+    assert!(db
+        .run_script(
+            "?[x, y] := x in [null], y = nvl2(x, 0, assert(false))",
+            Default::default(),
+        )
+        .is_err());
+
+    assert!(db
+        .run_script("?[y] := y = nvl2(1, 2)", Default::default())
+        .is_err());
+}
+
+static EXPENSIVE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn op_expensive(args: &[DataValue]) -> miette::Result<DataValue> {
+    EXPENSIVE_CALLS.fetch_add(1, Ordering::SeqCst);
+    op_abs(args)
+}
+
+static OP_EXPENSIVE: Op = Op {
+    name: "OP_EXPENSIVE",
+    min_arity: 1,
+    vararg: false,
+    inner: op_expensive,
+    propagates_null: false,
+    arg_types: &[],
+    output_type: |_| ValueType::Any,
+};
+
+#[test]
+fn common_subexpressions() {
+    // `abs` is replaced by a counting op, `x` is bound to the first column
+    fn instrument(expr: &mut Expr) {
+        match expr {
+            Expr::Binding { tuple_pos, .. } => *tuple_pos = Some(0),
+            Expr::Const { .. } => {}
+            Expr::Apply { op, args, .. } => {
+                if op.name == OP_ABS.name {
+                    *op = &OP_EXPENSIVE;
+                }
+                args.iter_mut().for_each(instrument);
+            }
+            Expr::Cond { clauses, .. } => {
+                for (cond, val) in clauses {
+                    instrument(cond);
+                    instrument(val);
+                }
+            }
+        }
+    }
+    let compile = |src: &str| {
+        let mut expr = parse_expr(src).unwrap();
+        instrument(&mut expr);
+        expr.compile()
+    };
+    // results for x = 1, 4, 9, and the number of calls to the expensive op
+    let run = |bytecode: &[Bytecode]| {
+        EXPENSIVE_CALLS.store(0, Ordering::SeqCst);
+        let mut stack = vec![];
+        let results = [1, 4, 9]
+            .into_iter()
+            .map(|x| {
+                eval_bytecode(bytecode, [DataValue::from(x)], &mut stack)
+                    .unwrap()
+                    .get_float()
+                    .unwrap()
+            })
+            .collect_vec();
+        (results, EXPENSIVE_CALLS.load(Ordering::SeqCst))
+    };
+
+    let bytecode = compile("abs(x) + abs(x) * 2 - abs(x) / abs(x)");
+    assert_eq!(run(&bytecode), (vec![2., 11., 26.], 3));
+    // nested repeated subexpressions are shared as a whole
+    let bytecode = compile("(abs(x) + 1) * (abs(x) + 1) + abs(x)");
+    assert_eq!(run(&bytecode), (vec![5., 29., 109.], 3));
+    // a value computed unconditionally can be reused within branches
+    let bytecode = compile("abs(x) + if(x > 1, abs(x), 0)");
+    assert_eq!(run(&bytecode), (vec![1., 8., 18.], 3));
+    // but not the other way round: the branch may not have been taken
+    let bytecode = compile("if(x > 1, abs(x), 0) + abs(x)");
+    assert_eq!(run(&bytecode), (vec![1., 8., 18.], 5));
+    // without repetition, the bytecode is unchanged
+    let bytecode = compile("abs(x) + 1");
+    assert!(!matches!(bytecode[0], Bytecode::Reserve { .. }));
+    assert_eq!(run(&bytecode), (vec![2., 5., 10.], 3));
+    // non-deterministic applications are never shared
+    let bytecode = compile("rand_float() + rand_float()");
+    assert!(!bytecode
+        .iter()
+        .any(|code| matches!(code, Bytecode::Load { .. })));
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[x, y] := x in [1, 4], y = [abs(x) + abs(x), if(x > 1, abs(x)), abs(x)]",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    let list = |xs: Vec<DataValue>| DataValue::List(xs);
+    assert_eq!(
+        res,
+        vec![
+            vec![
+                DataValue::from(1),
+                list(vec![
+                    DataValue::from(2),
+                    DataValue::Null,
+                    DataValue::from(1)
+                ])
+            ],
+            vec![
+                DataValue::from(4),
+                list(vec![
+                    DataValue::from(8),
+                    DataValue::from(4),
+                    DataValue::from(4)
+                ])
+            ],
+        ]
+    );
+}
+
+#[test]
+fn standalone_expression() {
+    let params = [
+        ("x".to_string(), DataValue::from(4)),
+        ("name".to_string(), DataValue::from("cozo")),
+    ]
+    .into_iter()
+    .collect();
+    let eval = |src: &str| evaluate_expression(src, &params);
+
+    assert_eq!(eval("2 + 3 * $x").unwrap(), DataValue::from(14));
+    assert_eq!(
+        eval("if($x > 3, uppercase($name), 'small')").unwrap(),
+        DataValue::from("COZO")
+    );
+    assert_eq!(
+        eval("[$x, null]").unwrap(),
+        DataValue::List(vec![DataValue::from(4), DataValue::Null])
+    );
+
+    // variables cannot be bound
+    let err = eval("x + 1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The expression cannot be reduced to a value: add(x, 1)"
+    );
+    // errors raised during evaluation are reported
+    assert!(eval("1 / 'a'").is_err());
+    // parameters must be given
+    assert!(eval("$y + 1").is_err());
+    // only a single expression is accepted
+    assert!(eval("1 + 1; 2").is_err());
+    assert!(eval("").is_err());
+}
+
+#[test]
+fn tree_and_bytecode_eval_agree() {
+    let build = |src: &str| {
+        let mut expr = parse_expr(src).unwrap();
+        let x = Symbol::new("x", SourceSpan(0, 0));
+        expr.fill_binding_indices(&[(x, 0)].into()).unwrap();
+        expr
+    };
+    // operators of arity 0, 1, 2, 3 and variadic ones, also failing ones
+    let exprs = [
+        "rand_float() * 0 + x",
+        "abs(-x)",
+        "x * 2 - 1",
+        "clamp(x, 2, 5)",
+        "concat('a', to_string(x), 'b', 'c')",
+        "list(x, x + 1, x + 2, x + 3)",
+        "if(x > 3, x, -x)",
+        "x / (x - 4)",
+        "1 % (x - 4)",
+        "'a' + x",
+    ];
+    let mut stack = vec![];
+    for src in exprs {
+        let expr = build(src);
+        let bytecode = expr.compile();
+        for x in [1, 4, 9] {
+            let bindings = [DataValue::from(x)];
+            let by_tree = expr.eval(bindings.as_slice());
+            let by_bytecode = eval_bytecode(&bytecode, bindings.as_slice(), &mut stack);
+            match (by_tree, by_bytecode) {
+                (Ok(a), Ok(b)) => assert_eq!(a, b, "{src} for x = {x}"),
+                (Err(a), Err(b)) => assert_eq!(a.to_string(), b.to_string(), "{src} for x = {x}"),
+                (a, b) => panic!("{src} for x = {x}: {a:?} vs {b:?}"),
+            }
+        }
+    }
+}
+
+#[test]
+fn arithmetic_chains_are_fused() {
+    let build = |src: &str| {
+        let mut expr = parse_expr(src).unwrap();
+        let x = Symbol::new("x", SourceSpan(0, 0));
+        expr.fill_binding_indices(&[(x, 0)].into()).unwrap();
+        expr
+    };
+    let steps = |trace: Vec<RewriteStep>| trace.iter().map(|s| s.to_string()).collect_vec();
+
+    let (folded, trace) = build("x + 1 + x + 2").constant_fold_traced();
+    assert_eq!(folded.to_string(), "add_chain(x, 1, x, 2)");
+    assert_eq!(
+        steps(trace),
+        vec![
+            "fuse_chain: add(add(x, 1), x) -> add_chain(x, 1, x)",
+            "fuse_chain: add(add_chain(x, 1, x), 2) -> add_chain(x, 1, x, 2)",
+        ]
+    );
+    assert_eq!(
+        build("x * 2 * (x + 1 + x)").constant_fold().to_string(),
+        "mul_chain(x, 2, add_chain(x, 1, x))"
+    );
+    // only left-nested binary operations are chains
+    assert_eq!(
+        build("x + (1 + x)").constant_fold().to_string(),
+        "add(x, add(1, x))"
+    );
+    assert_eq!(
+        build("add(x, 1, 2) + 3").constant_fold().to_string(),
+        "add(add(x, 1, 2), 3)"
+    );
+    // the chains are only produced by the rewrite, not callable from scripts
+    assert!(parse_expr("add_chain(1, 2)").is_err());
+
+    // the fused form, run as bytecode, agrees with the nested one evaluated as a tree
+    let exprs = [
+        "x + 1 + x + 2 + x",
+        "x * 3 * x * 2",
+        "x + 1.5 + x + -1.5",
+        "x * 0.5 * x * 2",
+        // adding up the integers first would overflow
+        "x + 0.5 + 9223372036854775807",
+        "x + 1 + 'a' + 2",
+        "x * 2 * null",
+    ];
+    let mut stack = vec![];
+    for src in exprs {
+        let expr = build(src);
+        let bytecode = expr.compile();
+        for x in [DataValue::from(3), DataValue::from(-1.5)] {
+            let bindings = [x.clone()];
+            let nested = expr.eval(bindings.as_slice());
+            let fused = eval_bytecode(&bytecode, bindings.as_slice(), &mut stack);
+            match (nested, fused) {
+                (Ok(a), Ok(b)) => assert_eq!(a, b, "{src} for x = {x:?}"),
+                (Err(a), Err(b)) => assert_eq!(a.to_string(), b.to_string(), "{src} for x = {x:?}"),
+                (a, b) => panic!("{src} for x = {x:?}: {a:?} vs {b:?}"),
+            }
+        }
+    }
+}
+
+#[test]
+fn skipped_list_elements_are_dropped() {
+    let parse = |src: &str| parse_expr(src).unwrap();
+
+    let mut expr = parse("[x, if(1 > 2, x + 1, skip()), cond(false, 1, true, skip()), 3]");
+    let trace = expr.partial_eval_traced().unwrap();
+    assert_eq!(expr.to_string(), "list(x, 3)");
+    assert_eq!(
+        trace.last().unwrap().to_string(),
+        // skipped elements are shown as nulls
+        "drop_skipped_elements: list(x, null, null, 3) -> list(x, 3)"
+    );
+
+    let folded = parse("[x, if(1 > 2, x + 1, skip()), if(true, 2, skip())]").constant_fold();
+    assert_eq!(folded.to_string(), "list(x, 2)");
+
+    // without variables, the whole list is constant
+    let mut expr = parse("[1, if(1 > 2, 2, skip()), 3]");
+    expr.partial_eval().unwrap();
+    assert_eq!(
+        expr.get_const(),
+        Some(&DataValue::List(vec![
+            DataValue::from(1),
+            DataValue::from(3)
+        ]))
+    );
+
+    // skipping that depends on the bindings is kept
+    let mut expr = parse("[x, if(x > 1, x, skip())]");
+    expr.partial_eval().unwrap();
+    assert!(matches!(&expr, Expr::Apply { args, .. } if args.len() == 2));
+}
+
+#[test]
+fn compiled_expressions() {
+    let mut expr = CompiledExpression::new("x * 2 + y", &["y", "x"]).unwrap();
+    assert_eq!(
+        expr.eval(&[DataValue::from(1), DataValue::from(3)])
+            .unwrap(),
+        DataValue::from(7)
+    );
+    assert_eq!(
+        expr.eval(&[DataValue::from(0.5), DataValue::from(1)])
+            .unwrap(),
+        DataValue::from(2.5)
+    );
+    assert!(CompiledExpression::new("x + z", &["x"]).is_err());
+}
@@ -6,12 +6,16 @@
  * You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::cmp::{Ordering, Reverse};
+use std::hash::Hasher;
+
 use approx::AbsDiffEq;
 use num_traits::FloatConst;
 use regex::Regex;
 
+use crate::data::expr::Expr;
 use crate::data::functions::*;
-use crate::data::value::{DataValue, RegexWrapper};
+use crate::data::value::{DataValue, NullOrder, Num, RegexWrapper, Validity, ValidityTs};
 use crate::new_cozo_mem;
 
 #[test]
@@ -81,6 +85,192 @@ fn test_div() {
     );
 }
 
+#[test]
+fn test_division_by_zero() {
+    let int = DataValue::from;
+    let float = |f: f64| DataValue::from(f);
+    let is_nan = |v: DataValue| v.get_float().unwrap().is_nan();
+    let zeros = [
+        (int(1), int(0)),
+        (int(0), int(0)),
+        (float(1.), int(0)),
+        (int(1), float(0.)),
+        (float(0.), float(-0.)),
+    ];
+
+    // by default, errors for integers only
+    for (a, b) in zeros.clone() {
+        let ints = matches!(
+            (&a, &b),
+            (DataValue::Num(Num::Int(_)), DataValue::Num(Num::Int(_)))
+        );
+        let args = [a, b];
+        assert_eq!(op_div(&args).is_err(), ints);
+        assert_eq!(op_mod(&args).is_err(), ints);
+        assert_eq!(op_floor_div(&args).is_err(), ints);
+        assert_eq!(op_true_div(&args).is_err(), ints);
+    }
+    assert_eq!(
+        op_div(&[int(1), int(0)]).unwrap_err().to_string(),
+        "division by zero"
+    );
+    assert_eq!(op_div(&[float(1.), int(0)]).unwrap(), float(f64::INFINITY));
+    assert!(is_nan(op_div(&[float(0.), float(0.)]).unwrap()));
+    assert!(is_nan(op_mod(&[int(1), float(0.)]).unwrap()));
+    assert_eq!(op_div(&[int(1), int(2)]).unwrap(), float(0.5));
+
+    with_division_by_zero(DivisionByZero::Error, || {
+        for (a, b) in zeros.clone() {
+            let args = [a, b];
+            assert_eq!(op_div(&args).unwrap_err().to_string(), "division by zero");
+            assert!(op_mod(&args).is_err());
+            assert!(op_floor_div(&args).is_err());
+            assert!(op_true_div(&args).is_err());
+        }
+    });
+
+    with_division_by_zero(DivisionByZero::Null, || {
+        for (a, b) in zeros.clone() {
+            let args = [a, b];
+            assert_eq!(op_div(&args).unwrap(), DataValue::Null);
+            assert_eq!(op_mod(&args).unwrap(), DataValue::Null);
+            assert_eq!(op_floor_div(&args).unwrap(), DataValue::Null);
+            assert_eq!(op_true_div(&args).unwrap(), DataValue::Null);
+        }
+        assert_eq!(op_mod(&[int(7), int(4)]).unwrap(), int(3));
+        assert!(op_div(&[DataValue::from("a"), int(0)]).is_err());
+    });
+
+    with_division_by_zero(DivisionByZero::Infinity, || {
+        assert_eq!(op_div(&[int(1), int(0)]).unwrap(), float(f64::INFINITY));
+        assert_eq!(
+            op_div(&[float(-1.), int(0)]).unwrap(),
+            float(f64::NEG_INFINITY)
+        );
+        assert_eq!(
+            op_true_div(&[int(1), float(-0.)]).unwrap(),
+            float(f64::NEG_INFINITY)
+        );
+        assert_eq!(
+            op_floor_div(&[int(-3), int(0)]).unwrap(),
+            float(f64::NEG_INFINITY)
+        );
+        assert!(is_nan(op_floor_div(&[int(0), int(0)]).unwrap()));
+        assert!(is_nan(op_div(&[int(0), int(0)]).unwrap()));
+        assert!(is_nan(op_div(&[float(0.), float(0.)]).unwrap()));
+        assert!(is_nan(op_mod(&[int(1), int(0)]).unwrap()));
+        assert!(is_nan(op_mod(&[float(1.), float(0.)]).unwrap()));
+    });
+    // the mode is restored afterwards
+    assert!(op_div(&[int(1), int(0)]).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let query = "?[a, b, c] := y in [0], a = 1 / y, b = 0.0 / y, c = y % y";
+    assert!(db.run_script(query, Default::default()).is_err());
+    let res = db
+        .run_script("?[a] := y in [0], a = 1.0 / y", Default::default())
+        .unwrap()
+        .rows;
+    assert_eq!(res[0][0], float(f64::INFINITY));
+    db.set_division_by_zero(DivisionByZero::Error);
+    assert!(db
+        .run_script("?[a] := y in [0], a = 1.0 / y", Default::default())
+        .is_err());
+    db.set_division_by_zero(DivisionByZero::Null);
+    let res = db.run_script(query, Default::default()).unwrap().rows;
+    assert_eq!(res[0], vec![DataValue::Null; 3]);
+    db.set_division_by_zero(DivisionByZero::Infinity);
+    let res = db.run_script(query, Default::default()).unwrap().rows;
+    assert_eq!(res[0][0], float(f64::INFINITY));
+    assert!(is_nan(res[0][1].clone()));
+    assert!(is_nan(res[0][2].clone()));
+}
+
+#[test]
+fn test_floor_true_div() {
+    let int = DataValue::from;
+    let float = |f: f64| DataValue::from(f);
+
+    assert_eq!(op_floor_div(&[int(-7), int(2)]).unwrap(), int(-4));
+    assert_eq!(op_true_div(&[int(-7), int(2)]).unwrap(), float(-3.5));
+    assert_eq!(op_floor_div(&[int(7), int(2)]).unwrap(), int(3));
+    assert_eq!(op_floor_div(&[int(7), int(-2)]).unwrap(), int(-4));
+    assert_eq!(op_floor_div(&[int(-8), int(2)]).unwrap(), int(-4));
+    assert_eq!(op_floor_div(&[int(-7), int(-2)]).unwrap(), int(3));
+    assert_eq!(op_floor_div(&[float(-7.5), int(2)]).unwrap(), int(-4));
+    assert_eq!(op_true_div(&[int(6), int(3)]).unwrap(), float(2.));
+
+    assert!(op_floor_div(&[int(1), int(0)]).is_err());
+    assert_eq!(
+        op_floor_div(&[float(1.), float(0.)]).unwrap(),
+        float(f64::INFINITY)
+    );
+    assert!(op_floor_div(&[int(i64::MIN), int(-1)]).is_err());
+    assert!(op_true_div(&[int(1), int(0)]).is_err());
+    assert!(op_floor_div(&[DataValue::from("a"), int(1)]).is_err());
+
+    assert_eq!(
+        op_floor_div(&[DataValue::Null, int(2)]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_true_div(&[int(2), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+}
+
+#[test]
+fn test_clamp_wrap() {
+    let call = |f: fn(&[DataValue]) -> miette::Result<DataValue>,
+                x: DataValue,
+                lo: i64,
+                hi: i64| { f(&[x, DataValue::from(lo), DataValue::from(hi)]) };
+    let wrap = |x: i64, lo: i64, hi: i64| call(op_wrap, DataValue::from(x), lo, hi).unwrap();
+
+    assert_eq!(wrap(370, 0, 360), DataValue::from(10));
+    assert_eq!(wrap(720, 0, 360), DataValue::from(0));
+    assert_eq!(wrap(-10, 0, 360), DataValue::from(350));
+    assert_eq!(wrap(-370, 0, 360), DataValue::from(350));
+    assert_eq!(wrap(45, 0, 360), DataValue::from(45));
+    assert_eq!(wrap(0, 0, 360), DataValue::from(0));
+    assert_eq!(wrap(360, 0, 360), DataValue::from(0));
+    assert_eq!(wrap(190, -180, 180), DataValue::from(-170));
+    assert_eq!(
+        wrap(i64::MIN, i64::MAX - 1, i64::MAX),
+        DataValue::from(i64::MAX - 1)
+    );
+    assert_eq!(
+        call(op_wrap, DataValue::from(-0.5), 0, 360).unwrap(),
+        DataValue::from(359.5)
+    );
+    assert_eq!(
+        call(op_wrap, DataValue::from(365.25), 0, 360).unwrap(),
+        DataValue::from(5.25)
+    );
+    assert!(call(op_wrap, DataValue::from(1), 360, 0).is_err());
+    assert!(call(op_wrap, DataValue::from(1), 0, 0).is_err());
+    assert!(call(op_wrap, DataValue::from("a"), 0, 1).is_err());
+    assert_eq!(
+        call(op_wrap, DataValue::Null, 0, 1).unwrap(),
+        DataValue::Null
+    );
+
+    let clamp = |x: DataValue| call(op_clamp, x, 0, 10).unwrap();
+    assert_eq!(clamp(DataValue::from(-3)), DataValue::from(0));
+    assert_eq!(clamp(DataValue::from(12.5)), DataValue::from(10));
+    assert_eq!(clamp(DataValue::from(2.5)), DataValue::from(2.5));
+    assert_eq!(clamp(DataValue::Null), DataValue::Null);
+    assert_eq!(
+        call(op_clamp, DataValue::from(3), 1, 1).unwrap(),
+        DataValue::from(1)
+    );
+    assert!(call(op_clamp, DataValue::from(3), 2, 1).is_err());
+    assert_eq!(
+        call(op_clamp, DataValue::from(i64::MAX), 0, i64::MAX - 1).unwrap(),
+        DataValue::from(i64::MAX - 1)
+    );
+}
+
 #[test]
 fn test_eq_neq() {
     assert_eq!(
@@ -105,6 +295,64 @@ fn test_eq_neq() {
     );
 }
 
+#[test]
+fn test_eq_nested() {
+    let nested = |n: usize, last: DataValue| {
+        let mut rows = (0..n)
+            .map(|i| {
+                DataValue::List(vec![
+                    DataValue::from(i as i64),
+                    DataValue::List(vec![DataValue::from("x"), DataValue::from(i as f64)]),
+                ])
+            })
+            .collect::<Vec<_>>();
+        rows.push(last);
+        DataValue::List(rows)
+    };
+    let comparisons = |a: &DataValue, b: &DataValue| {
+        VALUES_EQ_COMPARISONS.with(|c| c.set(0));
+        let eq = op_eq(&[a.clone(), b.clone()]).unwrap();
+        let count = VALUES_EQ_COMPARISONS.with(|c| c.get());
+        assert_eq!(
+            op_neq(&[a.clone(), b.clone()]).unwrap(),
+            DataValue::from(eq == DataValue::from(false))
+        );
+        (eq, count)
+    };
+
+    let a = nested(1000, DataValue::from(1));
+    // integers and floats with the same value are equal inside lists too
+    let b = nested(1000, DataValue::from(1.0));
+    assert_eq!(comparisons(&a, &b), (DataValue::from(true), 3001));
+    // a difference in the last element is found
+    let c = nested(1000, DataValue::from(2));
+    assert_eq!(comparisons(&a, &c), (DataValue::from(false), 3001));
+    // lists of different lengths are unequal without comparing elements
+    let d = nested(999, DataValue::from(1));
+    assert_eq!(comparisons(&a, &d), (DataValue::from(false), 0));
+    let inner_longer = nested(
+        1000,
+        DataValue::List(vec![DataValue::from(1), DataValue::from(1)]),
+    );
+    assert_eq!(comparisons(&a, &inner_longer).0, DataValue::from(false));
+
+    let set = |l: Vec<DataValue>| DataValue::Set(l.into_iter().collect());
+    assert_eq!(
+        comparisons(
+            &set(vec![DataValue::from(1), DataValue::from(2.0)]),
+            &set(vec![DataValue::from(1.0), DataValue::from(2)])
+        ),
+        (DataValue::from(true), 2)
+    );
+    assert_eq!(
+        comparisons(
+            &set(vec![DataValue::from(1), DataValue::from(2)]),
+            &set(vec![DataValue::from(1)])
+        ),
+        (DataValue::from(false), 0)
+    );
+}
+
 #[test]
 fn test_list() {
     assert_eq!(op_list(&[]).unwrap(), DataValue::List(vec![]));
@@ -523,6 +771,184 @@ fn test_inv_trig() {
         .abs_diff_eq(&(-3. * f64::PI() / 4.), 1e-5));
 }
 
+#[test]
+fn test_any_all() {
+    let ints = |xs: &[i64]| DataValue::List(xs.iter().map(|x| DataValue::from(*x)).collect());
+    let any_all = |l: DataValue, f: &str, rest: &[DataValue]| {
+        let mut args = vec![l, DataValue::from(f)];
+        args.extend_from_slice(rest);
+        (
+            op_any(&args).unwrap().get_bool(),
+            op_all(&args).unwrap().get_bool(),
+        )
+    };
+    let zero = [DataValue::from(0)];
+
+    // all true
+    assert_eq!(
+        any_all(ints(&[1, 2, 3]), "gt", &zero),
+        (Some(true), Some(true))
+    );
+    // all false
+    assert_eq!(
+        any_all(ints(&[-1, -2]), "gt", &zero),
+        (Some(false), Some(false))
+    );
+    // mixed
+    assert_eq!(
+        any_all(ints(&[-1, 2]), "gt", &zero),
+        (Some(true), Some(false))
+    );
+    // empty
+    assert_eq!(any_all(ints(&[]), "gt", &zero), (Some(false), Some(true)));
+    assert_eq!(any_all(DataValue::Null, "gt", &zero), (None, None));
+
+    // short-circuiting: the string after the deciding element is never compared
+    let l = DataValue::List(vec![DataValue::from(1), DataValue::from("a")]);
+    assert_eq!(
+        op_any(&[l.clone(), DataValue::from("gt"), DataValue::from(0)]).unwrap(),
+        DataValue::from(true)
+    );
+    assert_eq!(
+        op_all(&[l.clone(), DataValue::from("gt"), DataValue::from(2)]).unwrap(),
+        DataValue::from(false)
+    );
+    assert!(op_all(&[l, DataValue::from("gt"), DataValue::from(0)]).is_err());
+
+    assert!(op_any(&[DataValue::from(1), DataValue::from("is_int")]).is_err());
+    assert!(op_all(&[DataValue::from("a"), DataValue::from("is_int")]).is_err());
+    assert!(op_all(&[DataValue::Null, DataValue::from("no_such_function")]).is_err());
+}
+
+#[test]
+fn test_fill_forward_backward() {
+    let list = |xs: &[Option<i64>]| {
+        DataValue::List(
+            xs.iter()
+                .map(|x| x.map(DataValue::from).unwrap_or(DataValue::Null))
+                .collect(),
+        )
+    };
+    let fill_forward = |xs: &[Option<i64>]| op_fill_forward(&[list(xs)]).unwrap();
+    let fill_backward = |xs: &[Option<i64>]| op_fill_backward(&[list(xs)]).unwrap();
+
+    let xs = [None, Some(1), None, None, Some(2), None];
+    assert_eq!(
+        fill_forward(&xs),
+        list(&[None, Some(1), Some(1), Some(1), Some(2), Some(2)])
+    );
+    assert_eq!(
+        fill_backward(&xs),
+        list(&[Some(1), Some(1), Some(2), Some(2), Some(2), None])
+    );
+    assert_eq!(fill_forward(&[None, None]), list(&[None, None]));
+    assert_eq!(fill_backward(&[None, None]), list(&[None, None]));
+    assert_eq!(fill_forward(&[]), list(&[]));
+    assert_eq!(fill_backward(&[Some(3)]), list(&[Some(3)]));
+
+    assert_eq!(
+        op_fill_forward(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_fill_backward(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_fill_forward(&[DataValue::from(1)]).is_err());
+    assert!(op_fill_backward(&[DataValue::from("a")]).is_err());
+}
+
+#[test]
+fn test_cumulative() {
+    let list = |xs: Vec<DataValue>| DataValue::List(xs);
+    let xs = list(vec![
+        DataValue::from(3),
+        DataValue::from(1),
+        DataValue::Null,
+        DataValue::from(4),
+        DataValue::from(0.5),
+    ]);
+    assert_eq!(
+        op_cumsum(std::slice::from_ref(&xs)).unwrap(),
+        list(vec![
+            DataValue::from(3),
+            DataValue::from(4),
+            DataValue::from(4),
+            DataValue::from(8),
+            DataValue::from(8.5),
+        ])
+    );
+    assert_eq!(
+        op_cumprod(std::slice::from_ref(&xs)).unwrap(),
+        list(vec![
+            DataValue::from(3),
+            DataValue::from(3),
+            DataValue::from(3),
+            DataValue::from(12),
+            DataValue::from(6.0),
+        ])
+    );
+    assert_eq!(
+        op_cummax(std::slice::from_ref(&xs)).unwrap(),
+        list(vec![
+            DataValue::from(3),
+            DataValue::from(3),
+            DataValue::from(3),
+            DataValue::from(4),
+            DataValue::from(4),
+        ])
+    );
+    assert_eq!(
+        op_cummin(&[xs]).unwrap(),
+        list(vec![
+            DataValue::from(3),
+            DataValue::from(1),
+            DataValue::from(1),
+            DataValue::from(1),
+            DataValue::from(0.5),
+        ])
+    );
+
+    // leading nulls have nothing to carry forward
+    assert_eq!(
+        op_cumsum(&[list(vec![DataValue::Null, DataValue::from(2)])]).unwrap(),
+        list(vec![DataValue::Null, DataValue::from(2)])
+    );
+    assert_eq!(op_cumsum(&[list(vec![])]).unwrap(), list(vec![]));
+    assert_eq!(op_cumprod(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert!(op_cummax(&[DataValue::from(1)]).is_err());
+    assert!(op_cummin(&[list(vec![DataValue::from("a")])]).is_err());
+}
+
+#[test]
+fn test_activations() {
+    let f = |op: fn(&[DataValue]) -> miette::Result<DataValue>, x: DataValue| {
+        op(&[x]).unwrap().get_float().unwrap()
+    };
+    assert!(f(op_tanh, DataValue::from(1)).abs_diff_eq(&0.7615941559557649, 1e-10));
+    assert_eq!(f(op_tanh, DataValue::from(1000.)), 1.);
+    assert_eq!(f(op_tanh, DataValue::from(-1000.)), -1.);
+
+    assert_eq!(f(op_sigmoid, DataValue::from(0)), 0.5);
+    assert!(f(op_sigmoid, DataValue::from(2.)).abs_diff_eq(&0.8807970779778823, 1e-10));
+    assert!(f(op_sigmoid, DataValue::from(-2.)).abs_diff_eq(&0.11920292202211755, 1e-10));
+    // no overflow for inputs of large magnitude
+    assert_eq!(f(op_sigmoid, DataValue::from(1000.)), 1.);
+    assert_eq!(f(op_sigmoid, DataValue::from(-1000.)), 0.);
+    assert!(f(op_sigmoid, DataValue::from(-700.)) > 0.);
+    assert_eq!(f(op_sigmoid, DataValue::from(f64::INFINITY)), 1.);
+    assert_eq!(f(op_sigmoid, DataValue::from(f64::NEG_INFINITY)), 0.);
+
+    assert_eq!(f(op_relu, DataValue::from(3)), 3.);
+    assert_eq!(f(op_relu, DataValue::from(-3)), 0.);
+    assert_eq!(f(op_relu, DataValue::from(2.5)), 2.5);
+
+    for op in [op_tanh, op_sigmoid, op_relu] {
+        assert_eq!(op(&[DataValue::Null]).unwrap(), DataValue::Null);
+        assert!(op(&[DataValue::from("a")]).is_err());
+    }
+}
+
 #[test]
 fn test_pow() {
     assert_eq!(
@@ -675,6 +1101,32 @@ fn test_trim() {
     );
 }
 
+#[test]
+fn test_count_matches() {
+    let count = |s: &str, needle: &str| {
+        op_count_matches(&[DataValue::from(s), DataValue::from(needle)]).unwrap()
+    };
+    assert_eq!(
+        count("ERROR a, ERROR b, WARN c, ERROR", "ERROR"),
+        DataValue::from(3)
+    );
+    // matches do not overlap
+    assert_eq!(count("aaaa", "aa"), DataValue::from(2));
+    assert_eq!(count("aaa", "aa"), DataValue::from(1));
+    assert_eq!(count("abc", "d"), DataValue::from(0));
+    assert_eq!(count("", "d"), DataValue::from(0));
+    assert_eq!(count("日本語と日本人", "日本"), DataValue::from(2));
+    assert_eq!(count("café cafe", "é"), DataValue::from(1));
+
+    assert!(op_count_matches(&[DataValue::from("abc"), DataValue::from("")]).is_err());
+    assert!(op_count_matches(&[DataValue::from("abc"), DataValue::from(1)]).is_err());
+    assert!(op_count_matches(&[DataValue::from(1), DataValue::from("a")]).is_err());
+    assert_eq!(
+        op_count_matches(&[DataValue::Null, DataValue::from("a")]).unwrap(),
+        DataValue::Null
+    );
+}
+
 #[test]
 fn test_starts_ends_with() {
     assert_eq!(
@@ -949,7 +1401,45 @@ fn test_unicode_normalize() {
         op_unicode_normalize(&[DataValue::Str("abc".into()), DataValue::Str("nfc".into())])
             .unwrap(),
         DataValue::Str("abc".into())
-    )
+    );
+
+    let composed = DataValue::Str("\u{e9}".into());
+    let decomposed = DataValue::Str("e\u{301}".into());
+    assert_ne!(
+        op_eq(&[composed.clone(), decomposed.clone()]).unwrap(),
+        DataValue::from(true)
+    );
+    assert_eq!(
+        op_unicode_normalize(&[decomposed.clone(), DataValue::Str("NFC".into())]).unwrap(),
+        composed
+    );
+    assert_eq!(
+        op_unicode_normalize(&[composed.clone(), DataValue::Str("NFD".into())]).unwrap(),
+        decomposed
+    );
+    assert_eq!(
+        op_eq(&[
+            op_unicode_normalize(&[composed.clone(), DataValue::Str("NFC".into())]).unwrap(),
+            op_unicode_normalize(&[decomposed, DataValue::Str("NFC".into())]).unwrap(),
+        ])
+        .unwrap(),
+        DataValue::from(true)
+    );
+    // compatibility forms fold ligatures
+    assert_eq!(
+        op_unicode_normalize(&[
+            DataValue::Str("\u{fb01}".into()),
+            DataValue::Str("NFKC".into())
+        ])
+        .unwrap(),
+        DataValue::Str("fi".into())
+    );
+    assert_eq!(
+        op_unicode_normalize(&[DataValue::Null, DataValue::Str("NFC".into())]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_unicode_normalize(&[composed.clone(), DataValue::Str("NFX".into())]).is_err());
+    assert!(op_unicode_normalize(&[DataValue::from(1), DataValue::Str("NFC".into())]).is_err());
 }
 
 #[test]
@@ -1062,6 +1552,38 @@ fn test_first_last() {
     );
 }
 
+#[test]
+fn test_transpose() {
+    let matrix = |rows: &[&[i64]]| {
+        DataValue::List(
+            rows.iter()
+                .map(|row| DataValue::List(row.iter().map(|x| DataValue::from(*x)).collect()))
+                .collect(),
+        )
+    };
+    let transpose = |m: DataValue| op_transpose(&[m]);
+
+    assert_eq!(
+        transpose(matrix(&[&[1, 2, 3], &[4, 5, 6]])).unwrap(),
+        matrix(&[&[1, 4], &[2, 5], &[3, 6]])
+    );
+    assert_eq!(
+        transpose(matrix(&[&[1, 4], &[2, 5], &[3, 6]])).unwrap(),
+        matrix(&[&[1, 2, 3], &[4, 5, 6]])
+    );
+    assert_eq!(
+        transpose(matrix(&[&[1, 2, 3]])).unwrap(),
+        matrix(&[&[1], &[2], &[3]])
+    );
+    assert_eq!(transpose(matrix(&[])).unwrap(), matrix(&[]));
+    assert_eq!(transpose(matrix(&[&[], &[]])).unwrap(), matrix(&[]));
+    assert_eq!(transpose(DataValue::Null).unwrap(), DataValue::Null);
+
+    assert!(transpose(matrix(&[&[1, 2], &[3]])).is_err());
+    assert!(transpose(DataValue::List(vec![DataValue::from(1)])).is_err());
+    assert!(transpose(DataValue::from(1)).is_err());
+}
+
 #[test]
 fn test_chunks() {
     assert_eq!(
@@ -1334,6 +1856,63 @@ fn test_rand() {
     );
 }
 
+#[test]
+fn test_sample_shuffle() {
+    let l = DataValue::List((0..20).map(DataValue::from).collect());
+    let sorted = |v: DataValue| {
+        let mut v = v.get_slice().unwrap().to_vec();
+        v.sort();
+        DataValue::List(v)
+    };
+
+    let seeded = |seed| {
+        with_query_seed(Some(seed), || {
+            (
+                op_sample(&[l.clone(), DataValue::from(5)]).unwrap(),
+                op_shuffle(std::slice::from_ref(&l)).unwrap(),
+            )
+        })
+    };
+    let (sample, shuffled) = seeded(42);
+    assert_eq!(seeded(42), (sample.clone(), shuffled.clone()));
+    assert_ne!(seeded(43), (sample.clone(), shuffled.clone()));
+    assert_eq!(current_query_seed(), None);
+
+    let mut sample = sample.get_slice().unwrap().to_vec();
+    assert_eq!(sample.len(), 5);
+    sample.sort();
+    sample.dedup();
+    assert_eq!(sample.len(), 5);
+    assert_eq!(sorted(shuffled), l);
+
+    let all = op_sample(&[l.clone(), DataValue::from(20)]).unwrap();
+    assert_eq!(sorted(all), l);
+    assert_eq!(
+        op_sample(&[l.clone(), DataValue::from(0)]).unwrap(),
+        DataValue::List(vec![])
+    );
+    assert!(op_sample(&[l.clone(), DataValue::from(21)]).is_err());
+    assert!(op_sample(&[l, DataValue::from(-1)]).is_err());
+    assert_eq!(
+        op_shuffle(&[DataValue::List(vec![])]).unwrap(),
+        DataValue::List(vec![])
+    );
+    assert_eq!(op_shuffle(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(
+        op_sample(&[DataValue::Null, DataValue::from(1)]).unwrap(),
+        DataValue::Null
+    );
+
+    // seeded results must not change with the platform or the version of Rust
+    let mut hasher = StableHasher::default();
+    hasher.write(b"cozo");
+    assert_eq!(hasher.finish(), 0x7d6a_580c_cb5c_ff0b);
+    let (mut a, mut b) = (StableHasher::default(), StableHasher::default());
+    a.write_usize(3);
+    b.write_u64(3);
+    assert_eq!(a.finish(), b.finish());
+}
+
 #[test]
 fn test_set_ops() {
     assert_eq!(
@@ -1456,3 +2035,1990 @@ fn test_coalesce() {
         .rows;
     assert_eq!(res[0][0], DataValue::from(2));
 }
+
+#[test]
+fn test_first_last_non_null() {
+    let (one, two) = (DataValue::from(1), DataValue::from(2));
+    let null = DataValue::Null;
+    assert_eq!(
+        op_last_non_null(&[one.clone(), two.clone(), null.clone(), null.clone()]).unwrap(),
+        two
+    );
+    assert_eq!(
+        op_last_non_null(&[null.clone(), null.clone(), one.clone(), two.clone()]).unwrap(),
+        two
+    );
+    assert_eq!(
+        op_last_non_null(&[null.clone(), one.clone(), null.clone()]).unwrap(),
+        one
+    );
+    assert_eq!(
+        op_last_non_null(&[null.clone(), null.clone()]).unwrap(),
+        null
+    );
+    assert_eq!(op_last_non_null(&[]).unwrap(), null);
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[a, b, c, d] := a = first_non_null(null, 1, 2, null), \
+                b = last_non_null(null, 1, 2, null), \
+                c = first_non_null(null, null), d = last_non_null(null, null)",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(res[0], vec![one, two, null.clone(), null]);
+}
+
+#[test]
+fn test_null_aware_arithmetic() {
+    assert_eq!(
+        op_add_nn(&[DataValue::Null, DataValue::from(1)]).unwrap(),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        op_add_nn(&[DataValue::from(1.5), DataValue::Null, DataValue::from(2)]).unwrap(),
+        DataValue::from(3.5)
+    );
+    assert_eq!(
+        op_mul_nn(&[DataValue::from(3), DataValue::Null]).unwrap(),
+        DataValue::from(3)
+    );
+    assert_eq!(
+        op_concat_nn(&[DataValue::from("a"), DataValue::Null, DataValue::from("b")]).unwrap(),
+        DataValue::from("ab")
+    );
+    assert!(op_add_nn(&[DataValue::from("a"), DataValue::Null]).is_err());
+
+    assert_eq!(
+        op_add_nn(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::from(0)
+    );
+    assert_eq!(
+        op_mul_nn(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        op_concat_nn(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::from("")
+    );
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script("?[a] := a = add_nn(null, 1)", Default::default())
+        .unwrap()
+        .rows;
+    assert_eq!(res[0][0], DataValue::from(1));
+}
+
+#[test]
+fn test_median_percentile() {
+    let odd = DataValue::List([3, 1, 2].into_iter().map(DataValue::from).collect());
+    let even = DataValue::List([4, 1, 3, 2].into_iter().map(DataValue::from).collect());
+    assert_eq!(
+        op_median(std::slice::from_ref(&odd)).unwrap(),
+        DataValue::from(2.)
+    );
+    assert_eq!(
+        op_median(std::slice::from_ref(&even)).unwrap(),
+        DataValue::from(2.5)
+    );
+    assert_eq!(
+        op_median(&[DataValue::List(vec![
+            DataValue::Null,
+            DataValue::from(1),
+            DataValue::from(5.)
+        ])])
+        .unwrap(),
+        DataValue::from(3.)
+    );
+
+    assert_eq!(
+        op_percentile(&[odd.clone(), DataValue::from(0)]).unwrap(),
+        DataValue::from(1.)
+    );
+    assert_eq!(
+        op_percentile(&[odd.clone(), DataValue::from(100)]).unwrap(),
+        DataValue::from(3.)
+    );
+    assert_eq!(
+        op_percentile(&[odd.clone(), DataValue::from(25)]).unwrap(),
+        DataValue::from(1.5)
+    );
+    assert_eq!(
+        op_percentile(&[even.clone(), DataValue::from(50)]).unwrap(),
+        DataValue::from(2.5)
+    );
+    assert_eq!(
+        op_percentile(&[even, DataValue::from(90)]).unwrap(),
+        DataValue::from(3.7)
+    );
+
+    assert!(op_median(&[DataValue::List(vec![])]).is_err());
+    assert!(op_median(&[DataValue::List(vec![DataValue::Null])]).is_err());
+    assert!(op_median(&[DataValue::List(vec![DataValue::from("a")])]).is_err());
+    assert!(op_percentile(&[odd.clone(), DataValue::from(101)]).is_err());
+    assert!(op_percentile(&[odd, DataValue::from(-1)]).is_err());
+}
+
+#[test]
+fn test_bytes_ints() {
+    let bytes = DataValue::Bytes(vec![0, 1, 127, 128, 255]);
+    let ints = op_bytes_to_ints(std::slice::from_ref(&bytes)).unwrap();
+    assert_eq!(
+        ints,
+        DataValue::List(
+            [0, 1, 127, 128, 255]
+                .into_iter()
+                .map(DataValue::from)
+                .collect()
+        )
+    );
+    assert_eq!(op_ints_to_bytes(&[ints]).unwrap(), bytes);
+    let empty = DataValue::Bytes(vec![]);
+    let empty_ints = op_bytes_to_ints(std::slice::from_ref(&empty)).unwrap();
+    assert_eq!(empty_ints, DataValue::List(vec![]));
+    assert_eq!(op_ints_to_bytes(&[empty_ints]).unwrap(), empty);
+
+    let byte_at = |i: i64| op_byte_at(&[bytes.clone(), DataValue::from(i)]);
+    assert_eq!(byte_at(0).unwrap(), DataValue::from(0));
+    assert_eq!(byte_at(3).unwrap(), DataValue::from(128));
+    assert_eq!(byte_at(-1).unwrap(), DataValue::from(255));
+    assert!(byte_at(5).is_err());
+    assert!(byte_at(-6).is_err());
+
+    for bad in [-1, 256] {
+        assert!(op_ints_to_bytes(&[DataValue::List(vec![DataValue::from(bad)])]).is_err());
+    }
+    assert!(op_ints_to_bytes(&[DataValue::List(vec![DataValue::from("a")])]).is_err());
+    assert!(op_bytes_to_ints(&[DataValue::from("a")]).is_err());
+    assert!(op_byte_at(&[DataValue::from("a"), DataValue::from(0)]).is_err());
+
+    assert_eq!(
+        op_bytes_to_ints(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_ints_to_bytes(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_byte_at(&[DataValue::Null, DataValue::from(0)]).unwrap(),
+        DataValue::Null
+    );
+}
+
+#[test]
+fn test_base32_base58() {
+    for (raw, encoded) in [
+        ("", ""),
+        ("f", "MY======"),
+        ("fo", "MZXQ===="),
+        ("foob", "MZXW6YQ="),
+        ("foobar", "MZXW6YTBOI======"),
+    ] {
+        assert_eq!(
+            op_to_base32(&[DataValue::from(raw)]).unwrap(),
+            DataValue::from(encoded)
+        );
+        assert_eq!(
+            op_from_base32(&[DataValue::from(encoded)]).unwrap(),
+            DataValue::Bytes(raw.as_bytes().to_vec())
+        );
+    }
+    assert!(op_from_base32(&[DataValue::from("MY")]).is_err());
+    assert!(op_from_base32(&[DataValue::from("mzxq====")]).is_err());
+    assert!(op_from_base32(&[DataValue::from(1)]).is_err());
+
+    assert_eq!(
+        op_to_base58(&[DataValue::from("hello world")]).unwrap(),
+        DataValue::from("StV1DL6CwTryKyV")
+    );
+    assert_eq!(
+        op_to_base58(&[DataValue::Bytes(vec![0, 0, 1])]).unwrap(),
+        DataValue::from("112")
+    );
+    assert_eq!(
+        op_from_base58(&[DataValue::from("112")]).unwrap(),
+        DataValue::Bytes(vec![0, 0, 1])
+    );
+    assert_eq!(
+        op_from_base58(&[DataValue::from("StV1DL6CwTryKyV")]).unwrap(),
+        DataValue::Bytes(b"hello world".to_vec())
+    );
+    assert!(op_from_base58(&[DataValue::from("0OIl")]).is_err());
+
+    assert_eq!(op_to_base32(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(op_from_base32(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(op_to_base58(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(op_from_base58(&[DataValue::Null]).unwrap(), DataValue::Null);
+}
+
+#[test]
+fn test_greatest_least() {
+    assert_eq!(
+        op_greatest(&[DataValue::from(1), DataValue::Null, DataValue::from(3)]).unwrap(),
+        DataValue::from(3)
+    );
+    assert_eq!(
+        op_least(&[DataValue::from(1), DataValue::Null, DataValue::from(3)]).unwrap(),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        op_greatest(&[DataValue::from("a"), DataValue::from("b"), DataValue::Null]).unwrap(),
+        DataValue::from("b")
+    );
+    assert_eq!(
+        op_least(&[DataValue::Null, DataValue::from(2.5), DataValue::from(2)]).unwrap(),
+        DataValue::from(2)
+    );
+
+    assert_eq!(
+        op_greatest(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_least(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+
+    assert_eq!(
+        op_greatest(&[DataValue::from(7)]).unwrap(),
+        DataValue::from(7)
+    );
+    assert_eq!(op_least(&[DataValue::Null]).unwrap(), DataValue::Null);
+}
+
+#[test]
+fn test_case_conversion() {
+    assert_eq!(
+        op_capitalize(&[DataValue::from("élan vital")]).unwrap(),
+        DataValue::from("Élan vital")
+    );
+    assert_eq!(
+        op_capitalize(&[DataValue::from("ßtraße")]).unwrap(),
+        DataValue::from("SStraße")
+    );
+    assert_eq!(
+        op_capitalize(&[DataValue::from("")]).unwrap(),
+        DataValue::from("")
+    );
+    assert_eq!(
+        op_title_case(&[DataValue::from("the quick\tbrown  fox")]).unwrap(),
+        DataValue::from("The Quick\tBrown  Fox")
+    );
+    assert_eq!(
+        op_title_case(&[DataValue::from("ǉubljana öl-tanker o'neil")]).unwrap(),
+        DataValue::from("Ǉubljana Öl-tanker O'neil")
+    );
+    assert_eq!(
+        op_swap_case(&[DataValue::from("Hello Wörld ΣΑΣ")]).unwrap(),
+        DataValue::from("hELLO wÖRLD σασ")
+    );
+    assert_eq!(
+        op_swap_case(&[DataValue::from("ﬁx 123")]).unwrap(),
+        DataValue::from("FIX 123")
+    );
+    for op in [op_capitalize, op_title_case, op_swap_case] {
+        assert_eq!(op(&[DataValue::Null]).unwrap(), DataValue::Null);
+        assert!(op(&[DataValue::from(1)]).is_err());
+    }
+}
+
+#[test]
+fn test_similarity() {
+    let sim = |a: &str, b: &str| {
+        op_similarity(&[DataValue::from(a), DataValue::from(b)])
+            .unwrap()
+            .get_float()
+            .unwrap()
+    };
+    assert_eq!(sim("cozo", "cozo"), 1.);
+    // case and punctuation are ignored, as are word orders
+    assert_eq!(sim("Hello, World!", "world hello"), 1.);
+    // 4 shared trigrams out of 11, as in pg_trgm
+    assert!((sim("word", "two words") - 4. / 11.).abs() < 1e-10);
+    assert_eq!(sim("word", "two words"), sim("two words", "word"));
+    assert!((sim("café", "cafe") - 3. / 7.).abs() < 1e-10);
+    assert_eq!(sim("abc", "xyz"), 0.);
+    assert_eq!(sim("", ""), 1.);
+    assert_eq!(sim("", "--"), 1.);
+    assert_eq!(sim("", "a"), 0.);
+
+    assert_eq!(
+        op_similarity(&[DataValue::Null, DataValue::from("a")]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_similarity(&[DataValue::from("a"), DataValue::from(1)]).is_err());
+    assert!(op_similarity(&[DataValue::List(vec![]), DataValue::from("a")]).is_err());
+}
+
+#[test]
+fn test_jaccard_cosine() {
+    let list = |v: Vec<DataValue>| DataValue::List(v);
+    assert_eq!(
+        op_jaccard(&[
+            list(vec![
+                DataValue::from(1),
+                DataValue::from(2),
+                DataValue::from(3)
+            ]),
+            list(vec![
+                DataValue::from(2),
+                DataValue::from(3),
+                DataValue::from(4)
+            ]),
+        ])
+        .unwrap(),
+        DataValue::from(0.5)
+    );
+    // duplicates are ignored and `1` equals `1.0`
+    assert_eq!(
+        op_jaccard(&[
+            list(vec![
+                DataValue::from(1),
+                DataValue::from(1),
+                DataValue::from("a")
+            ]),
+            list(vec![DataValue::from(1.0)]),
+        ])
+        .unwrap(),
+        DataValue::from(0.5)
+    );
+    assert_eq!(
+        op_jaccard(&[list(vec![]), list(vec![])]).unwrap(),
+        DataValue::from(1.0)
+    );
+    assert_eq!(
+        op_jaccard(&[list(vec![]), list(vec![DataValue::from(1)])]).unwrap(),
+        DataValue::from(0.0)
+    );
+    assert_eq!(
+        op_jaccard(&[DataValue::Null, list(vec![])]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_jaccard(&[DataValue::from(1), list(vec![])]).is_err());
+
+    let cos = op_cosine(&[
+        list(vec![DataValue::from(1), DataValue::from(0)]),
+        list(vec![DataValue::from(1), DataValue::from(1)]),
+    ])
+    .unwrap()
+    .get_float()
+    .unwrap();
+    assert!(cos.abs_diff_eq(&f64::FRAC_1_SQRT_2(), 1e-10));
+    assert_eq!(
+        op_cosine(&[
+            list(vec![DataValue::from(1.5), DataValue::from(2)]),
+            list(vec![DataValue::from(-3), DataValue::from(-4)]),
+        ])
+        .unwrap(),
+        DataValue::from(-1.0)
+    );
+    assert_eq!(
+        op_cosine(&[
+            list(vec![DataValue::from(1), DataValue::from(0)]),
+            list(vec![DataValue::from(0), DataValue::from(2)]),
+        ])
+        .unwrap(),
+        DataValue::from(0.0)
+    );
+    assert_eq!(
+        op_cosine(&[list(vec![]), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_cosine(&[list(vec![]), list(vec![])]).is_err());
+    assert!(op_cosine(&[
+        list(vec![DataValue::from(1)]),
+        list(vec![DataValue::from(1), DataValue::from(2)])
+    ])
+    .is_err());
+    assert!(op_cosine(&[
+        list(vec![DataValue::from("a")]),
+        list(vec![DataValue::from(1)])
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_skip_list_elements() {
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            r#"
+            ?[x, l] := x in [1, 2],
+                       l = [x, if(x == 1, 'one', skip()), skip(), cond(x > 1, 'big', true, skip())]
+            "#,
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res[0][1],
+        DataValue::List(vec![DataValue::from(1), DataValue::from("one")])
+    );
+    assert_eq!(
+        res[1][1],
+        DataValue::List(vec![DataValue::from(2), DataValue::from("big")])
+    );
+
+    let res = db
+        .run_script("?[l] := l = list(1, skip(), [skip()])", Default::default())
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res[0][0],
+        DataValue::List(vec![DataValue::from(1), DataValue::List(vec![])])
+    );
+
+    assert!(db
+        .run_script("?[x] := x = skip()", Default::default())
+        .is_err());
+    assert!(db
+        .run_script("?[x] := x = [1 + skip()]", Default::default())
+        .is_err());
+}
+
+#[test]
+fn test_approx_eq() {
+    let sum = op_add(&[DataValue::from(0.1), DataValue::from(0.2)]).unwrap();
+    assert_eq!(
+        op_eq(&[sum.clone(), DataValue::from(0.3)]).unwrap(),
+        DataValue::from(false)
+    );
+    assert_eq!(
+        op_approx_eq(&[sum.clone(), DataValue::from(0.3)]).unwrap(),
+        DataValue::from(true)
+    );
+    assert_eq!(
+        op_approx_eq(&[sum, DataValue::from(0.3), DataValue::from(1e-20)]).unwrap(),
+        DataValue::from(false)
+    );
+    assert_eq!(
+        op_approx_eq(&[
+            DataValue::from(1),
+            DataValue::from(1.5),
+            DataValue::from(0.5)
+        ])
+        .unwrap(),
+        DataValue::from(true)
+    );
+    assert_eq!(
+        op_approx_eq(&[DataValue::from(1), DataValue::from(1.0001)]).unwrap(),
+        DataValue::from(false)
+    );
+    assert_eq!(
+        op_approx_eq(&[DataValue::from(f64::NAN), DataValue::from(f64::NAN)]).unwrap(),
+        DataValue::from(false)
+    );
+    assert_eq!(
+        op_approx_eq(&[
+            DataValue::from(f64::NAN),
+            DataValue::from(1.),
+            DataValue::from(f64::INFINITY)
+        ])
+        .unwrap(),
+        DataValue::from(false)
+    );
+    assert_eq!(
+        op_approx_eq(&[DataValue::Null, DataValue::from(1.)]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_approx_eq(&[DataValue::from("a"), DataValue::from(1.)]).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script("?[a] := a = approx_eq(0.1 + 0.2, 0.3)", Default::default())
+        .unwrap()
+        .rows;
+    assert_eq!(res[0][0], DataValue::from(true));
+}
+
+#[test]
+fn test_group_by() {
+    let row = |name: &str, n: DataValue| DataValue::List(vec![DataValue::from(name), n]);
+    let rows = DataValue::List(vec![
+        row("a", DataValue::from(1)),
+        row("b", DataValue::from(2)),
+        row("a", DataValue::from(3)),
+        row("c", DataValue::from(1.0)),
+    ]);
+    let group_by = |key_args: &[DataValue]| {
+        let mut args = vec![rows.clone()];
+        args.extend_from_slice(key_args);
+        op_group_by(&args)
+    };
+    assert_eq!(
+        group_by(&[DataValue::from("get"), DataValue::from(0)]).unwrap(),
+        DataValue::List(vec![
+            DataValue::List(vec![
+                DataValue::from("a"),
+                DataValue::List(vec![
+                    row("a", DataValue::from(1)),
+                    row("a", DataValue::from(3))
+                ])
+            ]),
+            DataValue::List(vec![
+                DataValue::from("b"),
+                DataValue::List(vec![row("b", DataValue::from(2))])
+            ]),
+            DataValue::List(vec![
+                DataValue::from("c"),
+                DataValue::List(vec![row("c", DataValue::from(1.0))])
+            ]),
+        ])
+    );
+    // `1` and `1.0` are the same key
+    assert_eq!(
+        group_by(&[DataValue::from("last")]).unwrap(),
+        DataValue::List(vec![
+            DataValue::List(vec![
+                DataValue::from(1),
+                DataValue::List(vec![
+                    row("a", DataValue::from(1)),
+                    row("c", DataValue::from(1.0))
+                ])
+            ]),
+            DataValue::List(vec![
+                DataValue::from(2),
+                DataValue::List(vec![row("b", DataValue::from(2))])
+            ]),
+            DataValue::List(vec![
+                DataValue::from(3),
+                DataValue::List(vec![row("a", DataValue::from(3))])
+            ]),
+        ])
+    );
+    assert_eq!(
+        op_group_by(&[
+            DataValue::List(vec![]),
+            DataValue::from("get"),
+            DataValue::from(0)
+        ])
+        .unwrap(),
+        DataValue::List(vec![])
+    );
+    assert_eq!(
+        op_group_by(&[DataValue::Null, DataValue::from("first")]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_group_by(&[DataValue::from("a"), DataValue::from("first")]).is_err());
+    // the key is given by a function, not a position
+    assert!(group_by(&[DataValue::from(0)]).is_err());
+    assert!(group_by(&[DataValue::from("no_such_function")]).is_err());
+    assert!(group_by(&[DataValue::from("get"), DataValue::from(5)]).is_err());
+    // keys must be strings or numbers
+    assert!(group_by(&[DataValue::from("is_null")]).is_err());
+}
+
+#[test]
+fn test_list_set_ops() {
+    let list = |v: Vec<i64>| DataValue::List(v.into_iter().map(DataValue::from).collect());
+    let overlapping = [list(vec![3, 1, 3, 2]), list(vec![2, 4, 3, 4])];
+    let disjoint = [list(vec![1, 1, 2]), list(vec![3, 4])];
+    let empty = [list(vec![]), list(vec![])];
+
+    assert_eq!(op_list_union(&overlapping).unwrap(), list(vec![3, 1, 2, 4]));
+    assert_eq!(op_list_union(&disjoint).unwrap(), list(vec![1, 2, 3, 4]));
+    assert_eq!(op_list_union(&empty).unwrap(), list(vec![]));
+
+    assert_eq!(op_list_intersect(&overlapping).unwrap(), list(vec![3, 2]));
+    assert_eq!(op_list_intersect(&disjoint).unwrap(), list(vec![]));
+    assert_eq!(op_list_intersect(&empty).unwrap(), list(vec![]));
+
+    assert_eq!(op_list_difference(&overlapping).unwrap(), list(vec![1]));
+    assert_eq!(op_list_difference(&disjoint).unwrap(), list(vec![1, 2]));
+    assert_eq!(op_list_difference(&empty).unwrap(), list(vec![]));
+
+    // `1` and `1.0` are the same element
+    assert_eq!(
+        op_list_intersect(&[
+            list(vec![1, 2]),
+            DataValue::List(vec![DataValue::from(1.0)])
+        ])
+        .unwrap(),
+        list(vec![1])
+    );
+
+    for op in [op_list_union, op_list_intersect, op_list_difference] {
+        assert_eq!(
+            op(&[DataValue::Null, list(vec![1])]).unwrap(),
+            DataValue::Null
+        );
+        assert!(op(&[DataValue::from(1), list(vec![1])]).is_err());
+    }
+}
+
+#[test]
+fn test_if_null() {
+    assert_eq!(
+        op_if_null(&[DataValue::Null, DataValue::from(1)]).unwrap(),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        op_if_null(&[DataValue::from(2), DataValue::from(1)]).unwrap(),
+        DataValue::from(2)
+    );
+    assert_eq!(
+        op_if_null(&[DataValue::Null, DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_if_not_null(&[DataValue::Null, DataValue::from(1)]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_if_not_null(&[DataValue::from(2), DataValue::from(1)]).unwrap(),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        op_if_not_null(&[DataValue::from(false), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+
+    let mut expr = Expr::Apply {
+        op: &OP_IF_NULL,
+        args: [
+            Expr::Const {
+                val: DataValue::Null,
+                span: Default::default(),
+            },
+            Expr::Const {
+                val: DataValue::from("fallback"),
+                span: Default::default(),
+            },
+        ]
+        .into(),
+        span: Default::default(),
+    };
+    expr.partial_eval().unwrap();
+    assert_eq!(
+        expr,
+        Expr::Const {
+            val: DataValue::from("fallback"),
+            span: Default::default()
+        }
+    );
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[a, b] := x in [null, 1], a = if_null(x, 0), b = if_not_null(x, 'present')",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res,
+        vec![
+            vec![DataValue::from(0), DataValue::Null],
+            vec![DataValue::from(1), DataValue::from("present")]
+        ]
+    );
+}
+
+#[test]
+fn test_json_lines() {
+    let ndjson = "{\"level\": \"info\", \"n\": 1}\n\n[1, 2.5, null]\n  \"text\"  \n";
+    let parsed = op_from_json_lines(&[DataValue::from(ndjson)]).unwrap();
+    assert_eq!(
+        parsed,
+        DataValue::List(vec![
+            DataValue::List(vec![
+                DataValue::List(vec![DataValue::from("level"), DataValue::from("info")]),
+                DataValue::List(vec![DataValue::from("n"), DataValue::from(1)]),
+            ]),
+            DataValue::List(vec![
+                DataValue::from(1),
+                DataValue::from(2.5),
+                DataValue::Null
+            ]),
+            DataValue::from("text"),
+        ])
+    );
+    let dumped = op_to_json_lines(std::slice::from_ref(&parsed)).unwrap();
+    assert_eq!(
+        dumped,
+        DataValue::from("[[\"level\",\"info\"],[\"n\",1]]\n[1,2.5,null]\n\"text\"")
+    );
+    assert_eq!(op_from_json_lines(&[dumped]).unwrap(), parsed);
+
+    assert_eq!(
+        op_from_json_lines(&[DataValue::from("")]).unwrap(),
+        DataValue::List(vec![])
+    );
+    assert_eq!(
+        op_to_json_lines(&[DataValue::List(vec![])]).unwrap(),
+        DataValue::from("")
+    );
+    let err = op_from_json_lines(&[DataValue::from("1\n\n{oops}\n")]).unwrap_err();
+    assert!(err.to_string().contains("line 3"));
+    assert_eq!(
+        op_from_json_lines(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_to_json_lines(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_from_json_lines(&[DataValue::from(1)]).is_err());
+    assert!(op_to_json_lines(&[DataValue::from("a")]).is_err());
+}
+
+#[test]
+fn test_json_merge_patch() {
+    use serde_json::json;
+
+    let patch = |target, patch| {
+        op_json_merge_patch(&[DataValue::from(target), DataValue::from(patch)]).unwrap()
+    };
+    // the examples of RFC 7386
+    let examples = [
+        (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+        (
+            json!({"a": "b"}),
+            json!({"b": "c"}),
+            json!({"a": "b", "b": "c"}),
+        ),
+        (json!({"a": "b"}), json!({"a": null}), json!({})),
+        (
+            json!({"a": "b", "b": "c"}),
+            json!({"a": null}),
+            json!({"b": "c"}),
+        ),
+        (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+        (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+        (
+            json!({"a": {"b": "c"}}),
+            json!({"a": {"b": "d", "c": null}}),
+            json!({"a": {"b": "d"}}),
+        ),
+        (
+            json!({"a": [{"b": "c"}]}),
+            json!({"a": [1]}),
+            json!({"a": [1]}),
+        ),
+        (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+        (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+        (json!({"a": "foo"}), json!(null), json!(null)),
+        (json!({"a": "foo"}), json!("bar"), json!("bar")),
+        (
+            json!({"e": null}),
+            json!({"a": 1}),
+            json!({"e": null, "a": 1}),
+        ),
+        (
+            json!([1, 2]),
+            json!({"a": "b", "c": null}),
+            json!({"a": "b"}),
+        ),
+        (
+            json!({}),
+            json!({"a": {"bb": {"ccc": null}}}),
+            json!({"a": {"bb": {}}}),
+        ),
+    ];
+    for (target, p, expected) in examples {
+        assert_eq!(
+            patch(target.clone(), p.clone()),
+            DataValue::from(expected),
+            "{target} patched with {p}"
+        );
+    }
+    // null targets are not patched
+    assert_eq!(patch(json!(null), json!({"a": 1})), DataValue::Null);
+}
+
+#[test]
+fn test_json_path() {
+    let doc = DataValue::from(serde_json::json!({
+        "a": {"b": [{"c": 1}, {"c": 2, "d": null}]},
+        "key with.dot": "x",
+        "tags": ["p", "q", "r"]
+    }));
+    let get = |path: &str| op_json_path(&[doc.clone(), DataValue::from(path)]);
+
+    assert_eq!(get("$.a.b[0].c").unwrap(), DataValue::from(1));
+    assert_eq!(get("$.a.b[1].c").unwrap(), DataValue::from(2));
+    assert_eq!(get("$['key with.dot']").unwrap(), DataValue::from("x"));
+    assert_eq!(get("$.tags[-1]").unwrap(), DataValue::from("r"));
+    assert_eq!(get("$[\"tags\"][1]").unwrap(), DataValue::from("q"));
+    assert_eq!(get("$").unwrap(), doc);
+    assert_eq!(
+        get("$.a.b[0]").unwrap(),
+        DataValue::List(vec![DataValue::List(vec![
+            DataValue::from("c"),
+            DataValue::from(1)
+        ])])
+    );
+
+    // missing steps give null
+    assert_eq!(get("$.a.x.c").unwrap(), DataValue::Null);
+    assert_eq!(get("$.a.b[5].c").unwrap(), DataValue::Null);
+    assert_eq!(get("$.tags[-4]").unwrap(), DataValue::Null);
+    assert_eq!(get("$.tags.p").unwrap(), DataValue::Null);
+    assert_eq!(get("$.a.b[1].d.e").unwrap(), DataValue::Null);
+    assert_eq!(
+        op_json_path(&[DataValue::Null, DataValue::from("$.a")]).unwrap(),
+        DataValue::Null
+    );
+
+    for malformed in ["a.b", "$.", "$.a..b", "$[1", "$[x]", "$a"] {
+        assert!(get(malformed).is_err(), "{malformed}");
+    }
+    assert!(op_json_path(&[doc.clone(), DataValue::from(1)]).is_err());
+}
+
+#[test]
+fn test_urls() {
+    let obj = |pairs: Vec<(&str, DataValue)>| {
+        DataValue::List(
+            pairs
+                .into_iter()
+                .map(|(k, v)| DataValue::List(vec![DataValue::from(k), v]))
+                .collect(),
+        )
+    };
+    let url = |s: &str| op_parse_url(&[DataValue::from(s)]);
+
+    assert_eq!(
+        url("https://user@example.com:8443/a/b%20c?x=1&y=two#frag").unwrap(),
+        obj(vec![
+            ("scheme", DataValue::from("https")),
+            ("host", DataValue::from("example.com")),
+            ("port", DataValue::from(8443)),
+            ("path", DataValue::from("/a/b%20c")),
+            ("query", DataValue::from("x=1&y=two")),
+            ("fragment", DataValue::from("frag")),
+        ])
+    );
+    // default ports are filled in
+    assert_eq!(
+        url("http://example.com").unwrap(),
+        obj(vec![
+            ("scheme", DataValue::from("http")),
+            ("host", DataValue::from("example.com")),
+            ("port", DataValue::from(80)),
+            ("path", DataValue::from("/")),
+            ("query", DataValue::Null),
+            ("fragment", DataValue::Null),
+        ])
+    );
+    assert_eq!(
+        url("/search?q=cozo#results").unwrap(),
+        obj(vec![
+            ("scheme", DataValue::Null),
+            ("host", DataValue::Null),
+            ("port", DataValue::Null),
+            ("path", DataValue::from("/search")),
+            ("query", DataValue::from("q=cozo")),
+            ("fragment", DataValue::from("results")),
+        ])
+    );
+    assert_eq!(
+        op_json_path(&[url("docs/index.html").unwrap(), DataValue::from("$.path")]).unwrap(),
+        DataValue::from("docs/index.html")
+    );
+    for malformed in [
+        "http://",
+        "https://exa mple.com",
+        "http://[::1/",
+        "http://a.com:99999",
+    ] {
+        assert!(url(malformed).is_err(), "{malformed}");
+    }
+    assert_eq!(op_parse_url(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert!(op_parse_url(&[DataValue::from(1)]).is_err());
+
+    let query = |s: &str| op_parse_query(&[DataValue::from(s)]).unwrap();
+    assert_eq!(
+        query("?a=1&b=hello+world&c=%26%3D&a=2&d&a=3"),
+        obj(vec![
+            (
+                "a",
+                DataValue::List(vec![
+                    DataValue::from("1"),
+                    DataValue::from("2"),
+                    DataValue::from("3")
+                ])
+            ),
+            ("b", DataValue::from("hello world")),
+            ("c", DataValue::from("&=")),
+            ("d", DataValue::from("")),
+        ])
+    );
+    assert_eq!(query(""), DataValue::List(vec![]));
+    assert_eq!(op_parse_query(&[DataValue::Null]).unwrap(), DataValue::Null);
+
+    let encode = |v: DataValue| op_encode_url_query(&[v]);
+    let params = obj(vec![
+        (
+            "a",
+            DataValue::List(vec![DataValue::from("1"), DataValue::from(2)]),
+        ),
+        ("b", DataValue::from("hello world")),
+        ("c", DataValue::from("&=")),
+        ("d", DataValue::Null),
+        ("e", DataValue::from(true)),
+    ]);
+    assert_eq!(
+        encode(params).unwrap(),
+        DataValue::from("a=1&a=2&b=hello+world&c=%26%3D&d=&e=true")
+    );
+    // round trip
+    assert_eq!(
+        encode(query("x=1&y=%C3%A9&x=2")).unwrap(),
+        DataValue::from("x=1&x=2&y=%C3%A9")
+    );
+    assert_eq!(encode(DataValue::Null).unwrap(), DataValue::Null);
+    assert!(encode(DataValue::from("a=1")).is_err());
+    assert!(encode(DataValue::List(vec![DataValue::from("a")])).is_err());
+    assert!(encode(obj(vec![("a", obj(vec![("b", DataValue::from(1))]))])).is_err());
+}
+
+#[test]
+fn test_parse_number() {
+    let cases = [
+        ("42", DataValue::from(42)),
+        ("-17", DataValue::from(-17)),
+        ("+8", DataValue::from(8)),
+        ("  12  ", DataValue::from(12)),
+        ("\t3.25\n", DataValue::from(3.25)),
+        ("-0.5", DataValue::from(-0.5)),
+        (".5", DataValue::from(0.5)),
+        ("1e3", DataValue::from(1000.0)),
+        ("-2.5E-2", DataValue::from(-0.025)),
+        ("99999999999999999999", DataValue::from(1e20)),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(op_parse_number(&[DataValue::from(s)]).unwrap(), expected);
+        assert_eq!(
+            op_parse_number_strict(&[DataValue::from(s)]).unwrap(),
+            expected
+        );
+    }
+    for s in [
+        "", "  ", "abc", "12abc", "1,000", "1 2", "--1", "e5", "inf", "NaN",
+    ] {
+        assert_eq!(
+            op_parse_number(&[DataValue::from(s)]).unwrap(),
+            DataValue::Null
+        );
+        assert!(op_parse_number_strict(&[DataValue::from(s)]).is_err());
+    }
+    assert_eq!(
+        op_parse_number(&[DataValue::from(7)]).unwrap(),
+        DataValue::from(7)
+    );
+    assert_eq!(
+        op_parse_number(&[DataValue::from(true)]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_parse_number_strict(&[DataValue::from(true)]).is_err());
+    assert_eq!(
+        op_parse_number(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_parse_number_strict(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[a, b] := a = parse_number(' 1.5e1 '), b = parse_number('n/a')",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(res, vec![vec![DataValue::from(15.0), DataValue::Null]]);
+}
+
+#[test]
+fn test_date_trunc() {
+    let ts = |s: &str| op_parse_timestamp(&[DataValue::from(s)]).unwrap();
+    // a Thursday
+    let dt = ts("2023-08-17T13:45:30.250Z");
+    for (unit, expected) in [
+        ("second", "2023-08-17T13:45:30Z"),
+        ("minute", "2023-08-17T13:45:00Z"),
+        ("hour", "2023-08-17T13:00:00Z"),
+        ("day", "2023-08-17T00:00:00Z"),
+        ("week", "2023-08-14T00:00:00Z"),
+        ("month", "2023-08-01T00:00:00Z"),
+        ("quarter", "2023-07-01T00:00:00Z"),
+        ("year", "2023-01-01T00:00:00Z"),
+    ] {
+        assert_eq!(
+            op_date_trunc(&[DataValue::from(unit), dt.clone()]).unwrap(),
+            ts(expected),
+            "{unit}"
+        );
+    }
+
+    // truncation happens in the given timezone
+    assert_eq!(
+        op_date_trunc(&[
+            DataValue::from("day"),
+            dt.clone(),
+            DataValue::from("Asia/Tokyo")
+        ])
+        .unwrap(),
+        ts("2023-08-17T00:00:00+09:00")
+    );
+    assert_eq!(
+        op_date_trunc(&[
+            DataValue::from("month"),
+            ts("2023-03-01T03:00:00Z"),
+            DataValue::from("America/New_York")
+        ])
+        .unwrap(),
+        ts("2023-02-01T00:00:00-05:00")
+    );
+
+    // integers stay integers, validities stay validities
+    assert_eq!(
+        op_date_trunc(&[DataValue::from("hour"), DataValue::from(7201)]).unwrap(),
+        DataValue::from(7200)
+    );
+    assert_eq!(
+        op_date_trunc(&[
+            DataValue::from("minute"),
+            DataValue::Validity(Validity {
+                timestamp: ValidityTs(Reverse(61_500_000)),
+                is_assert: Reverse(true),
+            })
+        ])
+        .unwrap(),
+        DataValue::Validity(Validity {
+            timestamp: ValidityTs(Reverse(60_000_000)),
+            is_assert: Reverse(true),
+        })
+    );
+    // before the epoch
+    assert_eq!(
+        op_date_trunc(&[DataValue::from("day"), DataValue::from(-1.5)]).unwrap(),
+        DataValue::from(-86400.0)
+    );
+
+    assert_eq!(
+        op_date_trunc(&[DataValue::from("day"), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_date_trunc(&[DataValue::from("fortnight"), dt.clone()]).is_err());
+    assert!(op_date_trunc(&[DataValue::from("day"), DataValue::from("2023-08-17")]).is_err());
+    assert!(op_date_trunc(&[DataValue::from("day"), dt, DataValue::from("Mars/Olympus")]).is_err());
+}
+
+#[test]
+fn test_month_weekday_name() {
+    let ts = |s: &str| op_parse_timestamp(&[DataValue::from(s)]).unwrap();
+    let name = |f: fn(&[DataValue]) -> miette::Result<DataValue>, args: &[DataValue]| {
+        f(args).unwrap().get_str().unwrap().to_string()
+    };
+    for (dt, month, short_month, weekday, short_weekday) in [
+        ("2023-08-17T13:45:30Z", "August", "Aug", "Thursday", "Thu"),
+        ("2024-02-29T00:00:00Z", "February", "Feb", "Thursday", "Thu"),
+        ("1970-01-01T00:00:00Z", "January", "Jan", "Thursday", "Thu"),
+        (
+            "2000-09-03T12:00:00+02:00",
+            "September",
+            "Sep",
+            "Sunday",
+            "Sun",
+        ),
+    ] {
+        let dt = ts(dt);
+        assert_eq!(name(op_month_name, std::slice::from_ref(&dt)), month);
+        assert_eq!(
+            name(op_month_name, &[dt.clone(), DataValue::from("long")]),
+            month
+        );
+        assert_eq!(
+            name(op_month_name, &[dt.clone(), DataValue::from("short")]),
+            short_month
+        );
+        assert_eq!(name(op_weekday_name, std::slice::from_ref(&dt)), weekday);
+        assert_eq!(
+            name(op_weekday_name, &[dt.clone(), DataValue::from("long")]),
+            weekday
+        );
+        assert_eq!(
+            name(op_weekday_name, &[dt, DataValue::from("short")]),
+            short_weekday
+        );
+    }
+    // integer seconds are timestamps too, including ones before the epoch
+    assert_eq!(
+        name(op_weekday_name, &[DataValue::from(86400 * 3)]),
+        "Sunday"
+    );
+    assert_eq!(name(op_month_name, &[DataValue::from(-1)]), "December");
+    assert_eq!(
+        name(
+            op_weekday_name,
+            &[DataValue::from(-1), DataValue::from("short")]
+        ),
+        "Wed"
+    );
+
+    assert_eq!(op_month_name(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(
+        op_weekday_name(&[DataValue::Null, DataValue::from("short")]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_month_name(&[DataValue::from("2023-08-17")]).is_err());
+    assert!(op_weekday_name(&[DataValue::from(0), DataValue::from("narrow")]).is_err());
+    assert!(op_weekday_name(&[DataValue::from(0), DataValue::from(1)]).is_err());
+    assert!(op_month_name(&[
+        DataValue::from(0),
+        DataValue::from("short"),
+        DataValue::from("en")
+    ])
+    .is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[m, w] := t = parse_timestamp('2023-08-17T13:45:30Z'), \
+             m = month_name(t, 'short'), w = weekday_name(t)",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res,
+        vec![vec![DataValue::from("Aug"), DataValue::from("Thursday")]]
+    );
+}
+
+#[test]
+fn test_durations() {
+    let parse = |s: &str| op_parse_duration(&[DataValue::from(s)]);
+    let format = |n: i64| op_format_duration(&[DataValue::from(n)]).unwrap();
+
+    assert_eq!(parse("1h30m").unwrap(), DataValue::from(5400));
+    assert_eq!(parse("90s").unwrap(), DataValue::from(90));
+    assert_eq!(parse("2d").unwrap(), DataValue::from(172800));
+    assert_eq!(parse("-1m1s").unwrap(), DataValue::from(-61));
+    assert_eq!(format(5400), DataValue::from("1h30m"));
+    assert_eq!(format(90), DataValue::from("1m30s"));
+    assert_eq!(format(0), DataValue::from("0s"));
+    assert_eq!(format(-61), DataValue::from("-1m1s"));
+
+    for s in [
+        "0s",
+        "1d1h1m1s",
+        "1h30m",
+        "2d",
+        "59s",
+        "-3h",
+        "106751991167300d15h30m7s",
+    ] {
+        let n = parse(s).unwrap();
+        assert_eq!(op_format_duration(&[n]).unwrap(), DataValue::from(s));
+    }
+    for n in [
+        1,
+        59,
+        60,
+        3599,
+        86400,
+        1_000_000,
+        -7,
+        i64::MAX,
+        i64::MIN + 1,
+    ] {
+        let s = format(n);
+        assert_eq!(op_parse_duration(&[s]).unwrap(), DataValue::from(n));
+    }
+    // amounts need not be below the next larger unit
+    assert_eq!(parse("90m").unwrap(), DataValue::from(5400));
+
+    for s in [
+        "1x",
+        "",
+        "-",
+        "10",
+        "h",
+        "1h1d",
+        "1h1h",
+        "1.5h",
+        " 1h",
+        "1h ",
+        "--1h",
+        "99999999999999999999s",
+        "106751991167301d",
+    ] {
+        assert!(parse(s).is_err(), "{s}");
+    }
+    assert!(op_parse_duration(&[DataValue::from(1)]).is_err());
+    assert!(op_format_duration(&[DataValue::from("1h")]).is_err());
+    assert_eq!(
+        op_parse_duration(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_format_duration(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+}
+
+#[test]
+fn test_bincount_value_counts() {
+    let ints = |v: &[i64]| DataValue::List(v.iter().map(|i| DataValue::from(*i)).collect());
+    let nums = DataValue::List(
+        [
+            0.0,
+            0.5,
+            1.0,
+            2.5,
+            4.99,
+            5.0,
+            7.5,
+            9.99,
+            10.0,
+            -0.1,
+            10.1,
+            f64::NAN,
+        ]
+        .into_iter()
+        .map(DataValue::from)
+        .chain([DataValue::Null, DataValue::from(3)])
+        .collect(),
+    );
+    // edges belong to the bin above, except `max` which is in the last bin;
+    // out-of-range values, NaN and null are dropped
+    assert_eq!(
+        op_bincount(&[
+            nums.clone(),
+            DataValue::from(4),
+            DataValue::from(0),
+            DataValue::from(10)
+        ])
+        .unwrap(),
+        ints(&[3, 3, 1, 3])
+    );
+    assert_eq!(
+        op_bincount(&[
+            nums.clone(),
+            DataValue::from(1),
+            DataValue::from(0.0),
+            DataValue::from(10.0)
+        ])
+        .unwrap(),
+        ints(&[10])
+    );
+    assert_eq!(
+        op_bincount(&[
+            ints(&[1, 2, 2, 3, 3, 3]),
+            DataValue::from(3),
+            DataValue::from(0.5),
+            DataValue::from(3.5)
+        ])
+        .unwrap(),
+        ints(&[1, 2, 3])
+    );
+    assert_eq!(
+        op_bincount(&[
+            DataValue::List(vec![]),
+            DataValue::from(2),
+            DataValue::from(0),
+            DataValue::from(1)
+        ])
+        .unwrap(),
+        ints(&[0, 0])
+    );
+    assert_eq!(
+        op_bincount(&[
+            DataValue::Null,
+            DataValue::from(2),
+            DataValue::from(0),
+            DataValue::from(1)
+        ])
+        .unwrap(),
+        DataValue::Null
+    );
+    for (n_bins, min, max) in [
+        (DataValue::from(0), DataValue::from(0), DataValue::from(1)),
+        (DataValue::from(1.5), DataValue::from(0), DataValue::from(1)),
+        (DataValue::from(2), DataValue::from(1), DataValue::from(1)),
+        (DataValue::from(2), DataValue::from(2), DataValue::from(1)),
+        (
+            DataValue::from(2),
+            DataValue::from(0),
+            DataValue::from(f64::INFINITY),
+        ),
+        (DataValue::from(2), DataValue::Null, DataValue::from(1)),
+    ] {
+        assert!(op_bincount(&[ints(&[1]), n_bins, min, max]).is_err());
+    }
+    assert!(op_bincount(&[
+        DataValue::from("1"),
+        DataValue::from(2),
+        DataValue::from(0),
+        DataValue::from(1)
+    ])
+    .is_err());
+    assert!(op_bincount(&[
+        DataValue::List(vec![DataValue::from("1")]),
+        DataValue::from(2),
+        DataValue::from(0),
+        DataValue::from(1)
+    ])
+    .is_err());
+
+    assert_eq!(
+        op_value_counts(&[DataValue::List(vec![
+            DataValue::from("b"),
+            DataValue::from(1),
+            DataValue::from("a"),
+            DataValue::from("b"),
+            DataValue::Null,
+            DataValue::from("b"),
+            DataValue::Null,
+        ])])
+        .unwrap(),
+        DataValue::List(vec![
+            DataValue::List(vec![DataValue::from("b"), DataValue::from(3)]),
+            DataValue::List(vec![DataValue::from(1), DataValue::from(1)]),
+            DataValue::List(vec![DataValue::from("a"), DataValue::from(1)]),
+            DataValue::List(vec![DataValue::Null, DataValue::from(2)]),
+        ])
+    );
+    assert_eq!(
+        op_value_counts(&[DataValue::List(vec![])]).unwrap(),
+        DataValue::List(vec![])
+    );
+    assert_eq!(
+        op_value_counts(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_value_counts(&[DataValue::from(1)]).is_err());
+}
+
+#[test]
+fn test_sort_null_order() {
+    let list = DataValue::List(vec![
+        DataValue::from("b"),
+        DataValue::Null,
+        DataValue::from(2),
+        DataValue::Null,
+        DataValue::from(1),
+        DataValue::List(vec![DataValue::Null]),
+    ]);
+    let nulls_first = DataValue::List(vec![
+        DataValue::Null,
+        DataValue::Null,
+        DataValue::from(1),
+        DataValue::from(2),
+        DataValue::from("b"),
+        DataValue::List(vec![DataValue::Null]),
+    ]);
+    let nulls_last = DataValue::List(vec![
+        DataValue::from(1),
+        DataValue::from(2),
+        DataValue::from("b"),
+        DataValue::List(vec![DataValue::Null]),
+        DataValue::Null,
+        DataValue::Null,
+    ]);
+    assert_eq!(op_sorted(std::slice::from_ref(&list)).unwrap(), nulls_first);
+    assert_eq!(
+        op_sorted(&[list.clone(), DataValue::from("nulls_first")]).unwrap(),
+        nulls_first
+    );
+    assert_eq!(
+        op_sorted(&[list.clone(), DataValue::from("nulls_last")]).unwrap(),
+        nulls_last
+    );
+    assert!(op_sorted(&[list.clone(), DataValue::from("last")]).is_err());
+    assert!(op_sorted(&[list, DataValue::Null]).is_err());
+
+    assert_eq!(
+        DataValue::Null.cmp_total(&DataValue::from(1), NullOrder::First),
+        Ordering::Less
+    );
+    assert_eq!(
+        DataValue::Null.cmp_total(&DataValue::from(1), NullOrder::Last),
+        Ordering::Greater
+    );
+    assert_eq!(
+        DataValue::from(1).cmp_total(&DataValue::Null, NullOrder::Last),
+        Ordering::Less
+    );
+    assert_eq!(
+        DataValue::Null.cmp_total(&DataValue::Null, NullOrder::Last),
+        Ordering::Equal
+    );
+    assert_eq!(
+        DataValue::from(2).cmp_total(&DataValue::from(1), NullOrder::Last),
+        Ordering::Greater
+    );
+}
+
+#[test]
+fn test_natural_sort() {
+    let strs = |xs: &[&str]| DataValue::List(xs.iter().map(|x| DataValue::from(*x)).collect());
+    assert_eq!(
+        op_natural_sort(&[strs(&["item10", "item2", "item1", "Item3", "item02", "a"])]).unwrap(),
+        strs(&["Item3", "a", "item1", "item2", "item02", "item10"])
+    );
+    assert_eq!(
+        op_natural_sort(&[strs(&["v1.10.0", "v1.9.2", "v1.9.10", "v1.9"])]).unwrap(),
+        strs(&["v1.9", "v1.9.2", "v1.9.10", "v1.10.0"])
+    );
+    assert_eq!(
+        op_natural_sort(&[strs(&[
+            "x99999999999999999999999",
+            "x100000000000000000000000"
+        ])])
+        .unwrap(),
+        strs(&["x99999999999999999999999", "x100000000000000000000000"])
+    );
+    assert_eq!(op_natural_sort(&[strs(&[])]).unwrap(), strs(&[]));
+    assert_eq!(
+        op_natural_sort(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_natural_sort(&[DataValue::List(vec![DataValue::from(1)])]).is_err());
+    assert!(op_natural_sort(&[DataValue::from("item2")]).is_err());
+}
+
+#[test]
+fn test_dot_product() {
+    let list = |v: Vec<DataValue>| DataValue::List(v);
+    let ints = |v: &[i64]| list(v.iter().map(|x| DataValue::from(*x)).collect());
+
+    assert_eq!(
+        op_dot(&[ints(&[1, 2, 3]), ints(&[4, 5, 6])]).unwrap(),
+        DataValue::from(32)
+    );
+    assert_eq!(
+        op_dot(&[
+            list(vec![DataValue::from(0.5), DataValue::from(2)]),
+            ints(&[4, 3])
+        ])
+        .unwrap(),
+        DataValue::from(8.0)
+    );
+    assert_eq!(op_dot(&[ints(&[]), ints(&[])]).unwrap(), DataValue::from(0));
+    assert_eq!(
+        op_dot(&[DataValue::Null, ints(&[1])]).unwrap(),
+        DataValue::Null
+    );
+    let err = op_dot(&[ints(&[1, 2]), ints(&[1, 2, 3])]).unwrap_err();
+    assert!(err.to_string().contains("equal length"));
+    assert!(op_dot(&[list(vec![DataValue::from("a")]), ints(&[1])]).is_err());
+
+    assert_eq!(
+        op_product(&[ints(&[2, 3, 4])]).unwrap(),
+        DataValue::from(24)
+    );
+    assert_eq!(
+        op_product(&[list(vec![DataValue::from(1.0), DataValue::from(3)])]).unwrap(),
+        DataValue::from(3.0)
+    );
+    assert_eq!(op_product(&[ints(&[])]).unwrap(), DataValue::from(1));
+    assert_eq!(op_product(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert!(op_product(&[list(vec![DataValue::Null])]).is_err());
+    assert!(op_product(&[DataValue::from(2)]).is_err());
+}
+
+#[test]
+fn test_generate_series_date_range() {
+    let ints = |v: &[i64]| DataValue::List(v.iter().map(|x| DataValue::from(*x)).collect());
+    let floats = |v: &[f64]| DataValue::List(v.iter().map(|x| DataValue::from(*x)).collect());
+
+    assert_eq!(
+        op_generate_series(&[DataValue::from(1), DataValue::from(5)]).unwrap(),
+        ints(&[1, 2, 3, 4, 5])
+    );
+    assert_eq!(
+        op_generate_series(&[DataValue::from(0), DataValue::from(10), DataValue::from(4)]).unwrap(),
+        ints(&[0, 4, 8])
+    );
+    assert_eq!(
+        op_generate_series(&[
+            DataValue::from(0),
+            DataValue::from(1),
+            DataValue::from(0.25)
+        ])
+        .unwrap(),
+        floats(&[0., 0.25, 0.5, 0.75, 1.])
+    );
+    assert_eq!(
+        op_generate_series(&[DataValue::from(5), DataValue::from(1), DataValue::from(-2)]).unwrap(),
+        ints(&[5, 3, 1])
+    );
+    assert_eq!(
+        op_generate_series(&[DataValue::from(3), DataValue::from(3), DataValue::from(-1)]).unwrap(),
+        ints(&[3])
+    );
+    assert_eq!(
+        op_generate_series(&[DataValue::Null, DataValue::from(3)]).unwrap(),
+        DataValue::Null
+    );
+    assert!(
+        op_generate_series(&[DataValue::from(1), DataValue::from(5), DataValue::from(0)]).is_err()
+    );
+    assert!(
+        op_generate_series(&[DataValue::from(1), DataValue::from(5), DataValue::from(-1)]).is_err()
+    );
+    assert!(op_generate_series(&[DataValue::from(5), DataValue::from(1)]).is_err());
+    assert!(op_generate_series(&[DataValue::from("a"), DataValue::from(1)]).is_err());
+
+    assert_eq!(
+        op_date_range(&[
+            DataValue::from("2023-01-01T00:00:00Z"),
+            DataValue::from("2023-01-03T00:00:00Z"),
+            DataValue::from("1d"),
+        ])
+        .unwrap(),
+        floats(&[1672531200., 1672617600., 1672704000.])
+    );
+    assert_eq!(
+        op_date_range(&[
+            DataValue::from(7200),
+            DataValue::from(0),
+            DataValue::from(-3600),
+        ])
+        .unwrap(),
+        ints(&[7200, 3600, 0])
+    );
+    assert!(op_date_range(&[
+        DataValue::from(0),
+        DataValue::from(3600),
+        DataValue::from("-1h"),
+    ])
+    .is_err());
+
+    // series too long are refused before anything is allocated
+    assert!(op_generate_series(&[DataValue::from(0), DataValue::from(i64::MAX)]).is_err());
+    assert!(op_generate_series(&[
+        DataValue::from(0),
+        DataValue::from(1),
+        DataValue::from(1e-300)
+    ])
+    .is_err());
+    let len = MAX_SERIES_LEN as i64;
+    assert!(op_generate_series(&[DataValue::from(0), DataValue::from(len)]).is_err());
+    assert!(op_date_range(&[
+        DataValue::from(0),
+        DataValue::from(i64::MAX),
+        DataValue::from("1s"),
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_strip_accents_to_ascii() {
+    let s = |v: &str| DataValue::from(v);
+    assert_eq!(
+        op_strip_accents(&[s("Crème brûlée à la façon de Zoë")]).unwrap(),
+        s("Creme brulee a la facon de Zoe")
+    );
+    // composed and decomposed forms give the same result
+    assert_eq!(op_strip_accents(&[s("e\u{301}")]).unwrap(), s("e"));
+    // letters without diacritics are kept, also outside of ASCII
+    assert_eq!(
+        op_strip_accents(&[s("Straße Ελληνικά")]).unwrap(),
+        s("Straße Ελληνικα")
+    );
+    assert_eq!(
+        op_strip_accents(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_strip_accents(&[DataValue::from(1)]).is_err());
+
+    assert_eq!(
+        op_to_ascii(&[s("Crème brûlée, Straße, Œuvre, Łódź")]).unwrap(),
+        s("Creme brulee, Strasse, OEuvre, Lodz")
+    );
+    assert_eq!(
+        op_to_ascii(&[s("“ﬁne” — ＡＢＣ…")]).unwrap(),
+        s("\"fine\" - ABC...")
+    );
+    assert_eq!(op_to_ascii(&[s("東京 Tokyo")]).unwrap(), s(" Tokyo"));
+    assert_eq!(
+        op_to_ascii(&[s("東京 Tokyo"), s("?")]).unwrap(),
+        s("?? Tokyo")
+    );
+    assert_eq!(op_to_ascii(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert_eq!(
+        op_to_ascii(&[s("a"), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_to_ascii(&[DataValue::from(1)]).is_err());
+}
+
+#[test]
+fn test_bitset() {
+    use rand::prelude::*;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut random_list = |n: usize| {
+        DataValue::List(
+            (0..n)
+                .map(|_| DataValue::from(rng.gen_range(0..5000i64)))
+                .collect(),
+        )
+    };
+    let to_list = |v: DataValue| match v {
+        DataValue::BitSet(bs) => {
+            DataValue::List(bs.0.iter().map(|el| DataValue::from(el as i64)).collect())
+        }
+        v => panic!("expected a bitset, got {v:?}"),
+    };
+
+    let a = random_list(3000);
+    let b = random_list(2000);
+    let c = random_list(1000);
+    let ba = op_bitset_from_list(std::slice::from_ref(&a)).unwrap();
+    let bb = op_bitset_from_list(std::slice::from_ref(&b)).unwrap();
+    let bc = op_bitset_from_list(std::slice::from_ref(&c)).unwrap();
+
+    assert_eq!(
+        to_list(ba.clone()),
+        op_union(std::slice::from_ref(&a)).unwrap(),
+        "a bitset holds the distinct elements of the list, sorted"
+    );
+    assert_eq!(
+        to_list(op_bitset_and(&[ba.clone(), bb.clone(), bc.clone()]).unwrap()),
+        op_intersection(&[a.clone(), b.clone(), c.clone()]).unwrap()
+    );
+    assert_eq!(
+        to_list(op_bitset_or(&[ba.clone(), bb.clone(), bc.clone()]).unwrap()),
+        op_union(&[a.clone(), b.clone(), c.clone()]).unwrap()
+    );
+    assert_eq!(
+        op_bitset_count(std::slice::from_ref(&ba)).unwrap(),
+        op_length(&[op_union(std::slice::from_ref(&a)).unwrap()]).unwrap()
+    );
+    for i in 0..5000 {
+        let i = DataValue::from(i);
+        assert_eq!(
+            op_bitset_contains(&[ba.clone(), i.clone()]).unwrap(),
+            op_is_in(&[i, a.clone()]).unwrap()
+        );
+    }
+    assert_eq!(
+        op_bitset_contains(&[ba.clone(), DataValue::from(-1)]).unwrap(),
+        DataValue::from(false)
+    );
+
+    assert_eq!(
+        op_bitset_from_list(&[DataValue::List(vec![DataValue::from(2.0)])]).unwrap(),
+        op_bitset_from_list(&[DataValue::List(vec![DataValue::from(2)])]).unwrap()
+    );
+    assert!(op_bitset_from_list(&[DataValue::List(vec![DataValue::from(-1)])]).is_err());
+    assert!(op_bitset_from_list(&[DataValue::List(vec![DataValue::from(1.5)])]).is_err());
+    assert!(op_bitset_from_list(&[DataValue::List(vec![DataValue::from("a")])]).is_err());
+    assert_eq!(
+        op_bitset_from_list(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_bitset_count(&[DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_bitset_and(&[ba.clone(), a]).is_err());
+    assert!(op_bitset_count(&[b]).is_err());
+}
+
+#[test]
+fn test_bitset_in_query() {
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[s, n, has_three, has_two, both] := s = bitset_from_list([5, 1, 3, 1]), \
+                n = bitset_count(s), has_three = bitset_contains(s, 3), \
+                has_two = bitset_contains(s, 2), \
+                both = bitset_and(s, bitset_from_list([3, 4, 5]))",
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.into_json()["rows"],
+        serde_json::json!([[[1, 3, 5], 3, true, false, [3, 5]]])
+    );
+}
+
+#[test]
+fn test_compact() {
+    let compact = |l: Vec<DataValue>| op_compact(&[DataValue::List(l)]).unwrap();
+    assert_eq!(
+        compact(vec![
+            DataValue::Null,
+            DataValue::from(1),
+            DataValue::Null,
+            DataValue::from(false),
+            DataValue::from(""),
+            DataValue::Null,
+        ]),
+        DataValue::List(vec![
+            DataValue::from(1),
+            DataValue::from(false),
+            DataValue::from("")
+        ])
+    );
+    // nested nulls are kept
+    assert_eq!(
+        compact(vec![DataValue::List(vec![DataValue::Null])]),
+        DataValue::List(vec![DataValue::List(vec![DataValue::Null])])
+    );
+    assert_eq!(
+        compact(vec![DataValue::Null, DataValue::Null]),
+        DataValue::List(vec![])
+    );
+    assert_eq!(compact(vec![]), DataValue::List(vec![]));
+    assert_eq!(op_compact(&[DataValue::Null]).unwrap(), DataValue::Null);
+    assert!(op_compact(&[DataValue::from(1)]).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[l] := l = compact([null, 1, null, 2])",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res[0][0],
+        DataValue::List(vec![DataValue::from(1), DataValue::from(2)])
+    );
+}
+
+#[test]
+fn test_zip_with() {
+    let ints = |xs: &[i64]| DataValue::List(xs.iter().map(|x| DataValue::from(*x)).collect());
+    let zip_with = |a: DataValue, b: DataValue, f: &str| op_zip_with(&[a, b, DataValue::from(f)]);
+
+    assert_eq!(
+        zip_with(ints(&[1, 2, 3]), ints(&[10, 20, 30]), "add").unwrap(),
+        ints(&[11, 22, 33])
+    );
+    assert_eq!(
+        zip_with(ints(&[1, 2]), ints(&[3, 4]), "list").unwrap(),
+        DataValue::List(vec![ints(&[1, 3]), ints(&[2, 4])])
+    );
+    // truncated to the shorter list
+    assert_eq!(
+        zip_with(ints(&[1, 2, 3]), ints(&[5]), "max").unwrap(),
+        ints(&[5])
+    );
+    assert_eq!(zip_with(ints(&[]), ints(&[1]), "add").unwrap(), ints(&[]));
+    assert_eq!(
+        zip_with(DataValue::Null, ints(&[1]), "add").unwrap(),
+        DataValue::Null
+    );
+    assert!(zip_with(DataValue::from(1), ints(&[1]), "add").is_err());
+    assert!(zip_with(ints(&[1]), ints(&[1]), "no_such_function").is_err());
+    assert!(zip_with(ints(&[1]), ints(&[1]), "abs").is_err());
+    assert!(zip_with(ints(&[1]), ints(&[1]), "rand_int").is_err());
+    // errors of the function are propagated
+    assert!(zip_with(
+        ints(&[1]),
+        DataValue::List(vec![DataValue::from("a")]),
+        "sub"
+    )
+    .is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[l] := l = zip_with(['x', 'y'], ['a', 'b'], 'concat')",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(
+        res[0][0],
+        DataValue::List(vec![DataValue::from("xa"), DataValue::from("yb")])
+    );
+}
+
+#[test]
+fn test_assertions() {
+    let msg = DataValue::from("x must be positive");
+    assert_eq!(
+        op_assert(&[DataValue::from(true), msg.clone()]).unwrap(),
+        DataValue::from(true)
+    );
+    for cond in [DataValue::from(false), DataValue::Null, DataValue::from(1)] {
+        let err = op_assert(&[cond, msg.clone()]).unwrap_err();
+        assert_eq!(err.to_string(), "assertion failed: x must be positive");
+    }
+    assert!(op_assert(&[DataValue::from(false)]).is_err());
+
+    assert_eq!(
+        op_ensure_not_null(&[DataValue::from(0), msg.clone()]).unwrap(),
+        DataValue::from(0)
+    );
+    let err = op_ensure_not_null(&[DataValue::Null, msg.clone()]).unwrap_err();
+    assert_eq!(err.to_string(), "unexpected null: x must be positive");
+    assert!(op_ensure_not_null(&[DataValue::from(0), DataValue::from(1)]).is_err());
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[x] := x in [1, 2], assert(x > 0, 'x must be positive')",
+            Default::default(),
+        )
+        .unwrap()
+        .rows;
+    assert_eq!(res.len(), 2);
+    let err = db
+        .run_script(
+            "?[x] := x in [1, -2], assert(x > 0, 'x must be positive')",
+            Default::default(),
+        )
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("x must be positive"));
+    let err = db
+        .run_script(
+            "?[y] := x in [1, null], y = ensure_not_null(x, 'x is required')",
+            Default::default(),
+        )
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("x is required"));
+}
+
+#[test]
+fn test_rotate_shift() {
+    let list = |xs: &[i64]| DataValue::List(xs.iter().map(|x| DataValue::from(*x)).collect());
+    let rotate = |l: &[i64], n: i64| op_rotate(&[list(l), DataValue::from(n)]).unwrap();
+    let shift =
+        |l: &[i64], n: i64| op_shift(&[list(l), DataValue::from(n), DataValue::from(0)]).unwrap();
+
+    assert_eq!(rotate(&[1, 2, 3, 4], 1), list(&[4, 1, 2, 3]));
+    assert_eq!(rotate(&[1, 2, 3, 4], -1), list(&[2, 3, 4, 1]));
+    assert_eq!(rotate(&[1, 2, 3, 4], 4), list(&[1, 2, 3, 4]));
+    assert_eq!(rotate(&[1, 2, 3, 4], -4), list(&[1, 2, 3, 4]));
+    assert_eq!(rotate(&[1, 2, 3, 4], 6), list(&[3, 4, 1, 2]));
+    assert_eq!(rotate(&[1, 2, 3, 4], -9), list(&[2, 3, 4, 1]));
+    assert_eq!(rotate(&[], 3), list(&[]));
+
+    assert_eq!(shift(&[1, 2, 3, 4], 1), list(&[0, 1, 2, 3]));
+    assert_eq!(shift(&[1, 2, 3, 4], -2), list(&[3, 4, 0, 0]));
+    assert_eq!(shift(&[1, 2, 3, 4], 0), list(&[1, 2, 3, 4]));
+    assert_eq!(shift(&[1, 2, 3, 4], 10), list(&[0, 0, 0, 0]));
+    assert_eq!(shift(&[1, 2, 3, 4], -10), list(&[0, 0, 0, 0]));
+    assert_eq!(shift(&[], 2), list(&[]));
+    assert_eq!(
+        op_shift(&[list(&[1, 2]), DataValue::from(1), DataValue::Null]).unwrap(),
+        DataValue::List(vec![DataValue::Null, DataValue::from(1)])
+    );
+
+    assert_eq!(
+        op_rotate(&[DataValue::Null, DataValue::from(1)]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_rotate(&[list(&[1]), DataValue::Null]).unwrap(),
+        DataValue::Null
+    );
+    assert_eq!(
+        op_shift(&[DataValue::Null, DataValue::from(1), DataValue::from(0)]).unwrap(),
+        DataValue::Null
+    );
+    assert!(op_rotate(&[DataValue::from(1), DataValue::from(1)]).is_err());
+    assert!(op_shift(&[
+        DataValue::from("abc"),
+        DataValue::from(1),
+        DataValue::from(0)
+    ])
+    .is_err());
+    assert!(op_rotate(&[list(&[1]), DataValue::from(0.5)]).is_err());
+}
+
+#[test]
+fn test_ntile() {
+    let ntile = |l: Vec<DataValue>, n: i64| op_ntile(&[DataValue::List(l), DataValue::from(n)]);
+    let ints = |l: &[i64]| l.iter().map(|i| DataValue::from(*i)).collect::<Vec<_>>();
+
+    let l = ints(&[5, 1, 4, 2, 3, 7]);
+    assert_eq!(
+        ntile(l.clone(), 2).unwrap(),
+        DataValue::List(ints(&[2, 1, 2, 1, 1, 2]))
+    );
+    // uneven division: the first buckets are the larger ones
+    assert_eq!(
+        ntile(l.clone(), 4).unwrap(),
+        DataValue::List(ints(&[3, 1, 2, 1, 2, 4]))
+    );
+    assert_eq!(ntile(l, 1).unwrap(), DataValue::List(ints(&[1; 6])));
+    // ties are ranked by their position in the list
+    assert_eq!(
+        ntile(ints(&[1, 1, 1]), 2).unwrap(),
+        DataValue::List(ints(&[1, 1, 2]))
+    );
+    // more buckets than elements
+    assert_eq!(
+        ntile(vec![DataValue::from(2.5), DataValue::from(1)], 4).unwrap(),
+        DataValue::List(ints(&[2, 1]))
+    );
+    assert_eq!(ntile(vec![], 3).unwrap(), DataValue::List(vec![]));
+
+    assert_eq!(
+        op_ntile(&[DataValue::Null, DataValue::from(2)]).unwrap(),
+        DataValue::Null
+    );
+    assert!(ntile(ints(&[1, 2]), 0).is_err());
+    assert!(ntile(ints(&[1, 2]), -1).is_err());
+    assert!(ntile(vec![DataValue::from(1), DataValue::from("a")], 2).is_err());
+    assert!(op_ntile(&[DataValue::from(1), DataValue::from(2)]).is_err());
+}
+
+#[test]
+fn test_find() {
+    let l = DataValue::List(vec![
+        DataValue::from("a"),
+        DataValue::from(1),
+        DataValue::from(5),
+        DataValue::from(7),
+    ]);
+    let find = |l: &DataValue, f: &str, rest: &[DataValue]| {
+        let mut args = vec![l.clone(), DataValue::from(f)];
+        args.extend_from_slice(rest);
+        (op_find(&args), op_find_index(&args))
+    };
+
+    let (v, i) = find(&l, "is_int", &[]);
+    assert_eq!(v.unwrap(), DataValue::from(1));
+    assert_eq!(i.unwrap(), DataValue::from(1));
+    // the rest of the arguments are passed after the element
+    let (v, i) = find(&l, "eq", &[DataValue::from(5)]);
+    assert_eq!(v.unwrap(), DataValue::from(5));
+    assert_eq!(i.unwrap(), DataValue::from(2));
+
+    // no match
+    let (v, i) = find(&l, "is_float", &[]);
+    assert_eq!(v.unwrap(), DataValue::Null);
+    assert_eq!(i.unwrap(), DataValue::Null);
+    // empty list
+    let (v, i) = find(&DataValue::List(vec![]), "is_int", &[]);
+    assert_eq!(v.unwrap(), DataValue::Null);
+    assert_eq!(i.unwrap(), DataValue::Null);
+    let (v, i) = find(&DataValue::Null, "is_int", &[]);
+    assert_eq!(v.unwrap(), DataValue::Null);
+    assert_eq!(i.unwrap(), DataValue::Null);
+
+    // stops at the first match, so the later string is never compared
+    let l = DataValue::List(vec![DataValue::from(1), DataValue::from("a")]);
+    let (v, _) = find(&l, "gt", &[DataValue::from(0)]);
+    assert_eq!(v.unwrap(), DataValue::from(1));
+    let (v, _) = find(&l, "gt", &[DataValue::from(2)]);
+    assert!(v.is_err());
+
+    let (v, i) = find(&DataValue::from(1), "is_int", &[]);
+    assert!(v.is_err() && i.is_err());
+    assert!(find(&l, "no_such_function", &[]).0.is_err());
+    assert!(find(&l, "add", &[]).0.is_err());
+    assert!(find(&l, "is_int", &[DataValue::from(1)]).0.is_err());
+    assert!(find(&l, "rand_bernoulli", &[]).0.is_err());
+    // the predicate must give booleans
+    assert!(find(&l, "to_string", &[]).0.is_err());
+}
@@ -9,8 +9,10 @@
 
 use serde_json::json;
 
+use crate::data::functions::op_from_json_lines;
 use crate::data::json::JsonValue;
 use crate::data::value::DataValue;
+use crate::new_cozo_mem;
 
 #[test]
 fn bad_values() {
@@ -19,3 +21,52 @@ fn bad_values() {
     println!("{}", JsonValue::from(DataValue::from(f64::NEG_INFINITY)));
     println!("{}", JsonValue::from(DataValue::from(f64::NAN)));
 }
+
+#[test]
+fn objects_have_sorted_keys() {
+    let mut inner = serde_json::Map::new();
+    inner.insert("z".to_string(), json!(1));
+    inner.insert("a".to_string(), json!(2));
+    let mut outer = serde_json::Map::new();
+    outer.insert("y".to_string(), JsonValue::Object(inner));
+    outer.insert("b".to_string(), json!([{"d": 3, "c": 4}]));
+    let outer = JsonValue::Object(outer);
+    assert_eq!(
+        outer.to_string(),
+        r#"{"b":[{"c":4,"d":3}],"y":{"a":2,"z":1}}"#
+    );
+
+    let pairs = |v: &DataValue| -> Vec<String> {
+        v.get_slice()
+            .unwrap()
+            .iter()
+            .map(|p| p.get_slice().unwrap()[0].get_str().unwrap().to_string())
+            .collect()
+    };
+    for converted in [DataValue::from(&outer), DataValue::from(outer.clone())] {
+        assert_eq!(pairs(&converted), ["b", "y"]);
+        let y = &converted.get_slice().unwrap()[1].get_slice().unwrap()[1];
+        assert_eq!(pairs(y), ["a", "z"]);
+    }
+
+    let parsed =
+        op_from_json_lines(&[DataValue::from(r#"{"q": {"n": 1, "m": 2}, "p": 0}"#)]).unwrap();
+    let obj = &parsed.get_slice().unwrap()[0];
+    assert_eq!(pairs(obj), ["p", "q"]);
+    assert_eq!(
+        pairs(&obj.get_slice().unwrap()[1].get_slice().unwrap()[1]),
+        ["m", "n"]
+    );
+
+    let db = new_cozo_mem().unwrap();
+    let res = db
+        .run_script(
+            "?[x] <- [[$x]]",
+            [("x".to_string(), DataValue::from(&outer))].into(),
+        )
+        .unwrap();
+    assert_eq!(
+        JsonValue::from(res.rows[0][0].clone()).to_string(),
+        r#"[["b",[[["c",4],["d",3]]]],["y",[["a",2],["z",1]]]]"#
+    );
+}
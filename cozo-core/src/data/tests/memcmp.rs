@@ -135,3 +135,38 @@ fn encode_decode_datavalues() {
     assert!(remaining.is_empty());
     assert_eq!(decoded, v);
 }
+
+#[test]
+fn test_encode_decode_bitset() {
+    use roaring::RoaringTreemap;
+
+    use crate::data::value::BitSetWrapper;
+
+    let bitset = |v: &[u64]| DataValue::BitSet(BitSetWrapper(v.iter().copied().collect()));
+    let mut values = vec![
+        bitset(&[]),
+        bitset(&[0]),
+        bitset(&[0, 1]),
+        bitset(&[0, 2]),
+        bitset(&[1]),
+        bitset(&[1, u64::MAX]),
+        bitset(&[300, 70000, 1 << 40]),
+        DataValue::BitSet(BitSetWrapper(RoaringTreemap::from_iter(0..100000u64))),
+    ];
+    let mut encoded = vec![];
+    for v in &values {
+        let mut encoder = vec![];
+        encoder.encode_datavalue(v);
+        let (decoded, remaining) = DataValue::decode_from_key(&encoder);
+        assert_eq!(&decoded, v);
+        assert!(remaining.is_empty());
+        encoded.push(encoder);
+    }
+    values.sort();
+    encoded.sort();
+    let decoded = encoded
+        .iter()
+        .map(|e| DataValue::decode_from_key(e).0)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, values);
+}
@@ -0,0 +1,107 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+
+use crate::data::symb::Symbol;
+use crate::data::type_infer::ValueType;
+use crate::parse::parse_expression;
+use crate::parse::SourceSpan;
+
+fn infer(src: &str, ctx: &[(&str, ValueType)]) -> miette::Result<ValueType> {
+    let ctx: BTreeMap<_, _> = ctx
+        .iter()
+        .map(|(name, t)| (Symbol::new(*name, SourceSpan(0, 0)), *t))
+        .collect();
+    parse_expression(src, &Default::default())?.infer_type(&ctx)
+}
+
+#[test]
+fn infer_types() {
+    assert_eq!(infer("2 + 3", &[]).unwrap(), ValueType::Int);
+    assert_eq!(infer("2 + 3.5", &[]).unwrap(), ValueType::Float);
+    assert_eq!(infer("2 + x", &[]).unwrap(), ValueType::Number);
+    assert_eq!(infer("7 / 2", &[]).unwrap(), ValueType::Float);
+    assert_eq!(infer("to_string(x)", &[]).unwrap(), ValueType::String);
+    assert_eq!(infer("x > 1", &[]).unwrap(), ValueType::Bool);
+    assert_eq!(infer("x == 'a' || y != 2", &[]).unwrap(), ValueType::Bool);
+    assert_eq!(
+        infer("x", &[("x", ValueType::Uuid)]).unwrap(),
+        ValueType::Uuid
+    );
+    assert_eq!(
+        infer("length(lowercase(x))", &[("x", ValueType::String)]).unwrap(),
+        ValueType::Int
+    );
+    assert_eq!(infer("[1, 2]", &[]).unwrap(), ValueType::List);
+    assert_eq!(infer("get(x, 0)", &[]).unwrap(), ValueType::Any);
+    assert_eq!(infer("coalesce(null, 1)", &[]).unwrap(), ValueType::Int);
+    assert_eq!(infer("if(x, 1, 2.5)", &[]).unwrap(), ValueType::Number);
+    assert_eq!(infer("if(x, 'a', 2)", &[]).unwrap(), ValueType::Any);
+    assert_eq!(
+        infer("regex_matches(x, '[a-z]+')", &[]).unwrap(),
+        ValueType::Bool
+    );
+
+    let err = infer("uppercase(42)", &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Argument 1 of 'uppercase' is of type int where string is expected"
+    );
+    assert!(infer("x - 1", &[("x", ValueType::String)]).is_err());
+    assert!(infer("slice([1, 2], 'a', 1)", &[]).is_err());
+    assert!(infer("if(1 + 2, 'a', 'b')", &[]).is_err());
+    // null and unknown arguments are fine anywhere
+    assert!(infer("uppercase(null)", &[]).is_ok());
+    assert!(infer("uppercase(x)", &[]).is_ok());
+}
+
+#[test]
+fn arithmetic_promotes_ints() {
+    use crate::data::functions::{op_add, op_div, op_mod, op_mul, op_sub};
+    use crate::data::value::DataValue;
+
+    let (i, f) = (DataValue::from(7), DataValue::from(2.0));
+    assert_eq!(
+        op_sub(&[i.clone(), f.clone()]).unwrap(),
+        DataValue::from(5.0)
+    );
+    assert_eq!(
+        op_sub(&[f.clone(), i.clone()]).unwrap(),
+        DataValue::from(-5.0)
+    );
+    assert_eq!(
+        op_div(&[i.clone(), f.clone()]).unwrap(),
+        DataValue::from(3.5)
+    );
+    assert_eq!(
+        op_div(&[i.clone(), DataValue::from(2)]).unwrap(),
+        DataValue::from(3.5)
+    );
+    assert_eq!(op_mod(&[i.clone(), f]).unwrap(), DataValue::from(1.0));
+    assert_eq!(
+        op_mod(&[i, DataValue::from(2)]).unwrap(),
+        DataValue::from(1)
+    );
+    assert!(op_sub(&[DataValue::from(1), DataValue::from("1")]).is_err());
+
+    // sums and products are floats as soon as any argument is one,
+    // even if the floats alone would leave the result unchanged
+    assert_eq!(
+        op_add(&[DataValue::from(1), DataValue::from(0.0)]).unwrap(),
+        DataValue::from(1.0)
+    );
+    assert_eq!(
+        op_mul(&[DataValue::from(2), DataValue::from(1.0)]).unwrap(),
+        DataValue::from(2.0)
+    );
+    assert_eq!(
+        op_add(&[DataValue::from(1), DataValue::from(2)]).unwrap(),
+        DataValue::from(3)
+    );
+}
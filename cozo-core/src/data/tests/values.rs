@@ -7,11 +7,18 @@
  *
  */
 
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, HashMap};
 use std::mem::size_of;
+use std::sync::Arc;
+
+use regex::Regex;
 
 use crate::data::symb::Symbol;
-use crate::data::value::DataValue;
+use crate::data::value::{
+    DataValue, InternedValue, RegexWrapper, StrInterner, Validity, ValidityTs,
+};
+use crate::parse::parse_expression;
 
 #[test]
 fn show_size() {
@@ -55,3 +62,197 @@ fn display_datavalues() {
         ])
     );
 }
+
+#[test]
+fn display_literals() {
+    let cases = [
+        (DataValue::Null, "null"),
+        (DataValue::Bot, "null"),
+        (DataValue::from(true), "true"),
+        (DataValue::from(-12), "-12"),
+        (DataValue::from(1.0), "1.0"),
+        (DataValue::from(-2.5), "-2.5"),
+        (DataValue::from(1e100), "1e100"),
+        (DataValue::from(f64::NAN), r#"to_float("NAN")"#),
+        (DataValue::from(f64::INFINITY), r#"to_float("INF")"#),
+        (DataValue::from(f64::NEG_INFINITY), r#"to_float("NEG_INF")"#),
+        (DataValue::from("plain"), "'plain'"),
+        (
+            DataValue::from("say \"hi\"\\ 'there'\n\t\r\x08\x0c\x01你好👌"),
+            r#"'say "hi"\\ \'there\'\n\t\r\b\f\u0001你好👌'"#,
+        ),
+        (DataValue::Bytes(vec![1, 2, 3]), "decode_base64('AQID')"),
+        (
+            DataValue::uuid(uuid::Uuid::nil()),
+            "to_uuid('00000000-0000-0000-0000-000000000000')",
+        ),
+        (
+            DataValue::Regex(RegexWrapper(Regex::new(r#"a\d+"b"#).unwrap())),
+            r#"regex('a\\d+"b')"#,
+        ),
+        (
+            DataValue::List(vec![
+                DataValue::from(1),
+                DataValue::List(vec![DataValue::from("x"), DataValue::Null]),
+                DataValue::List(vec![]),
+            ]),
+            "[1, ['x', null], []]",
+        ),
+        (
+            DataValue::Set([DataValue::from(2), DataValue::from(1)].into()),
+            "[1, 2]",
+        ),
+        (
+            DataValue::Validity(Validity {
+                timestamp: ValidityTs(Reverse(1000)),
+                is_assert: Reverse(false),
+            }),
+            "[1000, false]",
+        ),
+    ];
+    for (val, expected) in cases {
+        assert_eq!(val.display_literal(), expected);
+    }
+}
+
+#[test]
+fn display_literals_read_back() {
+    let vals = [
+        DataValue::Null,
+        DataValue::from(false),
+        DataValue::from(i64::MAX),
+        DataValue::from(3.0),
+        DataValue::from(-1121212121.3312122),
+        DataValue::from(1.5e-10),
+        DataValue::from(f64::NEG_INFINITY),
+        DataValue::from("a\"b\\c'd\ne\u{7f}\u{10FFFF}"),
+        DataValue::Bytes(vec![0, 255, 7]),
+        DataValue::uuid(uuid::Uuid::new_v4()),
+        DataValue::List(vec![
+            DataValue::from("nested"),
+            DataValue::List(vec![DataValue::from(1.0), DataValue::from(-1)]),
+        ]),
+    ];
+    for val in vals {
+        let src = val.display_literal();
+        let expr = parse_expression(&src, &Default::default()).unwrap();
+        assert_eq!(expr.eval(vec![]).unwrap(), val, "{src}");
+    }
+}
+
+#[test]
+fn approx_sizes() {
+    let base = size_of::<DataValue>();
+    assert_eq!(DataValue::Null.approx_size(), base);
+    assert_eq!(DataValue::from(1).approx_size(), base);
+    assert_eq!(DataValue::from("abc").approx_size(), base + 3);
+    assert_eq!(DataValue::Bytes(vec![0; 1000]).approx_size(), base + 1000);
+    assert!(DataValue::from("a".repeat(1000)).approx_size() > DataValue::from("a").approx_size());
+
+    let elements = vec![
+        DataValue::from("x".repeat(100)),
+        DataValue::from(1.5),
+        DataValue::List(vec![DataValue::from("nested"), DataValue::Null]),
+        DataValue::Validity(Validity {
+            timestamp: ValidityTs(Reverse(0)),
+            is_assert: Reverse(true),
+        }),
+    ];
+    let list = DataValue::List(elements.clone());
+    let set = DataValue::Set(elements.iter().cloned().collect());
+    for el in &elements {
+        assert!(list.approx_size() > el.approx_size());
+        assert!(set.approx_size() > el.approx_size());
+    }
+    assert_eq!(
+        list.approx_size(),
+        base + elements.iter().map(|v| v.approx_size()).sum::<usize>()
+    );
+    let nested = DataValue::List(vec![list.clone()]);
+    assert!(nested.approx_size() > list.approx_size());
+    assert_eq!(DataValue::List(vec![]).approx_size(), base);
+}
+
+#[test]
+fn interned_strings() {
+    let str_ptr = |v: &InternedValue| match v {
+        InternedValue::Str(s) => Arc::as_ptr(s) as *const u8,
+        v => panic!("expected a string, got {v:?}"),
+    };
+
+    let mut interner = StrInterner::new();
+    let a = interner.intern_value(DataValue::from("pending review"));
+    let b = interner.intern_value(DataValue::from("pending review".to_string()));
+    let c = interner.intern_value(DataValue::from("approved"));
+    let d = interner.intern_value(DataValue::from(1));
+    assert_eq!(interner.len(), 2);
+    assert_eq!(str_ptr(&a), str_ptr(&b));
+    assert_ne!(str_ptr(&a), str_ptr(&c));
+    assert_eq!(str_ptr(&a), interner.intern("pending review").as_ptr());
+    assert_eq!(d, InternedValue::Other(DataValue::from(1)));
+
+    assert_eq!(DataValue::from(a), DataValue::from("pending review"));
+    assert_eq!(DataValue::from(d), DataValue::from(1));
+}
+
+#[test]
+fn coerce_values() {
+    use crate::data::type_infer::ValueType;
+
+    let coerce = |v: DataValue, t: ValueType| v.coerce_to(t).unwrap();
+    assert_eq!(
+        coerce(DataValue::from(2.7), ValueType::Int),
+        DataValue::from(2)
+    );
+    assert_eq!(
+        coerce(DataValue::from("42"), ValueType::Int),
+        DataValue::from(42)
+    );
+    assert_eq!(
+        coerce(DataValue::from(true), ValueType::Int),
+        DataValue::from(1)
+    );
+    assert_eq!(
+        coerce(DataValue::from(3), ValueType::Float),
+        DataValue::from(3.0)
+    );
+    assert_eq!(
+        coerce(DataValue::from(3), ValueType::Number),
+        DataValue::from(3)
+    );
+    assert_eq!(
+        coerce(DataValue::from("1.5"), ValueType::Number),
+        DataValue::from(1.5)
+    );
+    assert_eq!(
+        coerce(DataValue::from(3), ValueType::String),
+        DataValue::from("3")
+    );
+    assert_eq!(
+        coerce(DataValue::from(""), ValueType::Bool),
+        DataValue::from(false)
+    );
+    assert_eq!(coerce(DataValue::Null, ValueType::Any), DataValue::Null);
+    assert_eq!(
+        coerce(
+            DataValue::List(vec![
+                DataValue::from(2),
+                DataValue::from(1),
+                DataValue::from(2)
+            ]),
+            ValueType::Set
+        ),
+        DataValue::Set([DataValue::from(1), DataValue::from(2)].into())
+    );
+    assert!(matches!(
+        coerce(DataValue::from("a+"), ValueType::Regex),
+        DataValue::Regex(_)
+    ));
+
+    assert!(DataValue::from("abc").coerce_to(ValueType::Int).is_err());
+    assert!(DataValue::from("abc").coerce_to(ValueType::Uuid).is_err());
+    assert!(DataValue::from(1).coerce_to(ValueType::Uuid).is_err());
+    assert!(DataValue::from(1).coerce_to(ValueType::Bytes).is_err());
+    assert!(DataValue::from(1).coerce_to(ValueType::List).is_err());
+    assert!(DataValue::from("x").coerce_to(ValueType::Null).is_err());
+}
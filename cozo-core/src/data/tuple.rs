@@ -7,8 +7,10 @@
  */
 
 use crate::data::functions::TERMINAL_VALIDITY;
-use miette::Result;
+use miette::{Diagnostic, Result};
 use std::cmp::Reverse;
+use thiserror::Error;
+use uuid::Uuid;
 
 use crate::data::memcmp::MemCmpEncoder;
 use crate::data::value::{DataValue, Validity, ValidityTs};
@@ -16,6 +18,93 @@ use crate::runtime::relation::RelationId;
 
 pub type Tuple = Vec<DataValue>;
 
+/// Typed access to the fields of a row, such as the rows of [NamedRows](crate::NamedRows).
+/// All methods fail if `idx` is out of range or the value has a different type.
+pub trait TypedRow {
+    /// Converts the value at `idx` with its [TryFrom] implementation
+    fn get_as<'a, T>(&'a self, idx: usize) -> Result<T>
+    where
+        T: TryFrom<&'a DataValue, Error = miette::Error>;
+    /// Like [get_as](Self::get_as), but `null` is converted to `None`
+    fn get_opt_as<'a, T>(&'a self, idx: usize) -> Result<Option<T>>
+    where
+        T: TryFrom<&'a DataValue, Error = miette::Error>;
+
+    /// Gets an integer. Floats holding integral values are accepted.
+    fn get_int(&self, idx: usize) -> Result<i64> {
+        self.get_as(idx)
+    }
+    /// Gets a number as float
+    fn get_float(&self, idx: usize) -> Result<f64> {
+        self.get_as(idx)
+    }
+    /// Gets a boolean
+    fn get_bool(&self, idx: usize) -> Result<bool> {
+        self.get_as(idx)
+    }
+    /// Gets a string
+    fn get_str(&self, idx: usize) -> Result<&str> {
+        self.get_as(idx)
+    }
+    /// Gets bytes
+    fn get_bytes(&self, idx: usize) -> Result<&[u8]> {
+        self.get_as(idx)
+    }
+    /// Gets a UUID
+    fn get_uuid(&self, idx: usize) -> Result<Uuid> {
+        self.get_as(idx)
+    }
+    /// Gets a list
+    fn get_list(&self, idx: usize) -> Result<&[DataValue]> {
+        self.get_as(idx)
+    }
+    /// Gets an integer or `null`
+    fn get_opt_int(&self, idx: usize) -> Result<Option<i64>> {
+        self.get_opt_as(idx)
+    }
+    /// Gets a number as float, or `null`
+    fn get_opt_float(&self, idx: usize) -> Result<Option<f64>> {
+        self.get_opt_as(idx)
+    }
+    /// Gets a boolean or `null`
+    fn get_opt_bool(&self, idx: usize) -> Result<Option<bool>> {
+        self.get_opt_as(idx)
+    }
+    /// Gets a string or `null`
+    fn get_opt_str(&self, idx: usize) -> Result<Option<&str>> {
+        self.get_opt_as(idx)
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("Index {0} is out of range for a row of length {1}")]
+#[diagnostic(code(eval::row_index_out_of_range))]
+struct RowIndexOutOfRange(usize, usize);
+
+fn get_field(row: &[DataValue], idx: usize) -> Result<&DataValue> {
+    row.get(idx)
+        .ok_or_else(|| RowIndexOutOfRange(idx, row.len()).into())
+}
+
+impl TypedRow for [DataValue] {
+    fn get_as<'a, T>(&'a self, idx: usize) -> Result<T>
+    where
+        T: TryFrom<&'a DataValue, Error = miette::Error>,
+    {
+        T::try_from(get_field(self, idx)?)
+    }
+
+    fn get_opt_as<'a, T>(&'a self, idx: usize) -> Result<Option<T>>
+    where
+        T: TryFrom<&'a DataValue, Error = miette::Error>,
+    {
+        match get_field(self, idx)? {
+            DataValue::Null => Ok(None),
+            val => T::try_from(val).map(Some),
+        }
+    }
+}
+
 pub(crate) type TupleIter<'a> = Box<dyn Iterator<Item = Result<Tuple>> + 'a>;
 
 pub(crate) trait TupleT {
@@ -0,0 +1,255 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use miette::{bail, Diagnostic, Result};
+use thiserror::Error;
+
+use crate::data::expr::{Expr, Op};
+use crate::data::symb::Symbol;
+use crate::data::value::{DataValue, Num};
+use crate::parse::SourceSpan;
+
+/// The type of the value an expression evaluates to, as inferred by [Expr::infer_type].
+///
+/// Nullability is not tracked: most functions give null for null arguments, so an expression
+/// inferred as `String` may still evaluate to null.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ValueType {
+    /// Only null
+    Null,
+    /// Booleans
+    Bool,
+    /// Integers
+    Int,
+    /// Floats
+    Float,
+    /// Either integers or floats
+    Number,
+    /// Strings
+    String,
+    /// Bytes
+    Bytes,
+    /// UUIDs
+    Uuid,
+    /// Regular expressions
+    Regex,
+    /// Lists
+    List,
+    /// Sets
+    Set,
+    /// Validities
+    Validity,
+    /// Bitsets of non-negative integers
+    BitSet,
+    /// Unknown before execution
+    Any,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ValueType::Null => "null",
+            ValueType::Bool => "bool",
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Number => "number",
+            ValueType::String => "string",
+            ValueType::Bytes => "bytes",
+            ValueType::Uuid => "uuid",
+            ValueType::Regex => "regex",
+            ValueType::List => "list",
+            ValueType::Set => "set",
+            ValueType::Validity => "validity",
+            ValueType::BitSet => "bitset",
+            ValueType::Any => "any",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl ValueType {
+    /// The type of a value
+    pub fn of(val: &DataValue) -> Self {
+        match val {
+            DataValue::Null => ValueType::Null,
+            DataValue::Bool(_) => ValueType::Bool,
+            DataValue::Num(Num::Int(_)) => ValueType::Int,
+            DataValue::Num(Num::Float(_)) => ValueType::Float,
+            DataValue::Str(_) => ValueType::String,
+            DataValue::Bytes(_) => ValueType::Bytes,
+            DataValue::Uuid(_) => ValueType::Uuid,
+            DataValue::Regex(_) => ValueType::Regex,
+            DataValue::List(_) => ValueType::List,
+            DataValue::Set(_) => ValueType::Set,
+            DataValue::Validity(_) => ValueType::Validity,
+            DataValue::BitSet(_) => ValueType::BitSet,
+            DataValue::Bot => ValueType::Any,
+        }
+    }
+    fn is_numeric(self) -> bool {
+        matches!(self, ValueType::Int | ValueType::Float | ValueType::Number)
+    }
+    /// Whether values of type `actual` may be passed where `self` is expected
+    fn accepts(self, actual: ValueType) -> bool {
+        match (self, actual) {
+            (ValueType::Any, _) | (_, ValueType::Any | ValueType::Null) => true,
+            (ValueType::Number | ValueType::Int, a) => a.is_numeric(),
+            (ValueType::List, a) => matches!(a, ValueType::List | ValueType::Set),
+            (e, a) => e == a,
+        }
+    }
+    /// Whether values of type `actual` are already of this type, with no conversion needed
+    pub(crate) fn accepts_exactly(self, actual: ValueType) -> bool {
+        match self {
+            ValueType::Number => actual == ValueType::Int || actual == ValueType::Float,
+            t => t == actual,
+        }
+    }
+    /// The narrowest type covering both
+    fn join(self, other: ValueType) -> ValueType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ValueType::Null, t) | (t, ValueType::Null) => t,
+            (a, b) if a.is_numeric() && b.is_numeric() => ValueType::Number,
+            _ => ValueType::Any,
+        }
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("Argument {position} of '{op}' is of type {found} where {expected} is expected")]
+#[diagnostic(code(eval::arg_type_mismatch))]
+struct ArgTypeMismatch {
+    op: String,
+    position: usize,
+    expected: ValueType,
+    found: ValueType,
+    #[label]
+    span: SourceSpan,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("Condition is of type {0} where bool is expected")]
+#[diagnostic(code(eval::cond_type_mismatch))]
+struct CondTypeMismatch(ValueType, #[label] SourceSpan);
+
+impl Expr {
+    /// Infer the type of the value the expression evaluates to, without evaluating it.
+    /// `ctx` gives the types of variables, those not in it are of type `Any`.
+    ///
+    /// Arguments whose types are known to be wrong for the function they are passed to
+    /// are reported as errors. Types that depend on the data are inferred as `Any`.
+    pub fn infer_type(&self, ctx: &BTreeMap<Symbol, ValueType>) -> Result<ValueType> {
+        Ok(match self {
+            Expr::Binding { var, .. } => ctx.get(var).copied().unwrap_or(ValueType::Any),
+            Expr::Const { val, .. } => ValueType::of(val),
+            Expr::Apply { op, args, span } => {
+                let arg_types: Vec<_> = args
+                    .iter()
+                    .map(|arg| arg.infer_type(ctx))
+                    .collect::<Result<_>>()?;
+                op_output_type(op, &arg_types, *span)?
+            }
+            Expr::Cond { clauses, .. } => {
+                let mut ret = ValueType::Null;
+                for (cond, val) in clauses {
+                    let cond_type = cond.infer_type(ctx)?;
+                    if !ValueType::Bool.accepts(cond_type) {
+                        bail!(CondTypeMismatch(cond_type, cond.span()))
+                    }
+                    ret = ret.join(val.infer_type(ctx)?);
+                }
+                ret
+            }
+        })
+    }
+}
+
+/// The type of the result of arithmetic on numbers of the given types
+pub(crate) fn numeric_type(args: &[ValueType]) -> ValueType {
+    if args.iter().all(|t| *t == ValueType::Int) {
+        ValueType::Int
+    } else if args.contains(&ValueType::Float) {
+        ValueType::Float
+    } else {
+        ValueType::Number
+    }
+}
+
+/// The type of the result of `signum`, an int for ints
+pub(crate) fn signum_type(args: &[ValueType]) -> ValueType {
+    if args == [ValueType::Int] {
+        ValueType::Int
+    } else {
+        ValueType::Number
+    }
+}
+
+/// The type of the result of functions returning their first argument
+pub(crate) fn first_arg_type(args: &[ValueType]) -> ValueType {
+    args.first().copied().unwrap_or(ValueType::Any)
+}
+
+/// The type of the result of functions returning their second argument
+pub(crate) fn second_arg_type(args: &[ValueType]) -> ValueType {
+    args.get(1).copied().unwrap_or(ValueType::Any)
+}
+
+/// The type of the result of concatenating strings or lists
+pub(crate) fn concat_type(args: &[ValueType]) -> ValueType {
+    if args.iter().all(|t| *t == ValueType::String) {
+        ValueType::String
+    } else if args
+        .iter()
+        .all(|t| matches!(t, ValueType::List | ValueType::Set))
+    {
+        ValueType::List
+    } else {
+        ValueType::Any
+    }
+}
+
+/// The type of the result of functions returning one of their arguments
+pub(crate) fn joined_type(args: &[ValueType]) -> ValueType {
+    args.iter().fold(ValueType::Null, |acc, t| acc.join(*t))
+}
+
+/// The type of the result of `date_trunc`, that of the time truncated
+pub(crate) fn date_trunc_type(args: &[ValueType]) -> ValueType {
+    match args.get(1) {
+        Some(t @ (ValueType::Int | ValueType::Float | ValueType::Validity)) => *t,
+        _ => ValueType::Any,
+    }
+}
+
+/// Checks the types of the arguments to `op` against [Op::arg_types],
+/// the last one repeated for any further arguments, and gives the type of its result
+fn op_output_type(op: &Op, args: &[ValueType], span: SourceSpan) -> Result<ValueType> {
+    if let Some(last) = op.arg_types.last() {
+        for (i, actual) in args.iter().enumerate() {
+            let expected = *op.arg_types.get(i).unwrap_or(last);
+            if !expected.accepts(*actual) {
+                bail!(ArgTypeMismatch {
+                    op: op
+                        .name
+                        .strip_prefix("OP_")
+                        .unwrap_or(op.name)
+                        .to_ascii_lowercase(),
+                    position: i + 1,
+                    expected,
+                    found: *actual,
+                    span,
+                })
+            }
+        }
+    }
+    Ok((op.output_type)(args))
+}
@@ -9,16 +9,27 @@
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use std::cmp::{Ordering, Reverse};
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::str::FromStr;
+use std::sync::Arc;
 
+use miette::{bail, miette, Diagnostic};
+use num_traits::FloatConst;
 use ordered_float::OrderedFloat;
 use regex::Regex;
+use roaring::RoaringTreemap;
+use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize};
 use smartstring::{LazyCompact, SmartString};
+use thiserror::Error;
 use uuid::Uuid;
 
+use crate::data::json::JsonValue;
+use crate::data::type_infer::ValueType;
+
 /// UUID value in the database
 #[derive(Clone, Hash, Eq, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
 pub struct UuidWrapper(pub Uuid);
@@ -88,6 +99,59 @@ impl PartialOrd for RegexWrapper {
     }
 }
 
+/// A compact set of non-negative integers, backed by a roaring bitmap.
+/// Ordered and compared like the sorted list of its elements.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct BitSetWrapper(pub RoaringTreemap);
+
+// `RoaringTreemap` only implements `PartialEq`, but equality of bitmaps is total
+impl Eq for BitSetWrapper {}
+
+impl Hash for BitSetWrapper {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        for el in self.0.iter() {
+            el.hash(state)
+        }
+    }
+}
+
+impl Serialize for BitSetWrapper {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut buf = Vec::with_capacity(self.0.serialized_size());
+        self.0
+            .serialize_into(&mut buf)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&buf)
+    }
+}
+
+impl<'de> Deserialize<'de> for BitSetWrapper {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let buf = serde_bytes::ByteBuf::deserialize(deserializer)?;
+        let set = RoaringTreemap::deserialize_from(&buf[..]).map_err(D::Error::custom)?;
+        Ok(BitSetWrapper(set))
+    }
+}
+
+impl Ord for BitSetWrapper {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().cmp(other.0.iter())
+    }
+}
+
+impl PartialOrd for BitSetWrapper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// Timestamp part of validity
 #[derive(
     Copy,
@@ -122,6 +186,16 @@ pub struct Validity {
     pub is_assert: Reverse<bool>,
 }
 
+/// Where nulls are placed when values are ordered
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum NullOrder {
+    /// Nulls come before all other values, as in the total order of [DataValue]
+    #[default]
+    First,
+    /// Nulls come after all other values
+    Last,
+}
+
 /// A Value in the database
 #[derive(
     Clone, PartialEq, Eq, PartialOrd, Ord, serde_derive::Deserialize, serde_derive::Serialize, Hash,
@@ -148,6 +222,8 @@ pub enum DataValue {
     Set(BTreeSet<DataValue>),
     /// validity
     Validity(Validity),
+    /// set of non-negative integers, stored as a bitmap
+    BitSet(BitSetWrapper),
     /// bottom type, used internally only
     Bot,
 }
@@ -182,6 +258,156 @@ impl From<bool> for DataValue {
     }
 }
 
+/// A value taken out of a result by [crate::NamedRows::interned_rows], with its string, if any,
+/// sharing its allocation with the equal strings interned by the same [StrInterner]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InternedValue {
+    /// an interned string
+    Str(Arc<str>),
+    /// any value other than a string, kept as it is
+    Other(DataValue),
+}
+
+impl From<InternedValue> for DataValue {
+    fn from(v: InternedValue) -> Self {
+        match v {
+            InternedValue::Str(s) => DataValue::from(&s as &str),
+            InternedValue::Other(v) => v,
+        }
+    }
+}
+
+/// A pool of strings, used to make equal strings share a single allocation
+#[derive(Default, Debug)]
+pub struct StrInterner {
+    pool: HashSet<Arc<str>>,
+}
+
+impl StrInterner {
+    /// Create an empty pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The shared copy of `s`, added to the pool if not already there
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        match self.pool.get(s) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared: Arc<str> = Arc::from(s);
+                self.pool.insert(shared.clone());
+                shared
+            }
+        }
+    }
+    /// The value with its string, if it is one, replaced by the shared copy
+    pub fn intern_value(&mut self, val: DataValue) -> InternedValue {
+        match val {
+            DataValue::Str(s) => InternedValue::Str(self.intern(&s)),
+            v => InternedValue::Other(v),
+        }
+    }
+    /// The number of distinct strings in the pool
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+    /// Whether the pool is empty
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+}
+
+/// Error for converting a [DataValue] into a Rust value of an incompatible type
+#[derive(Debug, Error, Diagnostic)]
+#[error("Expected {0}, got {}", .1.display_literal())]
+#[diagnostic(code(eval::value_type_mismatch))]
+pub struct ValueTypeMismatch(&'static str, DataValue);
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("{0} cannot be coerced to {1}")]
+#[diagnostic(code(eval::cannot_coerce))]
+struct CannotCoerce(String, ValueType);
+
+impl TryFrom<&DataValue> for i64 {
+    type Error = miette::Error;
+
+    /// Floats are accepted if they hold an integral value
+    fn try_from(value: &DataValue) -> Result<Self, Self::Error> {
+        value
+            .get_int()
+            .ok_or_else(|| ValueTypeMismatch("an integer", value.clone()).into())
+    }
+}
+
+impl TryFrom<&DataValue> for f64 {
+    type Error = miette::Error;
+
+    fn try_from(value: &DataValue) -> Result<Self, Self::Error> {
+        value
+            .get_float()
+            .ok_or_else(|| ValueTypeMismatch("a number", value.clone()).into())
+    }
+}
+
+impl TryFrom<&DataValue> for bool {
+    type Error = miette::Error;
+
+    fn try_from(value: &DataValue) -> Result<Self, Self::Error> {
+        value
+            .get_bool()
+            .ok_or_else(|| ValueTypeMismatch("a boolean", value.clone()).into())
+    }
+}
+
+impl<'a> TryFrom<&'a DataValue> for &'a str {
+    type Error = miette::Error;
+
+    fn try_from(value: &'a DataValue) -> Result<Self, Self::Error> {
+        value
+            .get_str()
+            .ok_or_else(|| ValueTypeMismatch("a string", value.clone()).into())
+    }
+}
+
+impl TryFrom<&DataValue> for String {
+    type Error = miette::Error;
+
+    fn try_from(value: &DataValue) -> Result<Self, Self::Error> {
+        <&str>::try_from(value).map(|s| s.to_string())
+    }
+}
+
+impl<'a> TryFrom<&'a DataValue> for &'a [u8] {
+    type Error = miette::Error;
+
+    fn try_from(value: &'a DataValue) -> Result<Self, Self::Error> {
+        match value {
+            DataValue::Bytes(b) => Ok(b),
+            v => Err(ValueTypeMismatch("bytes", v.clone()).into()),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Uuid {
+    type Error = miette::Error;
+
+    fn try_from(value: &DataValue) -> Result<Self, Self::Error> {
+        match value {
+            DataValue::Uuid(UuidWrapper(u)) => Ok(*u),
+            v => Err(ValueTypeMismatch("a UUID", v.clone()).into()),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a DataValue> for &'a [DataValue] {
+    type Error = miette::Error;
+
+    fn try_from(value: &'a DataValue) -> Result<Self, Self::Error> {
+        value
+            .get_slice()
+            .ok_or_else(|| ValueTypeMismatch("a list", value.clone()).into())
+    }
+}
+
 /// Representing a number
 #[derive(Copy, Clone, serde_derive::Deserialize, serde_derive::Serialize)]
 pub enum Num {
@@ -316,6 +542,11 @@ impl Display for DataValue {
             }
             DataValue::List(ls) => f.debug_list().entries(ls).finish(),
             DataValue::Set(s) => f.debug_list().entries(s).finish(),
+            DataValue::BitSet(bs) => {
+                f.write_str("bitset_from_list(")?;
+                f.debug_list().entries(bs.0.iter()).finish()?;
+                f.write_str(")")
+            }
             DataValue::Bot => write!(f, "null"),
             DataValue::Validity(v) => f
                 .debug_struct("Validity")
@@ -326,7 +557,120 @@ impl Display for DataValue {
     }
 }
 
+// Single quotes are used since a double-quoted string without leading underscores is read
+// back as a raw string, in which escapes are not processed.
+fn write_literal_str(f: &mut impl std::fmt::Write, s: &str) -> std::fmt::Result {
+    f.write_char('\'')?;
+    for c in s.chars() {
+        match c {
+            '\'' => f.write_str(r"\'")?,
+            '\\' => f.write_str(r"\\")?,
+            '\n' => f.write_str(r"\n")?,
+            '\r' => f.write_str(r"\r")?,
+            '\t' => f.write_str(r"\t")?,
+            '\x08' => f.write_str(r"\b")?,
+            '\x0c' => f.write_str(r"\f")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('\'')
+}
+
+fn write_literal_seq<'a>(
+    f: &mut impl std::fmt::Write,
+    items: impl Iterator<Item = &'a DataValue>,
+) -> std::fmt::Result {
+    f.write_char('[')?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write_literal(f, item)?;
+    }
+    f.write_char(']')
+}
+
+fn write_literal(f: &mut impl std::fmt::Write, v: &DataValue) -> std::fmt::Result {
+    match v {
+        DataValue::Null | DataValue::Bot => f.write_str("null"),
+        DataValue::Bool(b) => write!(f, "{b}"),
+        // the debug format of finite floats always contains a decimal point or an exponent,
+        // so that they are not read back as integers
+        DataValue::Num(Num::Float(n)) if n.is_finite() => write!(f, "{n:?}"),
+        DataValue::Num(n) => write!(f, "{n}"),
+        DataValue::Str(s) => write_literal_str(f, s),
+        DataValue::Bytes(b) => {
+            f.write_str("decode_base64(")?;
+            write_literal_str(f, &STANDARD.encode(b))?;
+            f.write_char(')')
+        }
+        DataValue::Uuid(u) => write!(f, "to_uuid('{}')", u.0),
+        DataValue::Regex(rx) => {
+            f.write_str("regex(")?;
+            write_literal_str(f, rx.0.as_str())?;
+            f.write_char(')')
+        }
+        DataValue::List(l) => write_literal_seq(f, l.iter()),
+        // sets have no literal syntax of their own, and are written as lists
+        DataValue::Set(s) => write_literal_seq(f, s.iter()),
+        // as accepted by columns of type `Validity`, read back as a list elsewhere
+        DataValue::Validity(vld) => write!(f, "[{}, {}]", vld.timestamp.0 .0, vld.is_assert.0),
+        DataValue::BitSet(bs) => {
+            f.write_str("bitset_from_list([")?;
+            for (i, el) in bs.0.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{el}")?;
+            }
+            f.write_str("])")
+        }
+    }
+}
+
 impl DataValue {
+    /// Compares by the total order of values, with nulls placed according to `nulls`.
+    /// Only the values themselves are affected: nulls nested in lists are ordered as usual.
+    pub fn cmp_total(&self, other: &Self, nulls: NullOrder) -> Ordering {
+        match (self, other, nulls) {
+            (DataValue::Null, DataValue::Null, _) => Ordering::Equal,
+            (DataValue::Null, _, NullOrder::Last) => Ordering::Greater,
+            (_, DataValue::Null, NullOrder::Last) => Ordering::Less,
+            _ => self.cmp(other),
+        }
+    }
+    /// Renders the value in the syntax of CozoScript literals, so that it reads back
+    /// as the same value when placed in a query.
+    ///
+    /// Sets and validities have no literal syntax of their own and read back as lists:
+    /// sets are written as lists of their elements, and validities as `[timestamp, is_assert]`,
+    /// the form that columns of type `Validity` accept.
+    pub fn display_literal(&self) -> String {
+        let mut ret = String::new();
+        write_literal(&mut ret, self).unwrap();
+        ret
+    }
+    /// A rough estimate of the memory taken up by the value, including its heap allocations.
+    /// Collections are accounted for recursively, so a collection is always larger than
+    /// any of its elements.
+    pub fn approx_size(&self) -> usize {
+        size_of::<DataValue>()
+            + match self {
+                DataValue::Null
+                | DataValue::Bool(_)
+                | DataValue::Num(_)
+                | DataValue::Uuid(_)
+                | DataValue::Validity(_)
+                | DataValue::Bot => 0,
+                DataValue::Str(s) => s.len(),
+                DataValue::Bytes(b) => b.len(),
+                DataValue::Regex(rx) => rx.0.as_str().len(),
+                DataValue::List(l) => l.iter().map(|v| v.approx_size()).sum(),
+                DataValue::Set(s) => s.iter().map(|v| v.approx_size()).sum(),
+                DataValue::BitSet(bs) => bs.0.serialized_size(),
+            }
+    }
     /// Returns a slice of DataValues if this one is a List
     pub fn get_slice(&self) -> Option<&[DataValue]> {
         match self {
@@ -380,6 +724,71 @@ impl DataValue {
             _ => None,
         }
     }
+    /// Convert the value to one of the given type, following the rules of the `to_*` functions,
+    /// and used by them and by the promotion of integers in arithmetic.
+    /// Values already of the type are returned as they are.
+    ///
+    /// Coercing to `Number` leaves numbers alone and converts other values as for `Float`,
+    /// coercing to `List` or `Set` converts between the two.
+    pub fn coerce_to(&self, target: ValueType) -> miette::Result<DataValue> {
+        if target == ValueType::Any || target.accepts_exactly(ValueType::of(self)) {
+            return Ok(self.clone());
+        }
+        Ok(match (target, self) {
+            (ValueType::Int, DataValue::Num(Num::Float(f))) => DataValue::from(*f as i64),
+            (ValueType::Int, DataValue::Null) => DataValue::from(0),
+            (ValueType::Int, DataValue::Bool(b)) => DataValue::from(if *b { 1 } else { 0 }),
+            (ValueType::Int, DataValue::Str(s)) => i64::from_str(s)
+                .map_err(|_| miette!("The string cannot be interpreted as int"))?
+                .into(),
+            (ValueType::Int, DataValue::Validity(vld)) => DataValue::from(vld.timestamp.0 .0),
+            (ValueType::Float | ValueType::Number, DataValue::Num(n)) => n.get_float().into(),
+            (ValueType::Float | ValueType::Number, DataValue::Null) => DataValue::from(0.0),
+            (ValueType::Float | ValueType::Number, DataValue::Bool(b)) => {
+                DataValue::from(if *b { 1.0 } else { 0.0 })
+            }
+            (ValueType::Float | ValueType::Number, DataValue::Str(s)) => match s as &str {
+                "PI" => f64::PI().into(),
+                "E" => f64::E().into(),
+                "NAN" => f64::NAN.into(),
+                "INF" => f64::INFINITY.into(),
+                "NEG_INF" => f64::NEG_INFINITY.into(),
+                s => f64::from_str(s)
+                    .map_err(|_| miette!("The string cannot be interpreted as float"))?
+                    .into(),
+            },
+            (ValueType::String, v) => DataValue::from(JsonValue::from(v.clone()).to_string()),
+            (ValueType::Bool, v) => DataValue::from(match v {
+                DataValue::Null => false,
+                DataValue::Bool(b) => *b,
+                DataValue::Num(n) => n.get_int() != Some(0),
+                DataValue::Str(s) => !s.is_empty(),
+                DataValue::Bytes(b) => !b.is_empty(),
+                DataValue::Uuid(u) => !u.0.is_nil(),
+                DataValue::Regex(r) => !r.0.as_str().is_empty(),
+                DataValue::List(l) => !l.is_empty(),
+                DataValue::Set(s) => !s.is_empty(),
+                DataValue::Validity(vld) => vld.is_assert.0,
+                DataValue::BitSet(bs) => !bs.0.is_empty(),
+                DataValue::Bot => false,
+            }),
+            (ValueType::Uuid, DataValue::Str(s)) => {
+                let id = uuid::Uuid::try_parse(s).map_err(|_| miette!("invalid UUID"))?;
+                DataValue::uuid(id)
+            }
+            (ValueType::Regex, DataValue::Str(s)) => {
+                DataValue::Regex(RegexWrapper(regex::Regex::new(s).map_err(|err| {
+                    miette!("The string cannot be interpreted as regex: {}", err)
+                })?))
+            }
+            (ValueType::List, DataValue::Set(s)) => DataValue::List(s.iter().cloned().collect()),
+            (ValueType::Set, DataValue::List(l)) => DataValue::Set(l.iter().cloned().collect()),
+            (ValueType::List, DataValue::BitSet(bs)) => {
+                DataValue::List(bs.0.iter().map(|el| DataValue::from(el as i64)).collect())
+            }
+            (t, v) => bail!(CannotCoerce(v.display_literal(), t)),
+        })
+    }
 }
 
 pub(crate) const LARGEST_UTF_CHAR: char = '\u{10ffff}';
@@ -48,7 +48,11 @@ use miette::{
 };
 use serde_json::json;
 
-pub use data::value::{DataValue, Num, RegexWrapper, UuidWrapper, Validity, ValidityTs};
+pub use data::tuple::TypedRow;
+pub use data::value::{
+    BitSetWrapper, DataValue, InternedValue, NullOrder, Num, RegexWrapper, StrInterner,
+    UuidWrapper, Validity, ValidityTs, ValueTypeMismatch,
+};
 pub use fixed_rule::{FixedRule, FixedRuleInputRelation, FixedRulePayload};
 pub use runtime::db::Db;
 pub use runtime::db::NamedRows;
@@ -63,16 +67,20 @@ pub use storage::sled::{new_cozo_sled, SledStorage};
 pub use storage::sqlite::{new_cozo_sqlite, SqliteStorage};
 #[cfg(feature = "storage-tikv")]
 pub use storage::tikv::{new_cozo_tikv, TiKvStorage};
-pub use storage::{Storage, StoreTx};
+pub use storage::{Storage, StoreTx, TransientStorageError};
 
-pub use crate::data::expr::Expr;
+pub use crate::data::expr::{evaluate_expression, CompiledExpression, Expr, RewriteStep};
+pub use crate::data::functions::{DivisionByZero, FloatEqTolerance};
+pub use crate::data::type_infer::ValueType;
 use crate::data::json::JsonValue;
 pub use crate::data::symb::Symbol;
 pub use crate::fixed_rule::SimpleFixedRule;
 pub use crate::parse::SourceSpan;
-pub use crate::runtime::callback::CallbackOp;
+pub use crate::runtime::callback::{CallbackOp, OverflowPolicy};
+pub use crate::runtime::csv_import::CsvImportOptions;
 pub use crate::runtime::db::Poison;
 pub use crate::runtime::db::TransactionPayload;
+pub use crate::runtime::plan::{PlanOp, PlanStep, QueryPlan};
 
 pub(crate) mod data;
 pub(crate) mod fixed_rule;
@@ -178,6 +186,85 @@ impl DbInstance {
             DbInstance::TiKv(db) => db.run_script(payload, params),
         }
     }
+    /// Dispatcher method. See [crate::Db::query_plan].
+    pub fn query_plan(
+        &self,
+        payload: &str,
+        params: BTreeMap<String, DataValue>,
+    ) -> Result<QueryPlan> {
+        match self {
+            DbInstance::Mem(db) => db.query_plan(payload, params),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.query_plan(payload, params),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.query_plan(payload, params),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.query_plan(payload, params),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.query_plan(payload, params),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::run_script_cancellable].
+    pub fn run_script_cancellable(
+        &self,
+        payload: &str,
+        params: BTreeMap<String, DataValue>,
+        cancellation: Poison,
+    ) -> Result<NamedRows> {
+        match self {
+            DbInstance::Mem(db) => db.run_script_cancellable(payload, params, cancellation),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.run_script_cancellable(payload, params, cancellation),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.run_script_cancellable(payload, params, cancellation),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.run_script_cancellable(payload, params, cancellation),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.run_script_cancellable(payload, params, cancellation),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::relation_exists].
+    pub fn relation_exists(&self, name: &str) -> Result<bool> {
+        match self {
+            DbInstance::Mem(db) => db.relation_exists(name),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.relation_exists(name),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.relation_exists(name),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.relation_exists(name),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.relation_exists(name),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::create_relation_if_not_exists].
+    pub fn create_relation_if_not_exists(&self, name: &str, schema: &str) -> Result<bool> {
+        match self {
+            DbInstance::Mem(db) => db.create_relation_if_not_exists(name, schema),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.create_relation_if_not_exists(name, schema),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.create_relation_if_not_exists(name, schema),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.create_relation_if_not_exists(name, schema),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.create_relation_if_not_exists(name, schema),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::remove_relation_if_exists].
+    pub fn remove_relation_if_exists(&self, name: &str) -> Result<bool> {
+        match self {
+            DbInstance::Mem(db) => db.remove_relation_if_exists(name),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.remove_relation_if_exists(name),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.remove_relation_if_exists(name),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.remove_relation_if_exists(name),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.remove_relation_if_exists(name),
+        }
+    }
     /// Run the CozoScript passed in. The `params` argument is a map of parameters.
     /// Fold any error into the return JSON itself.
     /// See [crate::Db::run_script].
@@ -281,6 +368,25 @@ impl DbInstance {
             DbInstance::TiKv(db) => db.import_relations(data),
         }
     }
+    /// Dispatcher method. See [crate::Db::import_from_csv].
+    pub fn import_from_csv(
+        &self,
+        relation: &str,
+        reader: impl std::io::Read,
+        options: &CsvImportOptions,
+    ) -> Result<usize> {
+        match self {
+            DbInstance::Mem(db) => db.import_from_csv(relation, reader, options),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.import_from_csv(relation, reader, options),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.import_from_csv(relation, reader, options),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.import_from_csv(relation, reader, options),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.import_from_csv(relation, reader, options),
+        }
+    }
     /// Import a relation, the data is given as a JSON string, and the returned result is converted into a string.
     /// See [crate::Db::import_relations].
     pub fn import_relations_str(&self, data: &str) -> String {
@@ -308,6 +414,20 @@ impl DbInstance {
             .collect::<Result<_>>()?;
         self.import_relations(mapping)
     }
+    /// Dispatcher method. See [crate::Db::compact].
+    pub fn compact(&self) -> Result<()> {
+        match self {
+            DbInstance::Mem(db) => db.compact(),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.compact(),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.compact(),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.compact(),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.compact(),
+        }
+    }
     /// Dispatcher method. See [crate::Db::backup_db].
     pub fn backup_db(&self, out_file: impl AsRef<Path>) -> Result<()> {
         match self {
@@ -409,6 +529,31 @@ impl DbInstance {
         }
     }
 
+    /// Dispatcher method. See [crate::Db::subscribe].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn subscribe<F>(
+        &self,
+        relation: &str,
+        capacity: usize,
+        overflow: OverflowPolicy,
+        callback: F,
+    ) -> u32
+    where
+        F: FnMut(CallbackOp, NamedRows, NamedRows) + Send + 'static,
+    {
+        match self {
+            DbInstance::Mem(db) => db.subscribe(relation, capacity, overflow, callback),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.subscribe(relation, capacity, overflow, callback),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.subscribe(relation, capacity, overflow, callback),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.subscribe(relation, capacity, overflow, callback),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.subscribe(relation, capacity, overflow, callback),
+        }
+    }
+
     /// Dispatcher method. See [crate::Db::unregister_callback].
     #[cfg(not(target_arch = "wasm32"))]
     pub fn unregister_callback(&self, id: u32) -> bool {
@@ -424,6 +569,90 @@ impl DbInstance {
             DbInstance::TiKv(db) => db.unregister_callback(id),
         }
     }
+    /// Dispatcher method. See [crate::Db::set_result_cache_capacity].
+    pub fn set_result_cache_capacity(&self, capacity: usize) {
+        match self {
+            DbInstance::Mem(db) => db.set_result_cache_capacity(capacity),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_result_cache_capacity(capacity),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_result_cache_capacity(capacity),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_result_cache_capacity(capacity),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_result_cache_capacity(capacity),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::set_query_memory_limit].
+    pub fn set_query_memory_limit(&self, bytes: usize) {
+        match self {
+            DbInstance::Mem(db) => db.set_query_memory_limit(bytes),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_query_memory_limit(bytes),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_query_memory_limit(bytes),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_query_memory_limit(bytes),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_query_memory_limit(bytes),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::set_retry_policy].
+    pub fn set_retry_policy(&self, max_retries: usize, backoff: std::time::Duration) {
+        match self {
+            DbInstance::Mem(db) => db.set_retry_policy(max_retries, backoff),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_retry_policy(max_retries, backoff),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_retry_policy(max_retries, backoff),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_retry_policy(max_retries, backoff),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_retry_policy(max_retries, backoff),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::set_float_eq_tolerance].
+    pub fn set_float_eq_tolerance(&self, tolerance: Option<FloatEqTolerance>) {
+        match self {
+            DbInstance::Mem(db) => db.set_float_eq_tolerance(tolerance),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_float_eq_tolerance(tolerance),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_float_eq_tolerance(tolerance),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_float_eq_tolerance(tolerance),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_float_eq_tolerance(tolerance),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::set_division_by_zero].
+    pub fn set_division_by_zero(&self, mode: DivisionByZero) {
+        match self {
+            DbInstance::Mem(db) => db.set_division_by_zero(mode),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_division_by_zero(mode),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_division_by_zero(mode),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_division_by_zero(mode),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_division_by_zero(mode),
+        }
+    }
+    /// Dispatcher method. See [crate::Db::set_catch_panics].
+    pub fn set_catch_panics(&self, catch: bool) {
+        match self {
+            DbInstance::Mem(db) => db.set_catch_panics(catch),
+            #[cfg(feature = "storage-sqlite")]
+            DbInstance::Sqlite(db) => db.set_catch_panics(catch),
+            #[cfg(feature = "storage-rocksdb")]
+            DbInstance::RocksDb(db) => db.set_catch_panics(catch),
+            #[cfg(feature = "storage-sled")]
+            DbInstance::Sled(db) => db.set_catch_panics(catch),
+            #[cfg(feature = "storage-tikv")]
+            DbInstance::TiKv(db) => db.set_catch_panics(catch),
+        }
+    }
     /// Dispatcher method. See [crate::Db::register_fixed_rule].
     pub fn register_fixed_rule<R>(&self, name: String, rule_impl: R) -> Result<()>
     where
@@ -15,10 +15,10 @@ use pest::pratt_parser::{Op, PrattParser};
 use smartstring::{LazyCompact, SmartString};
 use thiserror::Error;
 
-use crate::data::expr::{get_op, Bytecode, Expr};
+use crate::data::expr::{get_op, Bytecode, Expr, SharedSubexprs};
 use crate::data::functions::{
-    OP_ADD, OP_AND, OP_COALESCE, OP_CONCAT, OP_DIV, OP_EQ, OP_GE, OP_GT, OP_LE, OP_LIST, OP_LT,
-    OP_MINUS, OP_MOD, OP_MUL, OP_NEGATE, OP_NEQ, OP_OR, OP_POW, OP_SUB,
+    OP_ADD, OP_AND, OP_COALESCE, OP_CONCAT, OP_DIV, OP_EQ, OP_GE, OP_GT, OP_IS_NULL, OP_LE,
+    OP_LIST, OP_LT, OP_MINUS, OP_MOD, OP_MUL, OP_NEGATE, OP_NEQ, OP_OR, OP_POW, OP_SUB,
 };
 use crate::data::symb::Symbol;
 use crate::data::value::DataValue;
@@ -54,6 +54,17 @@ lazy_static! {
 pub(crate) struct InvalidExpression(#[label] pub(crate) SourceSpan);
 
 pub(crate) fn expr2bytecode(expr: &Expr, collector: &mut Vec<Bytecode>) {
+    expr2bytecode_shared(expr, &mut SharedSubexprs::default(), true, collector)
+}
+
+/// Compiles `expr`, computing repeated subexpressions only once.
+/// `unconditional` tells whether `expr` is always evaluated, i.e. not within a branch of a `cond`.
+pub(crate) fn expr2bytecode_shared<'a>(
+    expr: &'a Expr,
+    sharing: &mut SharedSubexprs<'a>,
+    unconditional: bool,
+    collector: &mut Vec<Bytecode>,
+) {
     match expr {
         Expr::Binding { var, tuple_pos } => collector.push(Bytecode::Binding {
             var: var.clone(),
@@ -64,21 +75,30 @@ pub(crate) fn expr2bytecode(expr: &Expr, collector: &mut Vec<Bytecode>) {
             span: *span,
         }),
         Expr::Apply { op, args, span } => {
+            let shape = sharing.shape_if_repeated(expr);
+            if let Some(slot) = shape.as_ref().and_then(|shape| sharing.slot(shape)) {
+                collector.push(Bytecode::Load { slot });
+                return;
+            }
             let arity = args.len();
             for arg in args.iter() {
-                expr2bytecode(arg, collector);
+                expr2bytecode_shared(arg, sharing, unconditional, collector);
             }
             collector.push(Bytecode::Apply {
                 op,
                 arity,
                 span: *span,
-            })
+            });
+            if let (Some(shape), true) = (shape, unconditional) {
+                let slot = sharing.assign_slot(shape);
+                collector.push(Bytecode::Store { slot });
+            }
         }
         Expr::Cond { clauses, span } => {
             let mut return_jump_pos = vec![];
-            for (cond, val) in clauses {
-                // +1
-                expr2bytecode(cond, collector);
+            for (i, (cond, val)) in clauses.iter().enumerate() {
+                // +1, only the first condition is evaluated unconditionally
+                expr2bytecode_shared(cond, sharing, unconditional && i == 0, collector);
                 // -1
                 collector.push(Bytecode::JumpIfFalse {
                     jump_to: 0,
@@ -86,7 +106,7 @@ pub(crate) fn expr2bytecode(expr: &Expr, collector: &mut Vec<Bytecode>) {
                 });
                 let false_jump_amend_pos = collector.len() - 1;
                 // +1 in this branch
-                expr2bytecode(val, collector);
+                expr2bytecode_shared(val, sharing, false, collector);
                 collector.push(Bytecode::Goto {
                     jump_to: 0,
                     span: *span,
@@ -264,6 +284,7 @@ fn build_term(pair: Pair<'_>, param_pool: &BTreeMap<String, DataValue>) -> Resul
             for p in pair.into_inner() {
                 collected.push(build_expr(p, param_pool)?)
             }
+            OP_LIST.post_process_args(&mut collected);
             Expr::Apply {
                 op: &OP_LIST,
                 args: collected.into(),
@@ -354,6 +375,40 @@ fn build_term(pair: Pair<'_>, param_pool: &BTreeMap<String, DataValue>) -> Resul
                     ));
                     Expr::Cond { clauses, span }
                 }
+                // `nvl2(x, a, b)` is `a` if `x` is not null and `b` otherwise.
+                // Built as a conditional so that only the branch taken is evaluated.
+                "nvl2" => {
+                    #[derive(Debug, Error, Diagnostic)]
+                    #[error("wrong number of arguments to nvl2: 3 required")]
+                    #[diagnostic(code(parser::bad_nvl2))]
+                    struct WrongArgsToNvl2(#[label] SourceSpan);
+
+                    ensure!(args.len() == 3, WrongArgsToNvl2(span));
+
+                    let mut args = args.into_iter();
+                    let val = args.next().unwrap();
+                    let if_not_null = args.next().unwrap();
+                    let if_null = args.next().unwrap();
+                    let val_span = val.span();
+                    let clauses = vec![
+                        (
+                            Expr::Apply {
+                                op: &OP_IS_NULL,
+                                args: [val].into(),
+                                span: val_span,
+                            },
+                            if_null,
+                        ),
+                        (
+                            Expr::Const {
+                                val: DataValue::from(true),
+                                span,
+                            },
+                            if_not_null,
+                        ),
+                    ];
+                    Expr::Cond { clauses, span }
+                }
                 _ => {
                     let op = get_op(ident).ok_or_else(|| {
                         FuncNotFoundError(ident.to_string(), ident_p.extract_span())
@@ -361,17 +416,18 @@ fn build_term(pair: Pair<'_>, param_pool: &BTreeMap<String, DataValue>) -> Resul
                     op.post_process_args(&mut args);
 
                     #[derive(Error, Diagnostic, Debug)]
-                    #[error("Wrong number of arguments for function '{0}'")]
+                    #[error("Wrong number of arguments for function '{0}': expected {1}, got {2}")]
                     #[diagnostic(code(parser::func_wrong_num_args))]
-                    struct WrongNumArgsError(String, #[label] SourceSpan, #[help] String);
+                    struct WrongNumArgsError(String, String, usize, #[label] SourceSpan);
 
                     if op.vararg {
                         ensure!(
                             op.min_arity <= args.len(),
                             WrongNumArgsError(
                                 ident.to_string(),
+                                format!("at least {}", op.min_arity),
+                                args.len(),
                                 span,
-                                format!("Need at least {} argument(s)", op.min_arity)
                             )
                         );
                     } else {
@@ -379,8 +435,9 @@ fn build_term(pair: Pair<'_>, param_pool: &BTreeMap<String, DataValue>) -> Resul
                             op.min_arity == args.len(),
                             WrongNumArgsError(
                                 ident.to_string(),
+                                format!("exactly {}", op.min_arity),
+                                args.len(),
                                 span,
-                                format!("Need exactly {} argument(s)", op.min_arity)
                             )
                         );
                     }
@@ -18,9 +18,11 @@ use pest::Parser;
 use smartstring::{LazyCompact, SmartString};
 use thiserror::Error;
 
+use crate::data::expr::Expr;
 use crate::data::program::InputProgram;
 use crate::data::relation::NullableColType;
 use crate::data::value::{DataValue, ValidityTs};
+use crate::parse::expr::build_expr;
 use crate::parse::imperative::parse_imperative_block;
 use crate::parse::query::parse_query;
 use crate::parse::schema::parse_nullable_type;
@@ -203,6 +205,23 @@ pub(crate) fn parse_type(src: &str) -> Result<NullableColType> {
     parse_nullable_type(parsed.into_inner().next().unwrap())
 }
 
+pub(crate) fn parse_expression(
+    src: &str,
+    param_pool: &BTreeMap<String, DataValue>,
+) -> Result<Expr> {
+    let parsed = CozoScriptParser::parse(Rule::expr_with_term, src)
+        .map_err(|err| {
+            let span = match err.location {
+                InputLocation::Pos(p) => SourceSpan(p, 0),
+                InputLocation::Span((start, end)) => SourceSpan(start, end - start),
+            };
+            ParseError { span }
+        })?
+        .next()
+        .unwrap();
+    build_expr(parsed.into_inner().next().unwrap(), param_pool)
+}
+
 pub(crate) fn parse_script(
     src: &str,
     param_pool: &BTreeMap<String, DataValue>,
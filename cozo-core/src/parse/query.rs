@@ -281,6 +281,16 @@ pub(crate) fn parse_query(
                     .ok_or(OptionNotNonNegIntError("offset", span))?;
                 out_opts.offset = Some(offset as usize);
             }
+            Rule::seed_option => {
+                let pair = pair.into_inner().next().unwrap();
+                let span = pair.extract_span();
+                let seed = build_expr(pair, param_pool)?
+                    .eval_to_const()
+                    .map_err(|err| OptionNotConstantError("seed", span, [err]))?
+                    .get_non_neg_int()
+                    .ok_or(OptionNotNonNegIntError("seed", span))?;
+                out_opts.seed = Some(seed);
+            }
             Rule::sort_option => {
                 for part in pair.into_inner() {
                     let mut var = "";
@@ -464,6 +474,20 @@ fn parse_rule(
             &mut ignored_counter,
         )?)
     }
+    // `count(*)` aggregates a constant, so that every row is counted
+    if let Some(symb) = head.iter().find(|s| s.name == COUNT_ALL_SYMBOL) {
+        body_clauses.push(InputAtom::Unification {
+            inner: Unification {
+                binding: symb.clone(),
+                expr: Expr::Const {
+                    val: DataValue::from(true),
+                    span: symb.span,
+                },
+                one_many_unif: false,
+                span: symb.span,
+            },
+        })
+    }
 
     Ok((
         name,
@@ -675,6 +699,9 @@ fn parse_rule_head(
     Ok((Symbol::new(name.as_str(), name.extract_span()), args, aggrs))
 }
 
+/// The variable bound for the `*` in `count(*)`, which cannot clash with any written variable.
+const COUNT_ALL_SYMBOL: &str = "*";
+
 #[derive(Error, Diagnostic, Debug)]
 #[diagnostic(code(parser::aggr_not_found))]
 #[error("Aggregation '{0}' not found")]
@@ -692,11 +719,26 @@ fn parse_rule_head_arg(
             let aggr_p = inner.next().unwrap();
             let aggr_name = aggr_p.as_str();
             let var = inner.next().unwrap();
+            let var_name = match var.as_rule() {
+                Rule::aggr_all => {
+                    #[derive(Error, Diagnostic, Debug)]
+                    #[diagnostic(code(parser::aggr_all_not_count))]
+                    #[error("Only 'count' can be applied to '*', not '{0}'")]
+                    struct AggrAllNotCount(String, #[label] SourceSpan);
+
+                    ensure!(
+                        aggr_name == "count",
+                        AggrAllNotCount(aggr_name.to_string(), var.extract_span())
+                    );
+                    COUNT_ALL_SYMBOL
+                }
+                _ => var.as_str(),
+            };
             let args: Vec<_> = inner
                 .map(|v| -> Result<DataValue> { build_expr(v, param_pool)?.eval_to_const() })
                 .try_collect()?;
             (
-                Symbol::new(var.as_str(), var.extract_span()),
+                Symbol::new(var_name, var.extract_span()),
                 Some((
                     parse_aggr(aggr_name)
                         .ok_or_else(|| AggrNotFound(aggr_name.to_string(), aggr_p.extract_span()))?
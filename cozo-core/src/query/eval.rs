@@ -13,10 +13,15 @@ use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use itertools::Itertools;
 use log::{debug, trace};
 use miette::Result;
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
 use rayon::prelude::*;
 
 use crate::data::aggr::Aggregation;
+#[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
+use crate::data::functions::{
+    current_division_by_zero, current_float_eq_tolerance, current_query_seed,
+    with_division_by_zero, with_float_eq_tolerance, with_query_seed,
+};
 use crate::data::program::{MagicSymbol, NoEntryError};
 use crate::data::symb::{Symbol, PROG_ENTRY};
 use crate::data::tuple::Tuple;
@@ -52,9 +57,14 @@ impl QueryLimiter {
         }
     }
     pub(crate) fn should_skip_next(&self) -> bool {
+        let count = self.counter.load(Ordering::Relaxed);
+        // the last row taken is one past the limit, only showing that the limit left rows out
+        if matches!(self.total, Some(limit) if count + 1 >= limit) {
+            return true;
+        }
         match self.skip {
             None => false,
-            Some(i) => i > self.counter.load(Ordering::Relaxed),
+            Some(i) => i > count,
         }
     }
 }
@@ -123,6 +133,14 @@ impl<'a> SessionTx<'a> {
         };
 
         let used_limiter: AtomicBool = false.into();
+        // rules evaluated on other threads compare floats, divide by zero and draw random numbers
+        // as this one does
+        #[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
+        let float_eq_tolerance = current_float_eq_tolerance();
+        #[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
+        let division_by_zero = current_division_by_zero();
+        #[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
+        let seed = current_query_seed();
 
         for epoch in 0u32.. {
             debug!("epoch {}", epoch);
@@ -179,7 +197,7 @@ impl<'a> SessionTx<'a> {
                     };
                     Ok((k, new_store))
                 };
-                #[cfg(not(target_arch = "wasm32"))]
+                #[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
                 {
                     let limiter_enabled = limiter.total.is_some();
                     for res in prog
@@ -197,14 +215,20 @@ impl<'a> SessionTx<'a> {
                     let execs = prog
                         .par_iter()
                         .filter(|(symb, _)| !(limiter_enabled && symb.is_prog_entry()))
-                        .map(execution);
+                        .map(|rule| {
+                            with_float_eq_tolerance(float_eq_tolerance, || {
+                                with_division_by_zero(division_by_zero, || {
+                                    with_query_seed(seed, || execution(rule))
+                                })
+                            })
+                        });
 
                     for res in execs.collect::<Vec<_>>() {
                         let (k, new_store) = res?;
                         to_merge.insert(k, new_store);
                     }
                 }
-                #[cfg(target_arch = "wasm32")]
+                #[cfg(any(target_arch = "wasm32", not(feature = "rayon")))]
                 {
                     for res in prog.iter().map(execution) {
                         let (k, new_store) = res?;
@@ -252,7 +276,7 @@ impl<'a> SessionTx<'a> {
                     };
                     Ok((k, new_store))
                 };
-                #[cfg(not(target_arch = "wasm32"))]
+                #[cfg(all(not(target_arch = "wasm32"), feature = "rayon"))]
                 {
                     let limiter_enabled = limiter.total.is_some();
                     // entry rules with limiter must execute sequentially in order to get deterministic ordering
@@ -271,13 +295,19 @@ impl<'a> SessionTx<'a> {
                     let execs = prog
                         .par_iter()
                         .filter(|(symb, _)| !(limiter_enabled && symb.is_prog_entry()))
-                        .map(execution);
+                        .map(|rule| {
+                            with_float_eq_tolerance(float_eq_tolerance, || {
+                                with_division_by_zero(division_by_zero, || {
+                                    with_query_seed(seed, || execution(rule))
+                                })
+                            })
+                        });
                     for res in execs.collect::<Vec<_>>() {
                         let (k, new_store) = res?;
                         to_merge.insert(k, new_store);
                     }
                 }
-                #[cfg(target_arch = "wasm32")]
+                #[cfg(any(target_arch = "wasm32", not(feature = "rayon")))]
                 {
                     for res in prog.iter().map(execution) {
                         let (k, new_store) = res?;
@@ -317,6 +347,7 @@ impl<'a> SessionTx<'a> {
                 trace!("item for {:?}.{}: {:?} at {}", rule_symb, rule_n, item, 0);
                 if should_check_limit {
                     if !out_store.exists(&item) {
+                        self.charge_memory(&item)?;
                         if limiter.should_skip_next() {
                             out_store.put_with_skip(item);
                         } else {
@@ -328,6 +359,10 @@ impl<'a> SessionTx<'a> {
                         }
                     }
                 } else {
+                    // tuples derived again take up no more memory
+                    if self.memory_budget.is_some() && !out_store.exists(&item) {
+                        self.charge_memory(&item)?;
+                    }
                     out_store.put(item);
                 }
             }
@@ -354,6 +389,9 @@ impl<'a> SessionTx<'a> {
             for item_res in rule.relation.iter(self, None, stores)? {
                 let item = item_res?;
                 trace!("item for {:?}.{}: {:?} at {}", rule_symb, rule_n, item, 0);
+                if self.memory_budget.is_some() && !out_store.exists(&item) {
+                    self.charge_memory(&item)?;
+                }
                 out_store.meet_put(item)?;
             }
             poison.check()?;
@@ -429,6 +467,7 @@ impl<'a> SessionTx<'a> {
                         }
                     }
                     Entry::Vacant(ent) => {
+                        self.charge_memory(ent.key())?;
                         let mut aggr_ops = Vec::with_capacity(val_indices_and_aggrs.len());
                         for (i, (aggr, params)) in &val_indices_and_aggrs {
                             let mut cur_aggr = aggr.clone();
@@ -550,6 +589,10 @@ impl<'a> SessionTx<'a> {
                             item,
                             epoch
                         );
+                        // the same tuple may be derived more than once in an epoch
+                        if self.memory_budget.is_some() && !out_store.exists(&item) {
+                            self.charge_memory(&item)?;
+                        }
                         if limiter.should_skip_next() {
                             out_store.put_with_skip(item);
                         } else {
@@ -598,7 +641,11 @@ impl<'a> SessionTx<'a> {
                     delta_key, rule_symb, rule_n
                 );
                 for item_res in rule.relation.iter(self, Some(delta_key), stores)? {
-                    out_store.meet_put(item_res?)?;
+                    let item = item_res?;
+                    if self.memory_budget.is_some() && !out_store.exists(&item) {
+                        self.charge_memory(&item)?;
+                    }
+                    out_store.meet_put(item)?;
                 }
                 poison.check()?;
             }
@@ -67,7 +67,7 @@ pub(crate) struct UnificationRA {
 }
 
 #[derive(Debug, Error, Diagnostic)]
-#[error("Found value {0:?} while iterating, unacceptable for an Entity ID")]
+#[error("Found value {} while iterating, unacceptable for an Entity ID", .0.display_literal())]
 #[diagnostic(code(eval::iter_bad_entity_id))]
 struct EntityIdExpected(DataValue, #[label] SourceSpan);
 
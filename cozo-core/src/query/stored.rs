@@ -37,6 +37,8 @@ use crate::{Db, NamedRows, StoreTx};
 struct RelationArityMismatch(String, usize, usize);
 
 impl<'a> SessionTx<'a> {
+    /// Returns the stored relations to be deleted at the end of the query,
+    /// and the number of rows written or removed.
     pub(crate) fn execute_relation<'s, S: Storage<'s>>(
         &mut self,
         db: &Db<S>,
@@ -48,8 +50,10 @@ impl<'a> SessionTx<'a> {
         callback_targets: &BTreeSet<SmartString<LazyCompact>>,
         callback_collector: &mut CallbackCollector,
         propagate_triggers: bool,
-    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    ) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, usize)> {
+        self.written_relations.insert(meta.name.name.clone());
         let mut to_clear = vec![];
+        let mut affected = 0;
         let mut replaced_old_triggers = None;
         if op == RelationOp::Replace {
             if !propagate_triggers {
@@ -158,6 +162,7 @@ impl<'a> SessionTx<'a> {
                     let key = relation_store.encode_key_for_store(&extracted, *span)?;
                     if need_to_collect || has_indices {
                         if let Some(existing) = self.store_tx.get(&key, false)? {
+                            affected += 1;
                             let mut tup = extracted.clone();
                             extend_tuple_from_v(&mut tup, &existing);
                             if has_indices {
@@ -176,11 +181,21 @@ impl<'a> SessionTx<'a> {
                         if need_to_collect {
                             new_tuples.push(DataValue::List(extracted.clone()));
                         }
-                    }
-                    if relation_store.is_temp {
-                        self.temp_store_tx.del(&key)?;
+                        if relation_store.is_temp {
+                            self.temp_store_tx.del(&key)?;
+                        } else {
+                            self.store_tx.del(&key)?;
+                        }
                     } else {
-                        self.store_tx.del(&key)?;
+                        // only rows that exist count as removed
+                        let existed = if relation_store.is_temp {
+                            self.temp_store_tx.del_and_check(&key)?
+                        } else {
+                            self.store_tx.del_and_check(&key)?
+                        };
+                        if existed {
+                            affected += 1;
+                        }
                     }
                 }
 
@@ -464,6 +479,7 @@ impl<'a> SessionTx<'a> {
                     } else {
                         self.store_tx.put(&key, &val)?;
                     }
+                    affected += 1;
                 }
 
                 if need_to_collect && !new_tuples.is_empty() {
@@ -560,7 +576,7 @@ impl<'a> SessionTx<'a> {
             }
         };
 
-        Ok(to_clear)
+        Ok((to_clear, affected))
     }
 }
 
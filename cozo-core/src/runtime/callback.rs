@@ -9,7 +9,8 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
 
-use crossbeam::channel::Sender;
+use crossbeam::channel::{Sender, TrySendError};
+use itertools::Itertools;
 use smartstring::{LazyCompact, SmartString};
 
 use crate::{Db, NamedRows, Storage};
@@ -42,10 +43,21 @@ impl CallbackOp {
     }
 }
 
+/// What happens to changes for a callback channel whose buffer is full
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for the receiver to make room, delaying the write that made the changes
+    #[default]
+    Block,
+    /// Drop the changes, so that the receiver misses them
+    Drop,
+}
+
 #[allow(dead_code)]
 pub struct CallbackDeclaration {
     pub(crate) dependent: SmartString<LazyCompact>,
     pub(crate) sender: Sender<(CallbackOp, NamedRows, NamedRows)>,
+    pub(crate) overflow: OverflowPolicy,
 }
 
 pub(crate) type CallbackCollector =
@@ -80,24 +92,27 @@ impl<'s, S: Storage<'s>> Db<S> {
         let mut to_remove = vec![];
 
         for (table, vals) in collector {
-            for (op, new, old) in vals {
+            // the senders are cloned so that no lock is held while blocked on full channels
+            let targets = {
                 let (cbs, cb_dir) = &*self.event_callbacks.read().unwrap();
-                if let Some(cb_ids) = cb_dir.get(&table) {
-                    let mut it = cb_ids.iter();
-                    if let Some(fst) = it.next() {
-                        for cb_id in it {
-                            if let Some(cb) = cbs.get(cb_id) {
-                                if cb.sender.send((op, new.clone(), old.clone())).is_err() {
-                                    to_remove.push(*cb_id)
-                                }
-                            }
-                        }
-
-                        if let Some(cb) = cbs.get(fst) {
-                            if cb.sender.send((op, new, old)).is_err() {
-                                to_remove.push(*fst)
-                            }
+                cb_dir
+                    .get(&table)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| cbs.get(id).map(|cb| (*id, cb.sender.clone(), cb.overflow)))
+                    .collect_vec()
+            };
+            for (op, new, old) in vals {
+                for (cb_id, sender, overflow) in &targets {
+                    let payload = (op, new.clone(), old.clone());
+                    let disconnected = match overflow {
+                        OverflowPolicy::Block => sender.send(payload).is_err(),
+                        OverflowPolicy::Drop => {
+                            matches!(sender.try_send(payload), Err(TrySendError::Disconnected(_)))
                         }
+                    };
+                    if disconnected {
+                        to_remove.push(*cb_id)
                     }
                 }
             }
@@ -0,0 +1,143 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use itertools::Itertools;
+use miette::{bail, Diagnostic, IntoDiagnostic, Result};
+use thiserror::Error;
+
+use crate::data::relation::{ColType, ColumnDef, NullableColType};
+use crate::data::type_infer::ValueType;
+use crate::data::value::DataValue;
+use crate::parse::parse_type;
+use crate::runtime::db::NamedRows;
+
+/// Options for [Db::import_from_csv](crate::Db::import_from_csv)
+#[derive(Clone, Debug)]
+pub struct CsvImportOptions {
+    /// The field delimiter, `,` by default
+    pub delimiter: u8,
+    /// Types to parse the fields of the columns as, keyed by the header, written as in schemas,
+    /// e.g. `Int?`. Columns not given here are parsed as the type of the column in the relation.
+    pub types: BTreeMap<String, String>,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            types: Default::default(),
+        }
+    }
+}
+
+/// At most this many bad lines are reported in an error
+const MAX_REPORTED_ERRORS: usize = 10;
+
+#[derive(Debug, Diagnostic, Error)]
+#[error("cannot import CSV data into relation '{relation}', {count} bad line(s): {}", .errors.join("; "))]
+#[diagnostic(code(import::bad_csv))]
+struct BadCsvData {
+    relation: String,
+    count: usize,
+    errors: Vec<String>,
+}
+
+/// Parses CSV data with a header line into rows to be imported into a relation with the given
+/// columns. Nothing is returned if any of the lines is bad, instead the error lists the line
+/// numbers, starting from 1 for the header, and what went wrong.
+pub(crate) fn parse_csv_for_import(
+    relation: &str,
+    columns: &[&ColumnDef],
+    reader: impl Read,
+    options: &CsvImportOptions,
+) -> Result<NamedRows> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(true)
+        .from_reader(reader);
+    let headers = rdr
+        .headers()
+        .into_diagnostic()?
+        .iter()
+        .map(|h| h.to_string())
+        .collect_vec();
+    for name in options.types.keys() {
+        if !headers.contains(name) {
+            bail!("type given for column '{}' not in the CSV header", name)
+        }
+    }
+    let types: Vec<Option<NullableColType>> = headers
+        .iter()
+        .map(|h| -> Result<Option<NullableColType>> {
+            if let Some(t) = options.types.get(h) {
+                return Ok(Some(parse_type(t)?));
+            }
+            Ok(columns
+                .iter()
+                .find(|col| col.name == h.as_str())
+                .map(|col| col.typing.clone()))
+        })
+        .try_collect()?;
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    let mut error_count = 0;
+    for (i, record) in rdr.records().enumerate() {
+        let parsed = record.into_diagnostic().and_then(|record| {
+            record
+                .iter()
+                .zip(types.iter())
+                .map(|(field, typ)| match typ {
+                    // not a column of the relation, ignored when importing
+                    None => Ok(DataValue::Null),
+                    Some(typ) => parse_field(field, typ),
+                })
+                .try_collect()
+        });
+        match parsed {
+            Ok(row) => rows.push(row),
+            Err(err) => {
+                error_count += 1;
+                if errors.len() < MAX_REPORTED_ERRORS {
+                    // the header is on line 1
+                    errors.push(format!("line {}: {}", i + 2, err));
+                }
+            }
+        }
+    }
+    if error_count > 0 {
+        bail!(BadCsvData {
+            relation: relation.to_string(),
+            count: error_count,
+            errors,
+        })
+    }
+    Ok(NamedRows::new(headers, rows))
+}
+
+fn parse_field(field: &str, typ: &NullableColType) -> Result<DataValue> {
+    if field.is_empty() && typ.nullable {
+        return Ok(DataValue::Null);
+    }
+    let dv = DataValue::from(field);
+    Ok(match &typ.coltype {
+        ColType::Any | ColType::String => dv,
+        ColType::Int => dv.coerce_to(ValueType::Int)?,
+        ColType::Float => dv.coerce_to(ValueType::Float)?,
+        ColType::Bool => match field {
+            "true" | "True" | "TRUE" => DataValue::from(true),
+            "false" | "False" | "FALSE" => DataValue::from(false),
+            _ => bail!("'{}' cannot be interpreted as bool", field),
+        },
+        ColType::Uuid => dv.coerce_to(ValueType::Uuid)?,
+        _ => bail!("CSV fields cannot be parsed as {}", typ),
+    })
+}
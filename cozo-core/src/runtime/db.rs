@@ -6,15 +6,19 @@
  * You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::cell::Cell;
 use std::collections::{BTreeMap, BTreeSet};
 use std::collections::btree_map::Entry;
 use std::default::Default;
 use std::fmt::{Debug, Formatter};
+use std::io::Read;
 use std::iter;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 #[allow(unused_imports)]
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 #[allow(unused_imports)]
 use std::thread;
 #[allow(unused_imports)]
@@ -33,31 +37,44 @@ use smartstring::{LazyCompact, SmartString};
 use thiserror::Error;
 
 use crate::{decode_tuple_from_kv, FixedRule};
-use crate::data::functions::current_validity;
+use crate::data::functions::{
+    current_validity, with_division_by_zero, with_float_eq_tolerance, with_query_seed,
+    DivisionByZero, FloatEqTolerance,
+};
 use crate::data::json::JsonValue;
 use crate::data::program::{InputProgram, QueryAssertion, RelationOp};
 use crate::data::relation::ColumnDef;
 use crate::data::tuple::{Tuple, TupleT};
-use crate::data::value::{DataValue, LARGEST_UTF_CHAR, ValidityTs};
+use crate::data::value::{DataValue, InternedValue, LARGEST_UTF_CHAR, StrInterner, ValidityTs};
 use crate::fixed_rule::DEFAULT_FIXED_RULES;
 use crate::parse::{CozoScript, parse_script, SourceSpan};
 use crate::parse::sys::SysOp;
-use crate::query::compile::{CompiledProgram, CompiledRule, CompiledRuleSet};
-use crate::query::ra::{
-    FilteredRA, InnerJoin, NegJoin, RelAlgebra, ReorderRA, StoredRA, StoredWithValidityRA,
-    TempStoreRA, UnificationRA,
-};
+use crate::data::symb::Symbol;
 #[allow(unused_imports)]
 use crate::runtime::callback::{
-    CallbackCollector, CallbackDeclaration, CallbackOp, EventCallbackRegistry,
+    CallbackCollector, CallbackDeclaration, CallbackOp, EventCallbackRegistry, OverflowPolicy,
 };
 use crate::runtime::relation::{
     AccessLevel, extend_tuple_from_v, InsufficientAccessLevel, RelationHandle, RelationId,
 };
+use crate::runtime::csv_import::{CsvImportOptions, parse_csv_for_import};
+use crate::runtime::memory_budget::MemoryBudget;
+use crate::runtime::plan::QueryPlan;
+use crate::runtime::result_cache::{eval_settings, ResultCache};
+use crate::runtime::retry::RetryPolicy;
 use crate::runtime::transact::SessionTx;
 use crate::storage::{Storage, StoreTx};
 use crate::storage::temp::TempStorage;
 
+/// Enters a tracing span named `$name` until the end of the enclosing block,
+/// if the `tracing` feature is enabled.
+macro_rules! phase_span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name).entered();
+    };
+}
+
 pub(crate) struct RunningQueryHandle {
     pub(crate) started_at: f64,
     pub(crate) poison: Poison,
@@ -96,6 +113,12 @@ pub struct Db<S> {
     #[cfg(not(target_arch = "wasm32"))]
     pub(crate) event_callbacks: Arc<ShardedLock<EventCallbackRegistry>>,
     relation_locks: Arc<ShardedLock<BTreeMap<SmartString<LazyCompact>, Arc<ShardedLock<()>>>>>,
+    pub(crate) result_cache: Arc<Mutex<ResultCache>>,
+    query_memory_limit: Arc<AtomicUsize>,
+    retry_policy: Arc<Mutex<RetryPolicy>>,
+    catch_panics: Arc<AtomicBool>,
+    float_eq_tolerance: Arc<Mutex<Option<FloatEqTolerance>>>,
+    division_by_zero: Arc<Mutex<DivisionByZero>>,
 }
 
 impl<S> Debug for Db<S> {
@@ -114,6 +137,46 @@ pub(crate) struct BadDbInit(#[help] pub(crate) String);
 #[diagnostic(code(tx::import_into_index))]
 pub(crate) struct ImportIntoIndex(pub(crate) String);
 
+#[derive(Debug, Error, Diagnostic)]
+#[error("Internal error: the query panicked with message '{0}'")]
+#[diagnostic(code(db::internal_panic))]
+#[diagnostic(help("This is a bug, please report it"))]
+pub(crate) struct InternalPanic(pub(crate) String);
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("'{0}' is not a valid name of a stored relation")]
+#[diagnostic(code(db::bad_relation_name))]
+pub(crate) struct BadRelationName(pub(crate) String);
+
+/// Names put into scripts by the helpers of [Db] must be made of identifiers separated by dots,
+/// so that they cannot change what the script does.
+fn ensure_plain_relation_name(name: &str) -> Result<()> {
+    let valid = !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.split('.').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+        });
+    ensure!(valid, BadRelationName(name.to_string()));
+    Ok(())
+}
+
+/// Takes at most `limit` items, setting `truncated` if there are more
+fn take_noting_truncation<'a, T: 'a>(
+    iter: impl Iterator<Item = T> + 'a,
+    limit: usize,
+    truncated: &'a Cell<bool>,
+) -> impl Iterator<Item = T> + 'a {
+    iter.take(limit.saturating_add(1))
+        .enumerate()
+        .filter_map(move |(i, item)| {
+            if i == limit {
+                truncated.set(true);
+                None
+            } else {
+                Some(item)
+            }
+        })
+}
+
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone, Default)]
 /// Rows in a relation, together with headers for the fields.
 pub struct NamedRows {
@@ -123,6 +186,10 @@ pub struct NamedRows {
     pub rows: Vec<Tuple>,
     /// Contains the next named rows, if exists
     pub next: Option<Box<NamedRows>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) affected_rows: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) warnings: Vec<String>,
 }
 
 impl NamedRows {
@@ -132,9 +199,34 @@ impl NamedRows {
             headers,
             rows,
             next: None,
+            affected_rows: None,
+            warnings: vec![],
+        }
+    }
+
+    /// The result of a query writing to a stored relation
+    pub(crate) fn status_ok(affected_rows: usize) -> Self {
+        Self {
+            affected_rows: Some(affected_rows),
+            ..Self::new(
+                vec![STATUS_STR.to_string()],
+                vec![vec![DataValue::from(OK_STR)]],
+            )
         }
     }
 
+    /// The number of rows written or removed, given only for queries writing to stored relations.
+    /// Rows written count even if they were already present, rows removed count only if they
+    /// were present.
+    pub fn affected_rows(&self) -> Option<usize> {
+        self.affected_rows
+    }
+
+    /// Caveats about the result, such as it being truncated by `:limit`
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     /// If there are more named rows after the current one
     pub fn has_more(&self) -> bool {
         self.next.is_some()
@@ -156,6 +248,17 @@ impl NamedRows {
         collected
     }
 
+    /// Take the rows out, with equal strings sharing a single allocation from `interner`.
+    /// This saves memory when results repeating the same strings in many rows are kept around,
+    /// at the cost of a pass over the rows. Only the rows of `self` are taken, call
+    /// [NamedRows::flatten] first to get those of the next named rows too.
+    pub fn interned_rows(self, interner: &mut StrInterner) -> Vec<Vec<InternedValue>> {
+        self.rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|v| interner.intern_value(v)).collect())
+            .collect()
+    }
+
     /// Convert to a JSON object
     pub fn into_json(self) -> JsonValue {
         let nxt = match self.next {
@@ -167,11 +270,19 @@ impl NamedRows {
             .into_iter()
             .map(|row| row.into_iter().map(JsonValue::from).collect::<JsonValue>())
             .collect::<JsonValue>();
-        json!({
+        let mut ret = json!({
             "headers": self.headers,
             "rows": rows,
             "next": nxt,
-        })
+        });
+        let obj = ret.as_object_mut().unwrap();
+        if let Some(n) = self.affected_rows {
+            obj.insert("affected_rows".to_string(), json!(n));
+        }
+        if !self.warnings.is_empty() {
+            obj.insert("warnings".to_string(), json!(self.warnings));
+        }
+        ret
     }
     /// Make named rows from JSON
     pub fn from_json(value: &JsonValue) -> Result<Self> {
@@ -200,10 +311,31 @@ impl NamedRows {
                 Ok(row.iter().map(|el| DataValue::from(el)).collect_vec())
             })
             .try_collect()?;
+        let affected_rows = match value.get("affected_rows") {
+            None | Some(JsonValue::Null) => None,
+            Some(n) => Some(
+                n.as_u64()
+                    .ok_or_else(|| miette!("'affected_rows' field must be a number"))?
+                    as usize,
+            ),
+        };
+        let warnings = match value.get("warnings") {
+            None | Some(JsonValue::Null) => vec![],
+            Some(w) => w
+                .as_array()
+                .and_then(|w| {
+                    w.iter()
+                        .map(|w| w.as_str().map(|w| w.to_string()))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| miette!("'warnings' field must be an array of strings"))?,
+        };
         Ok(Self {
             headers,
             rows,
             next: None,
+            affected_rows,
+            warnings,
         })
     }
 }
@@ -240,6 +372,12 @@ impl<'s, S: Storage<'s>> Db<S> {
             #[cfg(not(target_arch = "wasm32"))]
             event_callbacks: Default::default(),
             relation_locks: Default::default(),
+            result_cache: Default::default(),
+            query_memory_limit: Default::default(),
+            retry_policy: Default::default(),
+            catch_panics: Default::default(),
+            float_eq_tolerance: Default::default(),
+            division_by_zero: Default::default(),
         };
         Ok(ret)
     }
@@ -250,6 +388,65 @@ impl<'s, S: Storage<'s>> Db<S> {
         Ok(())
     }
 
+    /// Cache the results of up to `capacity` read-only queries, so that running an identical
+    /// query with identical parameters again skips execution entirely.
+    /// Cached results are dropped when any relation they read from is written to.
+    /// Queries using time travel, random functions or non-deterministic fixed rules are never cached.
+    /// Pass `0` to disable the cache, which is the default.
+    pub fn set_result_cache_capacity(&self, capacity: usize) {
+        self.result_cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Abort queries whose intermediate results are estimated to take up more than `bytes`,
+    /// instead of letting them exhaust the memory of the process.
+    /// Pass `0` to remove the limit, which is the default.
+    pub fn set_query_memory_limit(&self, bytes: usize) {
+        self.query_memory_limit.store(bytes, Ordering::Release);
+    }
+
+    /// Retry read-only queries failing with transient storage errors, such as lock contention,
+    /// up to `max_retries` times. The first retry happens after `backoff`, and the delay
+    /// doubles with each subsequent retry. Queries that write are never retried.
+    /// Pass `0` as `max_retries` to disable retrying, which is the default.
+    pub fn set_retry_policy(&self, max_retries: usize, backoff: Duration) {
+        *self.retry_policy.lock().unwrap() = RetryPolicy {
+            max_retries,
+            backoff,
+        };
+    }
+
+    /// Catch panics raised while running scripts with [Self::run_script] and
+    /// [Self::run_script_cancellable], and return them as errors instead, so that a single
+    /// bad query cannot take down the thread running it.
+    /// Panics are not caught by default.
+    pub fn set_catch_panics(&self, catch: bool) {
+        self.catch_panics.store(catch, Ordering::Release);
+    }
+
+    /// Compare floats with `eq` and `neq` (and `==` and `!=`) up to the given tolerance in
+    /// scripts run from now on, instead of exactly. Comparisons involving integers or values
+    /// of other types are not affected. Floats are compared exactly by default.
+    pub fn set_float_eq_tolerance(&self, tolerance: Option<FloatEqTolerance>) {
+        *self.float_eq_tolerance.lock().unwrap() = tolerance;
+        // cached results may have been computed with the previous tolerance
+        self.result_cache.lock().unwrap().clear();
+    }
+
+    /// Choose what dividing by zero with `/`, `mod`, `floor_div` and `true_div` gives in scripts
+    /// run from now on, see [DivisionByZero]. By default, division by zero raises an error if
+    /// both operands are integers, and gives an infinity or NaN otherwise.
+    pub fn set_division_by_zero(&self, mode: DivisionByZero) {
+        *self.division_by_zero.lock().unwrap() = mode;
+        self.result_cache.lock().unwrap().clear();
+    }
+
+    /// Run `f` with the settings affecting the evaluation of expressions in effect
+    fn with_eval_settings<T>(&self, f: impl FnOnce() -> T) -> T {
+        let float_eq_tolerance = *self.float_eq_tolerance.lock().unwrap();
+        let division_by_zero = *self.division_by_zero.lock().unwrap();
+        with_float_eq_tolerance(float_eq_tolerance, || with_division_by_zero(division_by_zero, f))
+    }
+
     /// Run a multi-transaction. A command should be sent to `payloads`, and the result should be
     /// retrieved from `results`. A transaction ends when it receives a `Commit` or `Abort`,
     /// or when a query is not successful. After a transaction ends, sending / receiving from
@@ -285,7 +482,9 @@ impl<'s, S: Storage<'s>> Db<S> {
         for payload in payloads {
             match payload {
                 TransactionPayload::Commit => {
-                    let _ = results.send(tx.commit_tx().map(|_| NamedRows::default()));
+                    let res = tx.commit_tx();
+                    self.invalidate_result_cache(&tx);
+                    let _ = results.send(res.map(|_| NamedRows::default()));
                     #[cfg(not(target_arch = "wasm32"))]
                     if !callback_collector.is_empty() {
                         self.send_callbacks(callback_collector)
@@ -340,14 +539,16 @@ impl<'s, S: Storage<'s>> Db<S> {
                         }
                     }
 
-                    let res = self.execute_single_program(
-                        p,
-                        &mut tx,
-                        &mut cleanups,
-                        ts,
-                        &callback_targets,
-                        &mut callback_collector,
-                    );
+                    let res = self.with_eval_settings(|| {
+                        self.execute_single_program(
+                            p,
+                            &mut tx,
+                            &mut cleanups,
+                            ts,
+                            &callback_targets,
+                            &mut callback_collector,
+                        )
+                    });
                     if results.send(res).is_err() {
                         break;
                     }
@@ -356,14 +557,83 @@ impl<'s, S: Storage<'s>> Db<S> {
         }
     }
 
-    /// Run the CozoScript passed in. The `params` argument is a map of parameters.
+    /// Run the CozoScript passed in. The `params` argument is a map of parameters,
+    /// which are bound as they are without any conversion through JSON.
     pub fn run_script(
         &'s self,
         payload: &str,
         params: BTreeMap<String, DataValue>,
     ) -> Result<NamedRows> {
         let cur_vld = current_validity();
-        self.do_run_script(payload, &params, cur_vld)
+        self.guard_panics(|| self.do_run_script(payload, &params, cur_vld, None))
+    }
+    /// Plan a query without running it. The script must consist of a single query,
+    /// the plan is the same as the one shown by `::explain`.
+    pub fn query_plan(
+        &'s self,
+        payload: &str,
+        params: BTreeMap<String, DataValue>,
+    ) -> Result<QueryPlan> {
+        let prog = parse_script(
+            payload,
+            &params,
+            &self.fixed_rules.read().unwrap(),
+            current_validity(),
+        )?
+        .get_single_program()?;
+        self.plan_program(prog)
+    }
+    /// Same as [Self::run_script], but all queries run by the script are killed
+    /// when `cancellation` is killed.
+    pub fn run_script_cancellable(
+        &'s self,
+        payload: &str,
+        params: BTreeMap<String, DataValue>,
+        cancellation: Poison,
+    ) -> Result<NamedRows> {
+        let cur_vld = current_validity();
+        self.guard_panics(|| self.do_run_script(payload, &params, cur_vld, Some(&cancellation)))
+    }
+    /// Whether a stored relation with the given name exists.
+    pub fn relation_exists(&'s self, name: &str) -> Result<bool> {
+        let mut tx = self.transact()?;
+        let exists = tx.relation_exists(name)?;
+        tx.commit_tx()?;
+        Ok(exists)
+    }
+    /// Create the stored relation `name` with the columns given by `schema`, as written after
+    /// the name in `:create`, e.g. `{k: Int => v}`, unless a relation with that name exists.
+    /// Returns whether the relation was created. The schema of an existing relation is not checked.
+    pub fn create_relation_if_not_exists(&'s self, name: &str, schema: &str) -> Result<bool> {
+        ensure_plain_relation_name(name)?;
+        if self.relation_exists(name)? {
+            return Ok(false);
+        }
+        match self.run_script(&format!(":create {name} {schema}"), Default::default()) {
+            Ok(_) => Ok(true),
+            // created by someone else in the meantime
+            Err(_) if self.relation_exists(name)? => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+    /// Remove the stored relation `name` if it exists, as `::remove` does.
+    /// Returns whether the relation was removed.
+    pub fn remove_relation_if_exists(&'s self, name: &str) -> Result<bool> {
+        ensure_plain_relation_name(name)?;
+        if !self.relation_exists(name)? {
+            return Ok(false);
+        }
+        let res = self.run_sys_op(SysOp::RemoveRelation(vec![Symbol::new(
+            name,
+            SourceSpan(0, 0),
+        )]));
+        self.result_cache.lock().unwrap().clear();
+        match res {
+            Ok(_) => Ok(true),
+            // removed by someone else in the meantime
+            Err(_) if !self.relation_exists(name)? => Ok(false),
+            Err(err) => Err(err),
+        }
     }
     /// Export relations to JSON data.
     ///
@@ -559,8 +829,43 @@ impl<'s, S: Storage<'s>> Db<S> {
             }
         }
         tx.commit_tx()?;
+        self.result_cache.lock().unwrap().invalidate(
+            rel_names
+                .iter()
+                .map(|name| name.strip_prefix('-').unwrap_or(name)),
+        );
         Ok(())
     }
+    /// Import CSV data with a header line into a stored relation, returning the number of rows
+    /// imported. The header must name every column of the relation, other columns are ignored.
+    /// Fields are parsed as the types of the columns unless other types are given in `options`,
+    /// and empty fields of nullable columns are imported as nulls.
+    ///
+    /// If any line cannot be parsed, nothing is imported and the error lists the bad lines.
+    /// As with [Self::import_relations], triggers and callbacks are _not_ run.
+    pub fn import_from_csv(
+        &'s self,
+        relation: &str,
+        reader: impl Read,
+        options: &CsvImportOptions,
+    ) -> Result<usize> {
+        let columns = {
+            let tx = self.transact()?;
+            let handle = tx.get_relation(relation, false)?;
+            handle
+                .metadata
+                .keys
+                .iter()
+                .chain(handle.metadata.non_keys.iter())
+                .cloned()
+                .collect_vec()
+        };
+        let columns = columns.iter().collect_vec();
+        let data = parse_csv_for_import(relation, &columns, reader, options)?;
+        let n = data.rows.len();
+        self.import_relations(BTreeMap::from([(relation.to_string(), data)]))?;
+        Ok(n)
+    }
     /// Backup the running database into an Sqlite file
     #[allow(unused_variables)]
     pub fn backup_db(&'s self, out_file: impl AsRef<Path>) -> Result<()> {
@@ -601,6 +906,7 @@ impl<'s, S: Storage<'s>> Db<S> {
             let iter = s_tx.store_tx.total_scan();
             self.db.batch_put(iter)?;
             s_tx.commit_tx()?;
+            self.result_cache.lock().unwrap().clear();
             Ok(())
         }
         #[cfg(not(feature = "storage-sqlite"))]
@@ -675,7 +981,12 @@ impl<'s, S: Storage<'s>> Db<S> {
             }
 
             src_tx.commit_tx()?;
-            dst_tx.commit_tx()
+            dst_tx.commit_tx()?;
+            self.result_cache
+                .lock()
+                .unwrap()
+                .invalidate(relations.iter().map(|name| name as &str));
+            Ok(())
         }
     }
     /// Register a custom fixed rule implementation.
@@ -707,11 +1018,49 @@ impl<'s, S: Storage<'s>> Db<S> {
 
     /// Register callback channel to receive changes when the requested relation are successfully committed.
     /// The returned ID can be used to unregister the callback channel.
+    /// With a `capacity`, writes wait for the receiver to make room when the channel is full.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn register_callback(
         &self,
         relation: &str,
         capacity: Option<usize>,
+    ) -> (u32, Receiver<(CallbackOp, NamedRows, NamedRows)>) {
+        self.register_callback_with_overflow(relation, capacity, OverflowPolicy::Block)
+    }
+
+    /// Subscribe to changes to `relation`: `callback` is run with the changes whenever they are
+    /// successfully committed, on a thread of its own so that it does not slow down writes.
+    /// Up to `capacity` changes are buffered while the callback is busy, after which
+    /// `overflow` decides whether writes wait or the changes are dropped.
+    /// The returned ID can be used to unsubscribe with [Self::unregister_callback].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn subscribe<F>(
+        &self,
+        relation: &str,
+        capacity: usize,
+        overflow: OverflowPolicy,
+        mut callback: F,
+    ) -> u32
+    where
+        F: FnMut(CallbackOp, NamedRows, NamedRows) + Send + 'static,
+    {
+        let (id, receiver) =
+            self.register_callback_with_overflow(relation, Some(capacity), overflow);
+        // ends when the callback is unregistered or the database dropped
+        thread::spawn(move || {
+            for (op, new, old) in receiver {
+                callback(op, new, old)
+            }
+        });
+        id
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn register_callback_with_overflow(
+        &self,
+        relation: &str,
+        capacity: Option<usize>,
+        overflow: OverflowPolicy,
     ) -> (u32, Receiver<(CallbackOp, NamedRows, NamedRows)>) {
         let (sender, receiver) = if let Some(c) = capacity {
             bounded(c)
@@ -720,7 +1069,8 @@ impl<'s, S: Storage<'s>> Db<S> {
         };
         let cb = CallbackDeclaration {
             dependent: SmartString::from(relation),
-            sender: sender,
+            sender,
+            overflow,
         };
 
         let mut guard = self.event_callbacks.write().unwrap();
@@ -777,7 +1127,10 @@ impl<'s, S: Storage<'s>> Db<S> {
         collected
     }
 
-    fn compact_relation(&'s self) -> Result<()> {
+    /// Compact the whole storage, reclaiming the space taken by deleted and overwritten data.
+    /// This can take a long time for large databases. Queries can still run concurrently,
+    /// and the call is a no-op for storage engines without the concept of compaction.
+    pub fn compact(&'s self) -> Result<()> {
         let l = Tuple::default().encode_as_key(RelationId(0));
         let u = vec![DataValue::Bot].encode_as_key(RelationId(u64::MAX));
         self.db.range_compact(&l, &u)?;
@@ -797,6 +1150,10 @@ impl<'s, S: Storage<'s>> Db<S> {
             temp_store_tx: self.temp_db.transact(true)?,
             relation_store_id: self.relation_store_id.clone(),
             temp_store_id: Default::default(),
+            written_relations: Default::default(),
+            cancellation: None,
+            memory_budget: None,
+            warnings: Default::default(),
         };
         Ok(ret)
     }
@@ -806,6 +1163,10 @@ impl<'s, S: Storage<'s>> Db<S> {
             temp_store_tx: self.temp_db.transact(true)?,
             relation_store_id: self.relation_store_id.clone(),
             temp_store_id: Default::default(),
+            written_relations: Default::default(),
+            cancellation: None,
+            memory_budget: None,
+            warnings: Default::default(),
         };
         Ok(ret)
     }
@@ -831,25 +1192,114 @@ impl<'s, S: Storage<'s>> Db<S> {
         Ok(q_res)
     }
 
+    fn guard_panics(&'s self, f: impl FnOnce() -> Result<NamedRows>) -> Result<NamedRows> {
+        if !self.catch_panics.load(Ordering::Acquire) {
+            return f();
+        }
+        panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+            let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "unknown panic payload".to_string()
+            };
+            bail!(InternalPanic(msg))
+        })
+    }
     fn do_run_script(
         &'s self,
         payload: &str,
         param_pool: &BTreeMap<String, DataValue>,
         cur_vld: ValidityTs,
+        cancellation: Option<&Poison>,
     ) -> Result<NamedRows> {
-        match parse_script(
-            payload,
-            param_pool,
-            &self.fixed_rules.read().unwrap(),
-            cur_vld,
-        )? {
-            CozoScript::Single(p) => self.execute_single(cur_vld, p),
-            CozoScript::Imperative(ps) => self.execute_imperative(cur_vld, &ps),
-            CozoScript::Sys(op) => self.run_sys_op(op),
+        self.with_eval_settings(|| {
+            self.do_run_script_inner(payload, param_pool, cur_vld, cancellation)
+        })
+    }
+    fn do_run_script_inner(
+        &'s self,
+        payload: &str,
+        param_pool: &BTreeMap<String, DataValue>,
+        cur_vld: ValidityTs,
+        cancellation: Option<&Poison>,
+    ) -> Result<NamedRows> {
+        phase_span!("run_script");
+        let script = {
+            phase_span!("parse");
+            parse_script(
+                payload,
+                param_pool,
+                &self.fixed_rules.read().unwrap(),
+                cur_vld,
+            )?
+        };
+        match script {
+            CozoScript::Single(p) => {
+                let retry_policy = *self.retry_policy.lock().unwrap();
+                if retry_policy.max_retries > 0 && p.out_opts.store_relation.is_none() {
+                    retry_policy
+                        .run(|| self.execute_single(cur_vld, p.clone(), param_pool, cancellation))
+                } else {
+                    self.execute_single(cur_vld, p, param_pool, cancellation)
+                }
+            }
+            CozoScript::Imperative(ps) => self.execute_imperative(cur_vld, &ps, cancellation),
+            CozoScript::Sys(op) => {
+                let modifies_relations = !matches!(
+                    op,
+                    SysOp::Explain(_)
+                        | SysOp::ListRelation(_)
+                        | SysOp::ListRelations
+                        | SysOp::ListRunning
+                        | SysOp::ListFixedRules
+                        | SysOp::ShowTrigger(_)
+                );
+                let res = self.run_sys_op(op);
+                if modifies_relations {
+                    self.result_cache.lock().unwrap().clear();
+                }
+                res
+            }
         }
     }
 
-    fn execute_single(&'s self, cur_vld: ValidityTs, p: InputProgram) -> Result<NamedRows, Report> {
+    pub(crate) fn invalidate_result_cache(&self, tx: &SessionTx<'_>) {
+        if !tx.written_relations.is_empty() {
+            self.result_cache
+                .lock()
+                .unwrap()
+                .invalidate(tx.written_relations.iter().map(|name| name as &str));
+        }
+    }
+
+    fn execute_single(
+        &'s self,
+        cur_vld: ValidityTs,
+        p: InputProgram,
+        param_pool: &BTreeMap<String, DataValue>,
+        cancellation: Option<&Poison>,
+    ) -> Result<NamedRows, Report> {
+        let cache_entry = {
+            let cache = self.result_cache.lock().unwrap();
+            if cache.is_enabled() {
+                p.cacheable_relations().map(|relations| {
+                    (
+                        (p.to_string(), param_pool.clone(), eval_settings()),
+                        relations,
+                        cache.epoch(),
+                    )
+                })
+            } else {
+                None
+            }
+        };
+        if let Some((key, _, _)) = &cache_entry {
+            if let Some(res) = self.result_cache.lock().unwrap().get(key) {
+                return Ok(res);
+            }
+        }
         let mut callback_collector = BTreeMap::new();
         let write_lock_names = p.needs_write_lock();
         let is_write = write_lock_names.is_some();
@@ -872,6 +1322,7 @@ impl<'s, S: Storage<'s>> Db<S> {
             } else {
                 self.transact()?
             };
+            tx.cancellation = cancellation.cloned();
 
             res = self.execute_single_program(
                 p,
@@ -884,6 +1335,7 @@ impl<'s, S: Storage<'s>> Db<S> {
 
             if is_write {
                 tx.commit_tx()?;
+                self.invalidate_result_cache(&tx);
             } else {
                 tx.commit_tx()?;
                 assert!(cleanups.is_empty(), "non-empty cleanups on read-only tx");
@@ -897,213 +1349,28 @@ impl<'s, S: Storage<'s>> Db<S> {
         for (lower, upper) in cleanups {
             self.db.del_range(&lower, &upper)?;
         }
+        if let Some((key, relations, epoch)) = cache_entry {
+            self.result_cache
+                .lock()
+                .unwrap()
+                .insert(key, res.clone(), relations, epoch);
+        }
         Ok(res)
     }
-    fn explain_compiled(&self, strata: &[CompiledProgram]) -> Result<NamedRows> {
-        let mut ret: Vec<JsonValue> = vec![];
-        const STRATUM: &str = "stratum";
-        const ATOM_IDX: &str = "atom_idx";
-        const OP: &str = "op";
-        const RULE_IDX: &str = "rule_idx";
-        const RULE_NAME: &str = "rule";
-        const REF_NAME: &str = "ref";
-        const OUT_BINDINGS: &str = "out_relation";
-        const JOINS_ON: &str = "joins_on";
-        const FILTERS: &str = "filters/expr";
-
-        let headers = vec![
-            STRATUM.to_string(),
-            RULE_IDX.to_string(),
-            RULE_NAME.to_string(),
-            ATOM_IDX.to_string(),
-            OP.to_string(),
-            REF_NAME.to_string(),
-            JOINS_ON.to_string(),
-            FILTERS.to_string(),
-            OUT_BINDINGS.to_string(),
-        ];
-
-        for (stratum, p) in strata.iter().enumerate() {
-            let mut clause_idx = -1;
-            for (rule_name, v) in p {
-                match v {
-                    CompiledRuleSet::Rules(rules) => {
-                        for CompiledRule { aggr, relation, .. } in rules.iter() {
-                            clause_idx += 1;
-                            let mut ret_for_relation = vec![];
-                            let mut rel_stack = vec![relation];
-                            let mut idx = 0;
-                            let mut atom_type = "out";
-                            for (a, _) in aggr.iter().flatten() {
-                                if a.is_meet {
-                                    if atom_type == "out" {
-                                        atom_type = "meet_aggr_out";
-                                    }
-                                } else {
-                                    atom_type = "aggr_out";
-                                }
-                            }
-
-                            ret_for_relation.push(json!({
-                                STRATUM: stratum,
-                                ATOM_IDX: idx,
-                                OP: atom_type,
-                                RULE_IDX: clause_idx,
-                                RULE_NAME: rule_name.to_string(),
-                                OUT_BINDINGS: relation.bindings_after_eliminate().into_iter().map(|v| v.to_string()).collect_vec()
-                            }));
-                            idx += 1;
-
-                            while let Some(rel) = rel_stack.pop() {
-                                let (atom_type, ref_name, joins_on, filters) = match rel {
-                                    r @ RelAlgebra::Fixed(..) => {
-                                        if r.is_unit() {
-                                            continue;
-                                        }
-                                        ("fixed", json!(null), json!(null), json!(null))
-                                    }
-                                    RelAlgebra::TempStore(TempStoreRA {
-                                        storage_key,
-                                        filters,
-                                        ..
-                                    }) => (
-                                        "load_mem",
-                                        json!(storage_key.to_string()),
-                                        json!(null),
-                                        json!(filters.iter().map(|f| f.to_string()).collect_vec()),
-                                    ),
-                                    RelAlgebra::Stored(StoredRA {
-                                        storage, filters, ..
-                                    }) => (
-                                        "load_stored",
-                                        json!(format!(":{}", storage.name)),
-                                        json!(null),
-                                        json!(filters.iter().map(|f| f.to_string()).collect_vec()),
-                                    ),
-                                    RelAlgebra::StoredWithValidity(StoredWithValidityRA {
-                                        storage,
-                                        filters,
-                                        ..
-                                    }) => (
-                                        "load_stored_with_validity",
-                                        json!(format!(":{}", storage.name)),
-                                        json!(null),
-                                        json!(filters.iter().map(|f| f.to_string()).collect_vec()),
-                                    ),
-                                    RelAlgebra::Join(inner) => {
-                                        if inner.left.is_unit() {
-                                            rel_stack.push(&inner.right);
-                                            continue;
-                                        }
-                                        let t = inner.join_type();
-                                        let InnerJoin {
-                                            left,
-                                            right,
-                                            joiner,
-                                            ..
-                                        } = inner.as_ref();
-                                        rel_stack.push(left);
-                                        rel_stack.push(right);
-                                        (t, json!(null), json!(joiner.as_map()), json!(null))
-                                    }
-                                    RelAlgebra::NegJoin(inner) => {
-                                        let t = inner.join_type();
-                                        let NegJoin {
-                                            left,
-                                            right,
-                                            joiner,
-                                            ..
-                                        } = inner.as_ref();
-                                        rel_stack.push(left);
-                                        rel_stack.push(right);
-                                        (t, json!(null), json!(joiner.as_map()), json!(null))
-                                    }
-                                    RelAlgebra::Reorder(ReorderRA { relation, .. }) => {
-                                        rel_stack.push(relation);
-                                        ("reorder", json!(null), json!(null), json!(null))
-                                    }
-                                    RelAlgebra::Filter(FilteredRA {
-                                        parent,
-                                        filters: pred,
-                                        ..
-                                    }) => {
-                                        rel_stack.push(parent);
-                                        (
-                                            "filter",
-                                            json!(null),
-                                            json!(null),
-                                            json!(pred.iter().map(|f| f.to_string()).collect_vec()),
-                                        )
-                                    }
-                                    RelAlgebra::Unification(UnificationRA {
-                                        parent,
-                                        binding,
-                                        expr,
-                                        is_multi,
-                                        ..
-                                    }) => {
-                                        rel_stack.push(parent);
-                                        (
-                                            if *is_multi { "multi-unify" } else { "unify" },
-                                            json!(binding.name),
-                                            json!(null),
-                                            json!(expr.to_string()),
-                                        )
-                                    }
-                                };
-                                ret_for_relation.push(json!({
-                                    STRATUM: stratum,
-                                    ATOM_IDX: idx,
-                                    OP: atom_type,
-                                    RULE_IDX: clause_idx,
-                                    RULE_NAME: rule_name.to_string(),
-                                    REF_NAME: ref_name,
-                                    OUT_BINDINGS: rel.bindings_after_eliminate().into_iter().map(|v| v.to_string()).collect_vec(),
-                                    JOINS_ON: joins_on,
-                                    FILTERS: filters,
-                                }));
-                                idx += 1;
-                            }
-                            ret_for_relation.reverse();
-                            ret.extend(ret_for_relation)
-                        }
-                    }
-                    CompiledRuleSet::Fixed(_) => ret.push(json!({
-                        STRATUM: stratum,
-                        ATOM_IDX: 0,
-                        OP: "algo",
-                        RULE_IDX: 0,
-                        RULE_NAME: rule_name.to_string(),
-                    })),
-                }
-            }
-        }
-
-        let rows = ret
-            .into_iter()
-            .map(|m| {
-                headers
-                    .iter()
-                    .map(|i| DataValue::from(m.get(i).unwrap_or(&JsonValue::Null)))
-                    .collect_vec()
-            })
-            .collect_vec();
-
-        Ok(NamedRows::new(headers, rows))
+    fn plan_program(&'s self, prog: InputProgram) -> Result<QueryPlan> {
+        let mut tx = self.transact()?;
+        let (normalized_program, _) = prog.into_normalized_program(&tx)?;
+        let (stratified_program, _) = normalized_program.into_stratified_program()?;
+        let program = stratified_program.magic_sets_rewrite(&tx)?;
+        let compiled = tx.stratified_magic_compile(program)?;
+        tx.commit_tx()?;
+        Ok(QueryPlan::from_compiled(&compiled))
     }
     fn run_sys_op(&'s self, op: SysOp) -> Result<NamedRows> {
         match op {
-            SysOp::Explain(prog) => {
-                let mut tx = self.transact()?;
-                let (normalized_program, _) = prog.into_normalized_program(&tx)?;
-                let (stratified_program, _) = normalized_program.into_stratified_program()?;
-                let program = stratified_program.magic_sets_rewrite(&tx)?;
-                let compiled = tx.stratified_magic_compile(program)?;
-                tx.commit_tx()?;
-                self.explain_compiled(&compiled)
-            }
+            SysOp::Explain(prog) => Ok(self.plan_program(*prog)?.into_named_rows()),
             SysOp::Compact => {
-                self.compact_relation()?;
+                self.compact()?;
                 Ok(NamedRows::new(
                     vec![STATUS_STR.to_string()],
                     vec![vec![DataValue::from(OK_STR)]],
@@ -1255,6 +1522,32 @@ impl<'s, S: Storage<'s>> Db<S> {
         callback_targets: &BTreeSet<SmartString<LazyCompact>>,
         callback_collector: &mut CallbackCollector,
         top_level: bool,
+    ) -> Result<(NamedRows, Vec<(Vec<u8>, Vec<u8>)>)> {
+        let res = self.run_query_inner(
+            tx,
+            input_program,
+            cur_vld,
+            callback_targets,
+            callback_collector,
+            top_level,
+        );
+        if !top_level {
+            // warnings of queries run by triggers go to the query setting them off
+            return res;
+        }
+        let warnings = mem::take(tx.warnings.get_mut().unwrap());
+        let (mut rows, clean_ups) = res?;
+        rows.warnings = warnings;
+        Ok((rows, clean_ups))
+    }
+    fn run_query_inner(
+        &self,
+        tx: &mut SessionTx<'_>,
+        input_program: InputProgram,
+        cur_vld: ValidityTs,
+        callback_targets: &BTreeSet<SmartString<LazyCompact>>,
+        callback_collector: &mut CallbackCollector,
+        top_level: bool,
     ) -> Result<(NamedRows, Vec<(Vec<u8>, Vec<u8>)>)> {
         // cleanups contain stored relations that should be deleted at the end of query
         let mut clean_ups = vec![];
@@ -1289,17 +1582,30 @@ impl<'s, S: Storage<'s>> Db<S> {
         };
 
         // query compilation
-        let entry_head_or_default = input_program.get_entry_out_head_or_default()?;
-        let (normalized_program, out_opts) = input_program.into_normalized_program(tx)?;
-        let (stratified_program, store_lifetimes) = normalized_program.into_stratified_program()?;
-        let program = stratified_program.magic_sets_rewrite(tx)?;
-        let compiled = tx.stratified_magic_compile(program)?;
+        let (entry_head_or_default, out_opts, store_lifetimes, compiled) = {
+            phase_span!("optimize");
+            let entry_head_or_default = input_program.get_entry_out_head_or_default()?;
+            let (normalized_program, out_opts) = input_program.into_normalized_program(tx)?;
+            let (stratified_program, store_lifetimes) =
+                normalized_program.into_stratified_program()?;
+            let program = stratified_program.magic_sets_rewrite(tx)?;
+            let compiled = tx.stratified_magic_compile(program)?;
+            (entry_head_or_default, out_opts, store_lifetimes, compiled)
+        };
+        phase_span!("execute");
 
         // poison is used to terminate queries early
-        let poison = Poison::default();
+        let poison = match &tx.cancellation {
+            Some(parent) => parent.derive(),
+            None => Poison::default(),
+        };
         if let Some(secs) = out_opts.timeout {
             poison.set_timeout(secs)?;
         }
+        tx.memory_budget = match self.query_memory_limit.load(Ordering::Acquire) {
+            0 => None,
+            limit => Some(Arc::new(MemoryBudget::new(limit))),
+        };
         // give the query an ID and store it so that it can be queried and cancelled
         let id = self.queries_count.fetch_add(1, Ordering::AcqRel);
 
@@ -1319,7 +1625,8 @@ impl<'s, S: Storage<'s>> Db<S> {
         };
 
         let total_num_to_take = if out_opts.sorters.is_empty() {
-            out_opts.num_to_take()
+            // one more row than asked for, stored as skipped, to tell whether `:limit` left any out
+            out_opts.num_to_take().map(|n| n.saturating_add(1))
         } else {
             None
         };
@@ -1331,13 +1638,15 @@ impl<'s, S: Storage<'s>> Db<S> {
         };
 
         // the real evaluation
-        let (result_store, early_return) = tx.stratified_magic_evaluate(
-            &compiled,
-            store_lifetimes,
-            total_num_to_take,
-            num_to_skip,
-            poison,
-        )?;
+        let (result_store, early_return) = with_query_seed(out_opts.seed, || {
+            tx.stratified_magic_evaluate(
+                &compiled,
+                store_lifetimes,
+                total_num_to_take,
+                num_to_skip,
+                poison,
+            )
+        })?;
 
         // deal with assertions
         if let Some(assertion) = &out_opts.assertion {
@@ -1369,6 +1678,16 @@ impl<'s, S: Storage<'s>> Db<S> {
             // sort outputs if required
             let sorted_result =
                 tx.sort_and_collect(result_store, &out_opts.sorters, &entry_head_or_default)?;
+            if let Some(limit) = out_opts.limit {
+                let found = sorted_result
+                    .len()
+                    .saturating_sub(out_opts.offset.unwrap_or(0));
+                if found > limit {
+                    tx.warn(format!(
+                        "the result was truncated to {limit} rows by :limit, {found} rows were found"
+                    ));
+                }
+            }
             let sorted_iter = if let Some(offset) = out_opts.offset {
                 Left(sorted_result.into_iter().skip(offset))
             } else {
@@ -1380,7 +1699,7 @@ impl<'s, S: Storage<'s>> Db<S> {
                 Right(sorted_iter)
             };
             if let Some((meta, relation_op)) = &out_opts.store_relation {
-                let to_clear = tx
+                let (to_clear, affected) = tx
                     .execute_relation(
                         self,
                         sorted_iter,
@@ -1394,13 +1713,7 @@ impl<'s, S: Storage<'s>> Db<S> {
                     )
                     .wrap_err_with(|| format!("when executing against relation '{}'", meta.name))?;
                 clean_ups.extend(to_clear);
-                Ok((
-                    NamedRows::new(
-                        vec![STATUS_STR.to_string()],
-                        vec![vec![DataValue::from(OK_STR)]],
-                    ),
-                    clean_ups,
-                ))
+                Ok((NamedRows::status_ok(affected), clean_ups))
             } else {
                 // not sorting outputs
                 let rows: Vec<Tuple> = sorted_iter.collect_vec();
@@ -1416,26 +1729,34 @@ impl<'s, S: Storage<'s>> Db<S> {
                 ))
             }
         } else {
+            let limit = out_opts.limit.unwrap_or(usize::MAX);
+            let truncated = Cell::new(false);
             let scan = if early_return {
+                // the row past the limit, if any, is the only one beyond the skipped and kept rows
+                let num_to_take = out_opts.num_to_take().unwrap_or(usize::MAX);
+                truncated.set(result_store.all_iter().nth(num_to_take).is_some());
                 Right(Left(
                     result_store.early_returned_iter().map(|t| t.into_tuple()),
                 ))
             } else if out_opts.limit.is_some() || out_opts.offset.is_some() {
-                let limit = out_opts.limit.unwrap_or(usize::MAX);
                 let offset = out_opts.offset.unwrap_or(0);
                 Right(Right(
-                    result_store
-                        .all_iter()
-                        .skip(offset)
-                        .take(limit)
+                    take_noting_truncation(result_store.all_iter().skip(offset), limit, &truncated)
                         .map(|t| t.into_tuple()),
                 ))
             } else {
                 Left(result_store.all_iter().map(|t| t.into_tuple()))
             };
+            let warn_if_truncated = |tx: &SessionTx<'_>| {
+                if truncated.get() {
+                    tx.warn(format!(
+                        "the result was truncated to {limit} rows by :limit, more rows were found"
+                    ));
+                }
+            };
 
             if let Some((meta, relation_op)) = &out_opts.store_relation {
-                let to_clear = tx
+                let (to_clear, affected) = tx
                     .execute_relation(
                         self,
                         scan,
@@ -1448,16 +1769,12 @@ impl<'s, S: Storage<'s>> Db<S> {
                         top_level,
                     )
                     .wrap_err_with(|| format!("when executing against relation '{}'", meta.name))?;
+                warn_if_truncated(tx);
                 clean_ups.extend(to_clear);
-                Ok((
-                    NamedRows::new(
-                        vec![STATUS_STR.to_string()],
-                        vec![vec![DataValue::from(OK_STR)]],
-                    ),
-                    clean_ups,
-                ))
+                Ok((NamedRows::status_ok(affected), clean_ups))
             } else {
                 let rows: Vec<Tuple> = scan.collect_vec();
+                warn_if_truncated(tx);
 
                 Ok((
                     NamedRows::new(
@@ -1583,9 +1900,13 @@ impl<'s, S: Storage<'s>> Db<S> {
     }
 }
 
-/// Used for user-initiated termination of running queries
+/// Used for user-initiated termination of running queries.
+/// A poison may be derived from a parent, in which case killing the parent also kills it.
 #[derive(Clone, Default)]
-pub struct Poison(pub(crate) Arc<AtomicBool>);
+pub struct Poison(
+    pub(crate) Arc<AtomicBool>,
+    pub(crate) Option<Arc<AtomicBool>>,
+);
 
 impl Poison {
     /// Will return `Err` if user has initiated termination.
@@ -1597,11 +1918,20 @@ impl Poison {
         #[diagnostic(help("A query may be killed by timeout, or explicit command"))]
         struct ProcessKilled;
 
-        if self.0.load(Ordering::Relaxed) {
+        if self.0.load(Ordering::Relaxed)
+            || matches!(&self.1, Some(parent) if parent.load(Ordering::Relaxed))
+        {
             bail!(ProcessKilled)
         }
         Ok(())
     }
+    /// Kill the queries checking this poison, or any poison derived from it.
+    pub fn kill(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub(crate) fn derive(&self) -> Poison {
+        Poison(Default::default(), Some(self.0.clone()))
+    }
     #[cfg(target_arch = "wasm32")]
     pub(crate) fn set_timeout(&self, _secs: f64) -> Result<()> {
         bail!("Cannot set timeout when threading is disallowed");
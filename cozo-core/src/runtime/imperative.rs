@@ -241,6 +241,7 @@ impl<'s, S: Storage<'s>> Db<S> {
         &'s self,
         cur_vld: ValidityTs,
         ps: &ImperativeProgram,
+        cancellation: Option<&Poison>,
     ) -> Result<NamedRows, Report> {
         let mut callback_collector = BTreeMap::new();
         let mut write_lock_names = BTreeSet::new();
@@ -264,8 +265,12 @@ impl<'s, S: Storage<'s>> Db<S> {
             } else {
                 self.transact()?
             };
+            tx.cancellation = cancellation.cloned();
 
-            let poison = Poison::default();
+            let poison = match cancellation {
+                Some(parent) => parent.derive(),
+                None => Poison::default(),
+            };
             let qid = self.queries_count.fetch_add(1, Ordering::AcqRel);
             let since_the_epoch = seconds_since_the_epoch()?;
 
@@ -306,6 +311,7 @@ impl<'s, S: Storage<'s>> Db<S> {
 
             if is_write {
                 tx.commit_tx()?;
+                self.invalidate_result_cache(&tx);
             } else {
                 tx.commit_tx()?;
                 assert!(cleanups.is_empty(), "non-empty cleanups on read-only tx");
@@ -0,0 +1,47 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use miette::{bail, Diagnostic, Result};
+use thiserror::Error;
+
+use crate::data::tuple::Tuple;
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("The query exceeded its memory limit of {0} bytes")]
+#[diagnostic(code(eval::memory_limit_exceeded))]
+#[diagnostic(help(
+    "The limit applies to the estimated size of the intermediate results of a single query"
+))]
+pub(crate) struct MemoryLimitExceeded(pub(crate) usize);
+
+/// Keeps a running estimate of the memory held by the tuples derived during the evaluation
+/// of a query, and aborts the evaluation when it crosses the limit.
+pub(crate) struct MemoryBudget {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+    pub(crate) fn charge(&self, tuple: &Tuple) -> Result<()> {
+        let size = size_of::<Tuple>() + tuple.iter().map(|v| v.approx_size()).sum::<usize>();
+        let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+        if used > self.limit {
+            bail!(MemoryLimitExceeded(self.limit))
+        }
+        Ok(())
+    }
+}
@@ -7,9 +7,14 @@
  */
 
 pub(crate) mod callback;
+pub(crate) mod csv_import;
 pub(crate) mod db;
 pub(crate) mod imperative;
+pub(crate) mod memory_budget;
+pub(crate) mod plan;
 pub(crate) mod relation;
+pub(crate) mod result_cache;
+pub(crate) mod retry;
 pub(crate) mod temp_store;
 #[cfg(test)]
 mod tests;
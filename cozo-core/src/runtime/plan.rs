@@ -0,0 +1,380 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use serde_json::json;
+
+use crate::data::expr::Expr;
+use crate::data::json::JsonValue;
+use crate::data::value::DataValue;
+use crate::query::compile::{CompiledProgram, CompiledRule, CompiledRuleSet};
+use crate::query::ra::{
+    FilteredRA, InnerJoin, NegJoin, RelAlgebra, ReorderRA, StoredRA, StoredWithValidityRA,
+    TempStoreRA, UnificationRA,
+};
+use crate::runtime::db::NamedRows;
+
+/// The plan of a query, as returned by [crate::Db::query_plan].
+/// `::explain` shows the same plan as rows.
+#[derive(Debug, Clone, PartialEq, serde_derive::Serialize)]
+pub struct QueryPlan {
+    /// The operations, grouped by rule and listed in the order they are executed
+    pub steps: Vec<PlanStep>,
+}
+
+/// A single operation in a [QueryPlan]
+#[derive(Debug, Clone, PartialEq, serde_derive::Serialize)]
+pub struct PlanStep {
+    /// The stratum the rule belongs to, strata are evaluated in order
+    pub stratum: usize,
+    /// Index of the rule within its stratum
+    pub rule_idx: usize,
+    /// Name of the rule
+    pub rule: String,
+    /// Index of the operation within the rule, counting from the output at 0
+    pub atom_idx: usize,
+    /// The operation
+    pub op: PlanOp,
+    /// The bindings produced by the operation, not given for fixed rules
+    pub out_relation: Option<Vec<String>>,
+}
+
+/// Kinds of operations in a [QueryPlan]
+#[derive(Debug, Clone, PartialEq, serde_derive::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlanOp {
+    /// Output of a rule without aggregations
+    Out,
+    /// Output of a rule with aggregations, given for each output binding
+    AggrOut {
+        /// The aggregation applied to each output binding, if any
+        aggregations: Vec<Option<String>>,
+    },
+    /// Output of a rule with meet aggregations only, given for each output binding
+    MeetAggrOut {
+        /// The aggregation applied to each output binding, if any
+        aggregations: Vec<Option<String>>,
+    },
+    /// Rows given inline in the query
+    Fixed,
+    /// Scan of a rule computed by the query
+    LoadMem {
+        /// Name of the relation scanned
+        relation: String,
+        /// Filters applied while scanning
+        filters: Vec<Expr>,
+    },
+    /// Scan of a stored relation
+    LoadStored {
+        /// Name of the relation scanned
+        relation: String,
+        /// Filters applied while scanning
+        filters: Vec<Expr>,
+    },
+    /// Scan of a stored relation at a validity
+    LoadStoredWithValidity {
+        /// Name of the relation scanned
+        relation: String,
+        /// Filters applied while scanning
+        filters: Vec<Expr>,
+    },
+    /// Join of the two operations preceding it
+    Join {
+        /// How the join is executed
+        join_type: String,
+        /// Bindings on the left joined with bindings on the right
+        joins_on: BTreeMap<String, String>,
+    },
+    /// Negated join, keeping the rows on the left without matches on the right
+    NegJoin {
+        /// How the join is executed
+        join_type: String,
+        /// Bindings on the left joined with bindings on the right
+        joins_on: BTreeMap<String, String>,
+    },
+    /// Reordering of the bindings
+    Reorder,
+    /// Filtering of rows
+    Filter {
+        /// The filters, all of which must be true for a row to be kept
+        filters: Vec<Expr>,
+    },
+    /// Binding of a variable to the value of an expression,
+    /// or to each element of its value if `multi`
+    Unify {
+        /// The variable bound
+        binding: String,
+        /// The expression giving the value
+        expr: Expr,
+        /// Whether each element of the value is bound in turn
+        multi: bool,
+    },
+    /// Application of a fixed rule
+    FixedRule,
+}
+
+impl PlanOp {
+    /// The name of the operation as shown by `::explain`
+    pub fn name(&self) -> &str {
+        match self {
+            PlanOp::Out => "out",
+            PlanOp::AggrOut { .. } => "aggr_out",
+            PlanOp::MeetAggrOut { .. } => "meet_aggr_out",
+            PlanOp::Fixed => "fixed",
+            PlanOp::LoadMem { .. } => "load_mem",
+            PlanOp::LoadStored { .. } => "load_stored",
+            PlanOp::LoadStoredWithValidity { .. } => "load_stored_with_validity",
+            PlanOp::Join { join_type, .. } | PlanOp::NegJoin { join_type, .. } => join_type,
+            PlanOp::Reorder => "reorder",
+            PlanOp::Filter { .. } => "filter",
+            PlanOp::Unify { multi: false, .. } => "unify",
+            PlanOp::Unify { multi: true, .. } => "multi-unify",
+            PlanOp::FixedRule => "algo",
+        }
+    }
+}
+
+fn output_op(rule: &CompiledRule) -> PlanOp {
+    let aggregations = rule
+        .aggr
+        .iter()
+        .map(|a| {
+            a.as_ref().map(|(aggr, _)| {
+                let name = aggr.name.strip_prefix("AGGR_").unwrap_or(aggr.name);
+                name.to_lowercase()
+            })
+        })
+        .collect_vec();
+    let mut aggrs = rule.aggr.iter().flatten();
+    if aggrs.clone().next().is_none() {
+        PlanOp::Out
+    } else if aggrs.all(|(aggr, _)| aggr.is_meet) {
+        PlanOp::MeetAggrOut { aggregations }
+    } else {
+        PlanOp::AggrOut { aggregations }
+    }
+}
+
+fn joins_on(map: BTreeMap<&str, &str>) -> BTreeMap<String, String> {
+    map.into_iter()
+        .map(|(l, r)| (l.to_string(), r.to_string()))
+        .collect()
+}
+
+impl QueryPlan {
+    pub(crate) fn from_compiled(strata: &[CompiledProgram]) -> Self {
+        let mut steps = vec![];
+        for (stratum, p) in strata.iter().enumerate() {
+            let mut rule_idx = 0;
+            for (rule_name, v) in p {
+                let rule = rule_name.to_string();
+                match v {
+                    CompiledRuleSet::Rules(rules) => {
+                        for compiled in rules.iter() {
+                            steps.extend(Self::rule_steps(stratum, rule_idx, &rule, compiled));
+                            rule_idx += 1;
+                        }
+                    }
+                    CompiledRuleSet::Fixed(_) => steps.push(PlanStep {
+                        stratum,
+                        rule_idx: 0,
+                        rule,
+                        atom_idx: 0,
+                        op: PlanOp::FixedRule,
+                        out_relation: None,
+                    }),
+                }
+            }
+        }
+        QueryPlan { steps }
+    }
+    fn rule_steps(
+        stratum: usize,
+        rule_idx: usize,
+        rule: &str,
+        compiled: &CompiledRule,
+    ) -> Vec<PlanStep> {
+        // collected from the output back to the scans, and reversed at the end
+        let mut ops = vec![(output_op(compiled), &compiled.relation)];
+        let mut rel_stack = vec![&compiled.relation];
+        while let Some(rel) = rel_stack.pop() {
+            let op = match rel {
+                r @ RelAlgebra::Fixed(..) => {
+                    if r.is_unit() {
+                        continue;
+                    }
+                    PlanOp::Fixed
+                }
+                RelAlgebra::TempStore(TempStoreRA {
+                    storage_key,
+                    filters,
+                    ..
+                }) => PlanOp::LoadMem {
+                    relation: storage_key.to_string(),
+                    filters: filters.clone(),
+                },
+                RelAlgebra::Stored(StoredRA {
+                    storage, filters, ..
+                }) => PlanOp::LoadStored {
+                    relation: format!(":{}", storage.name),
+                    filters: filters.clone(),
+                },
+                RelAlgebra::StoredWithValidity(StoredWithValidityRA {
+                    storage, filters, ..
+                }) => PlanOp::LoadStoredWithValidity {
+                    relation: format!(":{}", storage.name),
+                    filters: filters.clone(),
+                },
+                RelAlgebra::Join(inner) => {
+                    if inner.left.is_unit() {
+                        rel_stack.push(&inner.right);
+                        continue;
+                    }
+                    let join_type = inner.join_type().to_string();
+                    let InnerJoin {
+                        left,
+                        right,
+                        joiner,
+                        ..
+                    } = inner.as_ref();
+                    rel_stack.push(left);
+                    rel_stack.push(right);
+                    PlanOp::Join {
+                        join_type,
+                        joins_on: joins_on(joiner.as_map()),
+                    }
+                }
+                RelAlgebra::NegJoin(inner) => {
+                    let join_type = inner.join_type().to_string();
+                    let NegJoin {
+                        left,
+                        right,
+                        joiner,
+                        ..
+                    } = inner.as_ref();
+                    rel_stack.push(left);
+                    rel_stack.push(right);
+                    PlanOp::NegJoin {
+                        join_type,
+                        joins_on: joins_on(joiner.as_map()),
+                    }
+                }
+                RelAlgebra::Reorder(ReorderRA { relation, .. }) => {
+                    rel_stack.push(relation);
+                    PlanOp::Reorder
+                }
+                RelAlgebra::Filter(FilteredRA {
+                    parent, filters, ..
+                }) => {
+                    rel_stack.push(parent);
+                    PlanOp::Filter {
+                        filters: filters.clone(),
+                    }
+                }
+                RelAlgebra::Unification(UnificationRA {
+                    parent,
+                    binding,
+                    expr,
+                    is_multi,
+                    ..
+                }) => {
+                    rel_stack.push(parent);
+                    PlanOp::Unify {
+                        binding: binding.name.to_string(),
+                        expr: expr.clone(),
+                        multi: *is_multi,
+                    }
+                }
+            };
+            ops.push((op, rel));
+        }
+        ops.into_iter()
+            .enumerate()
+            .map(|(atom_idx, (op, rel))| PlanStep {
+                stratum,
+                rule_idx,
+                rule: rule.to_string(),
+                atom_idx,
+                op,
+                out_relation: Some(
+                    rel.bindings_after_eliminate()
+                        .into_iter()
+                        .map(|v| v.to_string())
+                        .collect(),
+                ),
+            })
+            .rev()
+            .collect()
+    }
+    /// The plan as shown by `::explain`
+    pub(crate) fn into_named_rows(self) -> NamedRows {
+        const STRATUM: &str = "stratum";
+        const ATOM_IDX: &str = "atom_idx";
+        const OP: &str = "op";
+        const RULE_IDX: &str = "rule_idx";
+        const RULE_NAME: &str = "rule";
+        const REF_NAME: &str = "ref";
+        const OUT_BINDINGS: &str = "out_relation";
+        const JOINS_ON: &str = "joins_on";
+        const FILTERS: &str = "filters/expr";
+
+        let headers = vec![
+            STRATUM.to_string(),
+            RULE_IDX.to_string(),
+            RULE_NAME.to_string(),
+            ATOM_IDX.to_string(),
+            OP.to_string(),
+            REF_NAME.to_string(),
+            JOINS_ON.to_string(),
+            FILTERS.to_string(),
+            OUT_BINDINGS.to_string(),
+        ];
+        let exprs = |exprs: &[Expr]| json!(exprs.iter().map(|f| f.to_string()).collect_vec());
+
+        let rows = self
+            .steps
+            .into_iter()
+            .map(|step| {
+                let (ref_name, joins_on, filters) = match &step.op {
+                    PlanOp::LoadMem { relation, filters }
+                    | PlanOp::LoadStored { relation, filters }
+                    | PlanOp::LoadStoredWithValidity { relation, filters } => {
+                        (json!(relation), json!(null), exprs(filters))
+                    }
+                    PlanOp::Join { joins_on, .. } | PlanOp::NegJoin { joins_on, .. } => {
+                        (json!(null), json!(joins_on), json!(null))
+                    }
+                    PlanOp::Filter { filters } => (json!(null), json!(null), exprs(filters)),
+                    PlanOp::Unify { binding, expr, .. } => {
+                        (json!(binding), json!(null), json!(expr.to_string()))
+                    }
+                    _ => (json!(null), json!(null), json!(null)),
+                };
+                let m = json!({
+                    STRATUM: step.stratum,
+                    ATOM_IDX: step.atom_idx,
+                    OP: step.op.name(),
+                    RULE_IDX: step.rule_idx,
+                    RULE_NAME: step.rule,
+                    REF_NAME: ref_name,
+                    OUT_BINDINGS: step.out_relation,
+                    JOINS_ON: joins_on,
+                    FILTERS: filters,
+                });
+                headers
+                    .iter()
+                    .map(|i| DataValue::from(m.get(i).unwrap_or(&JsonValue::Null)))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        NamedRows::new(headers, rows)
+    }
+}
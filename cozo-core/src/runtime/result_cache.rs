@@ -0,0 +1,112 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use smartstring::{LazyCompact, SmartString};
+
+use crate::data::functions::{current_division_by_zero, current_float_eq_tolerance};
+use crate::data::value::DataValue;
+use crate::runtime::db::NamedRows;
+
+/// The canonical form of the program together with the parameters it was run with,
+/// and the settings in effect, see [eval_settings]
+pub(crate) type ResultCacheKey = (String, BTreeMap<String, DataValue>, String);
+
+/// The settings of the current thread that change how expressions are evaluated,
+/// so that results computed under different settings are cached apart
+pub(crate) fn eval_settings() -> String {
+    format!(
+        "{:?} {:?}",
+        current_float_eq_tolerance(),
+        current_division_by_zero()
+    )
+}
+
+struct CachedResult {
+    rows: NamedRows,
+    relations: BTreeSet<SmartString<LazyCompact>>,
+}
+
+/// Cache for the results of read-only queries, keyed by the canonical program and params.
+/// Entries are evicted in insertion order when the capacity is exceeded,
+/// and invalidated whenever a relation they read from is written to.
+#[derive(Default)]
+pub(crate) struct ResultCache {
+    capacity: usize,
+    epoch: u64,
+    entries: BTreeMap<ResultCacheKey, CachedResult>,
+    order: VecDeque<ResultCacheKey>,
+}
+
+impl ResultCache {
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink_to_capacity();
+    }
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+    /// The epoch changes on every invalidation. Results computed across an epoch change may be
+    /// stale, so they are not admitted into the cache.
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch
+    }
+    pub(crate) fn get(&self, key: &ResultCacheKey) -> Option<NamedRows> {
+        self.entries.get(key).map(|c| c.rows.clone())
+    }
+    pub(crate) fn insert(
+        &mut self,
+        key: ResultCacheKey,
+        rows: NamedRows,
+        relations: BTreeSet<SmartString<LazyCompact>>,
+        epoch: u64,
+    ) {
+        if !self.is_enabled() || epoch != self.epoch {
+            return;
+        }
+        if self
+            .entries
+            .insert(key.clone(), CachedResult { rows, relations })
+            .is_none()
+        {
+            self.order.push_back(key);
+        }
+        self.shrink_to_capacity();
+    }
+    /// Drops the entries reading from any of the given relations, or from their indices.
+    pub(crate) fn invalidate<'a>(&mut self, relations: impl IntoIterator<Item = &'a str>) {
+        self.epoch += 1;
+        let relations: BTreeSet<&str> = relations.into_iter().collect();
+        if relations.is_empty() {
+            return;
+        }
+        self.entries.retain(|_, cached| {
+            !cached.relations.iter().any(|r| {
+                let base = r.split(':').next().unwrap_or_default();
+                relations.contains(base)
+            })
+        });
+        self.order.retain(|k| self.entries.contains_key(k));
+    }
+    pub(crate) fn clear(&mut self) {
+        self.epoch += 1;
+        self.entries.clear();
+        self.order.clear();
+    }
+    fn shrink_to_capacity(&mut self) {
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                None => break,
+                Some(k) => {
+                    self.entries.remove(&k);
+                }
+            }
+        }
+    }
+}
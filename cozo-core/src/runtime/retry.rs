@@ -0,0 +1,58 @@
+/*
+ * Copyright 2023, The Cozo Project Authors.
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+ * If a copy of the MPL was not distributed with this file,
+ * You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::thread;
+use std::time::Duration;
+
+use miette::{Report, Result};
+
+use crate::storage::TransientStorageError;
+
+/// How read-only queries failing with transient storage errors are retried
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_retries: usize,
+    /// Delay before the first retry, doubled for each subsequent retry
+    pub(crate) backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn run<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut delay = self.backoff;
+        let mut retries = 0;
+        loop {
+            match f() {
+                Err(err) if retries < self.max_retries && is_transient(&err) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    retries += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Whether the error is caused by a storage failure that may go away on retrying
+pub(crate) fn is_transient(err: &Report) -> bool {
+    err.chain().any(|e| {
+        if e.is::<TransientStorageError>() {
+            return true;
+        }
+        #[cfg(feature = "storage-rocksdb")]
+        if let Some(status) = e.downcast_ref::<cozorocks::RocksDbStatus>() {
+            return matches!(
+                status.code,
+                cozorocks::StatusCode::kBusy
+                    | cozorocks::StatusCode::kTryAgain
+                    | cozorocks::StatusCode::kTimedOut
+            );
+        }
+        false
+    })
+}
@@ -7,22 +7,30 @@
  *
  */
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use itertools::Itertools;
 use log::debug;
+use miette::bail;
 use serde_json::json;
 use smartstring::{LazyCompact, SmartString};
 
 use crate::data::expr::Expr;
 use crate::data::symb::Symbol;
-use crate::data::value::DataValue;
+use crate::data::value::{DataValue, InternedValue, StrInterner};
 use crate::fixed_rule::FixedRulePayload;
 use crate::parse::SourceSpan;
 use crate::runtime::callback::CallbackOp;
 use crate::runtime::db::Poison;
-use crate::{new_cozo_mem, DbInstance, FixedRule, RegularTempStore};
+use crate::{
+    new_cozo_mem, CsvImportOptions, Db, DbInstance, FixedRule, FloatEqTolerance, MemStorage,
+    NamedRows, OverflowPolicy, PlanOp, RegularTempStore, SimpleFixedRule, Storage,
+    TransientStorageError, TypedRow,
+};
 
 #[test]
 fn test_limit_offset() {
@@ -203,6 +211,7 @@ fn rm_does_not_need_all_keys() {
 }
 
 #[test]
+#[cfg(feature = "graph-algo")]
 fn strict_checks_for_fixed_rules_args() {
     let db = new_cozo_mem().unwrap();
     let res = db.run_script(
@@ -489,6 +498,64 @@ fn test_callback() {
     assert_eq!(collected[2].2.rows[0].len(), 3);
 }
 
+#[test]
+fn test_subscribe() {
+    let db = new_cozo_mem().unwrap();
+    db.run_script(":create watched {k: Int => v: Any}", Default::default())
+        .unwrap();
+    db.run_script(":create other {k: Int => v: Any}", Default::default())
+        .unwrap();
+    let put = |rel: &str, k: i64| {
+        db.run_script(
+            &format!("?[k, v] <- [[{k}, 'x']] :put {rel} {{k => v}}"),
+            Default::default(),
+        )
+        .unwrap();
+    };
+    let timeout = Duration::from_secs(5);
+
+    let (sender, events) = std::sync::mpsc::channel();
+    let id = db.subscribe(
+        "watched",
+        16,
+        OverflowPolicy::Block,
+        move |op, new, _old| sender.send((op, new.rows)).unwrap(),
+    );
+    put("other", 1);
+    put("watched", 2);
+    let (op, rows) = events.recv_timeout(timeout).unwrap();
+    assert_eq!(op, CallbackOp::Put);
+    assert_eq!(rows, vec![vec![DataValue::from(2), DataValue::from("x")]]);
+    db.run_script("?[k] <- [[2]] :rm watched {k}", Default::default())
+        .unwrap();
+    let (op, rows) = events.recv_timeout(timeout).unwrap();
+    assert_eq!(op, CallbackOp::Rm);
+    assert_eq!(rows, vec![vec![DataValue::from(2)]]);
+    // writes to other relations are not delivered
+    put("other", 3);
+    assert!(events.recv_timeout(Duration::from_millis(50)).is_err());
+    assert!(db.unregister_callback(id));
+    put("watched", 4);
+    assert!(events.recv_timeout(Duration::from_millis(50)).is_err());
+
+    // a slow subscriber dropping changes does not hold up writes
+    let (gate, gate_receiver) = std::sync::mpsc::channel::<()>();
+    let (sender, events) = std::sync::mpsc::channel();
+    db.subscribe("watched", 1, OverflowPolicy::Drop, move |_, new, _| {
+        let _ = gate_receiver.recv();
+        sender.send(new.rows).unwrap();
+    });
+    for k in 0..10 {
+        put("watched", k);
+    }
+    drop(gate);
+    let mut received = 0;
+    while events.recv_timeout(Duration::from_millis(200)).is_ok() {
+        received += 1;
+    }
+    assert!((1..=2).contains(&received), "{received}");
+}
+
 #[test]
 fn test_index() {
     let db = new_cozo_mem().unwrap();
@@ -738,3 +805,772 @@ fn test_multi_tx() {
     tx.abort().unwrap();
     assert!(db.run_script("?[a] := *a[a]", Default::default()).is_err());
 }
+
+#[test]
+fn test_result_cache() {
+    let db = new_cozo_mem().unwrap();
+    db.set_result_cache_capacity(10);
+    db.run_script(":create a {x => y}", Default::default())
+        .unwrap();
+    db.run_script("?[x, y] <- [[1, 2]] :put a {x => y}", Default::default())
+        .unwrap();
+    let query = "?[x, y] := *a{x, y}";
+
+    let executed = db.queries_count.load(Ordering::Acquire);
+    let res = db.run_script(query, Default::default()).unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1), DataValue::from(2)]]);
+    assert_eq!(db.queries_count.load(Ordering::Acquire), executed + 1);
+    let res = db.run_script(query, Default::default()).unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1), DataValue::from(2)]]);
+    assert_eq!(db.queries_count.load(Ordering::Acquire), executed + 1);
+
+    db.run_script("?[x, y] <- [[3, 4]] :put a {x => y}", Default::default())
+        .unwrap();
+    let executed = db.queries_count.load(Ordering::Acquire);
+    let res = db.run_script(query, Default::default()).unwrap();
+    assert_eq!(res.rows.len(), 2);
+    assert_eq!(db.queries_count.load(Ordering::Acquire), executed + 1);
+
+    // params are part of the key, and non-deterministic queries are never cached
+    let executed = db.queries_count.load(Ordering::Acquire);
+    for _ in 0..2 {
+        db.run_script(
+            "?[x] := *a{x, y}, y > $min",
+            BTreeMap::from([("min".to_string(), DataValue::from(2))]),
+        )
+        .unwrap();
+        db.run_script("?[x] := x = rand_float()", Default::default())
+            .unwrap();
+    }
+    assert_eq!(db.queries_count.load(Ordering::Acquire), executed + 3);
+    let res = db
+        .run_script(
+            "?[x] := *a{x, y}, y > $min",
+            BTreeMap::from([("min".to_string(), DataValue::from(3))]),
+        )
+        .unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(3)]]);
+
+    // a cached result would skip the sleep
+    let executed = db.queries_count.load(Ordering::Acquire);
+    for _ in 0..2 {
+        db.run_script("?[x, y] := *a{x, y} :sleep 0.001", Default::default())
+            .unwrap();
+    }
+    assert_eq!(db.queries_count.load(Ordering::Acquire), executed + 2);
+}
+
+#[test]
+fn test_cancellable_script() {
+    let db = new_cozo_mem().unwrap();
+    let slow_query = r#"
+        r[n] := n = 0
+        r[m] := r[n], m = n + 1, m < 100000000
+        ?[count(n)] := r[n]
+    "#;
+    let cancellation = Poison::default();
+    let handle = {
+        let db = db.clone();
+        let cancellation = cancellation.clone();
+        std::thread::spawn(move || {
+            db.run_script_cancellable(slow_query, Default::default(), cancellation)
+        })
+    };
+    std::thread::sleep(Duration::from_millis(100));
+    cancellation.kill();
+    let err = handle.join().unwrap().unwrap_err();
+    assert!(err.to_string().contains("killed"), "{err:?}");
+
+    // queries in imperative scripts are killed as well
+    let cancellation = Poison::default();
+    cancellation.kill();
+    assert!(db
+        .run_script_cancellable("{?[a] <- [[1]]}", Default::default(), cancellation)
+        .is_err());
+
+    // other queries are unaffected
+    let res = db
+        .run_script_cancellable("?[a] <- [[1]]", Default::default(), Poison::default())
+        .unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1)]]);
+}
+
+#[test]
+fn test_query_memory_limit() {
+    let db = DbInstance::new("mem", "", "").unwrap();
+    db.set_query_memory_limit(1 << 20);
+    let ints = |range: std::ops::Range<i64>| DataValue::List(range.map(DataValue::from).collect());
+    let params = BTreeMap::from([
+        ("xs".to_string(), ints(0..3000)),
+        ("ys".to_string(), ints(0..12000)),
+        ("zs".to_string(), ints(12000..24000)),
+        ("few".to_string(), ints(0..300)),
+    ]);
+
+    // a cross product that would take up hundreds of megabytes
+    let err = db
+        .run_script("?[a, b] := a in $xs, b in $xs", params.clone())
+        .unwrap_err();
+    assert_eq!(
+        err.code().unwrap().to_string(),
+        "eval::memory_limit_exceeded"
+    );
+
+    // large values count towards the limit as well
+    let err = db
+        .run_script("?[i, l] := i in $xs, l = $xs", params.clone())
+        .unwrap_err();
+    assert_eq!(
+        err.code().unwrap().to_string(),
+        "eval::memory_limit_exceeded"
+    );
+
+    // the limit applies to each query separately
+    let res = db
+        .run_script(
+            r#"
+            {?[a] := a in $ys :create r {a}}
+            {?[a] := a in $zs :put r {a}}
+            {?[count(a)] := *r[a]}
+            "#,
+            params.clone(),
+        )
+        .unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(24000)]]);
+
+    // tuples derived more than once are only charged for once
+    let res = db
+        .run_script("?[a] := a in $xs, b in $few", params.clone())
+        .unwrap();
+    assert_eq!(res.rows.len(), 3000);
+
+    // removing the limit
+    let query = "?[a, b] := a in $ys, b in [1, 2]";
+    assert!(db.run_script(query, params.clone()).is_err());
+    db.set_query_memory_limit(0);
+    let res = db.run_script(query, params).unwrap();
+    assert_eq!(res.rows.len(), 24000);
+}
+
+#[test]
+fn test_typed_row_access() {
+    let db = DbInstance::new("mem", "", "").unwrap();
+    let res = db
+        .run_script(
+            r#"
+            ?[i, f, b, s, bs, u, l, n] := i = 42, f = 2.5, b = true, s = 'hello',
+                bs = decode_base64('AQID'), u = to_uuid('00000000-0000-0000-0000-000000000001'),
+                l = [1, 'a'], n = null
+            "#,
+            Default::default(),
+        )
+        .unwrap();
+    let row = &res.rows[0];
+
+    assert_eq!(row.get_int(0).unwrap(), 42);
+    assert_eq!(row.get_float(0).unwrap(), 42.0);
+    assert_eq!(row.get_float(1).unwrap(), 2.5);
+    assert!(row.get_bool(2).unwrap());
+    assert_eq!(row.get_str(3).unwrap(), "hello");
+    assert_eq!(row.get_as::<String>(3).unwrap(), "hello");
+    assert_eq!(row.get_bytes(4).unwrap(), &[1, 2, 3]);
+    assert_eq!(row.get_uuid(5).unwrap(), uuid::Uuid::from_u128(1));
+    assert_eq!(
+        row.get_list(6).unwrap(),
+        &[DataValue::from(1), DataValue::from("a")]
+    );
+
+    assert_eq!(row.get_opt_int(0).unwrap(), Some(42));
+    assert_eq!(row.get_opt_int(7).unwrap(), None);
+    assert_eq!(row.get_opt_float(7).unwrap(), None);
+    assert_eq!(row.get_opt_bool(7).unwrap(), None);
+    assert_eq!(row.get_opt_str(3).unwrap(), Some("hello"));
+    assert_eq!(row.get_opt_str(7).unwrap(), None);
+
+    // type mismatches
+    let err = row.get_int(1).unwrap_err();
+    assert_eq!(err.to_string(), "Expected an integer, got 2.5");
+    let err = row.get_str(0).unwrap_err();
+    assert_eq!(err.to_string(), "Expected a string, got 42");
+    assert!(row.get_int(7).is_err());
+    assert!(row.get_opt_int(3).is_err());
+    assert!(row.get_bytes(3).is_err());
+
+    // out of range
+    let err = row.get_int(8).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Index 8 is out of range for a row of length 8"
+    );
+    assert!(row.get_opt_str(100).is_err());
+}
+
+/// Memory storage whose transactions fail with transient errors a given number of times
+#[derive(Clone, Default)]
+struct FlakyStorage {
+    inner: MemStorage,
+    failures: Arc<AtomicUsize>,
+    transactions: Arc<AtomicUsize>,
+}
+
+impl<'s> Storage<'s> for FlakyStorage {
+    type Tx = <MemStorage as Storage<'s>>::Tx;
+
+    fn storage_kind(&self) -> &'static str {
+        "flaky"
+    }
+
+    fn transact(&'s self, write: bool) -> miette::Result<Self::Tx> {
+        self.transactions.fetch_add(1, Ordering::AcqRel);
+        let fail = self
+            .failures
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+            .is_ok();
+        if fail {
+            bail!(TransientStorageError("database is locked".to_string()))
+        }
+        self.inner.transact(write)
+    }
+
+    fn del_range(&'s self, lower: &[u8], upper: &[u8]) -> miette::Result<()> {
+        self.inner.del_range(lower, upper)
+    }
+
+    fn range_compact(&'s self, lower: &[u8], upper: &[u8]) -> miette::Result<()> {
+        self.inner.range_compact(lower, upper)
+    }
+
+    fn batch_put<'a>(
+        &'a self,
+        data: Box<dyn Iterator<Item = miette::Result<(Vec<u8>, Vec<u8>)>> + 'a>,
+    ) -> miette::Result<()> {
+        self.inner.batch_put(data)
+    }
+}
+
+#[test]
+fn test_retry_transient_errors() {
+    let storage = FlakyStorage::default();
+    let db = Db::new(storage.clone()).unwrap();
+    db.initialize().unwrap();
+    db.run_script("?[a] <- [[1]] :create r {a}", Default::default())
+        .unwrap();
+    let read = "?[a] := *r[a]";
+
+    // without a retry policy the error surfaces immediately
+    storage.failures.store(1, Ordering::Release);
+    let err = db.run_script(read, Default::default()).unwrap_err();
+    assert_eq!(err.code().unwrap().to_string(), "storage::transient");
+
+    db.set_retry_policy(3, Duration::from_millis(1));
+    storage.failures.store(3, Ordering::Release);
+    let res = db.run_script(read, Default::default()).unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1)]]);
+    assert_eq!(storage.failures.load(Ordering::Acquire), 0);
+
+    // retries are exhausted
+    storage.failures.store(4, Ordering::Release);
+    assert!(db.run_script(read, Default::default()).is_err());
+    assert_eq!(storage.failures.load(Ordering::Acquire), 0);
+
+    // queries that write are not retried
+    storage.failures.store(1, Ordering::Release);
+    assert!(db
+        .run_script("?[a] <- [[2]] :put r {a}", Default::default())
+        .is_err());
+    assert_eq!(storage.failures.load(Ordering::Acquire), 0);
+    let res = db.run_script(read, Default::default()).unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1)]]);
+
+    // other errors are not retried
+    storage.transactions.store(0, Ordering::Release);
+    assert!(db
+        .run_script("?[a] := *r[a], a = assert(false)", Default::default())
+        .is_err());
+    assert_eq!(storage.transactions.load(Ordering::Acquire), 1);
+}
+
+#[test]
+fn test_typed_params() {
+    let db = new_cozo_mem().unwrap();
+    let params = BTreeMap::from([
+        ("i".to_string(), DataValue::from(i64::MAX)),
+        (
+            "l".to_string(),
+            DataValue::List(vec![DataValue::from(1), DataValue::from("a")]),
+        ),
+        ("b".to_string(), DataValue::Bytes(vec![0, 255])),
+    ]);
+    let res = db
+        .run_script(
+            "?[i, l, b, t] := i = $i, l = $l, b = $b, t = [is_int(i), is_list(l), is_bytes(b)]",
+            params.clone(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.rows,
+        vec![vec![
+            params["i"].clone(),
+            params["l"].clone(),
+            params["b"].clone(),
+            DataValue::List(vec![DataValue::from(true); 3]),
+        ]]
+    );
+}
+
+#[test]
+fn test_affected_rows() {
+    let db = new_cozo_mem().unwrap();
+    let run = |script: &str| db.run_script(script, Default::default()).unwrap();
+
+    let res = run("?[k, v] <- [[1, 'a'], [2, 'b'], [3, 'c']] :create r {k => v}");
+    assert_eq!(res.affected_rows(), Some(3));
+    // rows written count even if unchanged
+    let res = run("?[k, v] <- [[1, 'a'], [4, 'd']] :put r {k => v}");
+    assert_eq!(res.affected_rows(), Some(2));
+    // rows removed count only if present
+    let res = run("?[k] <- [[1], [2], [5]] :rm r {k}");
+    assert_eq!(res.affected_rows(), Some(2));
+    let res = run("?[k, v] <- [[3, 'c']] :ensure r {k => v}");
+    assert_eq!(res.affected_rows(), Some(0));
+
+    run("::index create r:v {v}");
+    let res = run("?[k] <- [[3], [6]] :rm r {k}");
+    assert_eq!(res.affected_rows(), Some(1));
+    let res = run("{?[k] <- [[1], [2]] :create _t {k}} {?[k] <- [[2], [3]] :rm _t {k}}");
+    assert_eq!(res.affected_rows(), Some(1));
+
+    let res = run("?[k] := *r[k, _]");
+    assert_eq!(res.affected_rows(), None);
+    assert_eq!(res.rows, vec![vec![DataValue::from(4)]]);
+    assert!(res.into_json().get("affected_rows").is_none());
+
+    let res = run("?[k, v] <- [[7, 'g']] :put r {k => v}");
+    let json = res.into_json();
+    assert_eq!(json["affected_rows"], json!(1));
+    assert_eq!(
+        NamedRows::from_json(&json).unwrap().affected_rows(),
+        Some(1)
+    );
+}
+
+#[test]
+fn test_limit_warnings() {
+    let db = new_cozo_mem().unwrap();
+    let run = |script: &str| db.run_script(script, Default::default()).unwrap();
+
+    let res = run("?[a] := a in [4, 3, 2, 1] :order a :limit 2");
+    assert_eq!(
+        res.rows,
+        vec![vec![DataValue::from(1)], vec![DataValue::from(2)]]
+    );
+    assert_eq!(
+        res.warnings,
+        vec!["the result was truncated to 2 rows by :limit, 4 rows were found"]
+    );
+    let res = run("?[a] := a in [4, 3, 2, 1] :order a :limit 2 :offset 1");
+    assert_eq!(
+        res.warnings,
+        vec!["the result was truncated to 2 rows by :limit, 3 rows were found"]
+    );
+    let json = res.into_json();
+    assert_eq!(json["warnings"].as_array().unwrap().len(), 1);
+    assert_eq!(NamedRows::from_json(&json).unwrap().warnings().len(), 1);
+
+    // without sorting, evaluation stops once a row past the limit is found
+    for script in [
+        "?[a] := a in [4, 3, 2, 1] :limit 2",
+        "?[a] := a in [4, 3, 2, 1] :limit 2 :offset 1",
+    ] {
+        let res = run(script);
+        assert_eq!(res.rows.len(), 2);
+        assert_eq!(
+            res.warnings(),
+            ["the result was truncated to 2 rows by :limit, more rows were found"]
+        );
+    }
+
+    for script in [
+        "?[a] := a in [4, 3, 2, 1] :order a :limit 4",
+        "?[a] := a in [4, 3, 2, 1] :limit 4",
+        "?[a] := a in [4, 3, 2, 1] :limit 3 :offset 1",
+        "?[a] := a in [4, 3, 2, 1] :limit 5",
+        "?[a] := a in [4, 3, 2, 1]",
+    ] {
+        let res = run(script);
+        assert!(res.warnings.is_empty(), "{script}");
+        assert!(res.into_json().get("warnings").is_none());
+    }
+}
+
+#[test]
+fn test_catch_panics() {
+    let db = new_cozo_mem().unwrap();
+    db.register_fixed_rule(
+        "Panic".to_string(),
+        SimpleFixedRule::new(1, |_, _| panic!("boom")),
+    )
+    .unwrap();
+    let script = "?[a] <~ Panic()";
+
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        db.run_script(script, Default::default())
+    }));
+    assert!(res.is_err());
+
+    db.set_catch_panics(true);
+    let err = db.run_script(script, Default::default()).unwrap_err();
+    assert_eq!(err.code().unwrap().to_string(), "db::internal_panic");
+    assert!(err.to_string().contains("boom"));
+
+    // the database is still usable afterwards
+    let res = db.run_script("?[a] <- [[1]]", Default::default()).unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(1)]]);
+}
+
+#[test]
+fn test_compact() {
+    #[cfg(feature = "storage-sqlite")]
+    let sqlite_path = std::env::temp_dir().join(format!("cozo-compact-{}.db", std::process::id()));
+    let dbs = [
+        DbInstance::new("mem", "", "").unwrap(),
+        #[cfg(feature = "storage-sqlite")]
+        DbInstance::new("sqlite", &sqlite_path, "").unwrap(),
+    ];
+
+    let params = BTreeMap::from([(
+        "ks".to_string(),
+        DataValue::List((0..1000).map(DataValue::from).collect()),
+    )]);
+    for db in dbs {
+        db.run_script(
+            "?[k, v] := k in $ks, v = to_string(k) :create kv {k => v}",
+            params.clone(),
+        )
+        .unwrap();
+        db.run_script("?[k] := *kv{k}, k % 2 == 0 :rm kv {k}", Default::default())
+            .unwrap();
+        db.compact().unwrap();
+        let res = db
+            .run_script("?[count(k)] := *kv{k}", Default::default())
+            .unwrap();
+        assert_eq!(res.rows[0][0], DataValue::from(500));
+        db.run_script(
+            "?[k, v] <- [[2, 'two']] :put kv {k => v}",
+            Default::default(),
+        )
+        .unwrap();
+        let res = db
+            .run_script("?[v] := *kv{k: 2, v}", Default::default())
+            .unwrap();
+        assert_eq!(res.rows[0][0], DataValue::from("two"));
+    }
+    #[cfg(feature = "storage-sqlite")]
+    std::fs::remove_file(sqlite_path).unwrap();
+}
+
+#[test]
+fn test_query_plan() {
+    let db = new_cozo_mem().unwrap();
+    db.run_script(
+        "?[a, b] <- [[1, 'x'], [2, 'y']] :create t {a => b}",
+        Default::default(),
+    )
+    .unwrap();
+
+    let plan = db
+        .query_plan(
+            "?[a, b] := *t{a, b}, a > $min",
+            BTreeMap::from([("min".to_string(), DataValue::from(1))]),
+        )
+        .unwrap();
+    let filters = plan
+        .steps
+        .iter()
+        .flat_map(|step| match &step.op {
+            PlanOp::LoadStored { relation, filters } => {
+                assert_eq!(relation, ":t");
+                filters.clone()
+            }
+            PlanOp::Filter { filters } => filters.clone(),
+            _ => vec![],
+        })
+        .map(|f| f.to_string())
+        .collect_vec();
+    assert_eq!(filters, vec!["gt(a, 1)"]);
+    assert!(plan
+        .steps
+        .iter()
+        .any(|step| matches!(&step.op, PlanOp::LoadStored { relation, .. } if relation == ":t")));
+    let out = plan.steps.last().unwrap();
+    assert_eq!(out.op, PlanOp::Out);
+    assert_eq!(out.rule, "?");
+    assert_eq!(
+        out.out_relation,
+        Some(vec!["a".to_string(), "b".to_string()])
+    );
+
+    let plan = db
+        .query_plan("?[b, count(a)] := *t{a, b}", Default::default())
+        .unwrap();
+    assert_eq!(
+        plan.steps.last().unwrap().op,
+        PlanOp::AggrOut {
+            aggregations: vec![None, Some("count".to_string())]
+        }
+    );
+
+    // `::explain` shows the same plan
+    let script = "?[a] := *t{a, b}, b == 'y'";
+    let plan = db.query_plan(script, Default::default()).unwrap();
+    let explained = db
+        .run_script(&format!("::explain {{ {script} }}"), Default::default())
+        .unwrap();
+    assert_eq!(
+        explained
+            .rows
+            .iter()
+            .map(|row| row[4].clone())
+            .collect_vec(),
+        plan.steps
+            .iter()
+            .map(|step| DataValue::from(step.op.name()))
+            .collect_vec()
+    );
+    let json = serde_json::to_value(&plan).unwrap();
+    assert_eq!(json["steps"][0]["op"]["type"], json!("load_stored"));
+
+    assert!(db.query_plan("::relations", Default::default()).is_err());
+    assert!(db
+        .query_plan("?[a] := *nope{a}", Default::default())
+        .is_err());
+}
+
+#[test]
+fn test_import_from_csv() {
+    let db = new_cozo_mem().unwrap();
+    db.run_script(
+        ":create people {id: Int => name: String, score: Float, active: Bool, note: String?}",
+        Default::default(),
+    )
+    .unwrap();
+
+    let data = "id;name;score;active;note;extra\n1;alice;3.5;true;;x\n2;bob;4;false;hi;y\n";
+    let options = CsvImportOptions {
+        delimiter: b';',
+        ..Default::default()
+    };
+    let n = db
+        .import_from_csv("people", data.as_bytes(), &options)
+        .unwrap();
+    assert_eq!(n, 2);
+    let res = db
+        .run_script(
+            "?[id, name, score, active, note] := *people{id, name, score, active, note}",
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.into_json()["rows"],
+        json!([[1, "alice", 3.5, true, null], [2, "bob", 4.0, false, "hi"]])
+    );
+
+    // type hints override the types of the columns
+    let data = "id,name,score,active,note\n3,carol,5,true,12\n";
+    let options = CsvImportOptions {
+        types: BTreeMap::from([("note".to_string(), "Int".to_string())]),
+        ..Default::default()
+    };
+    assert!(db
+        .import_from_csv("people", data.as_bytes(), &options)
+        .is_err());
+    db.run_script(":create hinted {id: Int => note: Any}", Default::default())
+        .unwrap();
+    db.import_from_csv("hinted", "id,note\n1,12\n".as_bytes(), &options)
+        .unwrap();
+    let res = db
+        .run_script("?[note] := *hinted{note}", Default::default())
+        .unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(12)]]);
+
+    // bad lines are reported with their line numbers and nothing is imported
+    let data = "id,name,score,active,note\n4,dave,1,true,\nfive,eve,1,true,\n6,frank,x,true,\n";
+    let err = db
+        .import_from_csv("people", data.as_bytes(), &Default::default())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("2 bad line(s)"), "{}", err);
+    assert!(err.contains("line 3:"), "{}", err);
+    assert!(err.contains("line 4:"), "{}", err);
+    let res = db
+        .run_script("?[count(id)] := *people{id}", Default::default())
+        .unwrap();
+    assert_eq!(res.rows, vec![vec![DataValue::from(2)]]);
+
+    // every column of the relation must be present
+    assert!(db
+        .import_from_csv(
+            "people",
+            "id,name\n7,gina\n".as_bytes(),
+            &Default::default()
+        )
+        .is_err());
+}
+
+#[test]
+fn test_conditional_relation_creation() {
+    let db = new_cozo_mem().unwrap();
+    assert!(!db.relation_exists("kv").unwrap());
+    assert!(!db.remove_relation_if_exists("kv").unwrap());
+
+    assert!(db
+        .create_relation_if_not_exists("kv", "{k: Int => v: String}")
+        .unwrap());
+    assert!(db.relation_exists("kv").unwrap());
+    db.run_script("?[k, v] <- [[1, 'a']] :put kv {k => v}", Default::default())
+        .unwrap();
+    // creating again leaves the relation and its data alone
+    for _ in 0..2 {
+        assert!(!db
+            .create_relation_if_not_exists("kv", "{k: Int => v: String}")
+            .unwrap());
+    }
+    let res = db
+        .run_script("?[k, v] := *kv[k, v]", Default::default())
+        .unwrap();
+    assert_eq!(res.rows.len(), 1);
+
+    assert!(db.remove_relation_if_exists("kv").unwrap());
+    assert!(!db.relation_exists("kv").unwrap());
+    assert!(!db.remove_relation_if_exists("kv").unwrap());
+
+    assert!(db
+        .create_relation_if_not_exists("kv {a} ?[a] <- [[1]]", "{a}")
+        .is_err());
+    assert!(db.create_relation_if_not_exists("a.b_1", "{a}").unwrap());
+    assert!(db.relation_exists("a.b_1").unwrap());
+}
+
+#[test]
+fn test_seeded_sample_shuffle() {
+    let db = new_cozo_mem().unwrap();
+    let script = r#"
+        l[x] <- [[[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]]]
+        ?[s, p, n] := l[x], s = sample(x, 3), p = shuffle(x), n = is_null(shuffle(null))
+        :seed 7
+    "#;
+    let run = || {
+        db.run_script(script, Default::default())
+            .unwrap()
+            .into_json()["rows"]
+            .clone()
+    };
+    let res = run();
+    assert_eq!(run(), res);
+    assert_eq!(res[0][0].as_array().unwrap().len(), 3);
+    assert_eq!(res[0][2], json!(true));
+    let err = db
+        .run_script("?[s] := s = sample([1, 2], 3)", Default::default())
+        .unwrap_err();
+    let err = format!("{err:?}");
+    assert!(err.contains("cannot take 3 elements"), "{}", err);
+}
+
+#[test]
+fn test_float_eq_tolerance() {
+    let db = new_cozo_mem().unwrap();
+    let script = r#"
+        data[x, y] <- [[0.1, 0.2]]
+        ?[eq, neq, int_eq] := data[x, y], eq = x + y == 0.3, neq = x + y != 0.3, int_eq = 1 == 1.0000001
+    "#;
+    let run = |db: &Db<MemStorage>| {
+        db.run_script(script, Default::default())
+            .unwrap()
+            .into_json()["rows"]
+            .clone()
+    };
+    assert_eq!(run(&db), json!([[false, true, false]]));
+
+    db.set_float_eq_tolerance(Some(FloatEqTolerance {
+        absolute: 1e-9,
+        relative: 0.,
+    }));
+    // integers are still compared exactly
+    assert_eq!(run(&db), json!([[true, false, false]]));
+    let res = db
+        .run_script("?[a] := a = 1e20 + 1e5 == 1e20", Default::default())
+        .unwrap();
+    assert_eq!(res.rows[0][0], DataValue::from(false));
+
+    db.set_float_eq_tolerance(Some(FloatEqTolerance {
+        absolute: 0.,
+        relative: 1e-12,
+    }));
+    let res = db
+        .run_script("?[a] := a = 1e20 + 1e5 == 1e20", Default::default())
+        .unwrap();
+    assert_eq!(res.rows[0][0], DataValue::from(true));
+
+    db.set_float_eq_tolerance(None);
+    assert_eq!(run(&db), json!([[false, true, false]]));
+}
+
+#[test]
+fn float_eq_tolerance_with_result_cache() {
+    let db = new_cozo_mem().unwrap();
+    db.set_result_cache_capacity(10);
+    db.run_script("?[x] <- [[0.1]] :create r {x}", Default::default())
+        .unwrap();
+    let run = |db: &Db<MemStorage>| {
+        db.run_script("?[eq] := *r[x], eq = x + 0.2 == 0.3", Default::default())
+            .unwrap()
+            .into_json()["rows"]
+            .clone()
+    };
+
+    assert_eq!(run(&db), json!([[false]]));
+    db.set_float_eq_tolerance(Some(FloatEqTolerance {
+        absolute: 1e-9,
+        relative: 0.,
+    }));
+    assert_eq!(run(&db), json!([[true]]));
+    db.set_float_eq_tolerance(None);
+    assert_eq!(run(&db), json!([[false]]));
+}
+
+#[test]
+fn interned_result_rows() {
+    let db = new_cozo_mem().unwrap();
+    let rows = (0..100i64)
+        .map(|id| {
+            let status = if id % 3 == 0 { "approved" } else { "pending" };
+            DataValue::List(vec![DataValue::from(id), DataValue::from(status)])
+        })
+        .collect_vec();
+    db.run_script(
+        "?[id, status] <- $rows :create tasks {id => status}",
+        BTreeMap::from([("rows".to_string(), DataValue::List(rows))]),
+    )
+    .unwrap();
+    let res = db
+        .run_script("?[id, status] := *tasks[id, status]", Default::default())
+        .unwrap();
+    let plain = res.rows.clone();
+
+    let mut interner = StrInterner::new();
+    let interned = res.interned_rows(&mut interner);
+    assert_eq!(interner.len(), 2);
+    let allocations = interned
+        .iter()
+        .map(|row| match &row[1] {
+            InternedValue::Str(s) => Arc::as_ptr(s) as *const u8 as usize,
+            v => panic!("expected a string, got {v:?}"),
+        })
+        .collect::<BTreeSet<_>>();
+    assert_eq!(allocations.len(), 2);
+    let back = interned
+        .into_iter()
+        .map(|row| row.into_iter().map(DataValue::from).collect_vec())
+        .collect_vec();
+    assert_eq!(back, plain);
+}
@@ -6,13 +6,17 @@
  * You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::collections::BTreeSet;
 use std::sync::atomic::{AtomicU32, AtomicU64};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use miette::{bail, Result};
+use smartstring::{LazyCompact, SmartString};
 
-use crate::data::tuple::TupleT;
+use crate::data::tuple::{Tuple, TupleT};
 use crate::data::value::DataValue;
+use crate::runtime::db::Poison;
+use crate::runtime::memory_budget::MemoryBudget;
 use crate::runtime::relation::RelationId;
 use crate::storage::temp::TempTx;
 use crate::storage::StoreTx;
@@ -22,6 +26,14 @@ pub struct SessionTx<'a> {
     pub(crate) temp_store_tx: TempTx,
     pub(crate) relation_store_id: Arc<AtomicU64>,
     pub(crate) temp_store_id: AtomicU32,
+    /// Stored relations written to in this transaction
+    pub(crate) written_relations: BTreeSet<SmartString<LazyCompact>>,
+    /// If set, killing it kills all queries run in this transaction
+    pub(crate) cancellation: Option<Poison>,
+    /// If set, limits the memory used by the intermediate results of the current query
+    pub(crate) memory_budget: Option<Arc<MemoryBudget>>,
+    /// Caveats found while running the current query, returned with its result
+    pub(crate) warnings: Mutex<Vec<String>>,
 }
 
 pub const CURRENT_STORAGE_VERSION: [u8; 1] = [0x00];
@@ -32,6 +44,17 @@ fn storage_version_key() -> Vec<u8> {
 }
 
 impl<'a> SessionTx<'a> {
+    /// Records a caveat about the result of the current query.
+    pub(crate) fn warn(&self, warning: String) {
+        self.warnings.lock().unwrap().push(warning);
+    }
+    /// Accounts for a tuple kept in the intermediate results of the current query.
+    pub(crate) fn charge_memory(&self, tuple: &Tuple) -> Result<()> {
+        match &self.memory_budget {
+            Some(budget) => budget.charge(tuple),
+            None => Ok(()),
+        }
+    }
     pub(crate) fn init_storage(&mut self) -> Result<RelationId> {
         let tuple = vec![DataValue::Null];
         let t_encoded = tuple.encode_as_key(RelationId::SYSTEM);
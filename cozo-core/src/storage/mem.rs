@@ -149,6 +149,18 @@ impl<'s> StoreTx<'s> for MemTx<'s> {
         }
     }
 
+    fn del_and_check(&mut self, key: &[u8]) -> Result<bool> {
+        match self {
+            MemTx::Reader(_) => {
+                bail!("write in read transaction")
+            }
+            MemTx::Writer(wtr, cache) => Ok(match cache.insert(key.to_vec(), None) {
+                Some(prev) => prev.is_some(),
+                None => wtr.contains_key(key),
+            }),
+        }
+    }
+
     fn exists(&self, key: &[u8], _for_update: bool) -> Result<bool> {
         Ok(match self {
             MemTx::Reader(rdr) => rdr.contains_key(key),
@@ -7,7 +7,8 @@
  */
 
 use itertools::Itertools;
-use miette::Result;
+use miette::{Diagnostic, Result};
+use thiserror::Error;
 
 use crate::data::tuple::Tuple;
 use crate::data::value::ValidityTs;
@@ -25,6 +26,14 @@ pub(crate) mod temp;
 pub(crate) mod tikv;
 // pub(crate) mod re;
 
+/// Error for storage failures that may go away when the operation is retried,
+/// such as lock contention. Storage engines should report such failures with this type
+/// so that read-only queries can be retried, see [Db::set_retry_policy](crate::Db::set_retry_policy).
+#[derive(Debug, Error, Diagnostic)]
+#[error("Transient storage error: {0}")]
+#[diagnostic(code(storage::transient))]
+pub struct TransientStorageError(pub String);
+
 /// Swappable storage trait for Cozo's storage engine
 pub trait Storage<'s>: Send + Sync + Clone {
     /// The associated transaction type used by this engine
@@ -78,6 +87,15 @@ pub trait StoreTx<'s>: Sync {
     /// Delete a key-value pair from the storage.
     fn del(&mut self, key: &[u8]) -> Result<()>;
 
+    /// Delete a key-value pair from the storage, returning whether the key was present.
+    /// The default implementation calls [`exists`](Self::exists) before deleting,
+    /// storage engines learning it from the deletion itself should override it.
+    fn del_and_check(&mut self, key: &[u8]) -> Result<bool> {
+        let existed = self.exists(key, true)?;
+        self.del(key)?;
+        Ok(existed)
+    }
+
     /// Check if a key exists. If `for_update` is `true` (only possible in a write transaction),
     /// then the database needs to guarantee that `commit()` can only succeed if
     /// the key has not been modified outside the transaction.
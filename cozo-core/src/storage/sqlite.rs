@@ -12,13 +12,13 @@ use std::sync::{Arc, Mutex};
 use ::sqlite::Connection;
 use crossbeam::sync::{ShardedLock, ShardedLockReadGuard, ShardedLockWriteGuard};
 use either::{Either, Left, Right};
-use miette::{bail, miette, IntoDiagnostic, Result};
+use miette::{bail, miette, Report, Result};
 use sqlite::{ConnectionWithFullMutex, State, Statement};
 
 use crate::data::tuple::{check_key_for_validity, Tuple};
 use crate::data::value::ValidityTs;
 use crate::runtime::relation::{decode_tuple_from_kv, extend_tuple_from_v};
-use crate::storage::{Storage, StoreTx};
+use crate::storage::{Storage, StoreTx, TransientStorageError};
 use crate::utils::swap_option_result;
 
 /// The Sqlite storage engine
@@ -38,7 +38,7 @@ pub fn new_cozo_sqlite(path: impl AsRef<Path>) -> Result<crate::Db<SqliteStorage
     if path.as_ref().to_str() == Some("") {
         bail!("empty path for sqlite storage")
     }
-    let conn = Connection::open_with_full_mutex(&path).into_diagnostic()?;
+    let conn = Connection::open_with_full_mutex(&path).map_err(sqlite_err)?;
     let query = r#"
         create table if not exists cozo
         (
@@ -47,7 +47,7 @@ pub fn new_cozo_sqlite(path: impl AsRef<Path>) -> Result<crate::Db<SqliteStorage
         );
     "#;
     let mut statement = conn.prepare(query).unwrap();
-    while statement.next().into_diagnostic()? != State::Done {}
+    while statement.next().map_err(sqlite_err)? != State::Done {}
 
     let ret = crate::Db::new(SqliteStorage {
         lock: Default::default(),
@@ -59,13 +59,21 @@ pub fn new_cozo_sqlite(path: impl AsRef<Path>) -> Result<crate::Db<SqliteStorage
     Ok(ret)
 }
 
+// SQLITE_BUSY and SQLITE_LOCKED mean that another connection holds a conflicting lock
+fn sqlite_err(err: sqlite::Error) -> Report {
+    match err.code {
+        Some(5) | Some(6) => TransientStorageError(err.to_string()).into(),
+        _ => miette!(err),
+    }
+}
+
 impl<'s> Storage<'s> for SqliteStorage {
     type Tx = SqliteTx<'s>;
 
     fn transact(&'s self, write: bool) -> Result<Self::Tx> {
         let conn = {
             match self.pool.lock().unwrap().pop() {
-                None => Connection::open_with_full_mutex(&self.name).into_diagnostic()?,
+                None => Connection::open_with_full_mutex(&self.name).map_err(sqlite_err)?,
                 Some(conn) => conn,
             }
         };
@@ -75,8 +83,8 @@ impl<'s> Storage<'s> for SqliteStorage {
             Left(self.lock.read().unwrap())
         };
         if write {
-            let mut stmt = conn.prepare("begin;").into_diagnostic()?;
-            while stmt.next().into_diagnostic()? != State::Done {}
+            let mut stmt = conn.prepare("begin;").map_err(sqlite_err)?;
+            while stmt.next().map_err(sqlite_err)? != State::Done {}
         }
         Ok(SqliteTx {
             lock,
@@ -203,9 +211,9 @@ impl<'s> StoreTx<'s> for SqliteTx<'s> {
         statement.reset().unwrap();
 
         statement.bind((1, key)).unwrap();
-        Ok(match statement.next().into_diagnostic()? {
+        Ok(match statement.next().map_err(sqlite_err)? {
             State::Row => {
-                let res = statement.read::<Vec<u8>, _>(0).into_diagnostic()?;
+                let res = statement.read::<Vec<u8>, _>(0).map_err(sqlite_err)?;
                 Some(res)
             }
             State::Done => None,
@@ -228,7 +236,7 @@ impl<'s> StoreTx<'s> for SqliteTx<'s> {
 
         statement.bind((1, key)).unwrap();
         statement.bind((2, val)).unwrap();
-        while statement.next().into_diagnostic()? != State::Done {}
+        while statement.next().map_err(sqlite_err)? != State::Done {}
         Ok(())
     }
 
@@ -239,7 +247,7 @@ impl<'s> StoreTx<'s> for SqliteTx<'s> {
         statement.reset().unwrap();
 
         statement.bind((1, key)).unwrap();
-        while statement.next().into_diagnostic()? != State::Done {}
+        while statement.next().map_err(sqlite_err)? != State::Done {}
 
         Ok(())
     }
@@ -251,7 +259,7 @@ impl<'s> StoreTx<'s> for SqliteTx<'s> {
         statement.reset().unwrap();
 
         statement.bind((1, key)).unwrap();
-        Ok(match statement.next().into_diagnostic()? {
+        Ok(match statement.next().map_err(sqlite_err)? {
             State::Row => true,
             State::Done => false,
         })
@@ -262,7 +270,7 @@ impl<'s> StoreTx<'s> for SqliteTx<'s> {
             if !self.committed {
                 let query = r#"commit;"#;
                 let mut statement = self.conn.as_ref().unwrap().prepare(query).unwrap();
-                while statement.next().into_diagnostic()? != State::Done {}
+                while statement.next().map_err(sqlite_err)? != State::Done {}
                 self.committed = true;
             } else {
                 bail!("multiple commits")
@@ -380,11 +388,11 @@ struct SkipIter<'l> {
 impl<'l> SkipIter<'l> {
     fn next_inner(&mut self) -> Result<Option<Tuple>> {
         loop {
-            self.stmt.reset().into_diagnostic()?;
+            self.stmt.reset().map_err(sqlite_err)?;
             self.stmt.bind((1, &self.next_bound as &[u8])).unwrap();
             self.stmt.bind((2, &self.upper_bound as &[u8])).unwrap();
 
-            match self.stmt.next().into_diagnostic()? {
+            match self.stmt.next().map_err(sqlite_err)? {
                 State::Done => return Ok(None),
                 State::Row => {
                     let k = self.stmt.read::<Vec<u8>, _>(0).unwrap();
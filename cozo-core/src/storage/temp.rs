@@ -76,6 +76,10 @@ impl<'s> StoreTx<'s> for TempTx {
         Ok(())
     }
 
+    fn del_and_check(&mut self, key: &[u8]) -> Result<bool> {
+        Ok(self.store.remove(key).is_some())
+    }
+
     fn exists(&self, key: &[u8], _for_update: bool) -> Result<bool> {
         Ok(self.store.contains_key(key))
     }
@@ -125,6 +125,14 @@ fn value2js<'a>(cx: &mut impl Context<'a>, val: &DataValue) -> JsResult<'a, JsVa
             target_l.set(cx, 1, a)?;
             target_l.as_value(cx)
         }
+        DataValue::BitSet(bs) => {
+            let target_l = cx.empty_array();
+            for (i, el) in bs.0.iter().enumerate() {
+                let el = cx.number(el as f64);
+                target_l.set(cx, i as u32, el)?;
+            }
+            target_l.as_value(cx)
+        }
         DataValue::Bot => cx.undefined().as_value(cx),
     })
 }
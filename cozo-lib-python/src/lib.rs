@@ -118,6 +118,7 @@ fn value_to_py(val: DataValue, py: Python<'_>) -> PyObject {
         DataValue::Validity(vld) => {
             [vld.timestamp.0 .0.into_py(py), vld.is_assert.0.into_py(py)].into_py(py)
         }
+        DataValue::BitSet(bs) => bs.0.iter().collect::<Vec<_>>().into_py(py),
         DataValue::Bot => py.None(),
     }
 }